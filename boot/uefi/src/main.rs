@@ -8,6 +8,7 @@ extern crate alloc;
 extern crate log;
 
 use ::boot::BootInfo;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 #[allow(unused)]
@@ -182,7 +183,7 @@ fn load_elf(elf_data: &[u8]) -> ELFEntry {
             map_kernel_pages_4k(unsafe { &mut *p4 }, vaddr_start.as_usize() as _, num_pages);
             let start_page = Page::new(vaddr_start);
             let end_page = Page::forward(start_page, num_pages);
-            start_page..end_page
+            Ok(start_page..end_page)
         },
         &|x| {
             let page = Page::containing(x);
@@ -232,7 +233,12 @@ fn gen_available_physical_memory() -> &'static [Range<Frame>] {
     return available_physical_memory_ranges;
 }
 
-fn gen_boot_info(device_tree: &'static [u8], init_fs: &'static [u8]) -> BootInfo {
+fn gen_boot_info(
+    device_tree: &'static [u8],
+    init_fs: &'static [u8],
+    kernel_image: &'static [u8],
+    command_line: &'static str,
+) -> BootInfo {
     let uart = {
         let devtree = DeviceTree::new(device_tree).unwrap();
         let node = devtree.compatible("arm,pl011").unwrap();
@@ -251,10 +257,23 @@ fn gen_boot_info(device_tree: &'static [u8], init_fs: &'static [u8]) -> BootInfo
         device_tree,
         uart,
         init_fs,
+        kernel_image,
         shutdown: Some(shutdown),
+        command_line,
     }
 }
 
+fn file_exists(handle: Handle, path: &str) -> bool {
+    let mut sfs =
+        boot::get_image_file_system(handle).expect("Cannot open `SimpleFileSystem` protocol");
+    let mut directory = sfs.open_volume().unwrap();
+    let mut data = [0u16; 512];
+    let filename = CStr16::from_str_with_buf(path, &mut data).unwrap();
+    directory
+        .open(filename, FileMode::Read, FileAttribute::empty())
+        .is_ok()
+}
+
 fn read_file(handle: Handle, path: &str) -> Vec<u8> {
     let mut sfs =
         boot::get_image_file_system(handle).expect("Cannot open `SimpleFileSystem` protocol");
@@ -287,19 +306,30 @@ fn read_file(handle: Handle, path: &str) -> Vec<u8> {
     }
 }
 
-fn read_dtb(handle: Handle) -> &'static mut [u8] {
-    // Try to get dtb path from command line args: dtb=...
+/// Command line args of the bootloader, followed by the contents of `cmdline.txt` if it exists.
+/// Args other than `dtb=...` are passed to the kernel.
+fn read_command_line(handle: Handle) -> String {
+    let mut command_line = String::new();
     let loaded_image = boot::open_protocol_exclusive::<LoadedImage>(boot::image_handle()).unwrap();
-    let options = loaded_image.load_options_as_bytes();
-    if let Some(options) = options {
-        let mut args = core::str::from_utf8(options).unwrap().split(" ");
-        if let Some(dtb_path) = args
-            .find(|x| x.starts_with("dtb="))
-            .map(|x| x.strip_prefix("dtb=").unwrap())
-        {
-            info!("Load device tree from {}", dtb_path);
-            return read_file(handle, dtb_path).leak();
-        }
+    if let Some(options) = loaded_image.load_options_as_bytes() {
+        command_line.push_str(core::str::from_utf8(options).unwrap());
+    }
+    if file_exists(handle, "cmdline.txt") {
+        let file = read_file(handle, "cmdline.txt");
+        command_line.push(' ');
+        command_line.push_str(core::str::from_utf8(&file).unwrap());
+    }
+    command_line
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_dtb(handle: Handle, command_line: &str) -> &'static mut [u8] {
+    // Try to get dtb path from command line args: dtb=...
+    if let Some(dtb_path) = command_line.split(" ").find_map(|x| x.strip_prefix("dtb=")) {
+        info!("Load device tree from {}", dtb_path);
+        return read_file(handle, dtb_path).leak();
     }
     // Try to load dtb from efi configuration table
     const FDT_TABLE_GUID: Guid = uefi::guid!("b1b621d5-f19c-41a5-830b-d9152c69aae0");
@@ -436,8 +466,10 @@ static mut BOOT_INFO: BootInfo = BootInfo {
     available_physical_memory: &[],
     device_tree: &[],
     init_fs: &[],
+    kernel_image: &[],
     uart: None,
     shutdown: None,
+    command_line: "",
 };
 
 static mut INIT_ARRAY: Option<&'static [extern "C" fn()]> = None;
@@ -467,16 +499,25 @@ pub unsafe fn main() -> Status {
 
     establish_el1_page_table();
 
-    let kernel_elf = read_file(image, "sophon");
+    let kernel_elf = read_file(image, "sophon").leak();
     let init_fs = read_file(image, "init.fs").leak();
-    let dtb = read_dtb(image);
-    let entry = load_elf(&kernel_elf);
+    let command_line = read_command_line(image);
+    let dtb = read_dtb(image, &command_line);
+    let entry = load_elf(kernel_elf);
 
     info!("Starting kernel...");
 
     info!("DTB @ {:?}", dtb.as_ptr_range());
 
-    BOOT_INFO = gen_boot_info(dtb, init_fs);
+    info!("Command line: {:?}", command_line);
+
+    let command_line = command_line
+        .split(" ")
+        .filter(|x| !x.starts_with("dtb="))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .leak();
+    BOOT_INFO = gen_boot_info(dtb, init_fs, kernel_elf, command_line);
     INIT_ARRAY = mem::transmute(entry.init_array);
 
     #[allow(static_mut_refs)]
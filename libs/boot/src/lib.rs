@@ -9,6 +9,10 @@ pub struct BootInfo {
     pub available_physical_memory: &'static [Range<Frame>],
     pub device_tree: &'static [u8],
     pub init_fs: &'static [u8],
+    /// The kernel ELF file, for its symbol table.
+    pub kernel_image: &'static [u8],
     pub uart: Option<Address>,
     pub shutdown: Option<extern "C" fn() -> !>,
+    /// Kernel options, as space-separated `key=value` pairs or flags.
+    pub command_line: &'static str,
 }
@@ -8,6 +8,10 @@ pub trait Device: Send + Sync {
     fn name(&self) -> &'static str;
     fn read(&self, offset: usize, buf: &mut [u8]) -> Option<usize>;
     fn write(&self, offset: usize, buf: &[u8]) -> Option<usize>;
+    /// Device-specific control operation.
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> Option<usize> {
+        None
+    }
 }
 
 pub enum DevRequest<'a> {
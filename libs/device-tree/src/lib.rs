@@ -52,6 +52,40 @@ impl<'buf, 'index> DeviceTree<'buf, 'index> {
         }
         None
     }
+
+    /// Iterate over all the nodes compatible with `name`, in device tree order.
+    pub fn all_compatible<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = Node<'a, 'index, 'buf>> + 'a {
+        self.index
+            .nodes()
+            .filter(move |n| {
+                n.props()
+                    .find(|p| p.name() == Ok("compatible"))
+                    .is_some_and(|compatible| {
+                        let mut strs = compatible.iter_str();
+                        while let Ok(Some(s)) = strs.next() {
+                            if s == name {
+                                return true;
+                            }
+                        }
+                        false
+                    })
+            })
+            .map(|node| Node { node })
+    }
+
+    /// Iterate over all the `cpu` nodes, in device tree order.
+    pub fn cpus(&self) -> impl Iterator<Item = Node> {
+        self.index
+            .nodes()
+            .filter(|n| {
+                n.props()
+                    .any(|p| p.name() == Ok("device_type") && p.str() == Ok("cpu"))
+            })
+            .map(|node| Node { node })
+    }
 }
 
 #[derive(Clone)]
@@ -88,6 +122,11 @@ impl<'a, 'index: 'a, 'buf: 'index> Node<'a, 'index, 'buf> {
         v
     }
 
+    pub fn prop_str(&self, name: &str) -> Option<&'buf str> {
+        let prop = self.node.props().find(|p| p.name() == Ok(name))?;
+        prop.str().ok()
+    }
+
     pub fn parent(&self) -> Option<Self> {
         self.node.parent().map(|node| Node { node })
     }
@@ -145,6 +184,7 @@ impl<'a, 'index: 'a, 'buf: 'index> Node<'a, 'index, 'buf> {
                 _ => unreachable!(),
             };
             let size = match size_cells {
+                0 => 0,
                 1 => Self::read_u32(&mut buf) as usize,
                 2 => Self::read_u64(&mut buf) as usize,
                 _ => unreachable!(),
@@ -0,0 +1,277 @@
+//! Validation of untrusted ELF files.
+//!
+//! `xmas_elf` panics on out-of-bounds offsets, misaligned tables and bad strings.
+//! Everything the loader later reads through it is checked here first.
+
+use core::ops::Range;
+
+use xmas_elf::dynamic::Tag;
+use xmas_elf::header::{Class, Data, Machine, Type as FileType};
+use xmas_elf::program::{ProgramHeader, SegmentData, Type};
+use xmas_elf::sections::{SectionHeader, ShType};
+use xmas_elf::ElfFile;
+
+const PROGRAM_HEADER_SIZE: u64 = 56;
+const SECTION_HEADER_SIZE: u64 = 64;
+const SYMBOL_SIZE: u64 = 24;
+const DYNAMIC_SIZE: u64 = 16;
+/// `xmas_elf` asserts that section indices are below `SHN_LORESERVE`.
+const MAX_SECTIONS: u16 = 0xff00;
+/// Segments must be below this address, so that adding a load offset can not overflow.
+const MAX_VADDR: u64 = 1 << 47;
+/// The loadable segments must fit in this much memory.
+const MAX_IMAGE_SIZE: u64 = 256 << 20;
+const PT_GNU_STACK: u32 = 0x6474e551;
+
+/// Parse and validate an ELF file.
+pub fn parse(data: &[u8]) -> Result<ElfFile<'_>, &'static str> {
+    let elf = ElfFile::new(data)?;
+    check_header(&elf)?;
+    check_program_headers(&elf)?;
+    check_sections(&elf)?;
+    check_dynamic(&elf)?;
+    Ok(elf)
+}
+
+/// The byte range `offset..offset + size` of the file, if it is inside the file.
+fn file_range(data: &[u8], offset: u64, size: u64) -> Result<Range<usize>, &'static str> {
+    let end = offset.checked_add(size).ok_or("offset overflow")?;
+    if end > data.len() as u64 {
+        return Err("offset out of file bounds");
+    }
+    Ok(offset as usize..end as usize)
+}
+
+/// Check that a string table holds a valid string at `index`.
+pub fn check_str(table: &[u8], index: u64) -> Result<(), &'static str> {
+    let bytes = table.get(index as usize..).ok_or("string out of bounds")?;
+    let len = bytes
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or("unterminated string")?;
+    core::str::from_utf8(&bytes[..len]).map_err(|_| "non-UTF-8 string")?;
+    Ok(())
+}
+
+fn check_header(elf: &ElfFile) -> Result<(), &'static str> {
+    let header = &elf.header;
+    if header.pt1.class() != Class::SixtyFour {
+        return Err("not a 64-bit ELF file");
+    }
+    if header.pt1.data() != Data::LittleEndian {
+        return Err("not a little-endian ELF file");
+    }
+    match header.pt2.type_().as_type() {
+        FileType::Executable | FileType::SharedObject => {}
+        _ => return Err("not an executable or shared object"),
+    }
+    match header.pt2.machine().as_machine() {
+        Machine::AArch64 | Machine::X86_64 => {}
+        _ => return Err("unsupported machine"),
+    }
+    Ok(())
+}
+
+fn loads<'a>(elf: &'a ElfFile) -> impl Iterator<Item = ProgramHeader<'a>> + 'a {
+    elf.program_iter()
+        .filter(|ph| ph.get_type() == Ok(Type::Load))
+}
+
+/// Is `start..start + size` inside a loadable segment?
+fn is_loaded(elf: &ElfFile, start: u64, size: u64) -> bool {
+    loads(elf).any(|ph| {
+        start >= ph.virtual_addr()
+            && ph
+                .virtual_addr()
+                .checked_add(ph.mem_size())
+                .is_some_and(|end| start.saturating_add(size) <= end)
+    })
+}
+
+/// Convert an address range in the file image to a byte range of the file.
+/// The range must be in the file-backed part of one loadable segment.
+pub fn vaddr_to_file_range(
+    elf: &ElfFile,
+    vaddr: u64,
+    size: u64,
+) -> Result<Range<usize>, &'static str> {
+    let end = vaddr.checked_add(size).ok_or("address overflow")?;
+    let ph = loads(elf)
+        .find(|ph| {
+            vaddr >= ph.virtual_addr()
+                && ph
+                    .virtual_addr()
+                    .checked_add(ph.file_size())
+                    .is_some_and(|segment_end| end <= segment_end)
+        })
+        .ok_or("address not in the file image")?;
+    file_range(elf.input, ph.offset() + (vaddr - ph.virtual_addr()), size)
+}
+
+fn check_program_headers(elf: &ElfFile) -> Result<(), &'static str> {
+    let pt2 = &elf.header.pt2;
+    if pt2.ph_count() == 0 {
+        return Err("no program headers");
+    }
+    if pt2.ph_entry_size() as u64 != PROGRAM_HEADER_SIZE || pt2.ph_offset() % 8 != 0 {
+        return Err("bad program header table");
+    }
+    file_range(
+        elf.input,
+        pt2.ph_offset(),
+        pt2.ph_count() as u64 * PROGRAM_HEADER_SIZE,
+    )?;
+    // The loadable segments first, as the other checks look up addresses in them.
+    for ph in elf.program_iter() {
+        let ty = ph.get_type().map_err(|_| "bad segment type")?;
+        file_range(elf.input, ph.offset(), ph.file_size())?;
+        if ty != Type::Load {
+            continue;
+        }
+        if ph.file_size() > ph.mem_size() {
+            return Err("segment file size exceeds its memory size");
+        }
+        match ph.virtual_addr().checked_add(ph.mem_size()) {
+            Some(end) if end <= MAX_VADDR => {}
+            _ => return Err("segment address out of range"),
+        }
+        if ph.flags().is_write() && ph.flags().is_execute() {
+            return Err("writable and executable segment");
+        }
+    }
+    for ph in elf.program_iter() {
+        match ph.get_type().unwrap() {
+            Type::Dynamic => {
+                if ph.offset() % 8 != 0 || ph.file_size() % DYNAMIC_SIZE != 0 {
+                    return Err("bad dynamic segment");
+                }
+            }
+            Type::Tls => {
+                if ph.file_size() > ph.mem_size() || !ph.align().is_power_of_two() {
+                    return Err("bad TLS segment");
+                }
+                if !is_loaded(elf, ph.virtual_addr(), ph.file_size()) {
+                    return Err("TLS image is not loaded");
+                }
+            }
+            Type::OsSpecific(PT_GNU_STACK) if ph.flags().is_execute() => {
+                return Err("executable stack");
+            }
+            _ => {}
+        }
+    }
+    let start = loads(elf).map(|ph| ph.virtual_addr()).min();
+    let end = loads(elf).map(|ph| ph.virtual_addr() + ph.mem_size()).max();
+    match (start, end) {
+        (None, _) | (_, None) => return Err("no loadable segments"),
+        (Some(start), Some(end)) if end - start > MAX_IMAGE_SIZE => {
+            return Err("loadable segments are too large")
+        }
+        _ => {}
+    }
+    let entry = pt2.entry_point();
+    if entry != 0
+        && !loads(elf).any(|ph| {
+            ph.flags().is_execute()
+                && entry >= ph.virtual_addr()
+                && entry < ph.virtual_addr() + ph.mem_size()
+        })
+    {
+        return Err("entry point is not in a code segment");
+    }
+    Ok(())
+}
+
+fn check_sections(elf: &ElfFile) -> Result<(), &'static str> {
+    let pt2 = &elf.header.pt2;
+    if pt2.sh_count() == 0 {
+        return Ok(());
+    }
+    if pt2.sh_count() >= MAX_SECTIONS
+        || pt2.sh_entry_size() as u64 != SECTION_HEADER_SIZE
+        || pt2.sh_offset() % 8 != 0
+    {
+        return Err("bad section header table");
+    }
+    file_range(
+        elf.input,
+        pt2.sh_offset(),
+        pt2.sh_count() as u64 * SECTION_HEADER_SIZE,
+    )?;
+    let index = pt2.sh_str_index();
+    if index == 0 || index >= pt2.sh_count() {
+        return Err("bad section name table index");
+    }
+    let names = elf.section_header(index)?;
+    if names.get_type() != Ok(ShType::StrTab) {
+        return Err("bad section name table");
+    }
+    // `xmas_elf` reads section names from the name table offset to the end of the file.
+    let names = &elf.input[file_range(elf.input, names.offset(), names.size())?.start..];
+    for section in elf.section_iter() {
+        let ty = section.get_type().map_err(|_| "bad section type")?;
+        if ty == ShType::Null {
+            continue;
+        }
+        check_str(names, section.name() as u64)?;
+        if ty != ShType::NoBits {
+            file_range(elf.input, section.offset(), section.size())?;
+        }
+        let entry_size = match ty {
+            ShType::SymTab | ShType::DynSym => SYMBOL_SIZE,
+            ShType::InitArray | ShType::FiniArray | ShType::PreInitArray => 8,
+            _ => continue,
+        };
+        if section.offset() % 8 != 0 || section.size() % entry_size != 0 {
+            return Err("bad section size");
+        }
+        if matches!(ty, ShType::InitArray) && !is_loaded(elf, section.address(), section.size()) {
+            return Err("init array is not loaded");
+        }
+    }
+    if let Some(dynsym) = elf.find_section_by_name(".dynsym") {
+        check_dynamic_symbols(elf, dynsym)?;
+    }
+    Ok(())
+}
+
+fn dynamic_strings<'a>(elf: &ElfFile<'a>) -> Result<&'a [u8], &'static str> {
+    let dynstr = elf
+        .find_section_by_name(".dynstr")
+        .ok_or("no .dynstr section")?;
+    if dynstr.get_type() != Ok(ShType::StrTab) {
+        return Err("bad .dynstr section");
+    }
+    Ok(dynstr.raw_data(elf))
+}
+
+fn check_dynamic_symbols(elf: &ElfFile, dynsym: SectionHeader) -> Result<(), &'static str> {
+    if dynsym.get_type() != Ok(ShType::DynSym) {
+        return Err("bad .dynsym section");
+    }
+    let strings = dynamic_strings(elf)?;
+    let symbols = &elf.input[file_range(elf.input, dynsym.offset(), dynsym.size())?];
+    for symbol in symbols.chunks_exact(SYMBOL_SIZE as usize) {
+        let name = u32::from_le_bytes(symbol[0..4].try_into().unwrap());
+        check_str(strings, name as u64)?;
+    }
+    Ok(())
+}
+
+fn check_dynamic(elf: &ElfFile) -> Result<(), &'static str> {
+    let Some(ph) = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Dynamic))
+    else {
+        return Ok(());
+    };
+    let SegmentData::Dynamic64(entries) = ph.get_data(elf)? else {
+        return Err("bad dynamic segment");
+    };
+    for entry in entries {
+        if entry.get_tag() == Ok(Tag::Needed) {
+            check_str(dynamic_strings(elf)?, entry.get_val()?)?;
+        }
+    }
+    Ok(())
+}
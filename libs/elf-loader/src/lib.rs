@@ -5,40 +5,62 @@
 #[allow(unused_imports)]
 #[macro_use]
 extern crate log;
+extern crate alloc;
 
+mod check;
+mod object;
+
+pub use object::ELFObject;
+
+use alloc::vec::Vec;
 use core::ops::Range;
 
 use memory::address::{Address, V};
 use memory::page::{Page, PageSize, Size4K};
-use xmas_elf::sections::ShType;
+use xmas_elf::sections::{SectionData, ShType};
+use xmas_elf::symbol_table::{Binding, Entry};
 use xmas_elf::{
-    dynamic,
+    dynamic::{self, Dynamic},
     program::{ProgramHeader, SegmentData, Type},
     sections::Rela,
     ElfFile,
 };
 
+const R_AARCH64_ABS64: u32 = 257;
+const R_AARCH64_GLOB_DAT: u32 = 1025;
+const R_AARCH64_JUMP_SLOT: u32 = 1026;
+const R_AARCH64_RELATIVE: u32 = 1027;
+const R_AMD64_RELATIVE: u32 = 8;
+
 pub struct ELFLoader<'a, 'b, 'c> {
     data: &'a [u8],
     elf: ElfFile<'a>,
     vaddr_offset: isize,
-    map_pages: &'b mut dyn FnMut(Range<Page>) -> Range<Page>,
+    /// Maps the pages to load into, and returns them. An error stops the load.
+    map_pages: &'b mut dyn FnMut(Range<Page>) -> Result<Range<Page>, &'static str>,
     translate: Option<&'c dyn Fn(Address) -> Address>,
+    /// Looks up symbols for relocations against other objects.
+    /// Without it, only relative relocations are applied.
+    resolve: Option<&'c dyn Fn(&str) -> Option<Address>>,
+    /// Do not copy the read-only segments. They are already loaded, shared with another process.
+    skip_read_only: bool,
 }
 
 impl<'a, 'b, 'c> ELFLoader<'a, 'b, 'c> {
     fn new(
         data: &'a [u8],
-        map_pages: &'b mut dyn FnMut(Range<Page>) -> Range<Page>,
+        map_pages: &'b mut dyn FnMut(Range<Page>) -> Result<Range<Page>, &'static str>,
         translate: Option<&'c dyn Fn(Address) -> Address>,
-    ) -> Self {
-        ELFLoader {
+    ) -> Result<Self, &'static str> {
+        Ok(ELFLoader {
             data: data,
-            elf: ElfFile::new(data).unwrap(),
+            elf: check::parse(data)?,
             vaddr_offset: 0,
             map_pages,
             translate,
-        }
+            resolve: None,
+            skip_read_only: false,
+        })
     }
 
     fn addr(&self, a: Address) -> Address {
@@ -143,13 +165,20 @@ impl<'a, 'b, 'c> ELFLoader<'a, 'b, 'c> {
         let vaddr_end = load_end.unwrap().align_up(Size4K::BYTES);
         // log!("vaddr: {:?} .. {:?}", vaddr_start, vaddr_end);
         let pages =
-            (self.map_pages)(Page::<Size4K>::new(vaddr_start)..Page::<Size4K>::new(vaddr_end));
+            (self.map_pages)(Page::<Size4K>::new(vaddr_start)..Page::<Size4K>::new(vaddr_end))?;
         self.vaddr_offset =
             pages.start.start().as_usize() as isize - vaddr_start.as_usize() as isize;
         Ok(())
     }
 
+    fn is_skipped(&self, ph: &ProgramHeader) -> bool {
+        self.skip_read_only && !ph.flags().is_write()
+    }
+
     fn load_segment(&self, ph: ProgramHeader) -> Result<(), &'static str> {
+        if self.is_skipped(&ph) {
+            return Ok(());
+        }
         // Copy data
         let start: Address = Address::from(ph.virtual_addr() as usize) + self.vaddr_offset;
         let bytes = ph.file_size() as usize;
@@ -170,63 +199,121 @@ impl<'a, 'b, 'c> ELFLoader<'a, 'b, 'c> {
     }
 
     fn flush_segment(&self, ph: ProgramHeader) -> Result<(), &'static str> {
+        if self.is_skipped(&ph) {
+            return Ok(());
+        }
         let start: Address = Address::from(ph.virtual_addr() as usize) + self.vaddr_offset;
         let bytes = ph.file_size() as usize;
         self.flush(start, bytes);
         Ok(())
     }
 
-    fn apply_relocation(&self, ph: ProgramHeader) -> Result<(), &'static str> {
-        let data = match ph.get_data(&self.elf)? {
-            SegmentData::Dynamic64(data) => data,
-            _ => unreachable!(),
-        };
-        let rela = data.iter().find_map(|x| {
-            if x.get_tag().ok()? == dynamic::Tag::Rela {
-                Some(x.get_ptr().ok()? as usize)
+    /// Find a value in the dynamic section.
+    fn dynamic_value(data: &[Dynamic<u64>], tag: dynamic::Tag<u64>) -> Option<usize> {
+        data.iter().find_map(|x| {
+            if x.get_tag().ok()? == tag {
+                x.get_val()
+                    .or_else(|_| x.get_ptr())
+                    .ok()
+                    .map(|v| v as usize)
             } else {
                 None
             }
-        });
-        let rela_offset = match rela {
-            Some(x) => x,
-            _ => return Ok(()),
+        })
+    }
+
+    /// The address of the symbol with index `index` in the dynamic symbol table.
+    fn symbol_address(&self, index: u32) -> Result<Address, &'static str> {
+        let dynsym = match self
+            .elf
+            .find_section_by_name(".dynsym")
+            .ok_or("no .dynsym section")?
+            .get_data(&self.elf)?
+        {
+            SectionData::DynSymbolTable64(symbols) => symbols,
+            _ => return Err("bad .dynsym section"),
         };
-        let rela_size = data
-            .iter()
-            .find_map(|x| {
-                if x.get_tag().ok()? == dynamic::Tag::RelaSize {
-                    Some(x.get_val().ok()? as usize)
-                } else {
-                    None
-                }
-            })
-            .ok_or("relasize not found")?;
-        let rela_ent = data
-            .iter()
-            .find_map(|x| {
-                if x.get_tag().ok()? == dynamic::Tag::RelaEnt {
-                    Some(x.get_val().ok()? as usize)
-                } else {
-                    None
-                }
+        let symbol = dynsym.get(index as usize).ok_or("bad symbol index")?;
+        let name = symbol.get_name(&self.elf)?;
+        if let Some(address) = self.resolve.and_then(|resolve| resolve(name)) {
+            return Ok(address);
+        }
+        if symbol.shndx() != 0 {
+            return Ok(Address::from(symbol.value() as usize) + self.vaddr_offset);
+        }
+        if symbol.get_binding() == Ok(Binding::Weak) {
+            return Ok(Address::ZERO);
+        }
+        error!("undefined symbol: {}", name);
+        Err("undefined symbol")
+    }
+
+    /// Can a relocation write 8 bytes at `vaddr`? The slot must be in a loaded segment,
+    /// and in a writable one if the read-only segments are shared.
+    fn is_relocatable(&self, vaddr: u64) -> bool {
+        self.elf
+            .program_iter()
+            .filter(|ph| ph.get_type() == Ok(Type::Load))
+            .filter(|ph| !self.is_skipped(ph))
+            .any(|ph| {
+                vaddr >= ph.virtual_addr()
+                    && vaddr.saturating_add(8) <= ph.virtual_addr() + ph.mem_size()
             })
-            .ok_or("relaent not found")?;
-        let relas = unsafe {
-            core::slice::from_raw_parts(
-                &self.data[rela_offset] as *const u8 as *const Rela<u64>,
-                rela_size / rela_ent,
-            )
-        };
+    }
+
+    fn relocate(&self, relas: &[Rela<u64>]) -> Result<(), &'static str> {
         for rela in relas {
-            match rela.get_type() {
-                8 /* R_AMD64_RELATIVE */ | 1027 /* R_AARCH64_RELATIVE */ => {
-                    let slot = self.addr(Address::from(rela.get_offset() as usize)+ self.vaddr_offset);
-                    let value = Address::<V>::from(rela.get_addend() as usize) + self.vaddr_offset;
-                    unsafe { slot.store(value) }
+            let addend = rela.get_addend() as usize;
+            let value = match rela.get_type() {
+                R_AMD64_RELATIVE | R_AARCH64_RELATIVE => {
+                    Address::<V>::from(addend) + self.vaddr_offset
                 }
-                _ => {}
+                R_AARCH64_ABS64 | R_AARCH64_GLOB_DAT | R_AARCH64_JUMP_SLOT
+                    if self.resolve.is_some() =>
+                {
+                    self.symbol_address(rela.get_symbol_table_index())? + addend
+                }
+                _ => continue,
+            };
+            let offset = rela.get_offset();
+            if offset % 8 != 0 {
+                return Err("misaligned relocation");
+            }
+            if !self.is_relocatable(offset) {
+                return Err("relocation outside a writable segment");
+            }
+            let slot = self.addr(Address::from(offset as usize) + self.vaddr_offset);
+            unsafe { slot.store(value) }
+        }
+        Ok(())
+    }
+
+    fn apply_relocation(&self, ph: ProgramHeader) -> Result<(), &'static str> {
+        let data = match ph.get_data(&self.elf)? {
+            SegmentData::Dynamic64(data) => data,
+            _ => return Err("bad dynamic segment"),
+        };
+        // `.rela.dyn`, then `.rela.plt` for the PLT slots, which are bound eagerly.
+        let tables = [
+            (dynamic::Tag::Rela, dynamic::Tag::RelaSize),
+            (dynamic::Tag::JmpRel, dynamic::Tag::PltRelSize),
+        ];
+        for (table, size) in tables {
+            let Some(vaddr) = Self::dynamic_value(data, table) else {
+                continue;
+            };
+            let size = Self::dynamic_value(data, size).ok_or("relasize not found")?;
+            let range = check::vaddr_to_file_range(&self.elf, vaddr as u64, size as u64)?;
+            if range.start % 8 != 0 || size % core::mem::size_of::<Rela<u64>>() != 0 {
+                return Err("bad relocation table");
             }
+            let relas = unsafe {
+                core::slice::from_raw_parts(
+                    self.data[range].as_ptr() as *const Rela<u64>,
+                    size / core::mem::size_of::<Rela<u64>>(),
+                )
+            };
+            self.relocate(relas)?;
         }
         Ok(())
     }
@@ -268,26 +355,93 @@ impl<'a, 'b, 'c> ELFLoader<'a, 'b, 'c> {
                 None
             }
         });
-        Ok(ELFEntry { entry, init_array })
+        let segments = self
+            .elf
+            .program_iter()
+            .filter(|ph| ph.get_type() == Ok(Type::Load))
+            .map(|ph| {
+                let start = Address::from(ph.virtual_addr() as usize) + self.vaddr_offset;
+                let end = start + ph.mem_size() as usize;
+                Segment {
+                    pages: Page::containing(start)..Page::new(end.align_up(Size4K::BYTES)),
+                    writable: ph.flags().is_write(),
+                    executable: ph.flags().is_execute(),
+                }
+            })
+            .collect();
+        let tls = self
+            .elf
+            .program_iter()
+            .find(|ph| ph.get_type() == Ok(Type::Tls))
+            .map(|ph| TLSSegment {
+                image: Address::from(ph.virtual_addr() as usize) + self.vaddr_offset,
+                image_size: ph.file_size() as usize,
+                size: ph.mem_size() as usize,
+                align: ph.align() as usize,
+            });
+        Ok(ELFEntry {
+            entry,
+            init_array,
+            segments,
+            tls,
+        })
     }
 
     pub fn load(
         data: &'a [u8],
-        map_pages: &'b mut dyn FnMut(Range<Page>) -> Range<Page>,
+        map_pages: &'b mut dyn FnMut(Range<Page>) -> Result<Range<Page>, &'static str>,
     ) -> Result<ELFEntry<'a>, &'static str> {
-        ELFLoader::new(data, map_pages, None).do_load()
+        ELFLoader::new(data, map_pages, None)?.do_load()
     }
 
     pub fn load_with_address_translation(
         data: &'a [u8],
-        map_pages: &'b mut dyn FnMut(Range<Page>) -> Range<Page>,
+        map_pages: &'b mut dyn FnMut(Range<Page>) -> Result<Range<Page>, &'static str>,
         translate: &'c dyn Fn(Address) -> Address,
     ) -> Result<ELFEntry<'a>, &'static str> {
-        ELFLoader::new(data, map_pages, Some(translate)).do_load()
+        ELFLoader::new(data, map_pages, Some(translate))?.do_load()
+    }
+
+    /// Load an object that is linked against shared libraries.
+    /// Symbols are looked up with `resolve` first, then in the object itself.
+    /// With `skip_read_only`, the read-only segments must already be mapped and loaded.
+    pub fn load_dynamic(
+        data: &'a [u8],
+        map_pages: &'b mut dyn FnMut(Range<Page>) -> Result<Range<Page>, &'static str>,
+        resolve: &'c dyn Fn(&str) -> Option<Address>,
+        skip_read_only: bool,
+    ) -> Result<ELFEntry<'a>, &'static str> {
+        let mut loader = ELFLoader::new(data, map_pages, None)?;
+        loader.resolve = Some(resolve);
+        loader.skip_read_only = skip_read_only;
+        loader.do_load()
     }
 }
 
 pub struct ELFEntry<'a> {
     pub entry: Address,
     pub init_array: Option<&'a [Address]>,
+    /// The loaded segments. Their pages are mapped by `map_pages` with full access,
+    /// it is up to the caller to apply the segment permissions.
+    pub segments: Vec<Segment>,
+    /// The thread-local storage template (`PT_TLS`), if any.
+    pub tls: Option<TLSSegment>,
+}
+
+/// A loaded segment and its permissions. Segments are never both writable and executable.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub pages: Range<Page>,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+/// The initialization image of thread-local storage, as loaded.
+/// Each thread gets a copy of `size` bytes, with the bytes after the image zeroed.
+#[derive(Debug, Clone, Copy)]
+pub struct TLSSegment {
+    pub image: Address,
+    pub image_size: usize,
+    pub size: usize,
+    pub align: usize,
 }
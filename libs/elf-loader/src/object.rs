@@ -0,0 +1,134 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use xmas_elf::dynamic::{Dynamic, Tag};
+use xmas_elf::program::{SegmentData, Type};
+use xmas_elf::sections::{SectionData, ShType};
+use xmas_elf::symbol_table::{Binding, Entry, Entry64, Type as SymbolType};
+use xmas_elf::ElfFile;
+
+/// Dynamic linking information of an ELF file, read without loading it.
+pub struct ELFObject<'a> {
+    elf: ElfFile<'a>,
+}
+
+impl<'a> ELFObject<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        Ok(Self {
+            elf: crate::check::parse(data)?,
+        })
+    }
+
+    fn dynamic(&self) -> Option<&'a [Dynamic<u64>]> {
+        let ph = self
+            .elf
+            .program_iter()
+            .find(|ph| ph.get_type() == Ok(Type::Dynamic))?;
+        match ph.get_data(&self.elf) {
+            Ok(SegmentData::Dynamic64(data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// The names of the shared libraries this object is linked against.
+    pub fn needed(&self) -> Result<Vec<&'a str>, &'static str> {
+        let Some(dynamic) = self.dynamic() else {
+            return Ok(Vec::new());
+        };
+        dynamic
+            .iter()
+            .filter(|d| d.get_tag() == Ok(Tag::Needed))
+            .map(|d| self.elf.get_dyn_string(d.get_val()? as u32))
+            .collect()
+    }
+
+    /// The entry point, before relocation.
+    pub fn entry_point(&self) -> usize {
+        self.elf.header.pt2.entry_point() as usize
+    }
+
+    /// Can the object be loaded at any address?
+    pub fn is_position_independent(&self) -> bool {
+        self.elf.header.pt2.type_().as_type() == xmas_elf::header::Type::SharedObject
+    }
+
+    /// Does the object have relocations in read-only segments?
+    pub fn has_text_relocations(&self) -> bool {
+        const DF_TEXTREL: u64 = 0x4;
+        self.dynamic().is_some_and(|dynamic| {
+            dynamic.iter().any(|d| match d.get_tag() {
+                Ok(Tag::TextRel) => true,
+                Ok(Tag::Flags) => d.get_val().is_ok_and(|f| f & DF_TEXTREL != 0),
+                _ => false,
+            })
+        })
+    }
+
+    /// The address of a global symbol defined by this object, before relocation.
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        let section = self.elf.find_section_by_name(".dynsym")?;
+        let Ok(SectionData::DynSymbolTable64(symbols)) = section.get_data(&self.elf) else {
+            return None;
+        };
+        symbols
+            .iter()
+            .filter(|s| s.shndx() != 0)
+            .filter(|s| matches!(s.get_binding(), Ok(Binding::Global | Binding::Weak)))
+            .find(|s| s.get_name(&self.elf) == Ok(name))
+            .map(|s| s.value() as usize)
+    }
+
+    /// The address ranges of the loadable segments before relocation,
+    /// and whether they are writable.
+    pub fn segments(&self) -> impl Iterator<Item = (Range<usize>, bool)> + '_ {
+        self.elf
+            .program_iter()
+            .filter(|ph| ph.get_type() == Ok(Type::Load))
+            .map(|ph| {
+                let start = ph.virtual_addr() as usize;
+                (start..start + ph.mem_size() as usize, ph.flags().is_write())
+            })
+    }
+
+    /// `.symtab` and its string table. Stripped objects have none.
+    fn symbol_table(&self) -> Option<(&'a [Entry64], &'a [u8])> {
+        let section = self.elf.find_section_by_name(".symtab")?;
+        let Ok(SectionData::SymbolTable64(symbols)) = section.get_data(&self.elf) else {
+            return None;
+        };
+        // `xmas_elf` panics on bad section indices and string offsets.
+        if section.link() >= self.elf.header.pt2.sh_count() as u32 {
+            return None;
+        }
+        let strings = self.elf.section_header(section.link() as u16).ok()?;
+        if strings.get_type() != Ok(ShType::StrTab) {
+            return None;
+        }
+        Some((symbols, strings.raw_data(&self.elf)))
+    }
+
+    /// The functions in `.symtab`, with their address ranges before relocation.
+    /// Symbols with bad names are skipped.
+    pub fn functions(&self) -> impl Iterator<Item = (Range<usize>, &'a str)> + 'a {
+        let (symbols, strings) = self.symbol_table().unwrap_or((&[], &[]));
+        symbols
+            .iter()
+            .filter(|s| s.shndx() != 0 && s.size() != 0)
+            .filter(|s| s.get_type() == Ok(SymbolType::Func))
+            .filter_map(move |s| {
+                let name = strings.get(s.name() as usize..)?;
+                let name = &name[..name.iter().position(|b| *b == 0)?];
+                let start = s.value() as usize;
+                let end = start.checked_add(s.size() as usize)?;
+                Some((start..end, core::str::from_utf8(name).ok()?))
+            })
+    }
+
+    /// The function containing an address before relocation, and the offset into it.
+    /// Does not allocate, so that it can be used to report panics.
+    pub fn find_function(&self, address: usize) -> Option<(&'a str, usize)> {
+        self.functions()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(range, name)| (name, address - range.start))
+    }
+}
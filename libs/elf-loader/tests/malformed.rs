@@ -0,0 +1,518 @@
+//! Load hand-made ELF files, valid and malformed, into a buffer on the host.
+//! A malformed file must be rejected with an error, never with a panic
+//! or a write outside of the pages the loader asked for.
+
+#![feature(step_trait)]
+
+use std::cell::RefCell;
+use std::iter::Step;
+use std::ops::Range;
+
+use elf_loader::{ELFEntry, ELFLoader, ELFObject};
+use memory::address::Address;
+use memory::page::{Page, PageSize, Size4K};
+
+/// Where the harness pretends to map the pages.
+const BASE: usize = 0x40_0000_0000;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_TLS: u32 = 7;
+const PT_GNU_STACK: u32 = 0x6474e551;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const R_AARCH64_RELATIVE: u64 = 1027;
+
+const CODE: u64 = 0x1000;
+const DATA: u64 = 0x2000;
+const RELA: u64 = 0x200;
+/// The slot written by the relocation.
+const SLOT: u64 = DATA + 0x40;
+const FILE_SIZE: usize = 0x2480;
+
+#[derive(Clone, Copy)]
+struct Phdr {
+    ty: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    file_size: u64,
+    mem_size: u64,
+    align: u64,
+}
+
+/// A position-independent AArch64 executable with a read-only segment holding a relocation
+/// table, a code segment, and a data segment holding the dynamic section and a `.bss`.
+#[derive(Clone)]
+struct Builder {
+    phdrs: Vec<Phdr>,
+    entry: u64,
+    rela: (u64, u64, u64),
+    with_sections: bool,
+}
+
+impl Builder {
+    fn new() -> Self {
+        let load = |flags, offset, file_size, mem_size| Phdr {
+            ty: PT_LOAD,
+            flags,
+            offset,
+            vaddr: offset,
+            file_size,
+            mem_size,
+            align: 0x1000,
+        };
+        Self {
+            phdrs: vec![
+                load(PF_R, 0, 0x300, 0x300),
+                load(PF_R | PF_X, CODE, 0x10, 0x10),
+                load(PF_R | PF_W, DATA, 0x100, 0x800),
+                Phdr {
+                    ty: PT_DYNAMIC,
+                    flags: PF_R | PF_W,
+                    offset: DATA,
+                    vaddr: DATA,
+                    file_size: 0x30,
+                    mem_size: 0x30,
+                    align: 8,
+                },
+                Phdr {
+                    ty: PT_GNU_STACK,
+                    flags: PF_R | PF_W,
+                    offset: 0,
+                    vaddr: 0,
+                    file_size: 0,
+                    mem_size: 0,
+                    align: 0x10,
+                },
+            ],
+            entry: CODE,
+            rela: (SLOT, R_AARCH64_RELATIVE, CODE),
+            with_sections: false,
+        }
+    }
+
+    fn phdr(&mut self, ty: u32) -> &mut Phdr {
+        self.phdrs.iter_mut().find(|p| p.ty == ty).unwrap()
+    }
+
+    fn load(&mut self, index: usize) -> &mut Phdr {
+        self.phdrs
+            .iter_mut()
+            .filter(|p| p.ty == PT_LOAD)
+            .nth(index)
+            .unwrap()
+    }
+
+    fn build(&self) -> Vec<u8> {
+        let mut f = vec![0u8; FILE_SIZE];
+        let put = |f: &mut Vec<u8>, at: usize, bytes: &[u8]| {
+            f[at..at + bytes.len()].copy_from_slice(bytes)
+        };
+        // ELF header
+        put(&mut f, 0, &[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+        put(&mut f, 16, &3u16.to_le_bytes()); // ET_DYN
+        put(&mut f, 18, &183u16.to_le_bytes()); // EM_AARCH64
+        put(&mut f, 20, &1u32.to_le_bytes());
+        put(&mut f, 24, &self.entry.to_le_bytes());
+        put(&mut f, 32, &64u64.to_le_bytes());
+        put(&mut f, 52, &64u16.to_le_bytes());
+        put(&mut f, 54, &56u16.to_le_bytes());
+        put(&mut f, 56, &(self.phdrs.len() as u16).to_le_bytes());
+        put(&mut f, 58, &64u16.to_le_bytes());
+        for (i, p) in self.phdrs.iter().enumerate() {
+            let at = 64 + i * 56;
+            put(&mut f, at, &p.ty.to_le_bytes());
+            put(&mut f, at + 4, &p.flags.to_le_bytes());
+            put(&mut f, at + 8, &p.offset.to_le_bytes());
+            put(&mut f, at + 16, &p.vaddr.to_le_bytes());
+            put(&mut f, at + 24, &p.vaddr.to_le_bytes());
+            put(&mut f, at + 32, &p.file_size.to_le_bytes());
+            put(&mut f, at + 40, &p.mem_size.to_le_bytes());
+            put(&mut f, at + 48, &p.align.to_le_bytes());
+        }
+        // Relocation table
+        let (offset, ty, addend) = self.rela;
+        put(&mut f, RELA as usize, &offset.to_le_bytes());
+        put(&mut f, RELA as usize + 8, &ty.to_le_bytes());
+        put(&mut f, RELA as usize + 16, &addend.to_le_bytes());
+        // Code: `ret`
+        put(&mut f, CODE as usize, &0xd65f03c0u32.to_le_bytes());
+        // Dynamic section
+        let dynamic = [DT_RELA, RELA, DT_RELASZ, 24, DT_NULL, 0];
+        for (i, v) in dynamic.iter().enumerate() {
+            put(&mut f, DATA as usize + i * 8, &v.to_le_bytes());
+        }
+        // `.bss` must be zeroed, whatever the file holds past the data.
+        put(&mut f, DATA as usize + 0x100, &[0xaa; 0x100]);
+        if self.with_sections {
+            // A null section, the section name table, and a symbol table
+            // with a function `entry` at the code.
+            let names = 0x2300usize;
+            put(&mut f, names, b"\0.shstrtab\0.symtab\0.strtab\0");
+            let strings = 0x2320usize;
+            put(&mut f, strings, b"\0entry\0");
+            let table = 0x2340usize;
+            put(&mut f, 40, &(table as u64).to_le_bytes());
+            put(&mut f, 60, &4u16.to_le_bytes());
+            put(&mut f, 62, &1u16.to_le_bytes());
+            let symbols = 0x2440usize;
+            let sections = [
+                (1u32, 3u32, names, 27u64, 0u32, 0u64), // SHT_STRTAB
+                (11, 2, symbols, 48, 3, 24),            // SHT_SYMTAB
+                (19, 3, strings, 7, 0, 0),              // SHT_STRTAB
+            ];
+            for (i, (name, ty, offset, size, link, entry_size)) in sections.iter().enumerate() {
+                let at = table + (i + 1) * 64;
+                put(&mut f, at, &name.to_le_bytes());
+                put(&mut f, at + 4, &ty.to_le_bytes());
+                put(&mut f, at + 24, &(*offset as u64).to_le_bytes());
+                put(&mut f, at + 32, &size.to_le_bytes());
+                put(&mut f, at + 40, &link.to_le_bytes());
+                put(&mut f, at + 56, &entry_size.to_le_bytes());
+            }
+            // The second symbol: STB_GLOBAL, STT_FUNC
+            let at = symbols + 24;
+            put(&mut f, at, &1u32.to_le_bytes());
+            put(&mut f, at + 4, &[0x12, 0]);
+            put(&mut f, at + 6, &1u16.to_le_bytes());
+            put(&mut f, at + 8, &CODE.to_le_bytes());
+            put(&mut f, at + 16, &4u64.to_le_bytes());
+        }
+        f
+    }
+}
+
+/// Load a file into a buffer. Panics if the loader touches memory outside of the pages it mapped.
+fn load(elf: &[u8]) -> (Result<ELFEntry<'_>, &'static str>, Vec<u8>) {
+    let memory = RefCell::new(Vec::new());
+    let mut map_pages = |pages: Range<Page>| {
+        let n = Page::steps_between(&pages.start, &pages.end).unwrap();
+        *memory.borrow_mut() = vec![0u8; n * Size4K::BYTES];
+        let start = Page::new(Address::from(BASE));
+        Ok(start..Page::forward(start, n))
+    };
+    let translate = |a: Address| {
+        let mut memory = memory.borrow_mut();
+        let offset = a.as_usize().wrapping_sub(BASE);
+        assert!(offset < memory.len(), "access outside the mapped pages");
+        Address::from(memory.as_mut_ptr() as usize + offset)
+    };
+    let result = ELFLoader::load_with_address_translation(elf, &mut map_pages, &translate);
+    (result, memory.into_inner())
+}
+
+fn read_u64(memory: &[u8], vaddr: u64) -> u64 {
+    u64::from_le_bytes(
+        memory[vaddr as usize..vaddr as usize + 8]
+            .try_into()
+            .unwrap(),
+    )
+}
+
+fn assert_rejected(builder: &Builder, error: &str) {
+    let elf = builder.build();
+    match load(&elf).0 {
+        Ok(_) => panic!("expected error: {}", error),
+        Err(e) => assert_eq!(e, error),
+    }
+}
+
+#[test]
+fn load_valid_file() {
+    let elf = Builder::new().build();
+    let (entry, memory) = load(&elf);
+    let entry = entry.unwrap();
+    assert_eq!(entry.entry.as_usize(), BASE + CODE as usize);
+    assert_eq!(read_u64(&memory, SLOT), (BASE as u64) + CODE);
+    assert_eq!(
+        &memory[CODE as usize..CODE as usize + 4],
+        &elf[CODE as usize..CODE as usize + 4]
+    );
+    assert!(memory[DATA as usize + 0x100..DATA as usize + 0x800]
+        .iter()
+        .all(|b| *b == 0));
+    let permissions: Vec<_> = entry
+        .segments
+        .iter()
+        .map(|s| (s.writable, s.executable))
+        .collect();
+    assert_eq!(permissions, [(false, false), (false, true), (true, false)]);
+    assert_eq!(
+        entry.segments[1].pages.start.start().as_usize(),
+        BASE + CODE as usize
+    );
+    assert!(entry.tls.is_none());
+}
+
+#[test]
+fn load_valid_file_with_sections() {
+    let mut builder = Builder::new();
+    builder.with_sections = true;
+    assert!(load(&builder.build()).0.is_ok());
+}
+
+#[test]
+fn report_tls_segment() {
+    let mut builder = Builder::new();
+    builder.phdrs.push(Phdr {
+        ty: PT_TLS,
+        flags: PF_R,
+        offset: DATA + 0x80,
+        vaddr: DATA + 0x80,
+        file_size: 0x10,
+        mem_size: 0x40,
+        align: 16,
+    });
+    let elf = builder.build();
+    let tls = load(&elf).0.unwrap().tls.unwrap();
+    assert_eq!(tls.image.as_usize(), BASE + DATA as usize + 0x80);
+    assert_eq!((tls.image_size, tls.size, tls.align), (0x10, 0x40, 16));
+}
+
+#[test]
+fn reject_truncated_file() {
+    let elf = Builder::new().build();
+    for len in (0..DATA as usize + 0x100).step_by(7) {
+        assert!(load(&elf[..len]).0.is_err(), "accepted {} bytes", len);
+    }
+}
+
+#[test]
+fn reject_bad_header() {
+    let elf = Builder::new().build();
+    for (offset, value, error) in [
+        (0, 0x7e, "Did not find ELF magic number"),
+        (4, 1, "not a 64-bit ELF file"),
+        (5, 2, "not a little-endian ELF file"),
+        (16, 1, "not an executable or shared object"),
+        (18, 40, "unsupported machine"),
+    ] {
+        let mut elf = elf.clone();
+        elf[offset] = value;
+        assert_eq!(load(&elf).0.err(), Some(error));
+    }
+}
+
+#[test]
+fn reject_bad_program_header_table() {
+    let mut elf = Builder::new().build();
+    elf[32] = 65;
+    assert_eq!(load(&elf).0.err(), Some("bad program header table"));
+    let mut elf = Builder::new().build();
+    elf[56..58].copy_from_slice(&0xffffu16.to_le_bytes());
+    assert_eq!(load(&elf).0.err(), Some("offset out of file bounds"));
+}
+
+#[test]
+fn reject_writable_and_executable_segment() {
+    let mut builder = Builder::new();
+    builder.load(1).flags = PF_R | PF_W | PF_X;
+    assert_rejected(&builder, "writable and executable segment");
+}
+
+#[test]
+fn reject_executable_stack() {
+    let mut builder = Builder::new();
+    builder.phdr(PT_GNU_STACK).flags = PF_R | PF_W | PF_X;
+    assert_rejected(&builder, "executable stack");
+}
+
+#[test]
+fn reject_segment_outside_file() {
+    let mut builder = Builder::new();
+    builder.load(2).offset = FILE_SIZE as u64;
+    assert_rejected(&builder, "offset out of file bounds");
+    let mut builder = Builder::new();
+    builder.load(2).file_size = u64::MAX;
+    assert_rejected(&builder, "offset overflow");
+}
+
+#[test]
+fn reject_file_size_larger_than_memory_size() {
+    let mut builder = Builder::new();
+    builder.load(2).mem_size = 0x10;
+    assert_rejected(&builder, "segment file size exceeds its memory size");
+}
+
+#[test]
+fn reject_huge_segment() {
+    let mut builder = Builder::new();
+    builder.load(2).vaddr = u64::MAX - 0x100;
+    assert_rejected(&builder, "segment address out of range");
+    let mut builder = Builder::new();
+    builder.load(2).mem_size = 1 << 40;
+    assert_rejected(&builder, "loadable segments are too large");
+}
+
+#[test]
+fn reject_entry_outside_code() {
+    let mut builder = Builder::new();
+    builder.entry = DATA;
+    assert_rejected(&builder, "entry point is not in a code segment");
+}
+
+#[test]
+fn reject_bad_tls_segment() {
+    let mut builder = Builder::new();
+    builder.phdrs.push(Phdr {
+        ty: PT_TLS,
+        flags: PF_R,
+        offset: DATA,
+        vaddr: 0x10000,
+        file_size: 0x10,
+        mem_size: 0x40,
+        align: 16,
+    });
+    assert_rejected(&builder, "TLS image is not loaded");
+}
+
+#[test]
+fn reject_tls_segment_before_huge_segment() {
+    // The TLS segment is checked against a loadable segment whose end overflows.
+    let mut builder = Builder::new();
+    let vaddr = 0xffff_ffff_ffff_f000;
+    builder.phdrs.insert(
+        0,
+        Phdr {
+            ty: PT_TLS,
+            flags: PF_R,
+            offset: 0,
+            vaddr,
+            file_size: 0,
+            mem_size: 0x10,
+            align: 16,
+        },
+    );
+    builder.phdrs.insert(
+        1,
+        Phdr {
+            ty: PT_LOAD,
+            flags: PF_R,
+            offset: 0,
+            vaddr,
+            file_size: 0,
+            mem_size: 0x2000,
+            align: 0x1000,
+        },
+    );
+    assert_rejected(&builder, "segment address out of range");
+    assert!(ELFObject::parse(&builder.build()).is_err());
+}
+
+#[test]
+fn reject_relocation_outside_writable_segment() {
+    for offset in [0x9000, CODE, u64::MAX - 7] {
+        let mut builder = Builder::new();
+        builder.rela.0 = offset;
+        // Code is relocatable, but only read-only data is copied when it is shared.
+        if offset == CODE {
+            builder.load(1).flags = PF_R;
+            builder.entry = 0;
+            assert!(load(&builder.build()).0.is_ok());
+            continue;
+        }
+        assert_rejected(&builder, "relocation outside a writable segment");
+    }
+    let mut builder = Builder::new();
+    builder.rela.0 = SLOT + 4;
+    assert_rejected(&builder, "misaligned relocation");
+}
+
+#[test]
+fn reject_relocation_table_outside_file() {
+    let mut builder = Builder::new();
+    builder.load(0).file_size = 0x210;
+    builder.load(0).mem_size = 0x210;
+    assert_rejected(&builder, "address not in the file image");
+}
+
+#[test]
+fn reject_bad_section_names() {
+    let mut builder = Builder::new();
+    builder.with_sections = true;
+    let elf = builder.build();
+    let mut bad_index = elf.clone();
+    bad_index[62] = 4;
+    assert_eq!(
+        load(&bad_index).0.err(),
+        Some("bad section name table index")
+    );
+    let mut bad_name = elf.clone();
+    bad_name[0x2340 + 64..0x2340 + 68].copy_from_slice(&0x1000u32.to_le_bytes());
+    assert_eq!(load(&bad_name).0.err(), Some("string out of bounds"));
+    let mut not_utf8 = elf.clone();
+    not_utf8[0x2301] = 0xff;
+    assert_eq!(load(&not_utf8).0.err(), Some("non-UTF-8 string"));
+}
+
+#[test]
+fn find_functions() {
+    let mut builder = Builder::new();
+    builder.with_sections = true;
+    let elf = builder.build();
+    let object = ELFObject::parse(&elf).unwrap();
+    assert_eq!(object.find_function(CODE as usize + 2), Some(("entry", 2)));
+    assert_eq!(object.find_function(CODE as usize + 4), None);
+    // Symbols with bad names are skipped.
+    let mut bad_name = elf.clone();
+    bad_name[0x2440 + 24..0x2440 + 28].copy_from_slice(&0x1000u32.to_le_bytes());
+    let object = ELFObject::parse(&bad_name).unwrap();
+    assert_eq!(object.find_function(CODE as usize), None);
+    // Files without sections have no symbols.
+    let elf = Builder::new().build();
+    let object = ELFObject::parse(&elf).unwrap();
+    assert_eq!(object.find_function(CODE as usize), None);
+}
+
+/// A xorshift generator, so that failures are reproducible.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[test]
+fn fuzz_corrupted_headers() {
+    let mut random = Random(0x5eed);
+    for builder in [Builder::new(), {
+        let mut b = Builder::new();
+        b.with_sections = true;
+        b
+    }] {
+        let elf = builder.build();
+        // The headers, the relocation table and the dynamic section.
+        let targets = [0..64 + 56 * 5, 0x200..0x218, 0x2000..0x2030, 0x2300..0x2480];
+        for _ in 0..4000 {
+            let mut elf = elf.clone();
+            for _ in 0..1 + random.below(4) {
+                let range = targets[random.below(targets.len())].clone();
+                let at = range.start + random.below(range.len());
+                elf[at] = random.next() as u8;
+            }
+            let len = if random.below(8) == 0 {
+                random.below(elf.len())
+            } else {
+                elf.len()
+            };
+            let _ = load(&elf[..len]);
+            if let Ok(object) = ELFObject::parse(&elf[..len]) {
+                let _ = object.find_function(CODE as usize);
+            }
+        }
+    }
+}
@@ -167,10 +167,16 @@ pub trait InterruptController {
     fn get_irq_handler(&self, irq: usize) -> Option<&IRQHandler>;
     /// Register an IRQ handler.
     fn set_irq_handler(&self, irq: usize, handler: IRQHandler);
+    /// Send an inter-processor interrupt to the given core.
+    fn send_ipi(&self, core: usize, irq: usize);
 }
 
 /// Timer controller. For initializing and handling timer interrupts.
 pub trait TimerController {
     /// Initialize the per-core timer controller.
     fn init(&self, bsp: bool);
+    /// Stop the periodic timer interrupts on the current core.
+    fn stop(&self);
+    /// Restart the periodic timer interrupts on the current core.
+    fn restart(&self);
 }
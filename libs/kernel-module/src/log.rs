@@ -2,15 +2,6 @@ use core::fmt::{self, Write};
 
 use crate::SERVICE;
 
-struct Output;
-
-impl fmt::Write for Output {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        super::SERVICE.log(s);
-        Ok(())
-    }
-}
-
 struct KernelLogger;
 
 impl log::Log for KernelLogger {
@@ -19,8 +10,7 @@ impl log::Log for KernelLogger {
     }
 
     fn log(&self, record: &log::Record) {
-        let mut stdout = Output;
-        writeln!(stdout, "[{}] {}", record.level(), record.args()).unwrap();
+        SERVICE.log_record(record);
     }
 
     fn flush(&self) {}
@@ -28,13 +18,10 @@ impl log::Log for KernelLogger {
 
 static LOGGER: KernelLogger = KernelLogger;
 
+/// Levels are filtered by the kernel, per module.
 pub fn init() {
     log::set_logger(&LOGGER).unwrap();
-    if cfg!(debug_assertions) {
-        log::set_max_level(log::LevelFilter::Trace);
-    } else {
-        log::set_max_level(log::LevelFilter::Info);
-    }
+    log::set_max_level(log::LevelFilter::Trace);
 }
 
 #[doc(hidden)]
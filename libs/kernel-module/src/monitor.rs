@@ -1,6 +1,69 @@
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+/// A sleeping lock plus a condition to wait on, provided by the kernel.
 pub trait SysMonitor {
     fn lock(&self);
     fn unlock(&self);
+    /// Release the lock, wait for a `notify_all` since the lock was acquired, and lock again.
     fn wait(&self);
+    /// Wake up the waiters. Does not need the lock, and is safe in interrupt handlers.
     fn notify_all(&self);
 }
+
+/// A sleeping lock provided by the kernel.
+/// Waiting tasks are blocked instead of spinning, and the holder is allowed to block.
+pub trait SysMutex {
+    fn lock(&self);
+    fn unlock(&self);
+}
+
+/// A mutex backed by a kernel sleeping lock. Use this for long critical sections.
+pub struct KMutex<T: ?Sized> {
+    raw: Box<dyn SysMutex>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for KMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for KMutex<T> {}
+
+impl<T> KMutex<T> {
+    /// Create a mutex. Mutexes with the same `name` are in the same class for lock-order checking.
+    pub fn new(name: &'static str, value: T) -> Self {
+        Self {
+            raw: crate::SERVICE.create_mutex(name),
+            data: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> KMutex<T> {
+    pub fn lock(&self) -> KMutexGuard<'_, T> {
+        self.raw.lock();
+        KMutexGuard { mutex: self }
+    }
+}
+
+pub struct KMutexGuard<'a, T: ?Sized> {
+    mutex: &'a KMutex<T>,
+}
+
+impl<T: ?Sized> Deref for KMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for KMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for KMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.raw.unlock();
+    }
+}
@@ -7,15 +7,18 @@ use interrupt::{InterruptController, TimerController};
 use klib::proc::{Process, PID};
 use memory::address::Address;
 use memory::page::{Frame, Page};
-use syscall::RawModuleRequest;
+use syscall::{RawModuleRequest, Signal};
 use testing::Tests;
 
 pub trait KernelService: Send + Sync + 'static {
     // === Logging === //
     fn log(&self, s: &str);
+    /// Add a record to the kernel log, if the level of its module allows it.
+    fn log_record(&self, record: &log::Record);
     fn set_sys_logger(&self, write: *mut dyn core::fmt::Write);
 
     fn create_monitor(&self) -> Box<dyn super::monitor::SysMonitor>;
+    fn create_mutex(&self, name: &'static str) -> Box<dyn super::monitor::SysMutex>;
 
     // === Testing === //
     fn register_tests(&self, tests: Tests);
@@ -62,6 +65,9 @@ pub trait KernelService: Send + Sync + 'static {
     fn timer_tick(&self) -> !;
     fn current_pid(&self) -> PID;
     fn current_proc(&self) -> Option<Arc<Process>>;
+    /// Send a signal to all the processes in a group.
+    /// It is delivered shortly after, by a kernel task, so this is safe in interrupt handlers.
+    fn signal_process_group(&self, pgid: PID, signal: Signal);
 }
 
 #[repr(C)]
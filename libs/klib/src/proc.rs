@@ -1,9 +1,10 @@
 use core::{
     any::Any,
-    sync::atomic::{AtomicBool, AtomicIsize, AtomicPtr, Ordering},
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicIsize, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
 };
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, string::String, vec::Vec};
 use atomic::Atomic;
 use memory::{
     address::{Address, V},
@@ -18,22 +19,59 @@ pub struct PID(pub usize);
 
 impl PID {
     pub const NULL: Self = Self(0);
+    /// The first user process. It adopts orphaned processes.
+    pub const INIT: Self = Self(1);
 }
 
 pub struct Process {
     pub id: PID,
+    /// The parent process. `PID::NULL` for processes created by the kernel.
+    /// Orphans are adopted by init.
+    pub parent: AtomicUsize,
+    /// Path of the running program.
+    pub name: Mutex<String>,
     pub threads: Mutex<Vec<TaskId>>,
     pub mem: Box<MemSpace>,
     pub fs: Box<dyn Any>,
-    pub monitor: Box<dyn Any>,
+    /// Tasks waiting for this process to exit.
+    pub exit_waiters: Box<dyn Any>,
+    /// Tasks waiting for any child of this process to exit, or for a signal.
+    pub child_waiters: Box<dyn Any>,
     pub is_zombie: AtomicBool,
     pub exit_code: AtomicIsize,
+    /// Process group id.
+    pub pgid: AtomicUsize,
+    /// Bit set of pending signals, indexed by signal number.
+    pub pending_signals: AtomicU32,
+    /// Is the process stopped by a signal?
+    pub stopped: AtomicBool,
 }
 
 pub struct MemSpace {
     pub page_table: AtomicPtr<PageTable<L4>>,
     pub has_user_page_table: AtomicBool,
     pub highwater: Atomic<Address<V>>,
+    /// The stack of the first thread. Thread `i` has its stack above the stack of thread `i - 1`.
+    pub stack_base: Atomic<Address<V>>,
+    /// Thread-local storage of the program. Each new thread gets a copy.
+    pub tls: Mutex<Option<TLSTemplate>>,
+    /// The addresses of the program, and its load address minus its link address.
+    /// Used to print backtraces.
+    pub program: Mutex<(Range<Address<V>>, isize)>,
+    /// Number of 4K user pages mapped, including the pages shared with other processes.
+    pub pages: AtomicUsize,
+    /// The heap can not grow beyond this many pages in total. Kept across `fork` and `exec`.
+    pub page_limit: AtomicUsize,
+}
+
+/// The thread-local storage image of a program, in its address space.
+#[derive(Debug, Clone, Copy)]
+pub struct TLSTemplate {
+    pub image: Address<V>,
+    pub image_size: usize,
+    /// Size of a copy. The bytes after the image are zeroed.
+    pub size: usize,
+    pub align: usize,
 }
 
 impl MemSpace {
@@ -1,4 +1,7 @@
-use core::{any::Any, sync::atomic::AtomicUsize};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicI8, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+};
 
 use alloc::boxed::Box;
 use atomic::Atomic;
@@ -37,12 +40,42 @@ unsafe impl bytemuck::ZeroableInOption for RunState {}
 
 unsafe impl bytemuck::PodInOption for RunState {}
 
+/// Per-task scheduling parameters and statistics.
+#[derive(Default)]
+pub struct SchedInfo {
+    /// Nice value, from `-20` (highest priority) to `19` (lowest priority).
+    pub nice: AtomicI8,
+    /// Fixed real-time priority, from `1` to `99`. `0` means a normal (non-real-time) task.
+    pub rt_priority: AtomicU8,
+    /// Weighted virtual run time in nanoseconds. Used by fair scheduling policies.
+    pub vruntime: AtomicU64,
+    /// Total CPU time in nanoseconds.
+    pub cpu_time: AtomicU64,
+}
+
+impl SchedInfo {
+    pub const MIN_NICE: i8 = -20;
+    pub const MAX_NICE: i8 = 19;
+    pub const MAX_RT_PRIORITY: u8 = 99;
+
+    /// Inherit the scheduling parameters from the parent task.
+    pub fn fork(&self) -> Self {
+        Self {
+            nice: AtomicI8::new(self.nice.load(Ordering::SeqCst)),
+            rt_priority: AtomicU8::new(self.rt_priority.load(Ordering::SeqCst)),
+            vruntime: AtomicU64::new(self.vruntime.load(Ordering::SeqCst)),
+            cpu_time: AtomicU64::new(0),
+        }
+    }
+}
+
 #[repr(C)]
 pub struct Task {
     pub id: TaskId,
     pub pid: PID,
     pub state: Atomic<RunState>,
     pub ticks: AtomicUsize,
+    pub sched: SchedInfo,
     pub context: Box<dyn Any>,
     pub runnable: Option<Box<dyn Runnable>>,
 }
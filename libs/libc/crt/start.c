@@ -0,0 +1,57 @@
+/*
+ * Start-up code for programs linked against libc.so.
+ *
+ * The `_start` in libc.so cannot be the entry point of a program, so each
+ * dynamically linked program carries its own. The memory routines are here
+ * too: libc.so gets them from compiler_builtins, which it does not export.
+ */
+
+#include <stddef.h>
+
+int main(int argc, char **argv, char **envp);
+
+_Noreturn void __libc_start_main(int (*main)(int, char **, char **), long argc, char **argv);
+
+_Noreturn void _start(long argc, char **argv) {
+    __libc_start_main(main, argc, argv);
+}
+
+void *memcpy(void *dst, const void *src, size_t n) {
+    unsigned char *d = dst;
+    const unsigned char *s = src;
+    while (n--)
+        *d++ = *s++;
+    return dst;
+}
+
+void *memmove(void *dst, const void *src, size_t n) {
+    unsigned char *d = dst;
+    const unsigned char *s = src;
+    if (d < s)
+        return memcpy(dst, src, n);
+    while (n--)
+        d[n] = s[n];
+    return dst;
+}
+
+void *memset(void *dst, int c, size_t n) {
+    unsigned char *d = dst;
+    while (n--)
+        *d++ = (unsigned char)c;
+    return dst;
+}
+
+int memcmp(const void *a, const void *b, size_t n) {
+    const unsigned char *x = a, *y = b;
+    for (; n; n--, x++, y++)
+        if (*x != *y)
+            return *x - *y;
+    return 0;
+}
+
+size_t strlen(const char *s) {
+    const char *p = s;
+    while (*p)
+        p++;
+    return p - s;
+}
@@ -0,0 +1,19 @@
+#ifndef _DIRENT_H
+#define _DIRENT_H
+
+#define DT_DIR 4
+#define DT_REG 8
+
+struct dirent {
+    unsigned char d_type;
+    char d_name[256];
+};
+
+typedef struct __sophon_dir DIR;
+
+DIR *opendir(const char *path);
+/* "." and ".." are not returned. */
+struct dirent *readdir(DIR *dir);
+int closedir(DIR *dir);
+
+#endif
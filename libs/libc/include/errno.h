@@ -0,0 +1,21 @@
+#ifndef _ERRNO_H
+#define _ERRNO_H
+
+/* The kernel does not report why a call failed, so errno is a best guess. */
+extern int errno;
+
+#define ENOENT 2
+#define EINTR 4
+#define EIO 5
+#define E2BIG 7
+#define EBADF 9
+#define ECHILD 10
+#define EAGAIN 11
+#define ENOMEM 12
+#define EEXIST 17
+#define ENOTDIR 20
+#define EINVAL 22
+#define ERANGE 34
+#define ENOSYS 38
+
+#endif
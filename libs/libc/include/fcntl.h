@@ -0,0 +1,18 @@
+#ifndef _FCNTL_H
+#define _FCNTL_H
+
+#include <sys/types.h>
+
+/* Files are always readable and writable. The access mode is ignored. */
+#define O_RDONLY 0
+#define O_WRONLY 1
+#define O_RDWR 2
+#define O_CREAT 0100
+#define O_EXCL 0200
+#define O_TRUNC 01000
+
+/* The mode argument is ignored, as there are no permissions. */
+int open(const char *path, int flags, ...);
+int creat(const char *path, mode_t mode);
+
+#endif
@@ -0,0 +1,18 @@
+#ifndef _SIGNAL_H
+#define _SIGNAL_H
+
+#include <sys/types.h>
+
+#define SIGINT 2
+#define SIGKILL 9
+#define SIGUSR1 10
+#define SIGUSR2 12
+#define SIGTERM 15
+#define SIGCONT 18
+#define SIGSTOP 19
+#define SIGTSTP 20
+
+/* A negative pid sends the signal to a process group. */
+int kill(pid_t pid, int sig);
+
+#endif
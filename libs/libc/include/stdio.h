@@ -0,0 +1,52 @@
+#ifndef _STDIO_H
+#define _STDIO_H
+
+#include <stdarg.h>
+#include <stddef.h>
+
+#define EOF (-1)
+
+/* Streams are not buffered, and cannot seek. */
+typedef struct __sophon_file FILE;
+
+extern FILE *stdin;
+extern FILE *stdout;
+extern FILE *stderr;
+
+/* The modes are "r" and "w", optionally with '+' and 'b'. */
+FILE *fopen(const char *path, const char *mode);
+FILE *fdopen(int fd, const char *mode);
+int fclose(FILE *stream);
+int fileno(FILE *stream);
+int fflush(FILE *stream);
+int feof(FILE *stream);
+int ferror(FILE *stream);
+void clearerr(FILE *stream);
+
+size_t fread(void *buf, size_t size, size_t n, FILE *stream);
+size_t fwrite(const void *buf, size_t size, size_t n, FILE *stream);
+int fgetc(FILE *stream);
+int getc(FILE *stream);
+int getchar(void);
+int ungetc(int c, FILE *stream);
+char *fgets(char *buf, int size, FILE *stream);
+int fputc(int c, FILE *stream);
+int putc(int c, FILE *stream);
+int putchar(int c);
+int fputs(const char *s, FILE *stream);
+int puts(const char *s);
+void perror(const char *s);
+
+int remove(const char *path);
+int rename(const char *from, const char *to);
+
+int printf(const char *format, ...) __attribute__((format(printf, 1, 2)));
+int fprintf(FILE *stream, const char *format, ...) __attribute__((format(printf, 2, 3)));
+int dprintf(int fd, const char *format, ...) __attribute__((format(printf, 2, 3)));
+int sprintf(char *buf, const char *format, ...) __attribute__((format(printf, 2, 3)));
+int snprintf(char *buf, size_t size, const char *format, ...) __attribute__((format(printf, 3, 4)));
+int vprintf(const char *format, va_list args);
+int vfprintf(FILE *stream, const char *format, va_list args);
+int vsnprintf(char *buf, size_t size, const char *format, va_list args);
+
+#endif
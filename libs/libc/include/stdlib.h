@@ -0,0 +1,32 @@
+#ifndef _STDLIB_H
+#define _STDLIB_H
+
+#include <stddef.h>
+
+#define EXIT_SUCCESS 0
+#define EXIT_FAILURE 1
+
+void *malloc(size_t size);
+void *calloc(size_t n, size_t size);
+void *realloc(void *p, size_t size);
+void free(void *p);
+
+int atexit(void (*f)(void));
+void exit(int code) __attribute__((noreturn));
+void _Exit(int code) __attribute__((noreturn));
+void abort(void) __attribute__((noreturn));
+
+int abs(int n);
+long labs(long n);
+int atoi(const char *s);
+long atol(const char *s);
+long strtol(const char *s, char **end, int base);
+unsigned long strtoul(const char *s, char **end, int base);
+void qsort(void *base, size_t n, size_t size, int (*compare)(const void *, const void *));
+
+/* Variables are local to the process. They are not passed to programs started with exec. */
+char *getenv(const char *name);
+int setenv(const char *name, const char *value, int overwrite);
+int unsetenv(const char *name);
+
+#endif
@@ -0,0 +1,20 @@
+#ifndef _SYS_WAIT_H
+#define _SYS_WAIT_H
+
+#include <sys/types.h>
+
+#define WNOHANG 1
+#define WUNTRACED 2
+
+#define WEXITSTATUS(s) (((s) >> 8) & 0xff)
+#define WTERMSIG(s) ((s) & 0x7f)
+#define WSTOPSIG(s) WEXITSTATUS(s)
+#define WIFEXITED(s) (WTERMSIG(s) == 0)
+#define WIFSTOPPED(s) (((s) & 0xff) == 0x7f)
+#define WIFSIGNALED(s) (!WIFEXITED(s) && !WIFSTOPPED(s))
+
+/* pid is a process id, or -1 for any child. Process groups are not supported. */
+pid_t waitpid(pid_t pid, int *status, int options);
+pid_t wait(int *status);
+
+#endif
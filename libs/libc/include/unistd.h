@@ -0,0 +1,33 @@
+#ifndef _UNISTD_H
+#define _UNISTD_H
+
+#include <sys/types.h>
+
+#define STDIN_FILENO 0
+#define STDOUT_FILENO 1
+#define STDERR_FILENO 2
+
+ssize_t read(int fd, void *buf, size_t len);
+ssize_t write(int fd, const void *buf, size_t len);
+int close(int fd);
+int dup2(int old, int new);
+int isatty(int fd);
+
+int unlink(const char *path);
+int rmdir(const char *path);
+char *getcwd(char *buf, size_t size);
+int chdir(const char *path);
+
+pid_t fork(void);
+/* argv[0] is replaced with the program path. envp is ignored. */
+int execve(const char *path, char *const argv[], char *const envp[]);
+int execv(const char *path, char *const argv[]);
+/* Names without a '/' are looked up in /bin. */
+int execvp(const char *file, char *const argv[]);
+void _exit(int code) __attribute__((noreturn));
+pid_t getpid(void);
+
+unsigned int sleep(unsigned int seconds);
+int usleep(unsigned int usec);
+
+#endif
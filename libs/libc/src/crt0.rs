@@ -0,0 +1,28 @@
+//! The program entry point. The kernel calls `_start`, which calls the C `main`.
+//!
+//! Programs linked against `libc.so` bring their own `_start` (`crt/start.c`),
+//! which calls `__libc_start_main`.
+
+use core::ffi::{c_char, c_int};
+use core::ptr;
+
+type Main = unsafe extern "C" fn(c_int, *mut *mut c_char, *mut *mut c_char) -> c_int;
+
+extern "C" {
+    fn main(argc: c_int, argv: *mut *mut c_char, envp: *mut *mut c_char) -> c_int;
+}
+
+/// Programs do not inherit an environment.
+static mut ENVP: [*mut c_char; 1] = [ptr::null_mut()];
+
+#[no_mangle]
+pub unsafe extern "C" fn __libc_start_main(main: Main, argc: isize, argv: *const *const u8) -> ! {
+    user::env::init(argc, argv);
+    let code = main(argc as c_int, argv as _, ptr::addr_of_mut!(ENVP) as _);
+    crate::stdlib::exit(code)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn _start(argc: isize, argv: *const *const u8) -> isize {
+    __libc_start_main(main, argc, argv)
+}
@@ -0,0 +1,69 @@
+//! Character classes, for the "C" locale only.
+
+use core::ffi::c_int;
+
+fn class(c: c_int, f: fn(&u8) -> bool) -> c_int {
+    u8::try_from(c).is_ok_and(|c| f(&c)) as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn isalnum(c: c_int) -> c_int {
+    class(c, u8::is_ascii_alphanumeric)
+}
+
+#[no_mangle]
+pub extern "C" fn isalpha(c: c_int) -> c_int {
+    class(c, u8::is_ascii_alphabetic)
+}
+
+#[no_mangle]
+pub extern "C" fn isdigit(c: c_int) -> c_int {
+    class(c, u8::is_ascii_digit)
+}
+
+#[no_mangle]
+pub extern "C" fn isxdigit(c: c_int) -> c_int {
+    class(c, u8::is_ascii_hexdigit)
+}
+
+#[no_mangle]
+pub extern "C" fn islower(c: c_int) -> c_int {
+    class(c, u8::is_ascii_lowercase)
+}
+
+#[no_mangle]
+pub extern "C" fn isupper(c: c_int) -> c_int {
+    class(c, u8::is_ascii_uppercase)
+}
+
+#[no_mangle]
+pub extern "C" fn isprint(c: c_int) -> c_int {
+    class(c, |c| (0x20..0x7f).contains(c))
+}
+
+#[no_mangle]
+pub extern "C" fn ispunct(c: c_int) -> c_int {
+    class(c, u8::is_ascii_punctuation)
+}
+
+/// Space, `\t`, `\n`, `\v`, `\f` and `\r`.
+#[no_mangle]
+pub extern "C" fn isspace(c: c_int) -> c_int {
+    class(c, |c| *c == b' ' || (b'\t'..=b'\r').contains(c))
+}
+
+#[no_mangle]
+pub extern "C" fn tolower(c: c_int) -> c_int {
+    match u8::try_from(c) {
+        Ok(c) => c.to_ascii_lowercase() as c_int,
+        Err(_) => c,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn toupper(c: c_int) -> c_int {
+    match u8::try_from(c) {
+        Ok(c) => c.to_ascii_uppercase() as c_int,
+        Err(_) => c,
+    }
+}
@@ -0,0 +1,61 @@
+use alloc::boxed::Box;
+use core::ffi::{c_char, c_int, c_uchar};
+use core::ptr;
+use user::fs::ReadDir;
+
+use crate::errno::{fail, ENOTDIR};
+
+pub const DT_DIR: c_uchar = 4;
+pub const DT_REG: c_uchar = 8;
+
+#[repr(C)]
+pub struct Dirent {
+    d_type: c_uchar,
+    d_name: [c_char; 256],
+}
+
+/// An open directory. `readdir` returns a pointer to `entry`.
+pub struct Dir {
+    entries: ReadDir,
+    entry: Dirent,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opendir(path: *const c_char) -> *mut Dir {
+    match user::fs::read_dir(&crate::to_str(path)) {
+        Ok(entries) => Box::into_raw(Box::new(Dir {
+            entries,
+            entry: Dirent {
+                d_type: 0,
+                d_name: [0; 256],
+            },
+        })),
+        Err(_) => fail(ENOTDIR, ptr::null_mut()),
+    }
+}
+
+/// Return the next entry, or null at the end of the directory.
+#[no_mangle]
+pub unsafe extern "C" fn readdir(dir: *mut Dir) -> *mut Dirent {
+    let dir = &mut *dir;
+    let Some(Ok(entry)) = dir.entries.next() else {
+        return ptr::null_mut();
+    };
+    let is_dir = entry.metadata().is_ok_and(|m| m.is_dir());
+    dir.entry.d_type = if is_dir { DT_DIR } else { DT_REG };
+    let name = entry.file_name().as_bytes();
+    let len = usize::min(name.len(), dir.entry.d_name.len() - 1);
+    ptr::copy_nonoverlapping(
+        name.as_ptr() as *const c_char,
+        dir.entry.d_name.as_mut_ptr(),
+        len,
+    );
+    dir.entry.d_name[len] = 0;
+    &mut dir.entry
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn closedir(dir: *mut Dir) -> c_int {
+    drop(Box::from_raw(dir));
+    0
+}
@@ -0,0 +1,45 @@
+use core::ffi::{c_int, CStr};
+
+/// The error number of the last failed call. Shared by all the threads.
+#[no_mangle]
+pub static mut errno: c_int = 0;
+
+pub const ENOENT: c_int = 2;
+pub const EINTR: c_int = 4;
+pub const EIO: c_int = 5;
+pub const E2BIG: c_int = 7;
+pub const EBADF: c_int = 9;
+pub const ECHILD: c_int = 10;
+pub const EAGAIN: c_int = 11;
+pub const ENOMEM: c_int = 12;
+pub const EEXIST: c_int = 17;
+pub const ENOTDIR: c_int = 20;
+pub const EINVAL: c_int = 22;
+pub const ERANGE: c_int = 34;
+pub const ENOSYS: c_int = 38;
+
+/// Set `errno` and return `ret`.
+pub fn fail<T>(error: c_int, ret: T) -> T {
+    unsafe { errno = error };
+    ret
+}
+
+pub fn message(error: c_int) -> &'static CStr {
+    match error {
+        0 => c"Success",
+        ENOENT => c"No such file or directory",
+        EINTR => c"Interrupted system call",
+        EIO => c"Input/output error",
+        E2BIG => c"Argument list too long",
+        EBADF => c"Bad file descriptor",
+        ECHILD => c"No child processes",
+        EAGAIN => c"Resource temporarily unavailable",
+        ENOMEM => c"Cannot allocate memory",
+        EEXIST => c"File exists",
+        ENOTDIR => c"Not a directory",
+        EINVAL => c"Invalid argument",
+        ERANGE => c"Numerical result out of range",
+        ENOSYS => c"Function not implemented",
+        _ => c"Unknown error",
+    }
+}
@@ -0,0 +1,34 @@
+use core::ffi::{c_char, c_int};
+
+use crate::errno::{fail, EEXIST, ENOENT};
+
+pub const O_WRONLY: c_int = 1;
+pub const O_CREAT: c_int = 0o100;
+pub const O_EXCL: c_int = 0o200;
+pub const O_TRUNC: c_int = 0o1000;
+
+/// Open a file. Files are always readable and writable; the access mode is ignored.
+/// The optional mode argument is ignored, as there are no permissions.
+#[no_mangle]
+pub unsafe extern "C" fn open(path: *const c_char, flags: c_int, _: ...) -> c_int {
+    let path = crate::to_str(path);
+    let exists = user::fs::exists(&path);
+    if flags & O_CREAT != 0 && flags & O_EXCL != 0 && exists {
+        return fail(EEXIST, -1);
+    }
+    let create = (flags & O_CREAT != 0 && !exists) || (flags & O_TRUNC != 0 && exists);
+    let fd = if create {
+        user::sys::create(&path)
+    } else {
+        user::sys::open(&path)
+    };
+    match fd {
+        Some(fd) => fd.0 as c_int,
+        None => fail(ENOENT, -1),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn creat(path: *const c_char, _mode: c_int) -> c_int {
+    open(path, O_WRONLY | O_CREAT | O_TRUNC)
+}
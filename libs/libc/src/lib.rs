@@ -0,0 +1,32 @@
+//! A minimal POSIX C library, for porting C programs to Sophon.
+//!
+//! The functions are implemented over `libs/user`, and declared in the headers under `include/`.
+//! Link a C program with `libc.a`, which also provides the program entry point (see `crt0`).
+//!
+//! `memcpy`, `memmove`, `memset`, `memcmp` and `strlen` come from `compiler_builtins`.
+
+#![no_std]
+#![feature(c_variadic)]
+#![allow(clippy::missing_safety_doc)]
+
+extern crate alloc;
+
+mod crt0;
+mod ctype;
+mod dirent;
+mod errno;
+mod fcntl;
+mod stat;
+mod stdio;
+mod stdlib;
+mod string;
+mod unistd;
+mod wait;
+
+use alloc::string::String;
+use core::ffi::{c_char, CStr};
+
+/// Borrow a C string as `&str`. Invalid UTF-8 is replaced.
+unsafe fn to_str<'a>(s: *const c_char) -> alloc::borrow::Cow<'a, str> {
+    String::from_utf8_lossy(CStr::from_ptr(s).to_bytes())
+}
@@ -0,0 +1,37 @@
+use core::ffi::{c_char, c_int, c_long, c_uint};
+
+use crate::errno::{fail, EEXIST, ENOENT};
+
+pub const S_IFDIR: c_uint = 0o040000;
+pub const S_IFREG: c_uint = 0o100000;
+
+/// File status. Only the type and the size are known.
+#[repr(C)]
+pub struct Stat {
+    st_mode: c_uint,
+    st_size: c_long,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn stat(path: *const c_char, buf: *mut Stat) -> c_int {
+    let Ok(stat) = user::sys::stat(&crate::to_str(path)) else {
+        return fail(ENOENT, -1);
+    };
+    *buf = Stat {
+        st_mode: if stat.is_dir { S_IFDIR } else { S_IFREG } | 0o755,
+        st_size: stat.size as c_long,
+    };
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mkdir(path: *const c_char, _mode: c_uint) -> c_int {
+    let path = crate::to_str(path);
+    if user::fs::exists(&path) {
+        return fail(EEXIST, -1);
+    }
+    match user::sys::mkdir(&path) {
+        Ok(()) => 0,
+        Err(()) => fail(ENOENT, -1),
+    }
+}
@@ -0,0 +1,638 @@
+//! Streams and formatted output. Streams are not buffered, and cannot seek.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_int, c_uint, c_void, CStr, VaList};
+use core::ptr;
+use user::sys::Fd;
+
+use crate::errno::{errno, fail, EBADF, EINVAL, ENOENT};
+
+pub const EOF: c_int = -1;
+
+/// A `FILE`.
+pub struct Stream {
+    fd: Fd,
+    /// Streams from `fopen` are closed and freed by `fclose`.
+    owned: bool,
+    eof: bool,
+    error: bool,
+    /// A byte pushed back by `ungetc`.
+    unget: Option<u8>,
+}
+
+impl Stream {
+    const fn new(fd: Fd, owned: bool) -> Self {
+        Self {
+            fd,
+            owned,
+            eof: false,
+            error: false,
+            unget: None,
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        if let Some(byte) = self.unget.take() {
+            buf[0] = byte;
+            return 1;
+        }
+        match user::sys::read(self.fd, buf) {
+            Ok(0) => {
+                self.eof = true;
+                0
+            }
+            Ok(len) => len,
+            Err(()) => {
+                self.error = true;
+                0
+            }
+        }
+    }
+
+    fn write_all(&mut self, mut buf: &[u8]) -> bool {
+        while !buf.is_empty() {
+            match user::sys::write(self.fd, buf) {
+                Ok(len @ 1..) => buf = &buf[len..],
+                _ => {
+                    self.error = true;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+static mut STDIN: Stream = Stream::new(Fd::STDIN, false);
+static mut STDOUT: Stream = Stream::new(Fd::STDOUT, false);
+static mut STDERR: Stream = Stream::new(Fd::STDERR, false);
+
+#[no_mangle]
+pub static mut stdin: *mut Stream = ptr::addr_of_mut!(STDIN);
+#[no_mangle]
+pub static mut stdout: *mut Stream = ptr::addr_of_mut!(STDOUT);
+#[no_mangle]
+pub static mut stderr: *mut Stream = ptr::addr_of_mut!(STDERR);
+
+/// Open a file. The modes are `r` and `w`, optionally with `+` and `b`.
+/// Appending is not supported, as streams cannot seek.
+#[no_mangle]
+pub unsafe extern "C" fn fopen(path: *const c_char, mode: *const c_char) -> *mut Stream {
+    let path = crate::to_str(path);
+    let fd = match CStr::from_ptr(mode).to_bytes().first() {
+        Some(b'r') => user::sys::open(&path),
+        Some(b'w') => user::sys::create(&path),
+        _ => return fail(EINVAL, ptr::null_mut()),
+    };
+    match fd {
+        Some(fd) => Box::into_raw(Box::new(Stream::new(fd, true))),
+        None => fail(ENOENT, ptr::null_mut()),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fclose(stream: *mut Stream) -> c_int {
+    let fd = (*stream).fd;
+    if (*stream).owned {
+        drop(Box::from_raw(stream));
+    }
+    user::sys::close(fd);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fileno(stream: *mut Stream) -> c_int {
+    (*stream).fd.0 as c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fdopen(fd: c_int, _mode: *const c_char) -> *mut Stream {
+    if fd < 0 {
+        return fail(EBADF, ptr::null_mut());
+    }
+    Box::into_raw(Box::new(Stream::new(Fd(fd as u32), true)))
+}
+
+/// Streams are not buffered, so this does nothing.
+#[no_mangle]
+pub extern "C" fn fflush(_stream: *mut Stream) -> c_int {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn feof(stream: *mut Stream) -> c_int {
+    (*stream).eof as c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ferror(stream: *mut Stream) -> c_int {
+    (*stream).error as c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn clearerr(stream: *mut Stream) {
+    (*stream).eof = false;
+    (*stream).error = false;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fread(
+    buf: *mut c_void,
+    size: usize,
+    n: usize,
+    stream: *mut Stream,
+) -> usize {
+    let Some(total) = size.checked_mul(n).filter(|t| *t > 0) else {
+        return 0;
+    };
+    let buf = core::slice::from_raw_parts_mut(buf as *mut u8, total);
+    let mut filled = 0;
+    while filled < total {
+        match (*stream).read(&mut buf[filled..]) {
+            0 => break,
+            len => filled += len,
+        }
+    }
+    filled / size
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fwrite(
+    buf: *const c_void,
+    size: usize,
+    n: usize,
+    stream: *mut Stream,
+) -> usize {
+    let Some(total) = size.checked_mul(n).filter(|t| *t > 0) else {
+        return 0;
+    };
+    let buf = core::slice::from_raw_parts(buf as *const u8, total);
+    if (*stream).write_all(buf) {
+        n
+    } else {
+        0
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fgetc(stream: *mut Stream) -> c_int {
+    let mut byte = [0u8; 1];
+    match (*stream).read(&mut byte) {
+        1 => byte[0] as c_int,
+        _ => EOF,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getc(stream: *mut Stream) -> c_int {
+    fgetc(stream)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getchar() -> c_int {
+    fgetc(stdin)
+}
+
+/// Push back one byte. Only one byte can be pushed back at a time.
+#[no_mangle]
+pub unsafe extern "C" fn ungetc(c: c_int, stream: *mut Stream) -> c_int {
+    if c == EOF || (*stream).unget.is_some() {
+        return EOF;
+    }
+    (*stream).unget = Some(c as u8);
+    (*stream).eof = false;
+    c
+}
+
+/// Read a line, including the `\n`, into a buffer of `size` bytes.
+/// Reads one byte at a time, so that nothing after the line is consumed.
+#[no_mangle]
+pub unsafe extern "C" fn fgets(buf: *mut c_char, size: c_int, stream: *mut Stream) -> *mut c_char {
+    if size <= 0 {
+        return ptr::null_mut();
+    }
+    let mut len = 0;
+    while len + 1 < size as usize {
+        let c = fgetc(stream);
+        if c == EOF {
+            break;
+        }
+        *buf.add(len) = c as c_char;
+        len += 1;
+        if c == b'\n' as c_int {
+            break;
+        }
+    }
+    if len == 0 {
+        return ptr::null_mut();
+    }
+    *buf.add(len) = 0;
+    buf
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fputc(c: c_int, stream: *mut Stream) -> c_int {
+    if (*stream).write_all(&[c as u8]) {
+        c as u8 as c_int
+    } else {
+        EOF
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn putc(c: c_int, stream: *mut Stream) -> c_int {
+    fputc(c, stream)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn putchar(c: c_int) -> c_int {
+    fputc(c, stdout)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fputs(s: *const c_char, stream: *mut Stream) -> c_int {
+    if (*stream).write_all(CStr::from_ptr(s).to_bytes()) {
+        0
+    } else {
+        EOF
+    }
+}
+
+/// Write `s` and a `\n` to the standard output.
+#[no_mangle]
+pub unsafe extern "C" fn puts(s: *const c_char) -> c_int {
+    let mut line = Vec::from(CStr::from_ptr(s).to_bytes());
+    line.push(b'\n');
+    if (*stdout).write_all(&line) {
+        0
+    } else {
+        EOF
+    }
+}
+
+/// Print `s: <message for errno>` to the standard error.
+#[no_mangle]
+pub unsafe extern "C" fn perror(s: *const c_char) {
+    let message = crate::errno::message(errno).to_string_lossy();
+    let line = match s.is_null() || *s == 0 {
+        true => format!("{}\n", message),
+        false => format!("{}: {}\n", crate::to_str(s), message),
+    };
+    (*stderr).write_all(line.as_bytes());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn remove(path: *const c_char) -> c_int {
+    crate::unistd::unlink(path)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rename(from: *const c_char, to: *const c_char) -> c_int {
+    match user::sys::rename(&crate::to_str(from), &crate::to_str(to)) {
+        Ok(()) => 0,
+        Err(()) => fail(ENOENT, -1),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn printf(format: *const c_char, mut args: ...) -> c_int {
+    vfprintf(stdout, format, args.as_va_list())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fprintf(
+    stream: *mut Stream,
+    format: *const c_char,
+    mut args: ...
+) -> c_int {
+    vfprintf(stream, format, args.as_va_list())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dprintf(fd: c_int, format: *const c_char, mut args: ...) -> c_int {
+    let mut stream = Stream::new(Fd(fd as u32), false);
+    vfprintf(&mut stream, format, args.as_va_list())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sprintf(buf: *mut c_char, format: *const c_char, mut args: ...) -> c_int {
+    vsnprintf(buf, usize::MAX, format, args.as_va_list())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn snprintf(
+    buf: *mut c_char,
+    size: usize,
+    format: *const c_char,
+    mut args: ...
+) -> c_int {
+    vsnprintf(buf, size, format, args.as_va_list())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vprintf(format: *const c_char, args: VaList) -> c_int {
+    vfprintf(stdout, format, args)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vfprintf(
+    stream: *mut Stream,
+    format: *const c_char,
+    args: VaList,
+) -> c_int {
+    let out = self::format(format, args);
+    if (*stream).write_all(&out) {
+        out.len() as c_int
+    } else {
+        -1
+    }
+}
+
+/// Format into `buf`, truncated to `size` bytes including the terminating zero.
+/// Returns the length of the untruncated output.
+#[no_mangle]
+pub unsafe extern "C" fn vsnprintf(
+    buf: *mut c_char,
+    size: usize,
+    format: *const c_char,
+    args: VaList,
+) -> c_int {
+    let out = self::format(format, args);
+    if size > 0 {
+        let len = usize::min(out.len(), size - 1);
+        ptr::copy_nonoverlapping(out.as_ptr() as *const c_char, buf, len);
+        *buf.add(len) = 0;
+    }
+    out.len() as c_int
+}
+
+/// A conversion specification, without the conversion.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Pad `body` to the field width. `sign` and `prefix` go before the zero padding.
+    fn pad(&self, out: &mut Vec<u8>, sign: &[u8], prefix: &[u8], body: &[u8]) {
+        let len = sign.len() + prefix.len() + body.len();
+        let fill = self.width.saturating_sub(len);
+        if self.left {
+            out.extend_from_slice(sign);
+            out.extend_from_slice(prefix);
+            out.extend_from_slice(body);
+            out.resize(out.len() + fill, b' ');
+        } else if self.zero {
+            out.extend_from_slice(sign);
+            out.extend_from_slice(prefix);
+            out.resize(out.len() + fill, b'0');
+            out.extend_from_slice(body);
+        } else {
+            out.resize(out.len() + fill, b' ');
+            out.extend_from_slice(sign);
+            out.extend_from_slice(prefix);
+            out.extend_from_slice(body);
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static [u8] {
+        match (negative, self.plus, self.space) {
+            (true, _, _) => b"-",
+            (false, true, _) => b"+",
+            (false, false, true) => b" ",
+            _ => b"",
+        }
+    }
+
+    fn integer(&self, out: &mut Vec<u8>, negative: bool, value: u64, radix: u32, upper: bool) {
+        let mut digits = match (value, self.precision) {
+            (0, Some(0)) => String::new(),
+            _ => match (radix, upper) {
+                (8, _) => format!("{:o}", value),
+                (16, false) => format!("{:x}", value),
+                (16, true) => format!("{:X}", value),
+                _ => format!("{}", value),
+            },
+        };
+        if let Some(precision) = self.precision {
+            while digits.len() < precision {
+                digits.insert(0, '0');
+            }
+        }
+        let prefix: &[u8] = match (self.alt, radix, upper) {
+            (true, 8, _) if !digits.starts_with('0') => b"0",
+            (true, 16, false) if value != 0 => b"0x",
+            (true, 16, true) if value != 0 => b"0X",
+            _ => b"",
+        };
+        let spec = Spec {
+            // The zero flag is ignored when a precision is given.
+            zero: self.zero && self.precision.is_none(),
+            ..*self
+        };
+        spec.pad(out, self.sign(negative), prefix, digits.as_bytes());
+    }
+
+    fn float(&self, out: &mut Vec<u8>, value: f64, conversion: u8) {
+        let upper = conversion.is_ascii_uppercase();
+        let negative = value.is_sign_negative() && !value.is_nan();
+        // `f64::abs` needs std.
+        let value = f64::from_bits(value.to_bits() & !(1 << 63));
+        if !value.is_finite() {
+            let body = match (value.is_nan(), upper) {
+                (true, false) => "nan",
+                (true, true) => "NAN",
+                (false, false) => "inf",
+                (false, true) => "INF",
+            };
+            let spec = Spec {
+                zero: false,
+                ..*self
+            };
+            return spec.pad(out, self.sign(negative), b"", body.as_bytes());
+        }
+        let precision = self.precision.unwrap_or(6);
+        let mut body = match conversion.to_ascii_lowercase() {
+            b'e' => exponential(value, precision),
+            b'g' => {
+                let precision = usize::max(precision, 1);
+                let exponent = exponential(value, precision - 1);
+                let exp: i32 = exponent[exponent.find('e').unwrap() + 1..].parse().unwrap();
+                let mut body = if exp < -4 || exp >= precision as i32 {
+                    exponent
+                } else {
+                    format!("{:.*}", (precision as i32 - 1 - exp) as usize, value)
+                };
+                if !self.alt && body.contains('.') {
+                    let e = body.find('e').unwrap_or(body.len());
+                    let mantissa = body[..e].trim_end_matches('0').trim_end_matches('.');
+                    body = format!("{}{}", mantissa, &body[e..]);
+                }
+                body
+            }
+            _ => format!("{:.*}", precision, value),
+        };
+        if upper {
+            body.make_ascii_uppercase();
+        }
+        self.pad(out, self.sign(negative), b"", body.as_bytes());
+    }
+}
+
+/// Format as `d.ddde+dd`. Rust prints the exponent without the sign and the padding.
+fn exponential(value: f64, precision: usize) -> String {
+    let s = format!("{:.*e}", precision, value);
+    let (mantissa, exp) = s.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exp.unsigned_abs())
+}
+
+/// Integer sizes, from the length modifiers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Length {
+    Char,
+    Short,
+    Int,
+    Long,
+}
+
+/// Format a `printf` string. Supports the flags, the width and the precision, and the
+/// conversions `d i u o x X c s p f F e E g G n %`.
+unsafe fn format(format: *const c_char, mut args: VaList) -> Vec<u8> {
+    let fmt = CStr::from_ptr(format).to_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < fmt.len() {
+        if fmt[i] != b'%' {
+            let end = fmt[i..]
+                .iter()
+                .position(|c| *c == b'%')
+                .map_or(fmt.len(), |p| i + p);
+            out.extend_from_slice(&fmt[i..end]);
+            i = end;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        let next = |i: usize| fmt.get(i).copied().unwrap_or(0);
+        let mut spec = Spec::default();
+        loop {
+            match next(i) {
+                b'-' => spec.left = true,
+                b'0' => spec.zero = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alt = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        if next(i) == b'*' {
+            let width = args.arg::<c_int>();
+            spec.left |= width < 0;
+            spec.width = width.unsigned_abs() as usize;
+            i += 1;
+        } else {
+            while next(i).is_ascii_digit() {
+                spec.width = spec.width * 10 + (next(i) - b'0') as usize;
+                i += 1;
+            }
+        }
+        if next(i) == b'.' {
+            i += 1;
+            if next(i) == b'*' {
+                spec.precision = usize::try_from(args.arg::<c_int>()).ok();
+                i += 1;
+            } else {
+                let mut precision = 0;
+                while next(i).is_ascii_digit() {
+                    precision = precision * 10 + (next(i) - b'0') as usize;
+                    i += 1;
+                }
+                spec.precision = Some(precision);
+            }
+        }
+        let mut length = Length::Int;
+        loop {
+            length = match (next(i), length) {
+                (b'h', Length::Short) => Length::Char,
+                (b'h', _) => Length::Short,
+                (b'l' | b'z' | b'j' | b't' | b'L', _) => Length::Long,
+                _ => break,
+            };
+            i += 1;
+        }
+        let conversion = next(i);
+        i += 1;
+        match conversion {
+            b'd' | b'i' => {
+                let value = match length {
+                    Length::Long => args.arg::<i64>(),
+                    Length::Int => args.arg::<c_int>() as i64,
+                    Length::Short => args.arg::<c_int>() as i16 as i64,
+                    Length::Char => args.arg::<c_int>() as i8 as i64,
+                };
+                spec.integer(&mut out, value < 0, value.unsigned_abs(), 10, false);
+            }
+            b'u' | b'o' | b'x' | b'X' => {
+                let value = match length {
+                    Length::Long => args.arg::<u64>(),
+                    Length::Int => args.arg::<c_uint>() as u64,
+                    Length::Short => args.arg::<c_uint>() as u16 as u64,
+                    Length::Char => args.arg::<c_uint>() as u8 as u64,
+                };
+                let radix = match conversion {
+                    b'o' => 8,
+                    b'u' => 10,
+                    _ => 16,
+                };
+                let spec = Spec {
+                    plus: false,
+                    space: false,
+                    ..spec
+                };
+                spec.integer(&mut out, false, value, radix, conversion == b'X');
+            }
+            b'c' => spec.pad(&mut out, b"", b"", &[args.arg::<c_int>() as u8]),
+            b's' => {
+                let s = args.arg::<*const c_char>();
+                let bytes = if s.is_null() {
+                    &b"(null)"[..]
+                } else {
+                    let len = crate::string::strnlen(s, spec.precision.unwrap_or(usize::MAX));
+                    core::slice::from_raw_parts(s as *const u8, len)
+                };
+                spec.zero = false;
+                spec.pad(&mut out, b"", b"", bytes);
+            }
+            b'p' => {
+                let p = args.arg::<*const c_void>() as u64;
+                spec.zero = false;
+                spec.pad(&mut out, b"", b"0x", format!("{:x}", p).as_bytes());
+            }
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                spec.float(&mut out, args.arg::<f64>(), conversion)
+            }
+            b'n' => {
+                let count = args.arg::<*mut c_int>();
+                *count = out.len() as c_int;
+            }
+            b'%' => out.push(b'%'),
+            _ => out.extend_from_slice(&fmt[start..usize::min(i, fmt.len())]),
+        }
+    }
+    out
+}
@@ -0,0 +1,286 @@
+use alloc::alloc::{alloc, dealloc, Layout};
+use alloc::collections::BTreeMap;
+use alloc::ffi::CString;
+use alloc::string::String;
+use core::ffi::{c_char, c_int, c_long, c_ulong, c_void};
+use core::ptr;
+use spin::Mutex;
+
+use crate::ctype::isspace;
+use crate::errno::{fail, EINVAL, ENOMEM, ERANGE};
+
+/// Blocks start with a header holding their size, so that `free` can rebuild the layout.
+const HEADER: usize = 16;
+
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    let Ok(layout) = Layout::from_size_align(HEADER + size, HEADER) else {
+        return fail(ENOMEM, ptr::null_mut());
+    };
+    let block = alloc(layout);
+    if block.is_null() {
+        return fail(ENOMEM, ptr::null_mut());
+    }
+    (block as *mut usize).write(size);
+    block.add(HEADER) as _
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn calloc(n: usize, size: usize) -> *mut c_void {
+    let Some(total) = n.checked_mul(size) else {
+        return fail(ENOMEM, ptr::null_mut());
+    };
+    let p = malloc(total);
+    if !p.is_null() {
+        ptr::write_bytes(p as *mut u8, 0, total);
+    }
+    p
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn realloc(p: *mut c_void, size: usize) -> *mut c_void {
+    if p.is_null() {
+        return malloc(size);
+    }
+    let new = malloc(size);
+    if !new.is_null() {
+        let old_size = ((p as *mut u8).sub(HEADER) as *const usize).read();
+        ptr::copy_nonoverlapping(p as *const u8, new as *mut u8, usize::min(old_size, size));
+        free(p);
+    }
+    new
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn free(p: *mut c_void) {
+    if p.is_null() {
+        return;
+    }
+    let block = (p as *mut u8).sub(HEADER);
+    let size = (block as *const usize).read();
+    dealloc(
+        block,
+        Layout::from_size_align_unchecked(HEADER + size, HEADER),
+    );
+}
+
+const MAX_AT_EXIT: usize = 32;
+
+static AT_EXIT: Mutex<([Option<extern "C" fn()>; MAX_AT_EXIT], usize)> =
+    Mutex::new(([None; MAX_AT_EXIT], 0));
+
+#[no_mangle]
+pub extern "C" fn atexit(f: extern "C" fn()) -> c_int {
+    let mut at_exit = AT_EXIT.lock();
+    let (handlers, len) = &mut *at_exit;
+    if *len == MAX_AT_EXIT {
+        return fail(ENOMEM, -1);
+    }
+    handlers[*len] = Some(f);
+    *len += 1;
+    0
+}
+
+/// Run the `atexit` handlers in reverse order, then exit.
+#[no_mangle]
+pub extern "C" fn exit(code: c_int) -> ! {
+    loop {
+        let handler = {
+            let mut at_exit = AT_EXIT.lock();
+            let (handlers, len) = &mut *at_exit;
+            if *len == 0 {
+                break;
+            }
+            *len -= 1;
+            handlers[*len].take()
+        };
+        if let Some(f) = handler {
+            f();
+        }
+    }
+    _Exit(code)
+}
+
+#[no_mangle]
+pub extern "C" fn _Exit(code: c_int) -> ! {
+    user::sys::exit(code as isize)
+}
+
+/// Exit with the status of a process killed by `SIGABRT`. There is no such signal yet.
+#[no_mangle]
+pub extern "C" fn abort() -> ! {
+    _Exit(128 + 6)
+}
+
+#[no_mangle]
+pub extern "C" fn abs(n: c_int) -> c_int {
+    n.wrapping_abs()
+}
+
+#[no_mangle]
+pub extern "C" fn labs(n: c_long) -> c_long {
+    n.wrapping_abs()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn atoi(s: *const c_char) -> c_int {
+    strtol(s, ptr::null_mut(), 10) as c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn atol(s: *const c_char) -> c_long {
+    strtol(s, ptr::null_mut(), 10)
+}
+
+/// Parse the magnitude and sign of a number. Returns the magnitude, whether it is negative,
+/// the end of the number and whether it overflowed, or `None` if there are no digits.
+unsafe fn parse_number(s: *const c_char, base: c_int) -> Option<(u64, bool, *const c_char, bool)> {
+    if base != 0 && !(2..=36).contains(&base) {
+        return None;
+    }
+    let mut p = s;
+    while isspace(*p as c_int) != 0 {
+        p = p.add(1);
+    }
+    let negative = *p as u8 == b'-';
+    if matches!(*p as u8, b'+' | b'-') {
+        p = p.add(1);
+    }
+    let hex_prefix = *p as u8 == b'0' && matches!(*p.add(1) as u8, b'x' | b'X');
+    let base = match base {
+        0 if hex_prefix => 16,
+        0 if *p as u8 == b'0' => 8,
+        0 => 10,
+        b => b as u32,
+    };
+    if base == 16 && hex_prefix && (*p.add(2) as u8 as char).is_digit(16) {
+        p = p.add(2);
+    }
+    let start = p;
+    let mut value: u64 = 0;
+    let mut overflow = false;
+    while let Some(digit) = (*p as u8 as char).to_digit(base) {
+        match value
+            .checked_mul(base as u64)
+            .and_then(|v| v.checked_add(digit as u64))
+        {
+            Some(v) => value = v,
+            None => overflow = true,
+        }
+        p = p.add(1);
+    }
+    if p == start {
+        return None;
+    }
+    Some((value, negative, p, overflow))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strtol(s: *const c_char, end: *mut *mut c_char, base: c_int) -> c_long {
+    let Some((value, negative, p, overflow)) = parse_number(s, base) else {
+        if !end.is_null() {
+            *end = s as _;
+        }
+        return fail(EINVAL, 0);
+    };
+    if !end.is_null() {
+        *end = p as _;
+    }
+    let limit = if negative {
+        c_long::MIN.unsigned_abs()
+    } else {
+        c_long::MAX as u64
+    };
+    if overflow || value > limit {
+        return fail(ERANGE, if negative { c_long::MIN } else { c_long::MAX });
+    }
+    if negative {
+        (value as c_long).wrapping_neg()
+    } else {
+        value as c_long
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strtoul(s: *const c_char, end: *mut *mut c_char, base: c_int) -> c_ulong {
+    let Some((value, negative, p, overflow)) = parse_number(s, base) else {
+        if !end.is_null() {
+            *end = s as _;
+        }
+        return fail(EINVAL, 0);
+    };
+    if !end.is_null() {
+        *end = p as _;
+    }
+    if overflow {
+        return fail(ERANGE, c_ulong::MAX);
+    }
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+/// Sort with insertion sort. Fine for the small arrays of the programs ported so far.
+#[no_mangle]
+pub unsafe extern "C" fn qsort(
+    base: *mut c_void,
+    n: usize,
+    size: usize,
+    compare: extern "C" fn(*const c_void, *const c_void) -> c_int,
+) {
+    let base = base as *mut u8;
+    for i in 1..n {
+        let mut j = i;
+        while j > 0 && compare(base.add((j - 1) * size) as _, base.add(j * size) as _) > 0 {
+            ptr::swap_nonoverlapping(base.add((j - 1) * size), base.add(j * size), size);
+            j -= 1;
+        }
+    }
+}
+
+/// C strings returned by `getenv`. A pointer is valid until the variable is changed.
+static ENV_STRINGS: Mutex<BTreeMap<String, CString>> = Mutex::new(BTreeMap::new());
+
+#[no_mangle]
+pub unsafe extern "C" fn getenv(name: *const c_char) -> *mut c_char {
+    let name = crate::to_str(name);
+    let Some(value) = user::env::var(&name) else {
+        return ptr::null_mut();
+    };
+    let name = name.into_owned();
+    let mut strings = ENV_STRINGS.lock();
+    if strings.get(&name).map(|s| s.to_bytes()) != Some(value.as_bytes()) {
+        let Ok(value) = CString::new(value) else {
+            return ptr::null_mut();
+        };
+        strings.insert(name.clone(), value);
+    }
+    strings[&name].as_ptr() as _
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn setenv(
+    name: *const c_char,
+    value: *const c_char,
+    overwrite: c_int,
+) -> c_int {
+    let name = crate::to_str(name);
+    if name.is_empty() || name.contains('=') {
+        return fail(EINVAL, -1);
+    }
+    if overwrite == 0 && user::env::var(&name).is_some() {
+        return 0;
+    }
+    user::env::set_var(&name, &crate::to_str(value));
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn unsetenv(name: *const c_char) -> c_int {
+    let name = crate::to_str(name);
+    user::env::remove_var(&name);
+    ENV_STRINGS.lock().remove(&*name);
+    0
+}
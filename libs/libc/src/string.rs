@@ -0,0 +1,132 @@
+use core::ffi::{c_char, c_int, c_void, CStr};
+use core::ptr;
+
+extern "C" {
+    fn strlen(s: *const c_char) -> usize;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memchr(s: *const c_void, c: c_int, n: usize) -> *mut c_void {
+    let bytes = core::slice::from_raw_parts(s as *const u8, n);
+    match bytes.iter().position(|b| *b == c as u8) {
+        Some(i) => s.add(i) as _,
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strcmp(a: *const c_char, b: *const c_char) -> c_int {
+    strncmp(a, b, usize::MAX)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strncmp(a: *const c_char, b: *const c_char, n: usize) -> c_int {
+    for i in 0..n {
+        let (x, y) = (*a.add(i) as u8, *b.add(i) as u8);
+        if x != y || x == 0 {
+            return x as c_int - y as c_int;
+        }
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strcpy(dst: *mut c_char, src: *const c_char) -> *mut c_char {
+    ptr::copy_nonoverlapping(src, dst, strlen(src) + 1);
+    dst
+}
+
+/// Copy at most `n` bytes, and pad `dst` with zeros up to `n` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn strncpy(dst: *mut c_char, src: *const c_char, n: usize) -> *mut c_char {
+    let len = usize::min(strnlen(src, n), n);
+    ptr::copy_nonoverlapping(src, dst, len);
+    ptr::write_bytes(dst.add(len), 0, n - len);
+    dst
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strnlen(s: *const c_char, n: usize) -> usize {
+    (0..n).find(|i| *s.add(*i) == 0).unwrap_or(n)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strcat(dst: *mut c_char, src: *const c_char) -> *mut c_char {
+    strcpy(dst.add(strlen(dst)), src);
+    dst
+}
+
+/// Append at most `n` bytes, plus the terminating zero.
+#[no_mangle]
+pub unsafe extern "C" fn strncat(dst: *mut c_char, src: *const c_char, n: usize) -> *mut c_char {
+    let end = dst.add(strlen(dst));
+    let len = strnlen(src, n);
+    ptr::copy_nonoverlapping(src, end, len);
+    *end.add(len) = 0;
+    dst
+}
+
+/// Find `c` in `s`. The terminating zero can be found.
+#[no_mangle]
+pub unsafe extern "C" fn strchr(s: *const c_char, c: c_int) -> *mut c_char {
+    memchr(s as _, c, strlen(s) + 1) as _
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strrchr(s: *const c_char, c: c_int) -> *mut c_char {
+    let bytes = CStr::from_ptr(s).to_bytes_with_nul();
+    match bytes.iter().rposition(|b| *b == c as u8) {
+        Some(i) => s.add(i) as _,
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strstr(haystack: *const c_char, needle: *const c_char) -> *mut c_char {
+    let h = CStr::from_ptr(haystack).to_bytes();
+    let n = CStr::from_ptr(needle).to_bytes();
+    if n.is_empty() {
+        return haystack as _;
+    }
+    match h.windows(n.len()).position(|w| w == n) {
+        Some(i) => haystack.add(i) as _,
+        None => ptr::null_mut(),
+    }
+}
+
+/// The length of the prefix of `s` made of bytes in `accept`.
+#[no_mangle]
+pub unsafe extern "C" fn strspn(s: *const c_char, accept: *const c_char) -> usize {
+    let accept = CStr::from_ptr(accept).to_bytes();
+    let s = CStr::from_ptr(s).to_bytes();
+    s.iter().take_while(|b| accept.contains(b)).count()
+}
+
+/// The length of the prefix of `s` made of bytes not in `reject`.
+#[no_mangle]
+pub unsafe extern "C" fn strcspn(s: *const c_char, reject: *const c_char) -> usize {
+    let reject = CStr::from_ptr(reject).to_bytes();
+    let s = CStr::from_ptr(s).to_bytes();
+    s.iter().take_while(|b| !reject.contains(b)).count()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strdup(s: *const c_char) -> *mut c_char {
+    strndup(s, usize::MAX)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strndup(s: *const c_char, n: usize) -> *mut c_char {
+    let len = strnlen(s, n);
+    let copy = crate::stdlib::malloc(len + 1) as *mut c_char;
+    if !copy.is_null() {
+        ptr::copy_nonoverlapping(s, copy, len);
+        *copy.add(len) = 0;
+    }
+    copy
+}
+
+#[no_mangle]
+pub extern "C" fn strerror(error: c_int) -> *const c_char {
+    crate::errno::message(error).as_ptr()
+}
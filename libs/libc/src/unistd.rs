@@ -0,0 +1,170 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_int, c_long, c_uint, c_void, CStr};
+use core::ptr;
+use core::time::Duration;
+use user::sys::{Fd, Signal};
+
+use crate::errno::{fail, EAGAIN, EBADF, EINVAL, EIO, ENOENT, ERANGE};
+
+#[no_mangle]
+pub unsafe extern "C" fn read(fd: c_int, buf: *mut c_void, len: usize) -> c_long {
+    let buf = core::slice::from_raw_parts_mut(buf as *mut u8, len);
+    match user::sys::read(Fd(fd as u32), buf) {
+        Ok(len) => len as c_long,
+        Err(()) => fail(EBADF, -1),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn write(fd: c_int, buf: *const c_void, len: usize) -> c_long {
+    let buf = core::slice::from_raw_parts(buf as *const u8, len);
+    match user::sys::write(Fd(fd as u32), buf) {
+        Ok(len) => len as c_long,
+        Err(()) => fail(EBADF, -1),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn close(fd: c_int) -> c_int {
+    user::sys::close(Fd(fd as u32));
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn dup2(old: c_int, new: c_int) -> c_int {
+    match user::sys::dup2(Fd(old as u32), Fd(new as u32)) {
+        Ok(()) => new,
+        Err(()) => fail(EBADF, -1),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn isatty(fd: c_int) -> c_int {
+    match termios::tcgetattr(Fd(fd as u32)) {
+        Ok(_) => 1,
+        Err(()) => fail(EINVAL, 0),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn unlink(path: *const c_char) -> c_int {
+    match user::sys::unlink(&crate::to_str(path)) {
+        Ok(()) => 0,
+        Err(()) => fail(ENOENT, -1),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rmdir(path: *const c_char) -> c_int {
+    unlink(path)
+}
+
+/// Copy the working directory into `buf`. If `buf` is null, a buffer is allocated with `malloc`.
+#[no_mangle]
+pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
+    let Ok(cwd) = user::sys::cwd() else {
+        return fail(EIO, ptr::null_mut());
+    };
+    let buf = if buf.is_null() {
+        let buf = crate::stdlib::malloc(cwd.len() + 1) as *mut c_char;
+        if buf.is_null() {
+            return buf;
+        }
+        buf
+    } else if size < cwd.len() + 1 {
+        return fail(ERANGE, ptr::null_mut());
+    } else {
+        buf
+    };
+    ptr::copy_nonoverlapping(cwd.as_ptr() as *const c_char, buf, cwd.len());
+    *buf.add(cwd.len()) = 0;
+    buf
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chdir(path: *const c_char) -> c_int {
+    match user::sys::chdir(&crate::to_str(path)) {
+        Ok(()) => 0,
+        Err(()) => fail(ENOENT, -1),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn fork() -> c_int {
+    match user::sys::fork() {
+        pid if pid < 0 => fail(EAGAIN, -1),
+        pid => pid as c_int,
+    }
+}
+
+/// Run a program. `argv[0]` is replaced with the program path, and `envp` is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn execve(
+    path: *const c_char,
+    argv: *const *const c_char,
+    _envp: *const *const c_char,
+) -> c_int {
+    let path = crate::to_str(path);
+    let mut args: Vec<String> = Vec::new();
+    if !argv.is_null() && !(*argv).is_null() {
+        let mut arg = argv.add(1);
+        while !(*arg).is_null() {
+            args.push(crate::to_str(*arg).into_owned());
+            arg = arg.add(1);
+        }
+    }
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    user::sys::exec(&path, &args);
+    fail(ENOENT, -1)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execv(path: *const c_char, argv: *const *const c_char) -> c_int {
+    execve(path, argv, ptr::null())
+}
+
+/// Same as `execv`. Names without a `/` are looked up in `/bin`.
+#[no_mangle]
+pub unsafe extern "C" fn execvp(file: *const c_char, argv: *const *const c_char) -> c_int {
+    if CStr::from_ptr(file).to_bytes().contains(&b'/') {
+        return execv(file, argv);
+    }
+    let mut path = Vec::from(&b"/bin/"[..]);
+    path.extend_from_slice(CStr::from_ptr(file).to_bytes_with_nul());
+    execv(path.as_ptr() as _, argv)
+}
+
+#[no_mangle]
+pub extern "C" fn _exit(code: c_int) -> ! {
+    crate::stdlib::_Exit(code)
+}
+
+#[no_mangle]
+pub extern "C" fn getpid() -> c_int {
+    user::sys::getpid() as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn sleep(seconds: c_uint) -> c_uint {
+    user::sys::sleep(Duration::from_secs(seconds as u64));
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn usleep(usec: c_uint) -> c_int {
+    user::sys::sleep(Duration::from_micros(usec as u64));
+    0
+}
+
+/// Send a signal. A negative `pid` sends it to a process group.
+#[no_mangle]
+pub extern "C" fn kill(pid: c_int, signal: c_int) -> c_int {
+    let Some(signal) = Signal::from_usize(signal as usize) else {
+        return fail(EINVAL, -1);
+    };
+    match user::sys::kill(pid as isize, signal) {
+        0 => 0,
+        _ => fail(ENOENT, -1),
+    }
+}
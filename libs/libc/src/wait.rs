@@ -0,0 +1,55 @@
+//! `waitpid` and `wait`. The status is encoded like on Linux, with the exit code in bits 8..16.
+
+use core::ffi::c_int;
+
+use crate::errno::{fail, ECHILD, EINTR};
+
+pub const WNOHANG: c_int = 1;
+pub const WUNTRACED: c_int = 2;
+
+/// The status of a process stopped by `SIGSTOP`.
+const STOPPED: c_int = 0x7f | (19 << 8);
+
+fn exited(code: isize) -> c_int {
+    ((code as c_int) & 0xff) << 8
+}
+
+/// Wait for a child. `pid` is a process id, or `-1` for any child.
+#[no_mangle]
+pub unsafe extern "C" fn waitpid(pid: c_int, status: *mut c_int, options: c_int) -> c_int {
+    let mut flags = 0;
+    if options & WNOHANG != 0 {
+        flags |= user::sys::WNOHANG;
+    }
+    if options & WUNTRACED == 0 {
+        // Sleep in the kernel while the child is stopped.
+        flags |= user::sys::WEXITED;
+    }
+    let mut exit_code = 0;
+    let (pid, code) = if pid == -1 {
+        match user::sys::wait(&mut exit_code, flags & user::sys::WNOHANG) {
+            -1 => return fail(ECHILD, -1),
+            -2 => return fail(EINTR, -1),
+            pid => (pid as c_int, exited(exit_code)),
+        }
+    } else if pid > 0 {
+        match user::sys::waitpid_with_options(pid as usize, &mut exit_code, flags) {
+            0 => (pid, exited(exit_code)),
+            1 => (pid, STOPPED),
+            2 => return 0,
+            _ => return fail(ECHILD, -1),
+        }
+    } else {
+        // Process groups are not supported.
+        return fail(ECHILD, -1);
+    };
+    if pid > 0 && !status.is_null() {
+        *status = code;
+    }
+    pid
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wait(status: *mut c_int) -> c_int {
+    waitpid(-1, status, 0)
+}
@@ -20,6 +20,8 @@ pub mod cache;
 pub mod free_list_allocator;
 pub mod page;
 pub mod page_table;
+pub mod slab_allocator;
+pub mod user_heap;
 pub mod volatile;
 
 pub fn sbrk(size: usize) -> Option<Address> {
@@ -69,8 +69,11 @@ impl PageFlags {
     pub fn user_code_flags_4k() -> PageFlagSet {
         Self::kernel_code_flags_4k() | PageFlags::USER
     }
+    pub fn user_data_flags_2m() -> PageFlagSet {
+        Self::kernel_data_flags_2m() | PageFlags::USER | PageFlags::NO_EXEC
+    }
     pub fn user_data_flags_4k() -> PageFlagSet {
-        Self::kernel_data_flags_4k() | PageFlags::USER
+        Self::kernel_data_flags_4k() | PageFlags::USER | PageFlags::NO_EXEC
     }
     pub fn user_stack_flags() -> PageFlagSet {
         PageFlags::NORMAL_MEMORY
@@ -79,6 +82,23 @@ impl PageFlags {
             | PageFlags::OUTER_SHARE
             | PageFlags::ACCESSED
             | PageFlags::USER
+            | PageFlags::NO_EXEC
+    }
+    /// Flags of a user page with the given permissions.
+    /// W^X: A page is never both writable and executable.
+    pub fn user_flags_4k(writable: bool, executable: bool) -> PageFlagSet {
+        assert!(
+            !(writable && executable),
+            "W^X: page is writable and executable"
+        );
+        let mut flags = Self::user_code_flags_4k();
+        if !writable {
+            flags |= PageFlags::NO_WRITE;
+        }
+        if !executable {
+            flags |= PageFlags::NO_EXEC;
+        }
+        flags
     }
     pub fn device() -> PageFlagSet {
         PageFlags::DEVICE_MEMORY
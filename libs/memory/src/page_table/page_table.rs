@@ -88,31 +88,36 @@ impl<L: TableLevel> PageTable<L> {
         unsafe {
             frame.zero();
             let new_table = frame.start().as_mut::<PageTable<L>>();
-            for (i, entry) in self.entries.iter().enumerate() {
-                new_table.entries[i] = entry.clone();
-                if L::ID == 4 && i >= 480 {
-                    // This are kernel page tables, don't duplicate them
-                    continue;
-                }
-                if L::ID != L1::ID
-                    && entry.present()
-                    && !entry.is_block()
-                    && entry.flags().contains(PageFlags::USER)
-                {
-                    let next_table = entry.address().as_mut::<PageTable<L::NextLevel>>();
-                    let next_table_cloned = next_table.clone(pa);
-                    new_table.entries[i].set::<Size4K>(
-                        Frame::new(Address::from(
-                            next_table_cloned as *mut PageTable<L::NextLevel> as usize,
-                        )),
-                        entry.flags(),
-                    );
-                }
-            }
+            self.clone_into(new_table, pa);
             new_table
         }
     }
 
+    /// Copy the entries to `new_table`, with copies of the user page tables.
+    pub fn clone_into(&self, new_table: &mut Self, pa: &impl PageAllocator<P>) {
+        for (i, entry) in self.entries.iter().enumerate() {
+            new_table.entries[i] = entry.clone();
+            if L::ID == 4 && i >= 480 {
+                // This are kernel page tables, don't duplicate them
+                continue;
+            }
+            if L::ID != L1::ID
+                && entry.present()
+                && !entry.is_block()
+                && entry.flags().contains(PageFlags::USER)
+            {
+                let next_table = unsafe { entry.address().as_mut::<PageTable<L::NextLevel>>() };
+                let next_table_cloned = next_table.clone(pa);
+                new_table.entries[i].set::<Size4K>(
+                    Frame::new(Address::from(
+                        next_table_cloned as *mut PageTable<L::NextLevel> as usize,
+                    )),
+                    entry.flags(),
+                );
+            }
+        }
+    }
+
     pub fn release(&mut self, pa: &impl PageAllocator<P>) {
         for (i, entry) in self.entries.iter_mut().enumerate() {
             if L::ID == L4::ID && i >= 480 {
@@ -367,7 +372,7 @@ impl PageTable<L4> {
                     return;
                 }
                 // Release P1
-                pa.dealloc::<S>(Page::new(p1.into()));
+                pa.dealloc::<Size4K>(Frame::new(p1.into()));
             }
             // Clear P2 entry
             p2[p2_index].clear();
@@ -375,7 +380,7 @@ impl PageTable<L4> {
                 return;
             }
             // Release P2
-            pa.dealloc::<S>(Page::new(p2.into()));
+            pa.dealloc::<Size4K>(Frame::new(p2.into()));
         }
         // Clear P3 entry
         p3[p3_index].clear();
@@ -383,19 +388,8 @@ impl PageTable<L4> {
             return;
         }
         // Release P3
-        pa.dealloc::<S>(Page::new(p3.into()));
+        pa.dealloc::<Size4K>(Frame::new(p3.into()));
         // Clear P4 entry
         p4[p4_index].clear();
     }
-
-    pub fn copy_on_write<S: PageSize>(&mut self, page: Page<S>, pa: &impl PageAllocator<P>) {
-        let (a, mut flags, _) = self.translate_with_flags(page.start()).unwrap();
-        flags = flags - PageFlags::NO_WRITE;
-        flags = flags - PageFlags::COPY_ON_WRITE;
-        let b = pa.alloc::<S>().unwrap();
-        unsafe {
-            core::ptr::copy_nonoverlapping::<u8>(a.as_ptr(), b.start().as_mut_ptr(), S::BYTES);
-        }
-        self.map(page, b, flags, pa);
-    }
 }
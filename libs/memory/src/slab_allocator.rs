@@ -0,0 +1,292 @@
+//! Slab allocator for small objects, with per-core magazines.
+//!
+//! Objects are grouped into power-of-two size classes. Each size class has a depot: a free list
+//! of objects carved out of slabs, behind a lock. Each core has a magazine per size class:
+//! a small stack of free objects that is refilled from, and flushed to, the depot in batches.
+//! Most allocations and frees only touch the magazine of the current core.
+//!
+//! Slabs are never returned to the page resource.
+
+use crate::address::{Address, MemoryKind};
+use crate::page::*;
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, Once};
+
+const LOG_MIN_SIZE: usize = 4;
+const LOG_MAX_SIZE: usize = 11;
+/// Number of size classes: 16, 32, ..., 2048 bytes.
+pub const SIZE_CLASSES: usize = LOG_MAX_SIZE - LOG_MIN_SIZE + 1;
+/// Largest object size served by the slab allocator.
+pub const MAX_SIZE: usize = 1 << LOG_MAX_SIZE;
+const MAGAZINE_SIZE: usize = 32;
+/// Number of objects moved between a magazine and the depot at a time.
+const BATCH: usize = MAGAZINE_SIZE / 2;
+const SLAB_PAGES: usize = 4;
+
+/// Allocation statistics of one size class.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SlabStats {
+    /// Object size in bytes.
+    pub size: usize,
+    pub allocs: usize,
+    pub frees: usize,
+    /// Number of slabs acquired so far.
+    pub slabs: usize,
+    /// Number of magazine refills and flushes. Each one takes the size class lock.
+    pub depot_transfers: usize,
+}
+
+struct Magazine<K: MemoryKind> {
+    len: usize,
+    objects: [Address<K>; MAGAZINE_SIZE],
+}
+
+/// Per-core state. Only accessed by its own core, with interrupts disabled.
+struct CoreCache<K: MemoryKind> {
+    magazines: UnsafeCell<[Magazine<K>; SIZE_CLASSES]>,
+    /// Written only by the owning core, but may be read by any core.
+    allocs: [AtomicUsize; SIZE_CLASSES],
+    frees: [AtomicUsize; SIZE_CLASSES],
+}
+
+/// A free list of objects, linked through their first word.
+struct Depot<K: MemoryKind> {
+    head: Address<K>,
+}
+
+struct SizeClass<K: MemoryKind> {
+    depot: Mutex<Depot<K>>,
+    slabs: AtomicUsize,
+    depot_transfers: AtomicUsize,
+    /// Allocations and frees that bypass the magazines.
+    allocs: AtomicUsize,
+    frees: AtomicUsize,
+}
+
+impl<K: MemoryKind> Magazine<K> {
+    const NEW: Self = Self {
+        len: 0,
+        objects: [Address::ZERO; MAGAZINE_SIZE],
+    };
+}
+
+impl<K: MemoryKind> CoreCache<K> {
+    const fn new() -> Self {
+        Self {
+            magazines: UnsafeCell::new([Magazine::NEW; SIZE_CLASSES]),
+            allocs: [const { AtomicUsize::new(0) }; SIZE_CLASSES],
+            frees: [const { AtomicUsize::new(0) }; SIZE_CLASSES],
+        }
+    }
+}
+
+impl<K: MemoryKind> SizeClass<K> {
+    const fn new() -> Self {
+        Self {
+            depot: Mutex::new(Depot {
+                head: Address::ZERO,
+            }),
+            slabs: AtomicUsize::new(0),
+            depot_transfers: AtomicUsize::new(0),
+            allocs: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+        }
+    }
+}
+
+struct Config<PA: 'static> {
+    page_resource: &'static PA,
+    current_core: fn() -> usize,
+}
+
+pub struct SlabAllocator<K: MemoryKind, PA: PageResource<K> + 'static, const CORES: usize> {
+    config: Once<Config<PA>>,
+    classes: [SizeClass<K>; SIZE_CLASSES],
+    cores: [CoreCache<K>; CORES],
+}
+
+unsafe impl<K: MemoryKind, PA: PageResource<K> + 'static, const CORES: usize> Sync
+    for SlabAllocator<K, PA, CORES>
+{
+}
+
+impl<K: MemoryKind, PA: PageResource<K> + 'static, const CORES: usize> Default
+    for SlabAllocator<K, PA, CORES>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: MemoryKind, PA: PageResource<K> + 'static, const CORES: usize> SlabAllocator<K, PA, CORES> {
+    pub const fn new() -> Self {
+        Self {
+            config: Once::new(),
+            classes: [const { SizeClass::new() }; SIZE_CLASSES],
+            cores: [const { CoreCache::new() }; CORES],
+        }
+    }
+
+    /// `current_core` returns the index of the running core. Cores with an index of `CORES`
+    /// or above, or that are not numbered yet, use the depot directly.
+    pub fn init(&self, page_resource: &'static PA, current_core: fn() -> usize) {
+        self.config.call_once(|| Config {
+            page_resource,
+            current_core,
+        });
+    }
+
+    /// The size class of a layout, or `None` if it is too large for the slab allocator.
+    const fn size_class(layout: &Layout) -> Option<usize> {
+        let size = if layout.size() > layout.align() {
+            layout.size()
+        } else {
+            layout.align()
+        };
+        let log_size = size.next_power_of_two().trailing_zeros() as usize;
+        if log_size > LOG_MAX_SIZE {
+            None
+        } else if log_size < LOG_MIN_SIZE {
+            Some(0)
+        } else {
+            Some(log_size - LOG_MIN_SIZE)
+        }
+    }
+
+    /// Can `layout` be allocated by the slab allocator?
+    pub const fn fits(layout: &Layout) -> bool {
+        Self::size_class(layout).is_some()
+    }
+
+    fn current_core_cache(&self) -> Option<&CoreCache<K>> {
+        self.cores.get((self.config.get()?.current_core)())
+    }
+
+    /// Allocate an object. Returns `None` if the layout is too large, or out of memory.
+    #[inline]
+    pub fn alloc(&self, layout: &Layout) -> Option<Address<K>> {
+        let class = Self::size_class(layout)?;
+        let _guard = interrupt::uninterruptible();
+        let Some(cache) = self.current_core_cache() else {
+            self.classes[class].allocs.fetch_add(1, Ordering::Relaxed);
+            return self.depot_pop(class);
+        };
+        let magazine = unsafe { &mut (*cache.magazines.get())[class] };
+        if magazine.len == 0 {
+            self.refill(class, magazine);
+            if magazine.len == 0 {
+                return None;
+            }
+        }
+        magazine.len -= 1;
+        bump(&cache.allocs[class]);
+        Some(magazine.objects[magazine.len])
+    }
+
+    /// Free an object allocated with the same layout.
+    #[inline]
+    pub fn free(&self, object: Address<K>, layout: &Layout) {
+        let class = Self::size_class(layout).unwrap();
+        let _guard = interrupt::uninterruptible();
+        let Some(cache) = self.current_core_cache() else {
+            let size_class = &self.classes[class];
+            size_class.frees.fetch_add(1, Ordering::Relaxed);
+            let mut depot = size_class.depot.lock();
+            unsafe { object.store(depot.head) };
+            depot.head = object;
+            return;
+        };
+        let magazine = unsafe { &mut (*cache.magazines.get())[class] };
+        if magazine.len == MAGAZINE_SIZE {
+            self.flush(class, magazine);
+        }
+        magazine.objects[magazine.len] = object;
+        magazine.len += 1;
+        bump(&cache.frees[class]);
+    }
+
+    fn depot_pop(&self, class: usize) -> Option<Address<K>> {
+        let mut depot = self.classes[class].depot.lock();
+        if depot.head.is_zero() {
+            self.grow(class, &mut depot)?;
+        }
+        let object = depot.head;
+        depot.head = unsafe { object.load() };
+        Some(object)
+    }
+
+    /// Move up to `BATCH` objects from the depot to an empty magazine.
+    #[cold]
+    fn refill(&self, class: usize, magazine: &mut Magazine<K>) {
+        let size_class = &self.classes[class];
+        size_class.depot_transfers.fetch_add(1, Ordering::Relaxed);
+        let mut depot = size_class.depot.lock();
+        if depot.head.is_zero() && self.grow(class, &mut depot).is_none() {
+            return;
+        }
+        while magazine.len < BATCH && !depot.head.is_zero() {
+            let object = depot.head;
+            depot.head = unsafe { object.load() };
+            magazine.objects[magazine.len] = object;
+            magazine.len += 1;
+        }
+    }
+
+    /// Move `BATCH` objects from a full magazine to the depot.
+    #[cold]
+    fn flush(&self, class: usize, magazine: &mut Magazine<K>) {
+        let size_class = &self.classes[class];
+        size_class.depot_transfers.fetch_add(1, Ordering::Relaxed);
+        let mut depot = size_class.depot.lock();
+        for _ in 0..BATCH {
+            magazine.len -= 1;
+            let object = magazine.objects[magazine.len];
+            unsafe { object.store(depot.head) };
+            depot.head = object;
+        }
+    }
+
+    /// Carve a new slab into the depot.
+    fn grow(&self, class: usize, depot: &mut Depot<K>) -> Option<()> {
+        let page_resource = self.config.get()?.page_resource;
+        let pages = page_resource.acquire_pages::<Size4K>(SLAB_PAGES)?;
+        self.classes[class].slabs.fetch_add(1, Ordering::Relaxed);
+        let size = 1 << (class + LOG_MIN_SIZE);
+        let start = pages.start.start();
+        let mut cursor = pages.end.start();
+        while cursor > start {
+            cursor -= size;
+            unsafe { cursor.store(depot.head) };
+            depot.head = cursor;
+        }
+        Some(())
+    }
+
+    pub fn stats(&self) -> [SlabStats; SIZE_CLASSES] {
+        core::array::from_fn(|class| {
+            let size_class = &self.classes[class];
+            let sum = |counters: fn(&CoreCache<K>) -> &[AtomicUsize; SIZE_CLASSES]| {
+                self.cores
+                    .iter()
+                    .map(|cache| counters(cache)[class].load(Ordering::Relaxed))
+                    .sum::<usize>()
+            };
+            SlabStats {
+                size: 1 << (class + LOG_MIN_SIZE),
+                allocs: size_class.allocs.load(Ordering::Relaxed) + sum(|c| &c.allocs),
+                frees: size_class.frees.load(Ordering::Relaxed) + sum(|c| &c.frees),
+                slabs: size_class.slabs.load(Ordering::Relaxed),
+                depot_transfers: size_class.depot_transfers.load(Ordering::Relaxed),
+            }
+        })
+    }
+}
+
+/// Increment a per-core counter. It has a single writer, so this does not need
+/// an atomic read-modify-write.
+#[inline(always)]
+fn bump(counter: &AtomicUsize) {
+    counter.store(counter.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+}
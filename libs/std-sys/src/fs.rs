@@ -0,0 +1,264 @@
+//! Files and directories, over the VFS.
+
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::{c_char, CStr};
+use core::mem::size_of;
+
+use hermit_abi::{
+    dirent64, errno, iovec, stat, DT_DIR, DT_REG, O_APPEND, O_CREAT, O_DIRECTORY, O_EXCL, O_TRUNC,
+    S_IFDIR, S_IFREG,
+};
+use spin::Mutex;
+use vfs::{Fd, FileStat};
+
+/// The files opened by `sys_open`.
+static FILES: Mutex<BTreeMap<i32, OpenFile>> = Mutex::new(BTreeMap::new());
+
+struct OpenFile {
+    /// The path it was opened with, for `fstat` and the entries of a directory.
+    path: String,
+    /// Are the directory entries already read?
+    listed: bool,
+}
+
+unsafe fn path<'a>(name: *const c_char) -> Result<&'a str, i32> {
+    CStr::from_ptr(name).to_str().map_err(|_| -errno::EINVAL)
+}
+
+fn to_stat(s: FileStat) -> stat {
+    stat {
+        st_nlink: 1,
+        st_mode: if s.is_dir {
+            S_IFDIR | 0o755
+        } else {
+            S_IFREG | 0o644
+        },
+        st_size: s.size as u64,
+        st_blksize: 4096,
+        st_blocks: s.size.div_ceil(512) as i64,
+        ..Default::default()
+    }
+}
+
+fn open(path: &str, flags: i32) -> Result<Fd, i32> {
+    let existing = vfs::stat(path).ok();
+    match existing {
+        Some(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => return Err(-errno::EEXIST),
+        None if flags & O_CREAT == 0 => return Err(-errno::ENOENT),
+        Some(s) if flags & O_DIRECTORY != 0 && !s.is_dir => return Err(-errno::ENOTDIR),
+        _ => {}
+    }
+    let fd = if existing.is_none() || flags & O_TRUNC != 0 {
+        vfs::create(path)
+    } else {
+        vfs::open(path)
+    };
+    let fd = fd.ok_or(-errno::EACCES)?;
+    if flags & O_APPEND != 0 {
+        // There is no `lseek`. Read to the end instead, to move the offset there.
+        let mut buf = [0u8; 512];
+        while let Ok(1..) = vfs::read(fd, &mut buf) {}
+    }
+    Ok(fd)
+}
+
+/// Open a file. The permission bits in `mode` are ignored.
+#[no_mangle]
+pub unsafe extern "C" fn sys_open(name: *const c_char, flags: i32, _mode: i32) -> i32 {
+    let path = match path(name) {
+        Ok(path) => path,
+        Err(e) => return e,
+    };
+    match open(path, flags) {
+        Ok(fd) => {
+            let file = OpenFile {
+                path: path.to_owned(),
+                listed: false,
+            };
+            FILES.lock().insert(fd.0 as i32, file);
+            fd.0 as i32
+        }
+        Err(e) => e,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_close(fd: i32) -> i32 {
+    FILES.lock().remove(&fd);
+    vfs::close(Fd(fd as u32));
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_read(fd: i32, buf: *mut u8, len: usize) -> isize {
+    let buf = core::slice::from_raw_parts_mut(buf, len);
+    vfs::read(Fd(fd as u32), buf).map_or(-errno::EIO as isize, |n| n as isize)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_write(fd: i32, buf: *const u8, len: usize) -> isize {
+    let buf = core::slice::from_raw_parts(buf, len);
+    vfs::write(Fd(fd as u32), buf).map_or(-errno::EIO as isize, |n| n as isize)
+}
+
+/// Read into each buffer in turn, until a read comes up short.
+#[no_mangle]
+pub unsafe extern "C" fn sys_readv(fd: i32, iov: *const iovec, iovcnt: usize) -> isize {
+    let mut total = 0;
+    for iov in core::slice::from_raw_parts(iov, iovcnt) {
+        let n = sys_read(fd, iov.iov_base as *mut u8, iov.iov_len);
+        if n < 0 {
+            return if total == 0 { n } else { total };
+        }
+        total += n;
+        if (n as usize) < iov.iov_len {
+            break;
+        }
+    }
+    total
+}
+
+/// Write each buffer in turn, until a write comes up short.
+#[no_mangle]
+pub unsafe extern "C" fn sys_writev(fd: i32, iov: *const iovec, iovcnt: usize) -> isize {
+    let mut total = 0;
+    for iov in core::slice::from_raw_parts(iov, iovcnt) {
+        let n = sys_write(fd, iov.iov_base as *const u8, iov.iov_len);
+        if n < 0 {
+            return if total == 0 { n } else { total };
+        }
+        total += n;
+        if (n as usize) < iov.iov_len {
+            break;
+        }
+    }
+    total
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_stat(name: *const c_char, out: *mut stat) -> i32 {
+    let path = match path(name) {
+        Ok(path) => path,
+        Err(e) => return e,
+    };
+    match vfs::stat(path) {
+        Ok(s) => {
+            *out = to_stat(s);
+            0
+        }
+        Err(_) => -errno::ENOENT,
+    }
+}
+
+/// Same as `sys_stat`. Symbolic links are always followed.
+#[no_mangle]
+pub unsafe extern "C" fn sys_lstat(name: *const c_char, out: *mut stat) -> i32 {
+    sys_stat(name, out)
+}
+
+/// Only works for the files opened by `sys_open`. The file is looked up by its path again.
+#[no_mangle]
+pub unsafe extern "C" fn sys_fstat(fd: i32, out: *mut stat) -> i32 {
+    let Some(path) = FILES.lock().get(&fd).map(|f| f.path.clone()) else {
+        return -errno::EBADF;
+    };
+    match vfs::stat(&path) {
+        Ok(s) => {
+            *out = to_stat(s);
+            0
+        }
+        Err(_) => -errno::ENOENT,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_mkdir(name: *const c_char, _mode: u32) -> i32 {
+    let path = match path(name) {
+        Ok(path) => path,
+        Err(e) => return e,
+    };
+    if vfs::stat(path).is_ok() {
+        return -errno::EEXIST;
+    }
+    vfs::mkdir(path).map_or(-errno::ENOENT, |_| 0)
+}
+
+fn remove(name: *const c_char, dir: bool) -> i32 {
+    let path = match unsafe { path(name) } {
+        Ok(path) => path,
+        Err(e) => return e,
+    };
+    match vfs::stat(path) {
+        Err(_) => -errno::ENOENT,
+        Ok(s) if s.is_dir && !dir => -errno::EISDIR,
+        Ok(s) if !s.is_dir && dir => -errno::ENOTDIR,
+        // Only an empty directory can be removed.
+        Ok(_) if dir => vfs::unlink(path).map_or(-errno::ENOTEMPTY, |_| 0),
+        Ok(_) => vfs::unlink(path).map_or(-errno::EACCES, |_| 0),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_unlink(name: *const c_char) -> i32 {
+    remove(name, false)
+}
+
+#[no_mangle]
+pub extern "C" fn sys_rmdir(name: *const c_char) -> i32 {
+    remove(name, true)
+}
+
+/// Read all the entries of a directory, including `.` and `..`, into `dirp`.
+///
+/// Fails with `EINVAL` if they do not all fit, as std then retries with a larger buffer.
+/// Returns `0` once all the entries are read.
+#[no_mangle]
+pub unsafe extern "C" fn sys_getdents64(fd: i32, dirp: *mut dirent64, count: usize) -> i64 {
+    let mut files = FILES.lock();
+    let Some(file) = files.get_mut(&fd) else {
+        return -errno::EBADF as i64;
+    };
+    if file.listed {
+        return 0;
+    }
+    let mut names: Vec<String> = Vec::from([".".to_owned(), "..".to_owned()]);
+    for i in 0.. {
+        match vfs::readdir(Fd(fd as u32), i) {
+            Ok(Some(name)) => names.push(name),
+            Ok(None) => break,
+            Err(_) => return -errno::ENOTDIR as i64,
+        }
+    }
+    // Each entry takes a whole `dirent64`, so that std can read it as one.
+    let size = size_of::<dirent64>();
+    if names.len() * size > count {
+        return -errno::EINVAL as i64;
+    }
+    for (i, name) in names.iter().enumerate() {
+        let is_dir = match name.as_str() {
+            "." | ".." => true,
+            _ => vfs::stat(&format!("{}/{}", file.path, name)).is_ok_and(|s| s.is_dir),
+        };
+        let mut entry = dirent64 {
+            d_ino: i as u64 + 1,
+            d_off: ((i + 1) * size) as i64,
+            d_reclen: size as u16,
+            d_type: if is_dir { DT_DIR } else { DT_REG },
+            d_name: [0; 256],
+        };
+        let len = name.len().min(entry.d_name.len() - 1);
+        for (c, b) in entry.d_name.iter_mut().zip(&name.as_bytes()[..len]) {
+            *c = *b as c_char;
+        }
+        dirp.cast::<u8>()
+            .add(i * size)
+            .cast::<dirent64>()
+            .write(entry);
+    }
+    file.listed = true;
+    (names.len() * size) as i64
+}
@@ -0,0 +1,157 @@
+//! The system layer of the Rust standard library for Sophon.
+//!
+//! Programs that use `std` are built for `sophon/aarch64-unknown-sophon.json`, with `-Zbuild-std`.
+//! Its `os` is `hermit`, so std is built with its Hermit backend. That backend calls the C
+//! functions of the Hermit ABI (`sys_open`, `sys_spawn2`, ...), which this crate implements
+//! over Sophon syscalls and the VFS. A program links it in with `use std_sys as _;`.
+//!
+//! Errors are returned as negative `errno` values, as the Hermit ABI expects.
+//! Sophon syscalls do not tell why they failed, so the values are the most likely cause.
+
+#![no_std]
+// The functions are the Hermit ABI, only called by std, which upholds their contracts.
+#![allow(clippy::missing_safety_doc)]
+
+extern crate alloc;
+
+mod fs;
+mod thread;
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::time::Duration;
+
+use hermit_abi::{errno, timespec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+use memory::user_heap::UserHeap;
+
+/// The heap behind std's `System` allocator.
+static HEAP: UserHeap = UserHeap::new();
+
+extern "C" {
+    /// The entry point of std. Runs `main`, then exits the process.
+    fn runtime_entry(argc: i32, argv: *const *const u8, env: *const *const u8) -> !;
+}
+
+#[no_mangle]
+pub extern "C" fn _start(argc: isize, argv: *const *const u8) -> ! {
+    // There are no environment variables.
+    unsafe { runtime_entry(argc as i32, argv, ptr::null()) }
+}
+
+fn layout(size: usize, align: usize) -> Option<Layout> {
+    Layout::from_size_align(size, align).ok()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_malloc(size: usize, align: usize) -> *mut u8 {
+    layout(size, align).map_or(ptr::null_mut(), |layout| HEAP.alloc(layout))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_alloc(size: usize, align: usize) -> *mut u8 {
+    sys_malloc(size, align)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_alloc_zeroed(size: usize, align: usize) -> *mut u8 {
+    layout(size, align).map_or(ptr::null_mut(), |layout| HEAP.alloc_zeroed(layout))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_realloc(
+    ptr: *mut u8,
+    size: usize,
+    align: usize,
+    new_size: usize,
+) -> *mut u8 {
+    layout(size, align).map_or(ptr::null_mut(), |layout| {
+        HEAP.realloc(ptr, layout, new_size)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_free(ptr: *mut u8, size: usize, align: usize) {
+    if let Some(layout) = layout(size, align) {
+        HEAP.dealloc(ptr, layout)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_dealloc(ptr: *mut u8, size: usize, align: usize) {
+    sys_free(ptr, size, align)
+}
+
+/// There is no real-time clock. Both clocks count from boot.
+#[no_mangle]
+pub unsafe extern "C" fn sys_clock_gettime(clock: i32, tp: *mut timespec) -> i32 {
+    match clock {
+        CLOCK_MONOTONIC | CLOCK_REALTIME => {
+            let now = syscall::uptime();
+            *tp = timespec {
+                tv_sec: now.as_secs() as i64,
+                tv_nsec: now.subsec_nanos() as i32,
+            };
+            0
+        }
+        _ => -errno::EINVAL,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_usleep(usecs: u64) {
+    syscall::sleep(Duration::from_micros(usecs))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sys_nanosleep(req: *const timespec) -> i32 {
+    let req = &*req;
+    if req.tv_sec < 0 || !(0..1_000_000_000).contains(&req.tv_nsec) {
+        return -errno::EINVAL;
+    }
+    syscall::sleep(Duration::new(req.tv_sec as u64, req.tv_nsec as u32));
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn sys_getpid() -> i32 {
+    syscall::getpid() as i32
+}
+
+#[no_mangle]
+pub extern "C" fn sys_exit(status: i32) -> ! {
+    syscall::exit(status as isize)
+}
+
+/// Exit with the status of a process killed by `SIGABRT`, as a shell reports it.
+#[no_mangle]
+pub extern "C" fn sys_abort() -> ! {
+    syscall::exit(128 + 6)
+}
+
+#[no_mangle]
+pub extern "C" fn sys_available_parallelism() -> usize {
+    syscall::num_cores()
+}
+
+#[no_mangle]
+pub extern "C" fn sys_get_processor_count() -> usize {
+    syscall::num_cores()
+}
+
+/// Random bytes for the keys of `HashMap`. Not for cryptography.
+#[no_mangle]
+pub unsafe extern "C" fn sys_read_entropy(buf: *mut u8, len: usize, _flags: u32) -> isize {
+    syscall::get_random(core::slice::from_raw_parts_mut(buf, len));
+    len as isize
+}
+
+/// Errors are returned by the functions themselves, never through `errno`.
+#[no_mangle]
+pub extern "C" fn sys_errno() -> i32 {
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn sys_get_errno() -> i32 {
+    0
+}
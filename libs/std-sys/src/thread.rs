@@ -0,0 +1,105 @@
+//! Threads and futexes.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
+
+use hermit_abi::{errno, timespec, Tid, FUTEX_RELATIVE_TIMEOUT};
+use spin::Mutex;
+
+use crate::HEAP;
+
+/// Threads get at least this much stack.
+const MIN_STACK_SIZE: usize = 64 << 10;
+
+/// The stacks of the threads that are not joined yet.
+/// The stack of a thread that is never joined is leaked, as it may still be running.
+static STACKS: Mutex<BTreeMap<Tid, (usize, Layout)>> = Mutex::new(BTreeMap::new());
+
+struct Start {
+    func: extern "C" fn(usize),
+    arg: usize,
+}
+
+extern "C" fn thread_start(start: usize) -> ! {
+    let start = unsafe { Box::from_raw(start as *mut Start) };
+    (start.func)(start.arg);
+    syscall::thread_exit()
+}
+
+/// Start a thread running `func(arg)`. Returns the thread id, or `0` on failure.
+/// Priorities and core affinity are not supported.
+#[no_mangle]
+pub unsafe extern "C" fn sys_spawn2(
+    func: extern "C" fn(usize),
+    arg: usize,
+    _prio: u8,
+    stack_size: usize,
+    _core_id: isize,
+) -> Tid {
+    let Ok(layout) = Layout::from_size_align(stack_size.max(MIN_STACK_SIZE), 16) else {
+        return 0;
+    };
+    let stack = HEAP.alloc(layout);
+    if stack.is_null() {
+        return 0;
+    }
+    let start = Box::into_raw(Box::new(Start { func, arg }));
+    let tid = syscall::spawn_thread(thread_start, start as usize, stack.add(layout.size()));
+    if tid <= 0 {
+        drop(Box::from_raw(start));
+        HEAP.dealloc(stack, layout);
+        return 0;
+    }
+    let tid = tid as Tid;
+    STACKS.lock().insert(tid, (stack as usize, layout));
+    tid
+}
+
+/// Wait for a thread to exit, and free its stack.
+#[no_mangle]
+pub unsafe extern "C" fn sys_join(tid: Tid) -> i32 {
+    let Some((stack, layout)) = STACKS.lock().remove(&tid) else {
+        return -errno::ESRCH;
+    };
+    syscall::join_thread(tid as usize);
+    HEAP.dealloc(stack as *mut u8, layout);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn sys_yield() {
+    syscall::_yield()
+}
+
+/// Block while `*address == expected`, until `sys_futex_wake` or the timeout.
+#[no_mangle]
+pub unsafe extern "C" fn sys_futex_wait(
+    address: *mut u32,
+    expected: u32,
+    timeout: *const timespec,
+    flags: u32,
+) -> i32 {
+    let timeout = timeout.as_ref().map(|t| {
+        let duration = Duration::new(t.tv_sec as u64, t.tv_nsec as u32);
+        if flags & FUTEX_RELATIVE_TIMEOUT != 0 {
+            duration
+        } else {
+            duration.saturating_sub(syscall::uptime())
+        }
+    });
+    match syscall::futex_wait(AtomicU32::from_ptr(address), expected, timeout) {
+        0 => 0,
+        _ => -errno::ETIMEDOUT,
+    }
+}
+
+/// Wake up to `count` waiters, or all of them if `count` is negative.
+/// Returns the number woken up.
+#[no_mangle]
+pub unsafe extern "C" fn sys_futex_wake(address: *mut u32, count: i32) -> i32 {
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    syscall::futex_wake(AtomicU32::from_ptr(address), count) as i32
+}
@@ -0,0 +1,133 @@
+//! System information returned by syscalls.
+
+/// Decode a null-padded string field.
+fn str_field(buf: &[u8]) -> &str {
+    let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+    core::str::from_utf8(&buf[..len]).unwrap_or("?")
+}
+
+/// Copy a string into a null-padded field. Long strings are truncated.
+pub fn set_str_field(buf: &mut [u8], s: &str) {
+    let len = usize::min(s.len(), buf.len() - 1);
+    buf[..len].copy_from_slice(&s.as_bytes()[..len]);
+    buf[len..].fill(0);
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcState {
+    /// Running or ready to run.
+    Running,
+    /// All the threads are blocked.
+    Sleeping,
+    /// Stopped by a signal.
+    Stopped,
+    /// Exited, but not reaped yet.
+    Zombie,
+}
+
+/// Information about a process. See `list_procs`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ProcInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    pub pgid: usize,
+    pub threads: usize,
+    /// CPU time of all the threads, in nanoseconds.
+    pub cpu_time: u64,
+    /// User memory mapped, in bytes.
+    pub memory: usize,
+    pub state: ProcState,
+    pub name: [u8; 64],
+}
+
+impl ProcInfo {
+    pub const EMPTY: Self = Self {
+        pid: 0,
+        ppid: 0,
+        pgid: 0,
+        threads: 0,
+        cpu_time: 0,
+        memory: 0,
+        state: ProcState::Running,
+        name: [0; 64],
+    };
+
+    pub fn name(&self) -> &str {
+        str_field(&self.name)
+    }
+}
+
+/// Physical memory usage, in bytes.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemInfo {
+    pub total: usize,
+    pub free: usize,
+}
+
+/// Physical memory usage of a memory zone, in 4 KiB frames. See `zone_info`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZoneInfo {
+    pub name: [u8; 16],
+    pub total: usize,
+    pub free: usize,
+    pub used: usize,
+}
+
+impl ZoneInfo {
+    pub fn name(&self) -> &str {
+        str_field(&self.name)
+    }
+}
+
+/// Allocation statistics of a kernel slab size class. See `slab_info`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SlabInfo {
+    /// Object size in bytes.
+    pub size: usize,
+    pub allocs: usize,
+    pub frees: usize,
+    /// Number of slabs. Slabs are never released.
+    pub slabs: usize,
+    /// Number of batch transfers between per-core caches and the shared free list.
+    pub depot_transfers: usize,
+}
+
+/// System identification. See `uname`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UtsName {
+    pub sysname: [u8; 32],
+    pub nodename: [u8; 32],
+    pub release: [u8; 32],
+    pub machine: [u8; 32],
+}
+
+impl UtsName {
+    pub const EMPTY: Self = Self {
+        sysname: [0; 32],
+        nodename: [0; 32],
+        release: [0; 32],
+        machine: [0; 32],
+    };
+
+    pub fn sysname(&self) -> &str {
+        str_field(&self.sysname)
+    }
+
+    pub fn release(&self) -> &str {
+        str_field(&self.release)
+    }
+
+    pub fn nodename(&self) -> &str {
+        str_field(&self.nodename)
+    }
+
+    pub fn machine(&self) -> &str {
+        str_field(&self.machine)
+    }
+}
@@ -0,0 +1,103 @@
+//! Records of the kernel log, as copied out by `read_kernel_log`.
+//!
+//! Each record is a header, followed by the module name and the message. Fields are
+//! little-endian, and records are packed without padding.
+
+pub use log::{Level, LevelFilter};
+
+/// Size of an encoded `Header`.
+pub const HEADER_SIZE: usize = 26;
+/// Longer messages are truncated in the log buffer.
+pub const MAX_MESSAGE_LEN: usize = 1024;
+/// Longer module names are truncated in the log buffer.
+pub const MAX_MODULE_LEN: usize = u8::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Counts all the records since boot. Gaps are records dropped from the log buffer.
+    pub seq: u64,
+    /// Nanoseconds since boot.
+    pub timestamp: u64,
+    /// The process running on the core, if any.
+    pub pid: Option<u32>,
+    pub core: u16,
+    pub level: Level,
+    pub module_len: u8,
+    pub message_len: u16,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0; HEADER_SIZE];
+        buf[0..8].copy_from_slice(&self.seq.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.timestamp.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.pid.unwrap_or(u32::MAX).to_le_bytes());
+        buf[20..22].copy_from_slice(&self.core.to_le_bytes());
+        buf[22] = self.level as u8;
+        buf[23] = self.module_len;
+        buf[24..26].copy_from_slice(&self.message_len.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf = buf.get(..HEADER_SIZE)?;
+        let pid = u32::from_le_bytes(buf[16..20].try_into().unwrap());
+        Some(Self {
+            seq: u64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            timestamp: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            pid: (pid != u32::MAX).then_some(pid),
+            core: u16::from_le_bytes(buf[20..22].try_into().unwrap()),
+            level: level_from_u8(buf[22])?,
+            module_len: buf[23],
+            message_len: u16::from_le_bytes(buf[24..26].try_into().unwrap()),
+        })
+    }
+
+    /// Size of the encoded record.
+    pub fn record_len(&self) -> usize {
+        HEADER_SIZE + self.module_len as usize + self.message_len as usize
+    }
+}
+
+pub struct Record<'a> {
+    pub header: Header,
+    pub module: &'a str,
+    pub message: &'a str,
+}
+
+/// The records in a buffer filled by `read_kernel_log`.
+pub fn records(mut buf: &[u8]) -> impl Iterator<Item = Record<'_>> {
+    core::iter::from_fn(move || {
+        let header = Header::decode(buf)?;
+        let record = buf.get(..header.record_len())?;
+        let (module, message) = record[HEADER_SIZE..].split_at(header.module_len as usize);
+        buf = &buf[header.record_len()..];
+        Some(Record {
+            header,
+            module: core::str::from_utf8(module).unwrap_or("?"),
+            message: core::str::from_utf8(message).unwrap_or("?"),
+        })
+    })
+}
+
+/// `Level` from its value, 1 (error) to 5 (trace).
+pub fn level_from_u8(level: u8) -> Option<Level> {
+    Level::iter().nth((level as usize).checked_sub(1)?)
+}
+
+/// `LevelFilter` from its value, 0 (off) to 5 (trace).
+pub fn level_filter_from_usize(level: usize) -> Option<LevelFilter> {
+    LevelFilter::iter().nth(level)
+}
+
+/// Truncate to at most `len` bytes, at a character boundary.
+pub fn truncate(s: &str, len: usize) -> &str {
+    if s.len() <= len {
+        return s;
+    }
+    let mut end = len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
@@ -4,14 +4,18 @@
 
 extern crate alloc;
 
+mod info;
+pub mod klog;
 #[macro_use]
 mod log;
 pub mod module_calls;
 mod syscall;
+pub mod trace;
 
 use core::marker::PhantomData;
 
 pub use crate::log::UserLogger;
+pub use info::*;
 pub use syscall::*;
 
 pub trait Payload {
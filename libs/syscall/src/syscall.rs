@@ -1,8 +1,10 @@
 #[allow(unused)]
 use core::arch::asm;
 use core::intrinsics::transmute;
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
 
-use crate::ModuleRequest;
+use crate::{MemInfo, ModuleRequest, ProcInfo, SlabInfo, UtsName, ZoneInfo};
 
 #[repr(usize)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -21,6 +23,142 @@ pub enum Syscall {
     Exit,
     ThreadExit,
     Halt,
+    /// Add to the nice value of the current process
+    Nice,
+    /// Set the nice value of a process
+    SetPriority,
+    /// Set the real-time priority of a process
+    SetRtPriority,
+    /// Time since boot
+    Uptime,
+    /// CPU time of the current thread
+    CpuTime,
+    /// Number of cores
+    NumCores,
+    /// Time a core has spent idle
+    IdleTime,
+    /// Send a signal to a process or a process group
+    Kill,
+    /// Set the process group of a process
+    SetPgid,
+    /// Get the process group of a process
+    GetPgid,
+    /// List the processes
+    ListProcs,
+    /// Physical memory usage
+    MemInfo,
+    /// System identification
+    Uname,
+    /// Block the current thread for some time
+    Sleep,
+    /// List the loaded kernel modules
+    ListModules,
+    /// Read the kernel log
+    ReadKernelLog,
+    /// Wait for any child process to exit
+    Wait,
+    /// Take the pending signals of the current process
+    TakeSignals,
+    /// Power off or reboot the machine
+    Power,
+    /// Start a thread in the current process
+    SpawnThread,
+    /// Wait for a thread of the current process to exit
+    JoinThread,
+    /// Get the id of the current process
+    GetPid,
+    /// Kernel slab allocator statistics
+    SlabInfo,
+    /// Print the live kernel heap allocations to the kernel log
+    HeapDump,
+    /// Physical memory usage of each memory zone
+    ZoneInfo,
+    /// Set the memory limit of a process
+    SetMemLimit,
+    /// Set the log level of a kernel module
+    SetLogLevel,
+    /// Fill a buffer with random bytes
+    GetRandom,
+    /// Block on a futex
+    FutexWait,
+    /// Wake up the tasks blocked on a futex
+    FutexWake,
+}
+
+/// Signals. The values follow the Linux numbering.
+#[repr(usize)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Signal {
+    /// Interrupt from the terminal (Ctrl-C). Terminates the process.
+    Interrupt = 2,
+    /// Undefined or privileged instruction. Terminates the process.
+    IllegalInstruction = 4,
+    /// Breakpoint or single step. Terminates the process.
+    Trap = 5,
+    /// Misaligned access, or an asynchronous bus error. Terminates the process.
+    BusError = 7,
+    /// Floating-point exception. Terminates the process.
+    FloatingPoint = 8,
+    /// Terminates the process. Cannot be blocked.
+    Kill = 9,
+    /// User-defined signal 1. Terminates the process.
+    User1 = 10,
+    /// Invalid memory access, or a stack overflow. Terminates the process.
+    SegmentationFault = 11,
+    /// User-defined signal 2. Terminates the process.
+    User2 = 12,
+    /// Terminates the process.
+    Terminate = 15,
+    /// Continue a stopped process.
+    Continue = 18,
+    /// Stop the process. Cannot be blocked.
+    Stop = 19,
+    /// Stop from the terminal (Ctrl-Z).
+    TerminalStop = 20,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 13] = [
+        Signal::Interrupt,
+        Signal::IllegalInstruction,
+        Signal::Trap,
+        Signal::BusError,
+        Signal::FloatingPoint,
+        Signal::Kill,
+        Signal::User1,
+        Signal::SegmentationFault,
+        Signal::User2,
+        Signal::Terminate,
+        Signal::Continue,
+        Signal::Stop,
+        Signal::TerminalStop,
+    ];
+
+    pub fn from_usize(signal: usize) -> Option<Self> {
+        Self::ALL.iter().find(|s| **s as usize == signal).copied()
+    }
+
+    /// Does this signal terminate the process?
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Signal::Interrupt
+                | Signal::IllegalInstruction
+                | Signal::Trap
+                | Signal::BusError
+                | Signal::FloatingPoint
+                | Signal::Kill
+                | Signal::User1
+                | Signal::SegmentationFault
+                | Signal::User2
+                | Signal::Terminate
+        )
+    }
+
+    /// Does this signal stop the process?
+    pub fn is_stop(&self) -> bool {
+        matches!(self, Signal::Stop | Signal::TerminalStop)
+    }
 }
 
 #[inline]
@@ -65,9 +203,26 @@ pub fn module_call<'a>(module: &str, request: &'a impl ModuleRequest<'a>) -> isi
     }
 }
 
+/// `waitpid` option: Return immediately if the child has neither exited nor stopped.
+pub const WNOHANG: usize = 1;
+/// `waitpid` option: Wait for the child to exit, and not return while it is stopped.
+pub const WEXITED: usize = 2;
+
+/// Wait for a child to exit or stop.
+/// Returns `0` if it has exited, and `1` if it is stopped. `exit_code` is only set on exit.
 #[inline]
 pub fn waitpid(pid: usize, exit_code: &mut isize) -> isize {
-    syscall(Syscall::WaitPid, &[pid, exit_code as *mut isize as usize])
+    waitpid_with_options(pid, exit_code, 0)
+}
+
+/// Same as `waitpid`. With `WNOHANG`, returns `2` if the child is still running.
+/// With `WEXITED`, a stopped child counts as running.
+#[inline]
+pub fn waitpid_with_options(pid: usize, exit_code: &mut isize, options: usize) -> isize {
+    syscall(
+        Syscall::WaitPid,
+        &[pid, exit_code as *mut isize as usize, options],
+    )
 }
 
 #[inline]
@@ -83,8 +238,8 @@ pub fn exec(path: &str, args: &[&str]) -> isize {
 }
 
 #[inline]
-pub fn exit() -> ! {
-    syscall(Syscall::Exit, &[]);
+pub fn exit(code: isize) -> ! {
+    syscall(Syscall::Exit, &[code as usize]);
     unreachable!()
 }
 
@@ -104,3 +259,245 @@ pub fn halt(code: usize) -> ! {
 pub fn _yield() {
     syscall(Syscall::Yield, &[]);
 }
+
+/// Add `inc` to the nice value of the current process. Returns the new nice value.
+/// Only init can go below `0`.
+#[inline]
+pub fn nice(inc: isize) -> isize {
+    syscall(Syscall::Nice, &[inc as usize])
+}
+
+/// Set the nice value (`-20..=19`) of the current process or one of its descendants.
+/// `pid = 0` means the current process. Only init can lower a nice value below `0`.
+#[inline]
+pub fn setpriority(pid: usize, nice: isize) -> isize {
+    syscall(Syscall::SetPriority, &[pid, nice as usize])
+}
+
+/// Set the real-time priority (`1..=99`) of the current process or one of its descendants.
+///
+/// `pid = 0` means the current process. Priority `0` turns it back to a normal process.
+/// Only init can raise a real-time priority.
+#[inline]
+pub fn set_rt_priority(pid: usize, priority: usize) -> isize {
+    syscall(Syscall::SetRtPriority, &[pid, priority])
+}
+
+#[inline]
+pub fn uptime() -> Duration {
+    Duration::from_nanos(syscall(Syscall::Uptime, &[]) as u64)
+}
+
+#[inline]
+pub fn cpu_time() -> Duration {
+    Duration::from_nanos(syscall(Syscall::CpuTime, &[]) as u64)
+}
+
+#[inline]
+pub fn num_cores() -> usize {
+    syscall(Syscall::NumCores, &[]) as usize
+}
+
+/// Time the given core has spent in its idle task. Returns `None` if the core does not exist.
+#[inline]
+pub fn idle_time(core: usize) -> Option<Duration> {
+    let ns = syscall(Syscall::IdleTime, &[core]);
+    if ns < 0 {
+        None
+    } else {
+        Some(Duration::from_nanos(ns as u64))
+    }
+}
+
+/// Send a signal. `pid > 0` targets a process, and `pid < 0` targets the process group `-pid`.
+#[inline]
+pub fn kill(pid: isize, signal: Signal) -> isize {
+    syscall(Syscall::Kill, &[pid as usize, signal as usize])
+}
+
+/// Move a process to a process group. `pid = 0` means the current process,
+/// and `pgid = 0` means a new group with the same id as the process.
+#[inline]
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(Syscall::SetPgid, &[pid, pgid])
+}
+
+/// Get the process group of a process. `pid = 0` means the current process.
+#[inline]
+pub fn getpgid(pid: usize) -> isize {
+    syscall(Syscall::GetPgid, &[pid])
+}
+
+/// Fill `procs` with the running processes. Returns the number of processes,
+/// which may be larger than `procs.len()`.
+#[inline]
+pub fn list_procs(procs: &mut [ProcInfo]) -> usize {
+    syscall(
+        Syscall::ListProcs,
+        &[procs.as_mut_ptr() as usize, procs.len()],
+    ) as usize
+}
+
+#[inline]
+pub fn meminfo() -> MemInfo {
+    let mut info = MemInfo::default();
+    syscall(Syscall::MemInfo, &[&mut info as *mut MemInfo as usize]);
+    info
+}
+
+#[inline]
+pub fn uname() -> UtsName {
+    let mut uts = UtsName::EMPTY;
+    syscall(Syscall::Uname, &[&mut uts as *mut UtsName as usize]);
+    uts
+}
+
+/// Block the current thread for at least `duration`.
+/// Returns early if the process receives a terminating signal.
+#[inline]
+pub fn sleep(duration: Duration) {
+    syscall(Syscall::Sleep, &[duration.as_nanos() as usize]);
+}
+
+/// Write the names of the loaded kernel modules to `buf`, one per line.
+/// Returns the number of bytes written.
+#[inline]
+pub fn list_modules(buf: &mut [u8]) -> usize {
+    syscall(
+        Syscall::ListModules,
+        &[buf.as_mut_ptr() as usize, buf.len()],
+    ) as usize
+}
+
+/// Copy the kernel log records from sequence number `seq` on to `buf`, oldest first.
+///
+/// Returns the number of bytes written. Only whole records are copied, see `klog::records`.
+/// The kernel keeps only the latest records, so the first one may be after `seq`.
+#[inline]
+pub fn read_kernel_log(seq: u64, buf: &mut [u8]) -> usize {
+    syscall(
+        Syscall::ReadKernelLog,
+        &[seq as usize, buf.as_mut_ptr() as usize, buf.len()],
+    ) as usize
+}
+
+/// Log only the messages of `module` up to `level`.
+///
+/// `module` matches itself and its submodules, e.g. `sophon::task` or a kernel module like
+/// `vfs`. An empty `module` sets the default level. Returns `-1` for a bad level.
+#[inline]
+pub fn set_log_level(module: &str, level: log::LevelFilter) -> isize {
+    syscall(
+        Syscall::SetLogLevel,
+        &[&module as *const &str as usize, level as usize],
+    )
+}
+
+/// Wait for any child process to exit, and reap it.
+///
+/// Returns the pid of the child, `0` if `WNOHANG` is set and no child has exited,
+/// `-1` if there are no children, or `-2` if a signal arrived while waiting.
+#[inline]
+pub fn wait(exit_code: &mut isize, options: usize) -> isize {
+    syscall(Syscall::Wait, &[exit_code as *mut isize as usize, options])
+}
+
+/// Take and clear the pending signals of the current process. Returns a bit set indexed by signal number.
+///
+/// Only useful for init. Other processes act on their signals before returning to user space.
+#[inline]
+pub fn take_signals() -> u32 {
+    syscall(Syscall::TakeSignals, &[]) as u32
+}
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    PowerOff = 0,
+    Reboot = 1,
+}
+
+/// Power off or reboot the machine. Only init is allowed to do this.
+/// Returns `-1` on failure.
+#[inline]
+pub fn power(action: PowerAction) -> isize {
+    syscall(Syscall::Power, &[action as usize])
+}
+
+/// Start a thread in the current process, running `entry(arg)` on the stack ending at `stack_top`.
+/// The thread must end with `thread_exit`. Returns the thread id, or `-1` on failure.
+#[inline]
+pub fn spawn_thread(entry: extern "C" fn(usize) -> !, arg: usize, stack_top: *mut u8) -> isize {
+    syscall(
+        Syscall::SpawnThread,
+        &[entry as usize, arg, stack_top as usize],
+    )
+}
+
+/// Wait for a thread of the current process to exit.
+#[inline]
+pub fn join_thread(tid: usize) -> isize {
+    syscall(Syscall::JoinThread, &[tid])
+}
+
+#[inline]
+pub fn getpid() -> usize {
+    syscall(Syscall::GetPid, &[]) as usize
+}
+
+/// Fill `info` with the statistics of the kernel slab size classes.
+/// Returns the number of size classes, which may be larger than `info.len()`.
+#[inline]
+pub fn slab_info(info: &mut [SlabInfo]) -> usize {
+    syscall(Syscall::SlabInfo, &[info.as_mut_ptr() as usize, info.len()]) as usize
+}
+
+/// Print the live kernel heap allocations, grouped by call site or module, to the kernel log.
+/// Returns `-1` if the kernel is not built with the `heap_debug` feature.
+#[inline]
+pub fn heap_dump() -> isize {
+    syscall(Syscall::HeapDump, &[])
+}
+
+/// Fill `info` with the physical memory usage of each memory zone.
+/// Returns the number of zones, which may be larger than `info.len()`.
+#[inline]
+pub fn zone_info(info: &mut [ZoneInfo]) -> usize {
+    syscall(Syscall::ZoneInfo, &[info.as_mut_ptr() as usize, info.len()]) as usize
+}
+
+/// Limit the user memory of a process (`0` for the current process) to `bytes`.
+///
+/// `usize::MAX` removes the limit. The limit is inherited by `fork`, and kept by `exec`.
+/// `sbrk` and `exec` fail once the process would exceed it.
+#[inline]
+pub fn set_mem_limit(pid: usize, bytes: usize) -> isize {
+    syscall(Syscall::SetMemLimit, &[pid, bytes])
+}
+
+/// Fill `buf` with random bytes from the kernel entropy pool. Not for cryptography.
+#[inline]
+pub fn get_random(buf: &mut [u8]) {
+    syscall(Syscall::GetRandom, &[buf.as_mut_ptr() as usize, buf.len()]);
+}
+
+/// Block while `futex` holds `expected`, until `futex_wake` or the timeout.
+///
+/// Returns `0` if woken up, or if the value is not `expected`, and `-1` on timeout.
+/// May return early, so the caller checks the value again.
+#[inline]
+pub fn futex_wait(futex: &AtomicU32, expected: u32, timeout: Option<Duration>) -> isize {
+    let timeout = timeout.map_or(usize::MAX, |t| {
+        t.as_nanos().min(usize::MAX as u128 - 1) as usize
+    });
+    syscall(
+        Syscall::FutexWait,
+        &[futex.as_ptr() as usize, expected as usize, timeout],
+    )
+}
+
+/// Wake up to `count` threads blocked on `futex` in `futex_wait`. Returns the number woken up.
+#[inline]
+pub fn futex_wake(futex: &AtomicU32, count: usize) -> isize {
+    syscall(Syscall::FutexWake, &[futex.as_ptr() as usize, count])
+}
@@ -0,0 +1,165 @@
+//! Kernel traces, as read from `/dev/trace` and decoded by `cargo dev trace`.
+//!
+//! A trace is a `TraceHeader`, the names of the kernel modules (a length byte each), then the
+//! events of all cores, each core in order. Fields are little-endian.
+
+use alloc::vec::Vec;
+
+pub const MAGIC: [u8; 8] = *b"SOPHTRC1";
+/// Size of an encoded `TraceHeader`.
+pub const HEADER_SIZE: usize = 32;
+/// Size of an encoded `Event`.
+pub const EVENT_SIZE: usize = 32;
+
+// `ioctl` commands of `/dev/trace`.
+pub const TRACE_START: usize = 1;
+pub const TRACE_STOP: usize = 2;
+/// Drop all the recorded events.
+pub const TRACE_CLEAR: usize = 3;
+/// Print the trace on the console, hex encoded on lines starting with `CONSOLE_PREFIX`.
+pub const TRACE_PRINT: usize = 4;
+
+pub const CONSOLE_PREFIX: &str = "#trace ";
+/// The line after the last one of a trace printed on the console.
+pub const CONSOLE_END: &str = "#trace end";
+
+/// Tracepoints.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// `handle_syscall`. The argument is the syscall number.
+    Syscall,
+    /// `raw_module_call`. The argument is the module id.
+    ModuleCall,
+    /// A context switch in `Scheduler::schedule`. The argument is the next task.
+    Schedule,
+    /// Synchronous exceptions. The argument is the exception class.
+    Exception,
+    /// The argument is the IRQ.
+    Interrupt,
+}
+
+impl Kind {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        [
+            Self::Syscall,
+            Self::ModuleCall,
+            Self::Schedule,
+            Self::Exception,
+            Self::Interrupt,
+        ]
+        .get(kind as usize)
+        .copied()
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Begin,
+    End,
+    Instant,
+}
+
+impl Phase {
+    pub fn from_u8(phase: u8) -> Option<Self> {
+        [Self::Begin, Self::End, Self::Instant]
+            .get(phase as usize)
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceHeader {
+    pub num_cores: u32,
+    pub num_modules: u32,
+    pub num_events: u64,
+    /// Events overwritten before the trace was read.
+    pub dropped: u64,
+}
+
+impl TraceHeader {
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0; HEADER_SIZE];
+        buf[0..8].copy_from_slice(&MAGIC);
+        buf[8..12].copy_from_slice(&self.num_cores.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.num_modules.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.num_events.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.dropped.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf = buf.get(..HEADER_SIZE)?;
+        if buf[0..8] != MAGIC {
+            return None;
+        }
+        Some(Self {
+            num_cores: u32::from_le_bytes(buf[8..12].try_into().unwrap()),
+            num_modules: u32::from_le_bytes(buf[12..16].try_into().unwrap()),
+            num_events: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+            dropped: u64::from_le_bytes(buf[24..32].try_into().unwrap()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// Nanoseconds since boot. Comparable across cores.
+    pub time: u64,
+    /// Cycles of the core. Only comparable with events of the same core.
+    pub cycles: u64,
+    pub arg: u64,
+    /// The running task, if any.
+    pub task: Option<u32>,
+    pub core: u16,
+    pub kind: Kind,
+    pub phase: Phase,
+}
+
+impl Event {
+    pub fn encode(&self) -> [u8; EVENT_SIZE] {
+        let mut buf = [0; EVENT_SIZE];
+        buf[0..8].copy_from_slice(&self.time.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.cycles.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.arg.to_le_bytes());
+        buf[24..28].copy_from_slice(&self.task.unwrap_or(u32::MAX).to_le_bytes());
+        buf[28..30].copy_from_slice(&self.core.to_le_bytes());
+        buf[30] = self.kind as u8;
+        buf[31] = self.phase as u8;
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf = buf.get(..EVENT_SIZE)?;
+        let task = u32::from_le_bytes(buf[24..28].try_into().unwrap());
+        Some(Self {
+            time: u64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            cycles: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            arg: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+            task: (task != u32::MAX).then_some(task),
+            core: u16::from_le_bytes(buf[28..30].try_into().unwrap()),
+            kind: Kind::from_u8(buf[30])?,
+            phase: Phase::from_u8(buf[31])?,
+        })
+    }
+}
+
+/// Split a trace into its header, the module names, and the events.
+pub fn parse(buf: &[u8]) -> Option<(TraceHeader, Vec<&str>, Vec<Event>)> {
+    let header = TraceHeader::decode(buf)?;
+    let mut rest = &buf[HEADER_SIZE..];
+    let mut modules = Vec::new();
+    for _ in 0..header.num_modules {
+        let (len, name) = rest.split_first()?;
+        let name = name.get(..*len as usize)?;
+        modules.push(core::str::from_utf8(name).ok()?);
+        rest = &rest[1 + *len as usize..];
+    }
+    let events = rest
+        .chunks_exact(EVENT_SIZE)
+        .take(header.num_events as usize)
+        .map(Event::decode)
+        .collect::<Option<Vec<_>>>()?;
+    Some((header, modules, events))
+}
@@ -0,0 +1,132 @@
+use crate::*;
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use dev::Device;
+use interrupt::UninterruptibleMutex;
+use kernel_module::monitor::SysMonitor;
+use kernel_module::SERVICE;
+use klib::proc::PID;
+use spin::{Mutex, Once};
+
+/// A raw serial port, driven by a TTY.
+pub trait SerialPort: Send + Sync {
+    /// Transmit a byte. May spin until the port is ready.
+    fn write_byte(&self, byte: u8);
+}
+
+/// A terminal device on top of a serial port.
+///
+/// The driver feeds the received bytes to [`Tty::receive`], usually from its IRQ handler.
+/// Readers block until the line discipline has input for them.
+pub struct Tty {
+    name: &'static str,
+    port: &'static dyn SerialPort,
+    /// Shared with the IRQ handler, so it is a spin lock, always taken with interrupts off.
+    ldisc: Mutex<LineDiscipline>,
+    /// A sleeping lock, only taken by readers. The IRQ handler only notifies the waiters.
+    /// Created by the first reader, as the IRQ handler must not allocate.
+    monitor: Once<Box<dyn SysMonitor>>,
+    /// Incremented when the input is discarded by a signal, to interrupt blocked readers.
+    interrupts: AtomicUsize,
+    /// Foreground process group. `0` means none.
+    foreground: AtomicUsize,
+}
+
+unsafe impl Send for Tty {}
+unsafe impl Sync for Tty {}
+
+impl Tty {
+    pub const fn new(name: &'static str, port: &'static dyn SerialPort) -> Self {
+        Self {
+            name,
+            port,
+            ldisc: Mutex::new(LineDiscipline::new()),
+            monitor: Once::new(),
+            interrupts: AtomicUsize::new(0),
+            foreground: AtomicUsize::new(0),
+        }
+    }
+
+    fn monitor(&self) -> &dyn SysMonitor {
+        &**self.monitor.call_once(|| SERVICE.create_monitor())
+    }
+
+    /// Wake up the blocked readers, if any.
+    fn notify_readers(&self) {
+        if let Some(monitor) = self.monitor.get() {
+            monitor.notify_all();
+        }
+    }
+
+    /// Process a byte received by the serial port. Safe to call from an IRQ handler:
+    /// job-control signals are only queued here, and delivered from task context.
+    pub fn receive(&self, byte: u8) {
+        let signal = {
+            let mut ldisc = self.ldisc.lock_uninterruptible();
+            ldisc.receive(byte, &mut |c| self.port.write_byte(c))
+        };
+        if signal.is_some() {
+            self.interrupts.fetch_add(1, Ordering::SeqCst);
+        }
+        self.notify_readers();
+        if let Some(signal) = signal {
+            let foreground = self.foreground.load(Ordering::SeqCst);
+            if foreground != 0 {
+                SERVICE.signal_process_group(PID(foreground), signal);
+            }
+        }
+    }
+}
+
+impl Device for Tty {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Block until there is input. Fails if the input is discarded by a signal while waiting.
+    fn read(&self, _offset: usize, buf: &mut [u8]) -> Option<usize> {
+        let monitor = self.monitor();
+        monitor.lock();
+        let interrupts = self.interrupts.load(Ordering::SeqCst);
+        let result = loop {
+            if let Some(n) = self.ldisc.lock_uninterruptible().read(buf) {
+                break Some(n);
+            }
+            if self.interrupts.load(Ordering::SeqCst) != interrupts {
+                break None;
+            }
+            monitor.wait();
+        };
+        monitor.unlock();
+        result
+    }
+
+    fn write(&self, _offset: usize, buf: &[u8]) -> Option<usize> {
+        let termios = *self.ldisc.lock_uninterruptible().termios();
+        termios.process_output(buf, &mut |c| self.port.write_byte(c));
+        Some(buf.len())
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> Option<usize> {
+        match cmd {
+            TCGETS => {
+                let termios = *self.ldisc.lock_uninterruptible().termios();
+                unsafe { *(arg as *mut Termios) = termios };
+                Some(0)
+            }
+            TCSETS => {
+                let termios = unsafe { *(arg as *const Termios) };
+                self.ldisc.lock_uninterruptible().set_termios(termios);
+                // Readers may be able to make progress in the new mode.
+                self.notify_readers();
+                Some(0)
+            }
+            TIOCGPGRP => Some(self.foreground.load(Ordering::SeqCst)),
+            TIOCSPGRP => {
+                self.foreground.store(arg, Ordering::SeqCst);
+                Some(0)
+            }
+            _ => None,
+        }
+    }
+}
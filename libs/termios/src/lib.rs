@@ -0,0 +1,297 @@
+//! Terminal line discipline and `termios`-style terminal settings.
+//!
+//! The [`LineDiscipline`] sits between a serial driver and the readers of `/dev/tty*`.
+//! In canonical mode it buffers input line by line, and handles line editing and echo.
+//! In raw mode every byte is passed through as-is.
+
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(feature = "kernel")]
+mod device;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use syscall::Signal;
+use vfs::Fd;
+
+#[cfg(feature = "kernel")]
+pub use device::{SerialPort, Tty};
+
+// Input flags
+/// Translate carriage return to newline on input.
+pub const ICRNL: u32 = 0x100;
+
+// Output flags
+/// Enable output processing.
+pub const OPOST: u32 = 0x1;
+/// Translate newline to carriage return + newline on output.
+pub const ONLCR: u32 = 0x4;
+
+// Local flags
+/// Generate signals for the INTR and SUSP characters.
+pub const ISIG: u32 = 0x1;
+/// Canonical mode: line buffering and line editing.
+pub const ICANON: u32 = 0x2;
+/// Echo input characters.
+pub const ECHO: u32 = 0x8;
+/// The ERASE character erases the previous character on screen.
+pub const ECHOE: u32 = 0x10;
+/// The KILL character erases the current line on screen.
+pub const ECHOK: u32 = 0x20;
+/// Echo newlines even if `ECHO` is not set.
+pub const ECHONL: u32 = 0x40;
+
+// Indices of control characters
+/// Interrupt (Ctrl-C).
+pub const VINTR: usize = 0;
+/// Erase the previous character (Backspace).
+pub const VERASE: usize = 1;
+/// Erase the current line (Ctrl-U).
+pub const VKILL: usize = 2;
+/// End of file (Ctrl-D).
+pub const VEOF: usize = 3;
+/// Suspend (Ctrl-Z).
+pub const VSUSP: usize = 4;
+/// Minimum number of bytes for a raw mode read. `0` makes reads non-blocking.
+pub const VMIN: usize = 5;
+pub const NCCS: usize = 8;
+
+// Control operations
+/// Get the terminal settings. The argument is a `*mut Termios`.
+pub const TCGETS: usize = 0x5401;
+/// Set the terminal settings. The argument is a `*const Termios`.
+pub const TCSETS: usize = 0x5402;
+/// Get the foreground process group.
+pub const TIOCGPGRP: usize = 0x540f;
+/// Set the foreground process group. The argument is the process group id.
+pub const TIOCSPGRP: usize = 0x5410;
+
+/// Terminal settings.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub lflag: u32,
+    pub cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Self::cooked()
+    }
+}
+
+impl Termios {
+    /// Cooked mode, with the usual control characters.
+    pub const fn cooked() -> Self {
+        let mut cc = [0; NCCS];
+        cc[VINTR] = 0x03;
+        cc[VERASE] = 0x7f;
+        cc[VKILL] = 0x15;
+        cc[VEOF] = 0x04;
+        cc[VSUSP] = 0x1a;
+        cc[VMIN] = 1;
+        Self {
+            iflag: ICRNL,
+            oflag: OPOST | ONLCR,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK,
+            cc,
+        }
+    }
+
+    /// Switch to raw mode: No line buffering, echo, signals, or input and output translation.
+    pub fn make_raw(&mut self) {
+        self.iflag &= !ICRNL;
+        self.oflag &= !OPOST;
+        self.lflag &= !(ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHONL);
+        self.cc[VMIN] = 1;
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        self.lflag & ICANON != 0
+    }
+
+    /// Output processing. Passes the translated bytes to `output`.
+    pub fn process_output(&self, buf: &[u8], output: &mut impl FnMut(u8)) {
+        let translate = self.oflag & (OPOST | ONLCR) == OPOST | ONLCR;
+        for &c in buf {
+            if translate && c == b'\n' {
+                output(b'\r');
+            }
+            output(c);
+        }
+    }
+}
+
+/// Get the settings of a terminal.
+pub fn tcgetattr(fd: Fd) -> Result<Termios, ()> {
+    let mut termios = Termios::default();
+    vfs::ioctl(fd, TCGETS, &mut termios as *mut Termios as usize)?;
+    Ok(termios)
+}
+
+/// Update the settings of a terminal.
+pub fn tcsetattr(fd: Fd, termios: &Termios) -> Result<(), ()> {
+    vfs::ioctl(fd, TCSETS, termios as *const Termios as usize)?;
+    Ok(())
+}
+
+/// Get the foreground process group of a terminal. Returns `0` if there is none.
+pub fn tcgetpgrp(fd: Fd) -> Result<usize, ()> {
+    vfs::ioctl(fd, TIOCGPGRP, 0)
+}
+
+/// Set the foreground process group of a terminal.
+/// Terminal-generated signals are sent to this group.
+pub fn tcsetpgrp(fd: Fd, pgid: usize) -> Result<(), ()> {
+    vfs::ioctl(fd, TIOCSPGRP, pgid)?;
+    Ok(())
+}
+
+/// Input processing and buffering of a terminal.
+///
+/// This type only keeps the state. Blocking and signal delivery are done by the owner.
+pub struct LineDiscipline {
+    termios: Termios,
+    /// The line being edited, in canonical mode.
+    line: Vec<u8>,
+    /// Completed lines, in canonical mode. An empty line is an end-of-file.
+    lines: VecDeque<Vec<u8>>,
+    /// Received bytes, in raw mode.
+    raw: VecDeque<u8>,
+}
+
+impl LineDiscipline {
+    pub const fn new() -> Self {
+        Self {
+            termios: Termios::cooked(),
+            line: Vec::new(),
+            lines: VecDeque::new(),
+            raw: VecDeque::new(),
+        }
+    }
+
+    pub fn termios(&self) -> &Termios {
+        &self.termios
+    }
+
+    pub fn set_termios(&mut self, termios: Termios) {
+        match (self.termios.is_canonical(), termios.is_canonical()) {
+            // Pass the incomplete line through.
+            (true, false) => self.raw.extend(self.line.drain(..)),
+            // Keep editing the unread input.
+            (false, true) => self.line.extend(self.raw.drain(..)),
+            _ => {}
+        }
+        self.termios = termios;
+    }
+
+    /// Discard all the buffered input.
+    pub fn flush(&mut self) {
+        self.line.clear();
+        self.lines.clear();
+        self.raw.clear();
+    }
+
+    fn echo(&self, buf: &[u8], output: &mut impl FnMut(u8)) {
+        if self.termios.lflag & ECHO != 0 {
+            self.termios.process_output(buf, output);
+        }
+    }
+
+    /// Echo a control character as `^X`.
+    fn echo_ctrl(&self, c: u8, output: &mut impl FnMut(u8)) {
+        self.echo(&[b'^', c ^ 0x40, b'\n'], output);
+    }
+
+    /// Process a received byte. Echoes are passed to `output`.
+    /// Returns a signal to send to the foreground process group, if any.
+    pub fn receive(&mut self, c: u8, output: &mut impl FnMut(u8)) -> Option<Signal> {
+        let cc = self.termios.cc;
+        let c = if self.termios.iflag & ICRNL != 0 && c == b'\r' {
+            b'\n'
+        } else {
+            c
+        };
+        if self.termios.lflag & ISIG != 0 {
+            let signal = if c == cc[VINTR] {
+                Some(Signal::Interrupt)
+            } else if c == cc[VSUSP] {
+                Some(Signal::TerminalStop)
+            } else {
+                None
+            };
+            if signal.is_some() {
+                self.flush();
+                self.echo_ctrl(c, output);
+                return signal;
+            }
+        }
+        if !self.termios.is_canonical() {
+            self.raw.push_back(c);
+            self.echo(&[c], output);
+            return None;
+        }
+        if c == cc[VERASE] || c == 0x08 {
+            if self.line.pop().is_some() && self.termios.lflag & ECHOE != 0 {
+                self.echo(b"\x08 \x08", output);
+            }
+        } else if c == cc[VKILL] {
+            let n = self.line.len();
+            self.line.clear();
+            if self.termios.lflag & ECHOK != 0 {
+                for _ in 0..n {
+                    self.echo(b"\x08 \x08", output);
+                }
+            }
+        } else if c == cc[VEOF] {
+            // Submit the current line without a newline. An empty line is an end-of-file.
+            self.lines.push_back(core::mem::take(&mut self.line));
+        } else if c == b'\n' {
+            self.line.push(c);
+            self.lines.push_back(core::mem::take(&mut self.line));
+            if self.termios.lflag & (ECHO | ECHONL) != 0 {
+                self.termios.process_output(b"\n", output);
+            }
+        } else {
+            self.line.push(c);
+            self.echo(&[c], output);
+        }
+        None
+    }
+
+    /// Read the buffered input into `buf`.
+    ///
+    /// Canonical mode returns at most one line per read, and `Some(0)` for an end-of-file.
+    /// Returns `None` if the reader should wait for more input.
+    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if let Some(mut line) = self.lines.pop_front() {
+            let n = usize::min(line.len(), buf.len());
+            buf[..n].copy_from_slice(&line[..n]);
+            if n < line.len() {
+                // Keep the rest of the line for the next read. The end-of-file is not consumed.
+                line.drain(..n);
+                self.lines.push_front(line);
+            }
+            return Some(n);
+        }
+        if self.termios.is_canonical() {
+            return None;
+        }
+        if self.raw.is_empty() {
+            return if self.termios.cc[VMIN] == 0 {
+                Some(0)
+            } else {
+                None
+            };
+        }
+        let n = usize::min(self.raw.len(), buf.len());
+        for (i, c) in self.raw.drain(..n).enumerate() {
+            buf[i] = c;
+        }
+        Some(n)
+    }
+}
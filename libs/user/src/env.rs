@@ -0,0 +1,66 @@
+//! The process environment: arguments, working directory and variables.
+//!
+//! Variables are local to the process. They are not passed to programs started with `exec`.
+
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use spin::{Mutex, Once};
+
+use crate::io::{self, Error};
+
+static ARGS: Once<Vec<&'static str>> = Once::new();
+static VARS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Save the arguments passed to `_start`. Only the first call has an effect.
+///
+/// # Safety
+///
+/// `argv` must point to `argc` null-terminated strings that live for the whole program,
+/// which is the case for the arguments passed by the kernel.
+pub unsafe fn init(argc: isize, argv: *const *const u8) {
+    ARGS.call_once(|| {
+        (0..usize::try_from(argc).unwrap_or(0))
+            .map(|i| {
+                let c_str: &CStr = CStr::from_ptr(argv.add(i).read() as _);
+                c_str.to_str().unwrap_or_default()
+            })
+            .collect()
+    });
+}
+
+/// The program arguments. The first one is the program path.
+/// Empty if `init` is not called.
+pub fn args() -> impl Iterator<Item = &'static str> {
+    ARGS.get().into_iter().flatten().copied()
+}
+
+pub fn current_dir() -> io::Result<String> {
+    vfs::cwd().map_err(|_| Error::Other)
+}
+
+pub fn set_current_dir(path: &str) -> io::Result<()> {
+    vfs::chdir(path).map_err(|_| Error::NotFound)
+}
+
+pub fn var(name: &str) -> Option<String> {
+    VARS.lock().get(name).cloned()
+}
+
+pub fn set_var(name: &str, value: &str) {
+    VARS.lock().insert(name.to_owned(), value.to_owned());
+}
+
+pub fn remove_var(name: &str) {
+    VARS.lock().remove(name);
+}
+
+/// All the variables, sorted by name.
+pub fn vars() -> Vec<(String, String)> {
+    VARS.lock()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
@@ -0,0 +1,183 @@
+//! Files and directories, in the style of `std::fs`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use vfs::Fd;
+
+use crate::io::{self, Error, Read, Write};
+use crate::path;
+
+/// An open file. Closed on drop.
+pub struct File {
+    fd: Fd,
+}
+
+impl File {
+    /// Open an existing file or directory.
+    pub fn open(path: &str) -> io::Result<Self> {
+        vfs::open(path).map(|fd| Self { fd }).ok_or(Error::NotFound)
+    }
+
+    /// Create a file, or truncate it if it exists.
+    pub fn create(path: &str) -> io::Result<Self> {
+        vfs::create(path).map(|fd| Self { fd }).ok_or(Error::Other)
+    }
+
+    pub fn fd(&self) -> Fd {
+        self.fd
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        vfs::read(self.fd, buf).map_err(|_| Error::Other)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        vfs::write(self.fd, buf).map_err(|_| Error::Other)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        vfs::close(self.fd);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    stat: vfs::FileStat,
+}
+
+impl Metadata {
+    pub fn len(&self) -> usize {
+        self.stat.size
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.stat.is_dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.stat.is_dir
+    }
+}
+
+pub fn metadata(path: &str) -> io::Result<Metadata> {
+    vfs::stat(path)
+        .map(|stat| Metadata { stat })
+        .map_err(|_| Error::NotFound)
+}
+
+pub fn exists(path: &str) -> bool {
+    vfs::stat(path).is_ok()
+}
+
+pub fn read(path: &str) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+pub fn read_to_string(path: &str) -> io::Result<String> {
+    let mut s = String::new();
+    File::open(path)?.read_to_string(&mut s)?;
+    Ok(s)
+}
+
+/// Write `data` to a file, replacing its content.
+pub fn write(path: &str, data: impl AsRef<[u8]>) -> io::Result<()> {
+    File::create(path)?.write_all(data.as_ref())
+}
+
+pub fn copy(from: &str, to: &str) -> io::Result<usize> {
+    let data = read(from)?;
+    write(to, &data)?;
+    Ok(data.len())
+}
+
+pub fn create_dir(path: &str) -> io::Result<()> {
+    vfs::mkdir(path).map_err(|_| Error::Other)
+}
+
+/// Remove a file, or an empty directory.
+pub fn remove_file(path: &str) -> io::Result<()> {
+    vfs::unlink(path).map_err(|_| Error::Other)
+}
+
+pub fn remove_dir(path: &str) -> io::Result<()> {
+    vfs::unlink(path).map_err(|_| Error::Other)
+}
+
+/// Remove a directory and everything in it.
+pub fn remove_dir_all(path: &str) -> io::Result<()> {
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        if entry.metadata()?.is_dir() {
+            remove_dir_all(entry.path())?;
+        } else {
+            remove_file(entry.path())?;
+        }
+    }
+    remove_dir(path)
+}
+
+pub fn rename(from: &str, to: &str) -> io::Result<()> {
+    vfs::rename(from, to).map_err(|_| Error::Other)
+}
+
+pub struct DirEntry {
+    path: String,
+    name_start: usize,
+}
+
+impl DirEntry {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.path[self.name_start..]
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        metadata(&self.path)
+    }
+}
+
+/// Iterator over the entries of a directory. `.` and `..` are not included.
+pub struct ReadDir {
+    dir: File,
+    path: String,
+    index: usize,
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match vfs::readdir(self.dir.fd, self.index) {
+            Ok(Some(name)) => {
+                self.index += 1;
+                let path = path::join(&self.path, &name);
+                let name_start = path.len() - name.len();
+                Some(Ok(DirEntry { path, name_start }))
+            }
+            Ok(None) => None,
+            Err(_) => Some(Err(Error::Other)),
+        }
+    }
+}
+
+pub fn read_dir(path: &str) -> io::Result<ReadDir> {
+    if !metadata(path)?.is_dir() {
+        return Err(Error::Other);
+    }
+    Ok(ReadDir {
+        dir: File::open(path)?,
+        path: String::from(path),
+        index: 0,
+    })
+}
@@ -0,0 +1,374 @@
+//! Reading and writing, in the style of `std::io`.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use vfs::Fd;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The file or directory does not exist.
+    NotFound,
+    /// A read ended before the buffer is filled.
+    UnexpectedEof,
+    /// A write returned zero bytes.
+    WriteZero,
+    /// The data is not valid UTF-8.
+    InvalidData,
+    /// The operation failed. The kernel does not report why.
+    Other,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Error::NotFound => "no such file or directory",
+            Error::UnexpectedEof => "unexpected end of file",
+            Error::WriteZero => "failed to write the whole buffer",
+            Error::InvalidData => "stream did not contain valid UTF-8",
+            Error::Other => "operation failed",
+        };
+        f.write_str(message)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+pub trait Read {
+    /// Read some bytes into `buf`. Returns `0` at the end of the input.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Read all the remaining bytes. Returns the number of bytes read.
+    fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<usize> {
+        let start = out.len();
+        let mut buf = [0u8; 512];
+        loop {
+            match self.read(&mut buf)? {
+                0 => return Ok(out.len() - start),
+                len => out.extend_from_slice(&buf[..len]),
+            }
+        }
+    }
+
+    fn read_to_string(&mut self, out: &mut String) -> Result<usize> {
+        let mut bytes = vec![];
+        let len = self.read_to_end(&mut bytes)?;
+        out.push_str(core::str::from_utf8(&bytes).map_err(|_| Error::InvalidData)?);
+        Ok(len)
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => return Err(Error::UnexpectedEof),
+                len => buf = &mut buf[len..],
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait Write {
+    /// Write some bytes from `buf`. Returns the number of bytes written.
+    fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Error::WriteZero),
+                len => buf = &buf[len..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Used by `write!` and `writeln!`.
+    fn write_fmt(&mut self, args: fmt::Arguments) -> Result<()> {
+        struct Adapter<'a, W: ?Sized> {
+            inner: &'a mut W,
+            error: Result<()>,
+        }
+        impl<W: Write + ?Sized> fmt::Write for Adapter<'_, W> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.inner.write_all(s.as_bytes()).map_err(|e| {
+                    self.error = Err(e);
+                    fmt::Error
+                })
+            }
+        }
+        let mut adapter = Adapter {
+            inner: self,
+            error: Ok(()),
+        };
+        match fmt::write(&mut adapter, args) {
+            Ok(()) => Ok(()),
+            Err(_) => adapter.error.and(Err(Error::Other)),
+        }
+    }
+}
+
+/// A reader with an internal buffer, which can read lines.
+pub trait BufRead: Read {
+    /// Return the buffered data, reading more if the buffer is empty.
+    fn fill_buf(&mut self) -> Result<&[u8]>;
+
+    /// Mark `len` bytes of the buffer as read.
+    fn consume(&mut self, len: usize);
+
+    /// Read until `delim` or the end of the input. `delim` is included in `out`.
+    fn read_until(&mut self, delim: u8, out: &mut Vec<u8>) -> Result<usize> {
+        let mut read = 0;
+        loop {
+            let buf = self.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(read);
+            }
+            let (len, done) = match buf.iter().position(|b| *b == delim) {
+                Some(i) => (i + 1, true),
+                None => (buf.len(), false),
+            };
+            out.extend_from_slice(&buf[..len]);
+            self.consume(len);
+            read += len;
+            if done {
+                return Ok(read);
+            }
+        }
+    }
+
+    /// Read a line, including the `\n`. Returns `0` at the end of the input.
+    fn read_line(&mut self, out: &mut String) -> Result<usize> {
+        let mut bytes = vec![];
+        let len = self.read_until(b'\n', &mut bytes)?;
+        out.push_str(core::str::from_utf8(&bytes).map_err(|_| Error::InvalidData)?);
+        Ok(len)
+    }
+
+    /// Iterate over the lines, without the line endings.
+    fn lines(self) -> Lines<Self>
+    where
+        Self: Sized,
+    {
+        Lines { reader: self }
+    }
+}
+
+pub struct Lines<B> {
+    reader: B,
+}
+
+impl<B: BufRead> Iterator for Lines<B> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+const DEFAULT_BUF_SIZE: usize = 1024;
+
+/// Adds buffering to a reader.
+pub struct BufReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: Read> BufReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; capacity],
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for BufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // Skip the buffer for large reads.
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return self.inner.read(buf);
+        }
+        let available = self.fill_buf()?;
+        let len = usize::min(available.len(), buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Read> BufRead for BufReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.filled {
+            self.filled = self.inner.read(&mut self.buf)?;
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.pos = usize::min(self.pos + len, self.filled);
+    }
+}
+
+/// Adds buffering to a writer. The buffer is flushed when it is full, and on drop.
+pub struct BufWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> BufWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn flush_buf(&mut self) -> Result<()> {
+        let result = self.inner.write_all(&self.buf);
+        self.buf.clear();
+        result
+    }
+}
+
+impl<W: Write> Write for BufWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.buf.len() + buf.len() > self.buf.capacity() {
+            self.flush_buf()?;
+        }
+        if buf.len() >= self.buf.capacity() {
+            return self.inner.write(buf);
+        }
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.flush_buf()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for BufWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush_buf();
+    }
+}
+
+fn read_fd(fd: Fd, buf: &mut [u8]) -> Result<usize> {
+    vfs::read(fd, buf).map_err(|_| Error::Other)
+}
+
+fn write_fd(fd: Fd, buf: &[u8]) -> Result<usize> {
+    vfs::write(fd, buf).map_err(|_| Error::Other)
+}
+
+/// The standard input. Reads are not buffered, so that other readers of the terminal
+/// are not starved.
+pub struct Stdin;
+
+pub fn stdin() -> Stdin {
+    Stdin
+}
+
+impl Stdin {
+    /// Read a line, including the `\n`. Returns `0` at the end of the input.
+    ///
+    /// A terminal in canonical mode returns a whole line per read. Otherwise this reads
+    /// one byte at a time.
+    pub fn read_line(&self, out: &mut String) -> Result<usize> {
+        let mut bytes = vec![];
+        let mut byte = [0u8; 1];
+        while read_fd(Fd::STDIN, &mut byte)? == 1 {
+            bytes.push(byte[0]);
+            if byte[0] == b'\n' {
+                break;
+            }
+        }
+        out.push_str(core::str::from_utf8(&bytes).map_err(|_| Error::InvalidData)?);
+        Ok(bytes.len())
+    }
+
+    /// Iterate over the lines, without the line endings.
+    pub fn lines(self) -> Lines<BufReader<Stdin>> {
+        BufReader::new(self).lines()
+    }
+}
+
+impl Read for Stdin {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        read_fd(Fd::STDIN, buf)
+    }
+}
+
+/// The standard output. Not buffered.
+pub struct Stdout;
+
+pub fn stdout() -> Stdout {
+    Stdout
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        write_fd(Fd::STDOUT, buf)
+    }
+}
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        Write::write_all(self, s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// The standard error. Not buffered.
+pub struct Stderr;
+
+pub fn stderr() -> Stderr {
+    Stderr
+}
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        write_fd(Fd::STDERR, buf)
+    }
+}
+
+impl fmt::Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        Write::write_all(self, s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
@@ -5,7 +5,13 @@ extern crate alloc;
 
 mod heap;
 
+pub mod env;
+pub mod fs;
+pub mod io;
+pub mod path;
 pub mod sys;
+pub mod thread;
+pub mod time;
 
 #[doc(hidden)]
 pub mod print;
@@ -16,5 +22,5 @@ static ALLOCATOR: heap::UserHeap = heap::UserHeap::new();
 #[panic_handler]
 fn panic(info: &::core::panic::PanicInfo) -> ! {
     println!("{}", info);
-    sys::exit()
+    sys::exit(101)
 }
@@ -0,0 +1,73 @@
+//! Path utilities. Paths are `/`-separated strings.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub fn is_absolute(path: &str) -> bool {
+    path.starts_with('/')
+}
+
+/// Join two paths. An absolute `path` replaces `base`.
+pub fn join(base: &str, path: &str) -> String {
+    if is_absolute(path) || base.is_empty() {
+        String::from(path)
+    } else if base.ends_with('/') {
+        format!("{}{}", base, path)
+    } else {
+        format!("{}/{}", base, path)
+    }
+}
+
+/// The non-empty components of a path.
+pub fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|c| !c.is_empty())
+}
+
+/// The last component. `None` for `/`, or if the path ends with `..`.
+pub fn file_name(path: &str) -> Option<&str> {
+    components(path).last().filter(|c| *c != "..")
+}
+
+/// The path without its last component. `None` for `/` and the empty path.
+pub fn parent(path: &str) -> Option<&str> {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return None;
+    }
+    match trimmed.rfind('/') {
+        Some(0) => Some("/"),
+        Some(i) => Some(&trimmed[..i]),
+        None => Some(""),
+    }
+}
+
+/// The extension of the file name, without the dot.
+pub fn extension(path: &str) -> Option<&str> {
+    let name = file_name(path)?;
+    match name.rfind('.') {
+        Some(0) | None => None,
+        Some(i) => Some(&name[i + 1..]),
+    }
+}
+
+/// Remove `.`, `..` and repeated separators, without looking at the file system.
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for c in components(path) {
+        match c {
+            "." => {}
+            ".." if parts.last().is_some_and(|p| *p != "..") => {
+                parts.pop();
+            }
+            ".." if is_absolute(path) => {}
+            _ => parts.push(c),
+        }
+    }
+    let joined = parts.join("/");
+    match (is_absolute(path), joined.is_empty()) {
+        (true, _) => format!("/{}", joined),
+        (false, true) => String::from("."),
+        (false, false) => joined,
+    }
+}
@@ -44,6 +44,15 @@ pub fn _print(args: fmt::Arguments) {
     });
 }
 
+#[doc(hidden)]
+#[inline(never)]
+pub fn _eprint(args: fmt::Arguments) {
+    let _ = format(args, |s| match vfs::write(Fd::STDERR, s.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(fmt::Error),
+    });
+}
+
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => ({
@@ -58,3 +67,18 @@ macro_rules! println {
         $crate::print!("\n");
     });
 }
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ({
+        $crate::print::_eprint(format_args!($($arg)*))
+    });
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    ($($arg:tt)*) => ({
+        $crate::eprint!($($arg)*);
+        $crate::eprint!("\n");
+    });
+}
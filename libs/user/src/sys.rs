@@ -1,7 +1,24 @@
 pub use syscall::{ModuleRequest, Payload, RawModuleRequest};
 
-pub use syscall::{exec, exit, fork, halt, log, module_call, waitpid};
+pub use syscall::{
+    exec, exit, fork, futex_wait, futex_wake, get_random, getpgid, getpid, halt, join_thread, kill,
+    log, module_call, power, setpgid, spawn_thread, take_signals, thread_exit, wait, waitpid,
+    waitpid_with_options, PowerAction, Signal, WEXITED, WNOHANG,
+};
 
-pub use vfs::{Fd, VFSRequest};
+pub use syscall::{
+    cpu_time, idle_time, nice, num_cores, set_rt_priority, setpriority, sleep, uptime,
+};
 
-pub use vfs::{chdir, close, cwd, open, read, readdir, write};
+pub use syscall::{
+    heap_dump, klog, list_modules, list_procs, meminfo, read_kernel_log, set_log_level,
+    set_mem_limit, slab_info, trace, uname, zone_info, MemInfo, ProcInfo, ProcState, SlabInfo,
+    UtsName, ZoneInfo,
+};
+
+pub use vfs::{Fd, FileStat, VFSRequest};
+
+pub use vfs::{
+    chdir, close, create, cwd, dup2, ioctl, mkdir, mount, mounts, open, read, readdir, rename,
+    stat, unlink, write,
+};
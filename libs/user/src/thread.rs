@@ -0,0 +1,95 @@
+//! Threads, in the style of `std::thread`.
+//!
+//! Threads share the address space of the process. Each thread runs on a stack allocated
+//! from the heap, which is released when the thread is joined.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+use spin::Mutex;
+
+use crate::io::{self, Error};
+
+const DEFAULT_STACK_SIZE: usize = 64 << 10;
+
+type Main = Box<dyn FnOnce() + Send>;
+
+/// An owned permission to join a thread.
+/// Dropping it detaches the thread, and leaks its stack.
+pub struct JoinHandle<T> {
+    tid: usize,
+    stack: Vec<u8>,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> usize {
+        self.tid
+    }
+
+    /// Wait for the thread to finish. Returns `Err` if the thread panicked.
+    pub fn join(mut self) -> Result<T, ()> {
+        syscall::join_thread(self.tid);
+        drop(core::mem::take(&mut self.stack));
+        self.result.lock().take().ok_or(())
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        // The thread may still be running on its stack.
+        core::mem::forget(core::mem::take(&mut self.stack));
+    }
+}
+
+extern "C" fn thread_start(main: usize) -> ! {
+    let main = unsafe { Box::from_raw(main as *mut Main) };
+    main();
+    syscall::thread_exit()
+}
+
+/// Spawn a thread with the default stack size.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with_stack_size(DEFAULT_STACK_SIZE, f).expect("failed to spawn thread")
+}
+
+pub fn spawn_with_stack_size<F, T>(stack_size: usize, f: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let result = Arc::new(Mutex::new(None));
+    let main: Main = {
+        let result = result.clone();
+        Box::new(move || *result.lock() = Some(f()))
+    };
+    let main = Box::into_raw(Box::new(main));
+    let mut stack = vec![0u8; stack_size];
+    // The stack pointer must be 16-byte aligned.
+    let end = stack.as_mut_ptr_range().end;
+    let stack_top = end.wrapping_sub(end as usize & 0xf);
+    let tid = syscall::spawn_thread(thread_start, main as usize, stack_top);
+    if tid < 0 {
+        drop(unsafe { Box::from_raw(main) });
+        return Err(Error::Other);
+    }
+    Ok(JoinHandle {
+        tid: tid as usize,
+        stack,
+        result,
+    })
+}
+
+pub fn sleep(duration: Duration) {
+    syscall::sleep(duration)
+}
+
+pub fn yield_now() {
+    syscall::_yield()
+}
@@ -38,6 +38,16 @@ pub struct Stat {
     pub fs: &'static dyn FileSystem,
     pub mount: Option<usize>,
     pub is_dir: bool,
+    /// File size in bytes. `0` for directories and devices.
+    pub size: usize,
+}
+
+/// File status, returned by `stat`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileStat {
+    pub size: usize,
+    pub is_dir: bool,
 }
 
 pub trait FileSystem: Sync + Send {
@@ -52,6 +62,31 @@ pub trait FileSystem: Sync + Send {
     fn read_dir(&self, node: &Node) -> Option<Vec<String>>;
     // Mount
     fn mount(&self, parent: &Node, file: &str, key: usize) -> Option<Node>;
+    /// Device-specific control operation.
+    fn ioctl(&self, _node: &Node, _cmd: usize, _arg: usize) -> Option<usize> {
+        None
+    }
+    /// Create an empty file, or truncate an existing one.
+    fn create(&self, _parent: &Node, _file: &str) -> Option<Node> {
+        None
+    }
+    fn mkdir(&self, _parent: &Node, _dir: &str) -> Option<()> {
+        None
+    }
+    /// Remove a file, or an empty directory.
+    fn unlink(&self, _parent: &Node, _file: &str) -> Option<()> {
+        None
+    }
+    /// Move a file within this file system.
+    fn rename(
+        &self,
+        _parent: &Node,
+        _file: &str,
+        _new_parent: &Node,
+        _new_file: &str,
+    ) -> Option<()> {
+        None
+    }
 }
 
 // Possible syscalls:
@@ -71,6 +106,16 @@ pub enum VFSRequest<'a> {
     },
     GetCwd(&'a mut [u8]),
     SetCwd(&'a str),
+    Ioctl(Fd, usize, usize),
+    Create(&'a str),
+    Mkdir(&'a str),
+    Unlink(&'a str),
+    Rename(&'a str, &'a str),
+    Stat(&'a str, &'a mut FileStat),
+    /// List the mount points, one `<path> <fs>` per line.
+    GetMounts(&'a mut [u8]),
+    /// Make the second descriptor refer to the same file as the first one.
+    Dup2(Fd, Fd),
 }
 
 impl<'a> ModuleRequest<'a> for VFSRequest<'a> {
@@ -84,6 +129,14 @@ impl<'a> ModuleRequest<'a> for VFSRequest<'a> {
             Self::Mount { path, dev, fs } => RawModuleRequest::new(6, path, dev, fs),
             Self::GetCwd(buf) => RawModuleRequest::new(7, buf, &(), &()),
             Self::SetCwd(s) => RawModuleRequest::new(8, s, &(), &()),
+            Self::Ioctl(fd, cmd, arg) => RawModuleRequest::new(9, &fd.0, cmd, arg),
+            Self::Create(s) => RawModuleRequest::new(10, s, &(), &()),
+            Self::Mkdir(s) => RawModuleRequest::new(11, s, &(), &()),
+            Self::Unlink(s) => RawModuleRequest::new(12, s, &(), &()),
+            Self::Rename(from, to) => RawModuleRequest::new(13, from, to, &()),
+            Self::Stat(s, stat) => RawModuleRequest::new(14, s, stat, &()),
+            Self::GetMounts(buf) => RawModuleRequest::new(15, buf, &(), &()),
+            Self::Dup2(old, new) => RawModuleRequest::new(16, &old.0, &new.0, &()),
         }
     }
     fn from_raw(raw: RawModuleRequest<'a>) -> Self {
@@ -100,6 +153,14 @@ impl<'a> ModuleRequest<'a> for VFSRequest<'a> {
             },
             7 => Self::GetCwd(raw.arg(0)),
             8 => Self::SetCwd(raw.arg(0)),
+            9 => Self::Ioctl(Fd(raw.arg(0)), raw.arg(1), raw.arg(2)),
+            10 => Self::Create(raw.arg(0)),
+            11 => Self::Mkdir(raw.arg(0)),
+            12 => Self::Unlink(raw.arg(0)),
+            13 => Self::Rename(raw.arg(0), raw.arg(1)),
+            14 => Self::Stat(raw.arg(0), raw.arg(1)),
+            15 => Self::GetMounts(raw.arg(0)),
+            16 => Self::Dup2(Fd(raw.arg(0)), Fd(raw.arg(1))),
             _ => panic!("Unknown request"),
         }
     }
@@ -118,6 +179,16 @@ pub fn close(fd: Fd) {
     syscall::module_call("vfs", &VFSRequest::Close(fd));
 }
 
+/// Make `new` refer to the same file as `old`. `new` is closed first if it is open.
+pub fn dup2(old: Fd, new: Fd) -> Result<(), ()> {
+    let ret = syscall::module_call("vfs", &VFSRequest::Dup2(old, new));
+    if ret < 0 {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
 pub fn read(fd: Fd, buf: &mut [u8]) -> Result<usize, ()> {
     let ret = syscall::module_call("vfs", &VFSRequest::Read(fd, buf));
     if ret < 0 {
@@ -171,6 +242,88 @@ pub fn chdir(path: &str) -> Result<(), ()> {
     }
 }
 
+/// Device-specific control operation on an open file.
+pub fn ioctl(fd: Fd, cmd: usize, arg: usize) -> Result<usize, ()> {
+    let ret = syscall::module_call("vfs", &VFSRequest::Ioctl(fd, cmd, arg));
+    if ret < 0 {
+        Err(())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// Create a file and open it. An existing file is truncated.
+pub fn create(path: &str) -> Option<Fd> {
+    let ret = syscall::module_call("vfs", &VFSRequest::Create(path));
+    if ret < 0 {
+        None
+    } else {
+        Some(Fd(ret as u32))
+    }
+}
+
+pub fn mkdir(path: &str) -> Result<(), ()> {
+    let ret = syscall::module_call("vfs", &VFSRequest::Mkdir(path));
+    if ret < 0 {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+/// Remove a file, or an empty directory.
+pub fn unlink(path: &str) -> Result<(), ()> {
+    let ret = syscall::module_call("vfs", &VFSRequest::Unlink(path));
+    if ret < 0 {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+/// Move a file. Both paths must be on the same file system.
+pub fn rename(from: &str, to: &str) -> Result<(), ()> {
+    let ret = syscall::module_call("vfs", &VFSRequest::Rename(from, to));
+    if ret < 0 {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn stat(path: &str) -> Result<FileStat, ()> {
+    let mut stat = FileStat::default();
+    let ret = syscall::module_call("vfs", &VFSRequest::Stat(path, &mut stat));
+    if ret < 0 {
+        Err(())
+    } else {
+        Ok(stat)
+    }
+}
+
+/// Mount a file system. Only allowed for privileged callers.
+pub fn mount(path: &str, dev: usize, fs: &str) -> Result<(), ()> {
+    let ret = syscall::module_call("vfs", &VFSRequest::Mount { path, dev, fs });
+    if ret < 0 {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+/// List the mount points, one `<path> <fs>` per line.
+pub fn mounts() -> Result<String, ()> {
+    let mut buf = [0u8; 1024];
+    let ret = syscall::module_call("vfs", &VFSRequest::GetMounts(&mut buf));
+    if ret < 0 {
+        Err(())
+    } else {
+        core::str::from_utf8(&buf[..ret as usize])
+            .map(|s| s.to_owned())
+            .map_err(|_| ())
+    }
+}
+
 pub trait VFSManager {
     fn init(&self, ramfs: &'static mut RamFS);
     fn register_process(&self, proc: PID, cwd: String) -> Box<dyn core::any::Any>;
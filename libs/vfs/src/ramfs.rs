@@ -1,5 +1,5 @@
 use alloc::borrow::ToOwned;
-use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
 use core::ops::Deref;
 use serde::{Deserialize, Serialize};
 
@@ -18,6 +18,20 @@ impl File {
     pub fn new(data: Vec<u8>) -> Self {
         Self { data }
     }
+
+    /// Write `buf` at `offset`. The file is extended if needed.
+    pub fn write(&mut self, offset: usize, buf: &[u8]) -> usize {
+        let end = offset + buf.len();
+        if end > self.data.len() {
+            self.data.resize(end, 0);
+        }
+        self.data[offset..end].copy_from_slice(buf);
+        buf.len()
+    }
+
+    pub fn truncate(&mut self) {
+        self.data.clear();
+    }
 }
 
 impl Deref for File {
@@ -49,18 +63,51 @@ impl Dir {
         }
     }
 
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut Entry> {
+        let (name, path) = match path.split_once('/') {
+            Some(x) => x,
+            _ => (path, ""),
+        };
+        match (self.entries.get_mut(name)?, path) {
+            (entry, "") => Some(entry),
+            (Entry::Dir(dir), _) => dir.get_mut(path),
+            (_, _) => None,
+        }
+    }
+
     pub fn entries(&self) -> Vec<String> {
         self.entries.keys().cloned().collect()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add an entry to this directory. Fails if the name is taken.
+    pub fn insert_entry(&mut self, name: &str, entry: Entry) -> Result<(), Entry> {
+        if self.entries.contains_key(name) {
+            return Err(entry);
+        }
+        self.entries.insert(name.to_owned(), entry);
+        Ok(())
+    }
+
+    pub fn remove_entry(&mut self, name: &str) -> Option<Entry> {
+        self.entries.remove(name)
+    }
+
     pub fn insert(&mut self, path: &str, file: File) {
+        self.insert_at(path, Entry::File(file))
+    }
+
+    fn insert_at(&mut self, path: &str, entry: Entry) {
         let (name, path) = match path.split_once('/') {
             Some(x) => x,
             _ => (path, ""),
         };
         if path.is_empty() {
             debug_assert!(!self.entries.contains_key(name));
-            self.entries.insert(name.to_owned(), Entry::File(file));
+            self.entries.insert(name.to_owned(), entry);
         } else {
             let dir = match self.entries.get_mut(name) {
                 Some(Entry::Dir(dir)) => dir,
@@ -74,7 +121,7 @@ impl Dir {
                     }
                 }
             };
-            dir.insert(path, file)
+            dir.insert_at(path, entry)
         }
     }
 
@@ -114,6 +161,8 @@ pub enum Entry {
     File(File),
     Dir(Dir),
     Mount(Mount),
+    /// A symbolic link to an absolute path, or a path relative to the link's directory.
+    Link(String),
 }
 
 impl Entry {
@@ -135,6 +184,18 @@ impl Entry {
             _ => None,
         }
     }
+    pub fn as_file_mut(&mut self) -> Option<&mut File> {
+        match self {
+            Self::File(file) => Some(file),
+            _ => None,
+        }
+    }
+    pub fn as_dir_mut(&mut self) -> Option<&mut Dir> {
+        match self {
+            Self::Dir(dir) => Some(dir),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -152,7 +213,26 @@ impl RamFS {
         }
     }
 
+    /// Get an entry by its absolute path. Symbolic links are followed, if they are the last
+    /// component of the path.
     pub fn get(&self, path: &str) -> Option<&Entry> {
+        let mut path = path.to_owned();
+        // Limit the depth, in case of cycles.
+        for _ in 0..8 {
+            match self.get_no_follow(&path)? {
+                Entry::Link(target) if target.starts_with('/') => path = target.clone(),
+                Entry::Link(target) => {
+                    let dir = path.rsplit_once('/').map(|x| x.0).unwrap_or("");
+                    path = format!("{}/{}", dir, target);
+                }
+                entry => return Some(entry),
+            }
+        }
+        None
+    }
+
+    /// Get an entry by its absolute path. Symbolic links are not followed.
+    pub fn get_no_follow(&self, path: &str) -> Option<&Entry> {
         debug_assert!(path.starts_with('/'));
         let path = path.strip_prefix('/')?;
         if path == "" {
@@ -161,6 +241,16 @@ impl RamFS {
         self.root.as_dir().unwrap().get(path)
     }
 
+    /// Get an entry by its absolute path, for modification. Symbolic links are not followed.
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut Entry> {
+        debug_assert!(path.starts_with('/'));
+        let path = path.strip_prefix('/')?;
+        if path == "" {
+            return Some(&mut self.root);
+        }
+        self.root.as_dir_mut().unwrap().get_mut(path)
+    }
+
     pub fn mount(&mut self, path: &str, mnt: Mount) -> Result<(), ()> {
         debug_assert!(path.starts_with('/'));
         let path = path.strip_prefix('/').ok_or(())?;
@@ -182,6 +272,17 @@ impl RamFS {
         }
     }
 
+    /// Create a symbolic link at `path`.
+    pub fn symlink(&mut self, path: &str, target: &str) {
+        debug_assert!(path.starts_with('/'));
+        let path = path.strip_prefix('/').unwrap();
+        if let Entry::Dir(dir) = &mut self.root {
+            dir.insert_at(path, Entry::Link(target.to_owned()))
+        } else {
+            unreachable!()
+        }
+    }
+
     pub fn serialize(&self) -> Vec<u8> {
         postcard::to_allocvec(self).unwrap()
     }
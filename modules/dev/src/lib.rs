@@ -75,6 +75,7 @@ impl FileSystem for DevFS {
             fs: unsafe { &*(self as *const Self) },
             mount: None,
             is_dir: false,
+            size: 0,
         })
     }
     fn open(&self, parent: &Node, fname: &str) -> Option<Node> {
@@ -120,4 +121,8 @@ impl FileSystem for DevFS {
     fn mount(&self, _parent: &Node, _file: &str, _key: usize) -> Option<Node> {
         unimplemented!()
     }
+    fn ioctl(&self, node: &Node, cmd: usize, arg: usize) -> Option<usize> {
+        let devices = self.devices.read();
+        devices.get(node.name.as_ref())?.ioctl(cmd, arg)
+    }
 }
@@ -76,7 +76,18 @@ impl TimerController for GICTimer {
         if bsp {
             // pass
         } else {
-            unimplemented!()
+            // The timer and its PPI are banked. Start them on the current core.
+            self.start_timer(self.irq);
         }
     }
+
+    fn stop(&self) {
+        CNTP_CTL_EL0.set(0);
+    }
+
+    fn restart(&self) {
+        let step = CNTFRQ_EL0.get() / TIMER_INTERRUPT_FREQUENCY as u64;
+        CNTP_TVAL_EL0.set(step);
+        CNTP_CTL_EL0.set(1);
+    }
 }
@@ -15,6 +15,8 @@ use memory::{page::Frame, volatile::*};
 use spin::Mutex;
 
 pub const IRQ_LINES: usize = 256;
+/// SGIs and PPIs. Their registers are banked per core.
+const BANKED_IRQ_LINES: usize = 32;
 
 #[repr(C)]
 #[allow(non_snake_case)]
@@ -129,17 +131,20 @@ impl GIC {
         let (GICD, GICC) = (self.gicd(), self.gicc());
         barrier::dsb(barrier::SY);
 
-        // Disable all interrupts
-        GICD.CTLR.set(GICD::CTLR_DISABLE);
+        // The distributor is shared by all cores. Only the BSP resets it.
+        // Secondary cores only initialize their banked SGI/PPI registers.
+        let lines = if bsp { IRQ_LINES } else { BANKED_IRQ_LINES };
         if bsp {
-            for n in 0..(IRQ_LINES / 32) {
-                GICD.ICENABLER[n].set(!0);
-                GICD.ICPENDR[n].set(!0);
-                GICD.ICACTIVER[n].set(!0);
-            }
+            // Disable all interrupts
+            GICD.CTLR.set(GICD::CTLR_DISABLE);
+        }
+        for n in 0..(lines / 32) {
+            GICD.ICENABLER[n].set(!0);
+            GICD.ICPENDR[n].set(!0);
+            GICD.ICACTIVER[n].set(!0);
         }
         // Set priority
-        for n in 0..(IRQ_LINES / 4) {
+        for n in 0..(lines / 4) {
             GICD.IPRIORITYR[n].set(
                 GICD::IPRIORITYRAULT
                     | GICD::IPRIORITYRAULT << 8
@@ -147,12 +152,15 @@ impl GIC {
                     | GICD::IPRIORITYRAULT << 24,
             );
         }
-        // set all interrupts to level triggered
-        for n in 0..(IRQ_LINES / 16) {
-            GICD.ICFGR[n].set(0);
+        if bsp {
+            // set all interrupts to level triggered
+            for n in 0..(IRQ_LINES / 16) {
+                GICD.ICFGR[n].set(0);
+            }
+            // Enable GIC
+            GICD.CTLR.set(GICD::CTLR_ENABLE);
         }
-        // Enable GIC
-        GICD.CTLR.set(GICD::CTLR_ENABLE);
+        // Enable the CPU interface of the current core
         GICC.PMR.set(GICC::PMR_PRIORITY);
         GICC.CTLR.set(GICC::CTLR_ENABLE);
         barrier::dmb(barrier::SY);
@@ -238,4 +246,13 @@ impl InterruptController for GIC {
             IRQ_HANDLERS[irq] = Some(handler);
         }
     }
+
+    fn send_ipi(&self, core: usize, irq: usize) {
+        debug_assert!(irq < 16, "IPIs must be SGIs");
+        barrier::dsb(barrier::ISHST);
+        self.gicd().SGIR.set(
+            (1 << (core as u32 + GICD::SGIR_CPU_TARGET_LIST__SHIFT))
+                | (irq as u32 & GICD::SGIR_SGIINTID__MASK),
+        );
+    }
 }
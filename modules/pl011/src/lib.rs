@@ -8,27 +8,24 @@ extern crate alloc;
 
 use alloc::boxed::Box;
 use core::fmt;
-use crossbeam::queue::SegQueue;
 use dev::{DevRequest, Device};
-use kernel_module::monitor::SysMonitor;
 use kernel_module::{kernel_module, KernelModule, SERVICE};
 use memory::{page::Frame, volatile::Volatile};
-use spin::{Lazy, RwLock};
+use spin::RwLock;
+use termios::{SerialPort, Tty};
 
 #[kernel_module]
 pub static PL011: PL011 = PL011 {
     uart: RwLock::new(core::ptr::null_mut()),
-    buffer: SegQueue::new(),
-    monitor: Lazy::new(|| SERVICE.create_monitor()),
 };
 
+static TTY: Tty = Tty::new("tty.serial", &PL011);
+
 unsafe impl Send for PL011 {}
 unsafe impl Sync for PL011 {}
 
 pub struct PL011 {
     pub uart: RwLock<*mut UART0>,
-    pub buffer: SegQueue<u8>,
-    monitor: Lazy<Box<dyn SysMonitor>>,
 }
 
 impl PL011 {
@@ -52,42 +49,25 @@ impl KernelModule for PL011 {
         SERVICE.interrupt_controller().set_irq_handler(
             irq,
             Box::new(|| {
-                PL011.monitor.lock();
-                while !self.uart().receive_fifo_empty() {
-                    let c = self.uart().dr.get() as u8;
-                    self.buffer.push(c);
+                while !PL011.uart().receive_fifo_empty() {
+                    let c = PL011.uart().dr.get() as u8;
+                    TTY.receive(c);
                 }
-                PL011.monitor.notify_all();
-                PL011.monitor.unlock();
                 0
             }),
         );
         SERVICE.interrupt_controller().enable_irq(irq);
         kernel_module::module_call(
             "dev",
-            &DevRequest::RegisterDev(&(self as &'static dyn Device)),
+            &DevRequest::RegisterDev(&(&TTY as &'static dyn Device)),
         );
         Ok(())
     }
 }
 
-impl Device for PL011 {
-    fn name(&self) -> &'static str {
-        "tty.serial"
-    }
-
-    fn read(&self, _offset: usize, buf: &mut [u8]) -> Option<usize> {
-        for i in 0..buf.len() {
-            buf[i] = self.uart().getchar(true).unwrap() as _;
-        }
-        Some(buf.len())
-    }
-
-    fn write(&self, _offset: usize, buf: &[u8]) -> Option<usize> {
-        for i in 0..buf.len() {
-            self.uart().putchar(buf[i] as _);
-        }
-        Some(buf.len())
+impl SerialPort for PL011 {
+    fn write_byte(&self, byte: u8) {
+        self.uart().putchar(byte);
     }
 }
 
@@ -120,37 +100,11 @@ impl UART0 {
         self.fr.get() & (1 << 4) != 0
     }
 
-    fn getchar(&mut self, block: bool) -> Option<char> {
-        if PL011.buffer.is_empty() {
-            if !block {
-                return None;
-            } else {
-                PL011.monitor.lock();
-                while PL011.buffer.is_empty() {
-                    PL011.monitor.wait();
-                }
-                PL011.monitor.unlock();
-            }
-        }
-        let mut c = PL011.buffer.pop().unwrap() as char;
-        if c == '\r' {
-            c = '\n';
-        }
-        Some(c)
-        // if ret as u8 == 127 {
-        //     ret = 0x8u8 as _;
-        // }
-    }
-
-    fn putchar(&mut self, c: char) {
+    fn putchar(&mut self, c: u8) {
         while self.transmit_fifo_full() {
             core::hint::spin_loop();
         }
-        if c == '\n' {
-            self.dr.set('\r' as u8 as u32);
-        }
-        while self.transmit_fifo_full() {}
-        self.dr.set(c as u8 as u32);
+        self.dr.set(c as u32);
     }
 
     fn init(&mut self) {
@@ -170,9 +124,9 @@ pub struct UARTLoggerInner;
 
 impl core::fmt::Write for UARTLoggerInner {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            if c == '\n' {
-                PL011.uart().putchar('\r');
+        for c in s.bytes() {
+            if c == b'\n' {
+                PL011.uart().putchar(b'\r');
             }
             PL011.uart().putchar(c);
         }
@@ -82,3 +82,19 @@ pub fn dir_or_mnt_exists(path: &str) -> bool {
     };
     stat.is_dir || stat.mount.is_some()
 }
+
+/// Find the directory that contains `path`. Returns the directory and the last component of `path`.
+/// The last component may not exist.
+pub fn vfs_locate_parent(path: &str) -> Option<(Node, &str)> {
+    let path = path.trim().trim_end_matches('/');
+    let (dir, name) = path.rsplit_once('/')?;
+    let dir = vfs_open(if dir.is_empty() { "/" } else { dir })?;
+    let dir = match dir.mount {
+        Some(mnt) => super::mount::MOUNT_POINTS.read()[mnt]
+            .as_ref()?
+            .root
+            .clone(),
+        None => dir,
+    };
+    Some((dir, name))
+}
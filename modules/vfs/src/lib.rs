@@ -15,11 +15,12 @@ use crate::fs::FileDescriptor;
 use alloc::{
     borrow::ToOwned, boxed::Box, collections::BTreeMap, format, string::String, vec, vec::Vec,
 };
+use kernel_module::monitor::KMutex;
 use kernel_module::{kernel_module, KernelModule, SERVICE};
 use klib::proc::{Process, PID};
 use rootfs::ROOT_FS;
-use spin::{Mutex, RwLock};
-use vfs::{ramfs::RamFS, FileSystem, VFSManager, VFSRequest};
+use spin::RwLock;
+use vfs::{ramfs::RamFS, FileStat, FileSystem, Node, VFSManager, VFSRequest};
 
 #[kernel_module]
 pub static VFS: VFS = VFS {};
@@ -28,14 +29,25 @@ pub struct VFS {}
 
 impl VFS {
     #[inline]
-    fn get_current_state(&self) -> Option<&Mutex<ProcData>> {
+    fn get_current_state(&self) -> Option<&KMutex<ProcData>> {
         Some(self.get_state(&*SERVICE.current_proc()?))
     }
 
     #[inline]
-    fn get_state(&self, proc: &Process) -> &Mutex<ProcData> {
+    fn get_state(&self, proc: &Process) -> &KMutex<ProcData> {
         let state = proc.fs.as_ref() as *const dyn Any;
-        unsafe { (*state).downcast_ref_unchecked::<Mutex<ProcData>>() }
+        unsafe { (*state).downcast_ref_unchecked::<KMutex<ProcData>>() }
+    }
+}
+
+impl VFS {
+    /// Canonicalize `path` for the current process, and find the directory that contains it.
+    fn locate_parent(&self, path: &str) -> Option<(String, Node)> {
+        let proc_data = self.get_current_state().unwrap().lock();
+        let path = proc_data.canonicalize(path.to_owned()).ok()?;
+        drop(proc_data);
+        let (parent, _) = fs::vfs_locate_parent(&path)?;
+        Some((path, parent))
     }
 }
 
@@ -45,7 +57,7 @@ impl VFSManager for VFS {
     }
 
     fn register_process(&self, _proc: PID, cwd: String) -> Box<dyn Any> {
-        Box::new(Mutex::new(ProcData::new(cwd)))
+        Box::new(KMutex::new("vfs.proc", ProcData::new(cwd)))
     }
 
     fn deregister_process(&self, _proc: PID) {}
@@ -56,11 +68,14 @@ impl VFSManager for VFS {
 
     fn fork_process(&self, proc: &Process, _new_proc: PID) -> Box<dyn core::any::Any> {
         let proc_data = self.get_state(proc).lock();
-        Box::new(Mutex::new(ProcData {
-            nodes: proc_data.nodes.clone(),
-            cwd: proc_data.cwd.clone(),
-            files: proc_data.files,
-        }))
+        Box::new(KMutex::new(
+            "vfs.proc",
+            ProcData {
+                nodes: proc_data.nodes.clone(),
+                cwd: proc_data.cwd.clone(),
+                files: proc_data.files,
+            },
+        ))
     }
 }
 
@@ -103,6 +118,16 @@ impl ProcData {
         data
     }
 
+    /// Open a file descriptor for `node`, in the lowest free slot.
+    fn alloc_fd(&mut self, node: Node) -> isize {
+        let Some(fd) = self.nodes.iter().position(|n| n.is_none()) else {
+            return -1;
+        };
+        self.nodes[fd] = Some(FileDescriptor { node, offset: 0 });
+        self.files += 1;
+        fd as _
+    }
+
     fn set_cwd(&mut self, cwd: &str) -> Result<(), ()> {
         let cwd = self.canonicalize(cwd.to_owned())?;
         if !fs::dir_or_mnt_exists(&cwd) {
@@ -172,10 +197,92 @@ impl KernelModule for VFS {
                 } else {
                     node
                 };
-                let fd = proc_data.files;
-                proc_data.nodes[fd] = Some(FileDescriptor { node, offset: 0 });
-                proc_data.files += 1;
-                fd as _
+                proc_data.alloc_fd(node)
+            }
+            VFSRequest::Create(path) => {
+                let mut proc_data = self.get_current_state().unwrap().lock();
+                let Ok(path) = proc_data.canonicalize(path.to_owned()) else {
+                    return -1;
+                };
+                let Some((parent, file)) = fs::vfs_locate_parent(&path) else {
+                    return -1;
+                };
+                match parent.fs.create(&parent, file) {
+                    Some(node) => proc_data.alloc_fd(node),
+                    None => -1,
+                }
+            }
+            VFSRequest::Mkdir(path) => {
+                let Some((path, parent)) = self.locate_parent(path) else {
+                    return -1;
+                };
+                let dir = path.rsplit_once('/').unwrap().1;
+                match parent.fs.mkdir(&parent, dir) {
+                    Some(_) => 0,
+                    None => -1,
+                }
+            }
+            VFSRequest::Unlink(path) => {
+                let Some((path, parent)) = self.locate_parent(path) else {
+                    return -1;
+                };
+                let file = path.rsplit_once('/').unwrap().1;
+                match parent.fs.unlink(&parent, file) {
+                    Some(_) => 0,
+                    None => -1,
+                }
+            }
+            VFSRequest::Rename(from, to) => {
+                let (Some((from, parent)), Some((to, new_parent))) =
+                    (self.locate_parent(from), self.locate_parent(to))
+                else {
+                    return -1;
+                };
+                if parent.fs.name() != new_parent.fs.name() {
+                    return -1;
+                }
+                let file = from.rsplit_once('/').unwrap().1;
+                let new_file = to.rsplit_once('/').unwrap().1;
+                match parent.fs.rename(&parent, file, &new_parent, new_file) {
+                    Some(_) => 0,
+                    None => -1,
+                }
+            }
+            VFSRequest::Stat(path, stat) => {
+                let proc_data = self.get_current_state().unwrap().lock();
+                let Ok(path) = proc_data.canonicalize(path.to_owned()) else {
+                    return -1;
+                };
+                drop(proc_data);
+                if path == "/" {
+                    *stat = FileStat {
+                        size: 0,
+                        is_dir: true,
+                    };
+                    return 0;
+                }
+                let Some((parent, file)) = fs::vfs_locate_parent(&path) else {
+                    return -1;
+                };
+                match parent.fs.stat(&parent, file) {
+                    Some(s) => {
+                        *stat = FileStat {
+                            size: s.size,
+                            is_dir: s.is_dir || s.mount.is_some(),
+                        };
+                        0
+                    }
+                    None => -1,
+                }
+            }
+            VFSRequest::GetMounts(buf) => {
+                let mut mounts = String::from("/ rootfs\n");
+                for mnt in mount::MOUNT_POINTS.read().iter().flatten() {
+                    mounts += &format!("{} {}\n", mnt.parent.path, mnt.fs.name());
+                }
+                let len = usize::min(mounts.len(), buf.len());
+                buf[..len].copy_from_slice(&mounts.as_bytes()[..len]);
+                len as _
             }
             VFSRequest::Close(fd) => {
                 if fd.0 < 3 {
@@ -192,6 +299,23 @@ impl KernelModule for VFS {
                 node.fs.close(&node);
                 0
             }
+            VFSRequest::Dup2(old, new) => {
+                if old == new {
+                    return 0;
+                }
+                let mut proc_data = self.get_current_state().unwrap().lock();
+                let Some(fdesc) = proc_data.nodes.get(old.0 as usize).cloned().flatten() else {
+                    return -1;
+                };
+                if new.0 as usize >= proc_data.nodes.len() {
+                    return -1;
+                }
+                match proc_data.nodes[new.0 as usize].replace(fdesc) {
+                    Some(replaced) => replaced.node.fs.close(&replaced.node),
+                    None => proc_data.files += 1,
+                }
+                0
+            }
             VFSRequest::Read(fd, buf) => {
                 // log!("vfs read start");
                 let mut proc_data = self.get_current_state().unwrap().lock();
@@ -261,10 +385,16 @@ impl KernelModule for VFS {
                 }
             }
             VFSRequest::Mount { path, dev, fs } => {
-                assert!(privileged);
-                let fs = FILE_SYSTEMS.read()[fs];
-                mount::vfs_mount(&path, dev, unsafe { &*(fs as *const dyn FileSystem) }).unwrap();
-                0
+                if !privileged {
+                    return -1;
+                }
+                let Some(fs) = FILE_SYSTEMS.read().get(fs).copied() else {
+                    return -1;
+                };
+                match mount::vfs_mount(&path, dev, unsafe { &*(fs as *const dyn FileSystem) }) {
+                    Some(_) => 0,
+                    None => -1,
+                }
             }
             VFSRequest::GetCwd(buf) => {
                 let proc_data = self.get_current_state().unwrap().lock();
@@ -275,6 +405,19 @@ impl KernelModule for VFS {
                 unsafe { core::ptr::copy_nonoverlapping(cwd.as_ptr(), buf.as_mut_ptr(), cwd.len()) }
                 cwd.len() as _
             }
+            VFSRequest::Ioctl(fd, cmd, arg) => {
+                let proc_data = self.get_current_state().unwrap().lock();
+                let Some(fdesc) = proc_data.nodes.get(fd.0 as usize).and_then(|n| n.as_ref())
+                else {
+                    return -1;
+                };
+                let node = fdesc.node.clone();
+                drop(proc_data);
+                match node.fs.ioctl(&node, cmd, arg) {
+                    Some(v) => v as _,
+                    None => -1,
+                }
+            }
             VFSRequest::SetCwd(path) => {
                 let mut proc_data = self.get_current_state().unwrap().lock();
                 match proc_data.set_cwd(path) {
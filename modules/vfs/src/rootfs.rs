@@ -5,7 +5,7 @@ use core::{
 
 use alloc::{borrow::ToOwned, boxed::Box, format, string::String, vec::Vec};
 use spin::{Lazy, RwLock};
-use vfs::ramfs::{self, RamFS};
+use vfs::ramfs::{self, Dir, Entry, File, RamFS};
 use vfs::{FileSystem, Node, Stat};
 
 pub static ROOT_FS: Lazy<RootFS> = Lazy::new(|| RootFS::new());
@@ -43,6 +43,15 @@ impl RootFS {
             .insert("rootfs".to_owned(), self);
     }
 
+    fn dir_mut<'a>(fs: &'a mut RamFS, node: &Node) -> Option<&'a mut Dir> {
+        let path = if node.path.is_empty() {
+            "/"
+        } else {
+            &node.path
+        };
+        fs.get_mut(path)?.as_dir_mut()
+    }
+
     pub fn root_node(&self) -> Node {
         Node {
             name: "/".into(),
@@ -67,6 +76,7 @@ impl FileSystem for RootFS {
                 fs: unsafe { &*(self as *const Self) },
                 mount: entry.as_mnt().map(|x| x.key),
                 is_dir: entry.as_dir().is_some(),
+                size: entry.as_file().map(|f| f.len()).unwrap_or(0),
             })
     }
     fn open(&self, parent: &Node, fname: &str) -> Option<Node> {
@@ -105,8 +115,10 @@ impl FileSystem for RootFS {
             None
         }
     }
-    fn write(&self, _node: &Node, _offset: usize, _buf: &[u8]) -> Option<usize> {
-        unimplemented!()
+    fn write(&self, node: &Node, offset: usize, buf: &[u8]) -> Option<usize> {
+        let mut fs = self.ramfs.write();
+        let file = fs.get_mut(&node.path)?.as_file_mut()?;
+        Some(file.write(offset, buf))
     }
     fn read_dir(&self, node: &Node) -> Option<Vec<String>> {
         let fs = self.ramfs.read();
@@ -121,6 +133,52 @@ impl FileSystem for RootFS {
             None
         }
     }
+    fn create(&self, parent: &Node, file: &str) -> Option<Node> {
+        {
+            let mut fs = self.ramfs.write();
+            let dir = Self::dir_mut(&mut fs, parent)?;
+            match dir.get_mut(file) {
+                Some(Entry::File(f)) => f.truncate(),
+                Some(_) => return None,
+                None => dir.insert_entry(file, Entry::File(File::default())).ok()?,
+            }
+        }
+        self.open(parent, file)
+    }
+    fn mkdir(&self, parent: &Node, dir: &str) -> Option<()> {
+        let mut fs = self.ramfs.write();
+        Self::dir_mut(&mut fs, parent)?
+            .insert_entry(dir, Entry::Dir(Dir::default()))
+            .ok()
+    }
+    fn unlink(&self, parent: &Node, file: &str) -> Option<()> {
+        let mut fs = self.ramfs.write();
+        let dir = Self::dir_mut(&mut fs, parent)?;
+        match dir.get_mut(file)? {
+            Entry::Dir(d) if !d.is_empty() => return None,
+            Entry::Mount(_) => return None,
+            _ => {}
+        }
+        dir.remove_entry(file).map(|_| ())
+    }
+    fn rename(&self, parent: &Node, file: &str, new_parent: &Node, new_file: &str) -> Option<()> {
+        let mut fs = self.ramfs.write();
+        let dir = Self::dir_mut(&mut fs, parent)?;
+        if let Entry::Mount(_) = dir.get_mut(file)? {
+            return None;
+        }
+        let entry = dir.remove_entry(file)?;
+        let result = match Self::dir_mut(&mut fs, new_parent) {
+            Some(dir) => dir.insert_entry(new_file, entry),
+            None => Err(entry),
+        };
+        if let Err(entry) = result {
+            // Put it back
+            let _ = Self::dir_mut(&mut fs, parent)?.insert_entry(file, entry);
+            return None;
+        }
+        Some(())
+    }
     fn mount(&self, parent: &Node, file: &str, key: usize) -> Option<Node> {
         let mut fs = self.ramfs.write();
         let path = format!("{}/{}", parent.path, file);
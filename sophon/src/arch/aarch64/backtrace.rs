@@ -0,0 +1,56 @@
+//! Stack unwinding with frame pointers.
+//!
+//! Everything is built with `frame-pointer = always`, so `x29` points to a frame record
+//! holding the caller's `x29` and the return address.
+
+use core::arch::asm;
+
+/// Records are not followed deeper than this.
+const MAX_FRAMES: usize = 64;
+
+/// The call sites in the chain of frame records starting at `fp`, innermost first.
+///
+/// A record is only read if it can be read at the given exception level, so that a corrupted
+/// chain ends the walk instead of faulting.
+pub struct Frames {
+    fp: usize,
+    user: bool,
+    depth: usize,
+}
+
+impl Frames {
+    pub fn new(fp: usize, user: bool) -> Self {
+        Self { fp, user, depth: 0 }
+    }
+
+    /// The frames of the caller of this function.
+    #[inline(always)]
+    pub fn current() -> Self {
+        let fp: usize;
+        unsafe { asm!("mov {}, x29", out(reg) fp) };
+        Self::new(fp, false)
+    }
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.fp == 0 || self.fp % 16 != 0 || self.depth >= MAX_FRAMES {
+            return None;
+        }
+        if super::translate(self.fp, self.user).is_none() {
+            return None;
+        }
+        let record = self.fp as *const [usize; 2];
+        let [caller_fp, lr] = unsafe { record.read_volatile() };
+        self.depth += 1;
+        // The stack grows down, so the caller's record is above this one.
+        self.fp = if caller_fp > self.fp { caller_fp } else { 0 };
+        if lr < 4 {
+            return None;
+        }
+        // The `bl` before the return address
+        Some(lr - 4)
+    }
+}
@@ -6,39 +6,33 @@ use alloc::vec;
 use alloc::vec::Vec;
 use atomic::Ordering;
 use core::arch::asm;
+use core::iter::Step;
 use core::ops::Range;
 use core::ptr;
-use core::sync::atomic::{AtomicBool, AtomicIsize};
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize};
 use cortex_a::registers::*;
 use memory::address::{Address, V};
 use memory::page::PageResource;
 use memory::page::*;
 use spin::Mutex;
-use tock_registers::interfaces::Readable;
+use tock_registers::interfaces::{Readable, Writeable};
 
 #[repr(C, align(4096))]
 pub struct KernelStack {
-    /// This page is protected to trap stack overflow
+    /// This page is not mapped, to trap stack overflow
     guard: [u8; Size4K::BYTES],
     stack: [u8; KERNEL_STACK_SIZE],
 }
 
 impl KernelStack {
     pub fn new() -> &'static mut Self {
-        let pages = KERNEL_STACK_PAGES + 1;
-        let stack = KERNEL_HEAP.acquire_pages::<Size4K>(pages).unwrap();
-        let kernel_stack = unsafe { stack.start.start().as_mut::<Self>() };
+        let pages = KERNEL_HEAP.virtual_allocate::<Size4K>(KERNEL_STACK_PAGES + 1);
+        KERNEL_HEAP.back_pages(Page::forward(pages.start, 1)..pages.end);
+        let kernel_stack = unsafe { pages.start.start().as_mut::<Self>() };
         kernel_stack.init();
         kernel_stack
     }
     pub fn init(&mut self) {
-        // let guard_page = Page::<Size4K, V>::new(Address::from(&self.guard as *const [u8; Size4K::SIZE]));
-        // PageTable::<L4>::get(true).update_flags(guard_page, PageFlags::_KERNEL_STACK_GUARD_FLAGS);
-        // let stack_page_start = Page::<Size4K, V>::new(Address::from(&self.stack as *const [u8; KERNEL_STACK_SIZE]));
-        // let stack_page_end = stack_page_start.add_usize(KERNEL_STACK_PAGES).unwrap();
-        // for stack_page in stack_page_start..stack_page_end {
-        //     PageTable::<L4>::get(true).update_flags(stack_page, PageFlags::_KERNEL_STACK_FLAGS);
-        // }
         for i in 0..KERNEL_STACK_SIZE {
             unsafe {
                 core::ptr::write_volatile(&mut self.stack[i], 0);
@@ -52,21 +46,28 @@ impl KernelStack {
     }
 }
 
-impl Drop for KernelStack {
-    // Unprotect stack pages
-    fn drop(&mut self) {
-        unreachable!()
-        // let guard_page = Page::<Size4K, V>::new(Address::from(&self.guard as *const [u8; Size4K::SIZE]));
-        // PageTable::<L4>::get(true).update_flags(guard_page, PageFlags::_KERNEL_DATA_FLAGS_4K);
-        // let stack_page_start = Page::<Size4K, V>::new(Address::from(&self.stack as *const [u8; KERNEL_STACK_SIZE]));
-        // let stack_page_end = ::core::iter::Step::forward(stack_page_start, KERNEL_STACK_PAGES);
-        // for stack_page in stack_page_start..stack_page_end {
-        //     PageTable::<L4>::get(true).update_flags(stack_page, PageFlags::_KERNEL_DATA_FLAGS_4K);
-        // }
+impl KernelStack {
+    /// Release the stack pages.
+    ///
+    /// Safety: No core is running on this stack, and the stack is no longer referenced.
+    pub unsafe fn free(stack: *mut Self) {
+        let start = Page::<Size4K>::new(Address::from(stack as usize));
+        let pages = start..Page::forward(start, KERNEL_STACK_PAGES + 1);
+        // The guard page is not mapped
+        KERNEL_HEAP.release_pages(Page::forward(start, 1)..pages.end);
+        KERNEL_HEAP.virtual_release(pages);
+        // Other cores may still cache the translations.
+        crate::task::ipi::tlb_shootdown();
     }
 }
 
 /// Represents the archtectural context (i.e. registers)
+///
+/// Each task owns a kernel stack. Exceptions taken by the task push an `ExceptionFrame`
+/// to its kernel stack, and exceptions can nest: A syscall that blocks issues another
+/// `svc` from EL1, whose frame is on top of the syscall frame.
+/// Switching to a task loads its top-most frame, so a blocked syscall continues
+/// right after the point it blocked, instead of being restarted.
 #[allow(improper_ctypes)]
 #[repr(C)]
 pub struct AArch64Context {
@@ -91,6 +92,9 @@ pub struct AArch64Context {
     kernel_stack_top: *mut u8,
     response_status: AtomicIsize,
     response_status_set: AtomicBool,
+    /// `TPIDR_EL0`, loaded when switching to the task.
+    /// User code must not change it, as it is not saved on a switch.
+    thread_pointer: AtomicUsize,
 }
 
 impl AArch64Context {
@@ -102,6 +106,19 @@ impl AArch64Context {
         let _guard = interrupt::uninterruptible();
         self.exception_frames.lock().pop()
     }
+    /// The frame the task continues from. `None` if it has none, or the frame list is locked.
+    pub fn top_exception_frame(&self) -> Option<*mut ExceptionFrame> {
+        self.exception_frames.try_lock()?.last().copied()
+    }
+    /// Will the next `return_to_user` return to EL0?
+    fn is_returning_to_el0(&self) -> bool {
+        let _guard = interrupt::uninterruptible();
+        self.exception_frames
+            .lock()
+            .last()
+            .map(|f| unsafe { (**f).spsr_el1 } & 0b1111 == 0)
+            .unwrap_or(false)
+    }
 }
 
 impl ArchContext for AArch64Context {
@@ -113,6 +130,7 @@ impl ArchContext for AArch64Context {
             kernel_stack_top: ptr::null_mut(),
             response_status: AtomicIsize::new(0),
             response_status_set: AtomicBool::new(false),
+            thread_pointer: AtomicUsize::new(0),
         }
     }
 
@@ -136,6 +154,7 @@ impl ArchContext for AArch64Context {
             kernel_stack_top: sp,
             response_status: AtomicIsize::new(0),
             response_status_set: AtomicBool::new(false),
+            thread_pointer: AtomicUsize::new(self.thread_pointer.load(Ordering::SeqCst)),
         }
     }
 
@@ -158,8 +177,16 @@ impl ArchContext for AArch64Context {
         self.response_status_set.store(true, Ordering::SeqCst);
     }
 
+    fn set_thread_pointer(&self, tp: usize) {
+        self.thread_pointer.store(tp, Ordering::SeqCst);
+    }
+
     unsafe extern "C" fn return_to_user(&self) -> ! {
         assert!(!interrupt::is_enabled());
+        // Signals are only handled at the boundary to user space, where no kernel locks are held.
+        if self.is_returning_to_el0() {
+            crate::task::signal::handle_pending_signals();
+        }
         // Switch page table
         let p4 = crate::task::proc::PROCESS_MANAGER
             .current_proc()
@@ -189,10 +216,17 @@ impl ArchContext for AArch64Context {
             slot.store(status);
             (*exception_frame).x0 = ::core::mem::transmute(status);
         }
-        // Set stack pointer
-        asm!("mov sp, {}", in(reg) exception_frame);
-        // Return from exception
-        super::exception::exit_exception();
+        TPIDR_EL0.set(self.thread_pointer.load(Ordering::SeqCst) as u64);
+        // Switch to the task's kernel stack, finish the context switch, and return from exception.
+        // Note: No other core can pick up the previous task until `finish_task_switch` is called.
+        asm!(
+            "mov sp, {frame}",
+            "bl {finish}",
+            "b exit_exception",
+            frame = in(reg) exception_frame,
+            finish = sym crate::task::sched::finish_task_switch,
+            options(noreturn)
+        );
     }
 
     unsafe fn enter_usermode(
@@ -215,6 +249,7 @@ impl ArchContext for AArch64Context {
                 msr spsr_el1, {0}
                 msr elr_el1, {1}
                 msr sp_el0, {2}
+                msr tpidr_el0, {7}
                 msr	ttbr0_el1, {3}
                 mov x0, {4}
                 mov x1, {5}
@@ -231,6 +266,7 @@ impl ArchContext for AArch64Context {
             in(reg) argc,
             in(reg) argv,
             in(reg) self.kernel_stack_top,
+            in(reg) self.thread_pointer.load(Ordering::SeqCst),
         }
         unreachable!()
     }
@@ -238,6 +274,9 @@ impl ArchContext for AArch64Context {
 
 impl Drop for AArch64Context {
     fn drop(&mut self) {
-        // println!("Context drop");
+        // The scheduler only drops a task after all the cores left its kernel stack.
+        if let Some(stack) = self.kernel_stack.take() {
+            unsafe { KernelStack::free(stack) }
+        }
     }
 }
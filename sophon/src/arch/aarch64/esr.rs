@@ -0,0 +1,209 @@
+//! Decoding of the exception syndrome register (`ESR_EL1`).
+
+use core::fmt;
+
+/// Exception classes: bits [31:26] of `ESR_EL1`.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionClass {
+    /// Unknown reason, e.g. an undefined instruction.
+    Unknown = 0b000000,
+    TrappedWfiWfe = 0b000001,
+    /// Access to SIMD or floating-point registers, when it is disabled.
+    TrappedFpAccess = 0b000111,
+    IllegalExecutionState = 0b001110,
+    SVCAArch64 = 0b010101,
+    /// `MSR`, `MRS` or a system instruction that is not allowed at the current level.
+    TrappedSystemRegister = 0b011000,
+    InstructionAbortLowerEL = 0b100000,
+    InstructionAbortHigherEL = 0b100001,
+    PCAlignment = 0b100010,
+    DataAbortLowerEL = 0b100100,
+    DataAbortHigherEL = 0b100101,
+    SPAlignment = 0b100110,
+    FpException = 0b101100,
+    SError = 0b101111,
+    BreakpointLowerEL = 0b110000,
+    BreakpointHigherEL = 0b110001,
+    SoftwareStepLowerEL = 0b110010,
+    SoftwareStepHigherEL = 0b110011,
+    WatchpointLowerEL = 0b110100,
+    WatchpointHigherEL = 0b110101,
+    /// The `BRK` instruction.
+    Brk = 0b111100,
+}
+
+impl ExceptionClass {
+    const ALL: [ExceptionClass; 21] = [
+        Self::Unknown,
+        Self::TrappedWfiWfe,
+        Self::TrappedFpAccess,
+        Self::IllegalExecutionState,
+        Self::SVCAArch64,
+        Self::TrappedSystemRegister,
+        Self::InstructionAbortLowerEL,
+        Self::InstructionAbortHigherEL,
+        Self::PCAlignment,
+        Self::DataAbortLowerEL,
+        Self::DataAbortHigherEL,
+        Self::SPAlignment,
+        Self::FpException,
+        Self::SError,
+        Self::BreakpointLowerEL,
+        Self::BreakpointHigherEL,
+        Self::SoftwareStepLowerEL,
+        Self::SoftwareStepHigherEL,
+        Self::WatchpointLowerEL,
+        Self::WatchpointHigherEL,
+        Self::Brk,
+    ];
+
+    pub fn from_u32(ec: u32) -> Option<Self> {
+        Self::ALL.iter().find(|c| **c as u32 == ec).copied()
+    }
+
+    pub const fn description(&self) -> &'static str {
+        match self {
+            Self::Unknown => "undefined instruction",
+            Self::TrappedWfiWfe => "trapped WFI/WFE",
+            Self::TrappedFpAccess => "trapped floating-point access",
+            Self::IllegalExecutionState => "illegal execution state",
+            Self::SVCAArch64 => "system call",
+            Self::TrappedSystemRegister => "trapped system register access",
+            Self::InstructionAbortLowerEL | Self::InstructionAbortHigherEL => "instruction abort",
+            Self::PCAlignment => "PC alignment fault",
+            Self::DataAbortLowerEL | Self::DataAbortHigherEL => "data abort",
+            Self::SPAlignment => "SP alignment fault",
+            Self::FpException => "floating-point exception",
+            Self::SError => "SError",
+            Self::BreakpointLowerEL | Self::BreakpointHigherEL => "breakpoint",
+            Self::SoftwareStepLowerEL | Self::SoftwareStepHigherEL => "software step",
+            Self::WatchpointLowerEL | Self::WatchpointHigherEL => "watchpoint",
+            Self::Brk => "BRK instruction",
+        }
+    }
+}
+
+/// A value of `ESR_EL1`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Esr(pub u32);
+
+impl Esr {
+    pub fn read() -> Self {
+        let esr: u64;
+        unsafe { core::arch::asm!("mrs {}, esr_el1", out(reg) esr) };
+        Self(esr as u32)
+    }
+
+    /// The exception class, or `None` if it is not known.
+    pub fn class(&self) -> Option<ExceptionClass> {
+        ExceptionClass::from_u32(self.0 >> 26)
+    }
+
+    /// Instruction specific syndrome.
+    pub const fn iss(&self) -> u32 {
+        self.0 & 0x1ff_ffff
+    }
+
+    fn is_abort(&self) -> bool {
+        matches!(
+            self.class(),
+            Some(
+                ExceptionClass::InstructionAbortLowerEL
+                    | ExceptionClass::InstructionAbortHigherEL
+                    | ExceptionClass::DataAbortLowerEL
+                    | ExceptionClass::DataAbortHigherEL
+            )
+        )
+    }
+
+    /// Does `FAR_EL1` hold the faulting address? It is unknown for the other exceptions.
+    pub fn has_fault_address(&self) -> bool {
+        match self.class() {
+            Some(ExceptionClass::PCAlignment)
+            | Some(ExceptionClass::WatchpointLowerEL)
+            | Some(ExceptionClass::WatchpointHigherEL) => true,
+            // FnV: FAR is not valid
+            _ => self.is_abort() && self.iss() & (1 << 10) == 0,
+        }
+    }
+
+    /// Is the fault of a data abort caused by a write?
+    pub fn is_write(&self) -> bool {
+        self.is_abort() && self.iss() & (1 << 6) != 0
+    }
+
+    /// The fault status code of an abort.
+    pub fn fault_status(&self) -> Option<FaultStatus> {
+        if !self.is_abort() {
+            return None;
+        }
+        let code = self.iss() & 0x3f;
+        let status = match code {
+            0b000000..=0b000011 => FaultStatus::AddressSize(code & 3),
+            0b000100..=0b000111 => FaultStatus::Translation(code & 3),
+            0b001001..=0b001011 => FaultStatus::AccessFlag(code & 3),
+            0b001101..=0b001111 => FaultStatus::Permission(code & 3),
+            0b010000 => FaultStatus::External,
+            0b100001 => FaultStatus::Alignment,
+            0b110000 => FaultStatus::TlbConflict,
+            _ => FaultStatus::Other(code),
+        };
+        Some(status)
+    }
+}
+
+impl fmt::Debug for Esr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
+
+impl fmt::Display for Esr {
+    /// e.g. "data abort, translation fault at level 3 on write"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.class() {
+            Some(class) => write!(f, "{}", class.description())?,
+            None => write!(f, "unknown exception class {:#b}", self.0 >> 26)?,
+        }
+        if let Some(status) = self.fault_status() {
+            write!(f, ", {:?}", status)?;
+            if matches!(
+                self.class(),
+                Some(ExceptionClass::DataAbortLowerEL | ExceptionClass::DataAbortHigherEL)
+            ) {
+                write!(f, " on {}", if self.is_write() { "write" } else { "read" })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Data and instruction fault status codes. The numbers are translation table levels.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FaultStatus {
+    AddressSize(u32),
+    Translation(u32),
+    AccessFlag(u32),
+    Permission(u32),
+    /// Synchronous external abort.
+    External,
+    Alignment,
+    TlbConflict,
+    Other(u32),
+}
+
+impl fmt::Debug for FaultStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddressSize(level) => write!(f, "address size fault at level {}", level),
+            Self::Translation(level) => write!(f, "translation fault at level {}", level),
+            Self::AccessFlag(level) => write!(f, "access flag fault at level {}", level),
+            Self::Permission(level) => write!(f, "permission fault at level {}", level),
+            Self::External => write!(f, "synchronous external abort"),
+            Self::Alignment => write!(f, "alignment fault"),
+            Self::TlbConflict => write!(f, "TLB conflict"),
+            Self::Other(code) => write!(f, "fault status {:#08b}", code),
+        }
+    }
+}
@@ -1,13 +1,21 @@
+use super::backtrace::Frames;
+use super::esr::{Esr, ExceptionClass, FaultStatus};
 use crate::arch::{aarch64::context::*, *};
 use crate::memory::kernel::KERNEL_MEMORY_MAPPER;
-use crate::memory::physical::PHYSICAL_MEMORY;
+use crate::memory::utils::copy_on_write;
 use crate::modules::INTERRUPT;
 use crate::task::sched::SCHEDULER;
-use crate::task::PROCESS_MANAGER;
+use crate::task::user::{self, StackFault};
+use crate::task::{signal, PROCESS_MANAGER};
+use crate::utils::backtrace::{self, Symbolized};
+use crate::utils::trace;
 use core::arch::{asm, global_asm};
 use cortex_a::{asm::barrier, registers::*};
+use klib::proc::PID;
 use memory::page::{Page, Size1G, Size2M, Size4K};
 use memory::page_table::PageFlags;
+use syscall::trace::Kind;
+use syscall::Signal;
 use tock_registers::interfaces::{Readable, Writeable};
 
 #[repr(usize)]
@@ -29,15 +37,6 @@ pub enum ExceptionKind {
     SError = 3,
 }
 
-#[repr(u32)]
-#[derive(Debug)]
-#[allow(unused)]
-pub enum ExceptionClass {
-    SVCAArch64 = 0b010101,
-    DataAbortLowerEL = 0b100100,
-    DataAbortHigherEL = 0b100101,
-}
-
 #[repr(C)]
 #[derive(Debug)]
 pub struct ExceptionFrame {
@@ -121,10 +120,82 @@ impl Default for ExceptionFrame {
     }
 }
 
-unsafe fn get_exception_class() -> ExceptionClass {
-    let esr_el1: u32;
-    asm!("mrs {:x}, esr_el1", out(reg) esr_el1);
-    ::core::mem::transmute(esr_el1 >> 26)
+impl ExceptionFrame {
+    /// The general purpose register `x<i>`, for `i` in `0..=30`.
+    pub fn x(&self, i: usize) -> usize {
+        match i {
+            0 => self.x0,
+            1 => self.x1,
+            2 => self.x2,
+            3 => self.x3,
+            4 => self.x4,
+            5 => self.x5,
+            6 => self.x6,
+            7 => self.x7,
+            8 => self.x8,
+            9 => self.x9,
+            10 => self.x10,
+            11 => self.x11,
+            12 => self.x12,
+            13 => self.x13,
+            14 => self.x14,
+            15 => self.x15,
+            16 => self.x16,
+            17 => self.x17,
+            18 => self.x18,
+            19 => self.x19,
+            20 => self.x20,
+            21 => self.x21,
+            22 => self.x22,
+            23 => self.x23,
+            24 => self.x24,
+            25 => self.x25,
+            26 => self.x26,
+            27 => self.x27,
+            28 => self.x28,
+            29 => self.x29,
+            30 => self.x30,
+            _ => panic!("invalid register x{}", i),
+        }
+    }
+
+    /// Mutable `x<i>`, for `i` in `0..=30`.
+    pub fn x_mut(&mut self, i: usize) -> &mut usize {
+        match i {
+            0 => &mut self.x0,
+            1 => &mut self.x1,
+            2 => &mut self.x2,
+            3 => &mut self.x3,
+            4 => &mut self.x4,
+            5 => &mut self.x5,
+            6 => &mut self.x6,
+            7 => &mut self.x7,
+            8 => &mut self.x8,
+            9 => &mut self.x9,
+            10 => &mut self.x10,
+            11 => &mut self.x11,
+            12 => &mut self.x12,
+            13 => &mut self.x13,
+            14 => &mut self.x14,
+            15 => &mut self.x15,
+            16 => &mut self.x16,
+            17 => &mut self.x17,
+            18 => &mut self.x18,
+            19 => &mut self.x19,
+            20 => &mut self.x20,
+            21 => &mut self.x21,
+            22 => &mut self.x22,
+            23 => &mut self.x23,
+            24 => &mut self.x24,
+            25 => &mut self.x25,
+            26 => &mut self.x26,
+            27 => &mut self.x27,
+            28 => &mut self.x28,
+            29 => &mut self.x29,
+            30 => &mut self.x30,
+            _ => panic!("invalid register x{}", i),
+        }
+    }
 }
 
 unsafe fn is_el0(frame: &ExceptionFrame) -> bool {
@@ -134,12 +205,20 @@ unsafe fn is_el0(frame: &ExceptionFrame) -> bool {
 #[no_mangle]
 pub unsafe extern "C" fn handle_exception(exception_frame: &mut ExceptionFrame) {
     let privileged = !is_el0(exception_frame);
+    if SCHEDULER.get_current_task_id().is_none() {
+        // Before the scheduler starts. Only the initial breakpoint of the debugger is expected.
+        if !super::gdb::handle_debug_exception(exception_frame, Esr::read()) {
+            panic_for_unhandled_exception(exception_frame);
+        }
+        return_from_exception(exception_frame);
+    }
     AArch64Context::of(&*SCHEDULER.get_current_task().unwrap())
         .push_exception_frame(exception_frame);
-    let exception = get_exception_class();
-    // trace!("Exception received {:?}", exception);
-    match exception {
-        ExceptionClass::SVCAArch64 => {
+    let esr = Esr::read();
+    trace::begin(Kind::Exception, (esr.0 >> 26) as usize);
+    // trace!("Exception received {:?}", esr.class());
+    match esr.class() {
+        Some(ExceptionClass::SVCAArch64) => {
             // trace!(
             //     "SVCAArch64 Start {:?} #{} privileged={}",
             //     SCHEDULER.get_current_task().unwrap().id,
@@ -162,7 +241,7 @@ pub unsafe extern "C" fn handle_exception(exception_frame: &mut ExceptionFrame)
             exception_frame.x0 = ::core::mem::transmute(r);
             // trace!("SVCAArch64 End");
         }
-        ExceptionClass::DataAbortLowerEL | ExceptionClass::DataAbortHigherEL => {
+        Some(ExceptionClass::DataAbortLowerEL | ExceptionClass::DataAbortHigherEL) => {
             let mut far: usize;
             asm!("mrs {:x}, far_el1", out(reg) far);
             let mut elr: usize;
@@ -183,21 +262,16 @@ pub unsafe extern "C" fn handle_exception(exception_frame: &mut ExceptionFrame)
                     // trace!("FAULT_FLAGS {:?} {:?} {:?}", fault_addr, flags, a);
                     if flags.contains(PageFlags::COPY_ON_WRITE) {
                         // trace!("COW {:?} {:?}", fault_addr, flags);
-                        match level {
-                            1 => pt.copy_on_write::<Size4K>(
-                                Page::containing(fault_addr),
-                                &PHYSICAL_MEMORY,
-                            ),
-                            2 => pt.copy_on_write::<Size2M>(
-                                Page::containing(fault_addr),
-                                &PHYSICAL_MEMORY,
-                            ),
-                            3 => pt.copy_on_write::<Size1G>(
-                                Page::containing(fault_addr),
-                                &PHYSICAL_MEMORY,
-                            ),
+                        // If out of memory, the fault is retried after the OOM victim has
+                        // released its memory. The victim may be this process, which then exits
+                        // on the way back to user space.
+                        let _ = match level {
+                            1 => copy_on_write::<Size4K>(pt, Page::containing(fault_addr)),
+                            2 => copy_on_write::<Size2M>(pt, Page::containing(fault_addr)),
+                            3 => copy_on_write::<Size1G>(pt, Page::containing(fault_addr)),
                             _ => unreachable!(),
-                        }
+                        };
+                        crate::task::ipi::tlb_shootdown();
                         handled = true;
                     }
                 }
@@ -208,10 +282,29 @@ pub unsafe extern "C" fn handle_exception(exception_frame: &mut ExceptionFrame)
                 // );
             }
             if !handled {
+                match user::handle_stack_fault(&proc, Address::from(far)) {
+                    StackFault::NotStack => {}
+                    StackFault::Handled => handled = true,
+                    StackFault::Overflow => {
+                        error!(
+                            "stack overflow in pid {:?} / task {:?}: FAR={:?} ELR={:?}",
+                            proc.id, task.id, far as *mut (), elr as *mut ()
+                        );
+                        if !privileged {
+                            handle_user_fault(exception_frame, esr, Signal::SegmentationFault);
+                            handled = true;
+                        }
+                    }
+                }
+            }
+            if !handled && !privileged {
+                handle_user_fault(exception_frame, esr, fault_signal(esr));
+            } else if !handled {
                 error!(
                     "Data Abort: FAR={:?} ELR={:?} PRIV={:?} TID={:?} PID={:?}",
                     far as *mut (), elr as *mut (), privileged, task.id, proc.id
                 );
+                error!("ELR: {}", Symbolized::kernel(elr));
                 let translation = {
                     let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
                     PageTable::get().translate_with_flags(far.into())
@@ -225,50 +318,176 @@ pub unsafe extern "C" fn handle_exception(exception_frame: &mut ExceptionFrame)
                 panic!()
             }
         }
-        #[allow(unreachable_patterns)]
+        Some(
+            ExceptionClass::Brk
+            | ExceptionClass::SoftwareStepLowerEL
+            | ExceptionClass::SoftwareStepHigherEL,
+        ) if super::gdb::handle_debug_exception(exception_frame, esr) => {}
+        _ if !privileged => handle_user_fault(exception_frame, esr, fault_signal(esr)),
         _ => panic_for_unhandled_exception(exception_frame),
     }
+    trace::end(Kind::Exception, (esr.0 >> 26) as usize);
     // Note: `Task::current()` must be dropped before calling `return_to_user`.
     let context =
         AArch64Context::of(&*SCHEDULER.get_current_task().unwrap()) as *const AArch64Context;
     (*context).return_to_user();
 }
 
+/// The signal that terminates a user process for an exception.
+fn fault_signal(esr: Esr) -> Signal {
+    match esr.class() {
+        Some(
+            ExceptionClass::InstructionAbortLowerEL
+            | ExceptionClass::InstructionAbortHigherEL
+            | ExceptionClass::DataAbortLowerEL
+            | ExceptionClass::DataAbortHigherEL,
+        ) => match esr.fault_status() {
+            Some(FaultStatus::Alignment) => Signal::BusError,
+            _ => Signal::SegmentationFault,
+        },
+        Some(
+            ExceptionClass::PCAlignment | ExceptionClass::SPAlignment | ExceptionClass::SError,
+        ) => Signal::BusError,
+        Some(ExceptionClass::FpException) => Signal::FloatingPoint,
+        Some(
+            ExceptionClass::BreakpointLowerEL
+            | ExceptionClass::BreakpointHigherEL
+            | ExceptionClass::SoftwareStepLowerEL
+            | ExceptionClass::SoftwareStepHigherEL
+            | ExceptionClass::WatchpointLowerEL
+            | ExceptionClass::WatchpointHigherEL
+            | ExceptionClass::Brk,
+        ) => Signal::Trap,
+        Some(
+            ExceptionClass::Unknown
+            | ExceptionClass::TrappedWfiWfe
+            | ExceptionClass::TrappedFpAccess
+            | ExceptionClass::IllegalExecutionState
+            | ExceptionClass::SVCAArch64
+            | ExceptionClass::TrappedSystemRegister,
+        )
+        | None => Signal::IllegalInstruction,
+    }
+}
+
+/// Report an exception taken from user space, and terminate the process with `signal`
+/// on its way back to user space. Only the faulting process is affected.
+/// A fault in init is fatal, as init has no default signal actions.
+unsafe fn handle_user_fault(exception_frame: &ExceptionFrame, esr: Esr, signal: Signal) {
+    let proc = PROCESS_MANAGER.current_proc().unwrap();
+    let task = SCHEDULER.get_current_task_id().unwrap();
+    error!(
+        "user fault in pid {} ({}) / task {:?}: {}",
+        proc.id.0,
+        proc.name.lock(),
+        task,
+        esr
+    );
+    if esr.has_fault_address() {
+        error!("  FAR={:#018x}", FAR_EL1.get());
+    }
+    error!(
+        "  ELR={:?} ESR={:?} SPSR={:#x}",
+        exception_frame.elr_el1, esr, exception_frame.spsr_el1
+    );
+    // x0-x30 and sp, four per line
+    let reg = |i: usize| match i {
+        31 => exception_frame.sp_el0,
+        _ => exception_frame.x(i),
+    };
+    for i in (0..32).step_by(4) {
+        error!(
+            "  {}={:#018x} {}={:#018x} {}={:#018x} {}={:#018x}",
+            RegisterName(i),
+            reg(i),
+            RegisterName(i + 1),
+            reg(i + 1),
+            RegisterName(i + 2),
+            reg(i + 2),
+            RegisterName(i + 3),
+            reg(i + 3)
+        );
+    }
+    backtrace::print_user_backtrace(
+        &proc,
+        exception_frame.elr_el1 as usize,
+        Frames::new(exception_frame.x29, true),
+    );
+    if proc.id == PID::INIT {
+        panic!("init terminated by {:?}", signal);
+    }
+    error!("  terminating pid {} with {:?}", proc.id.0, signal);
+    signal::send(&proc, signal);
+}
+
+/// Formats `x<i>`, or `sp` for 31, padded to three characters.
+struct RegisterName(usize);
+
+impl core::fmt::Display for RegisterName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            31 => write!(f, "sp "),
+            i if i < 10 => write!(f, "x{} ", i),
+            i => write!(f, "x{}", i),
+        }
+    }
+}
+
+/// SErrors are asynchronous aborts, e.g. external bus errors. One taken from user space
+/// terminates the process. In the kernel it is fatal.
 #[no_mangle]
-pub unsafe extern "C" fn handle_exception_serror(exception_frame: *mut ExceptionFrame) {
+pub unsafe extern "C" fn handle_exception_serror(exception_frame: &mut ExceptionFrame) {
     info!("SError received");
-    panic_for_unhandled_exception(exception_frame);
+    if !is_el0(exception_frame) {
+        panic_for_unhandled_exception(exception_frame);
+    }
+    AArch64Context::of(&*SCHEDULER.get_current_task().unwrap())
+        .push_exception_frame(exception_frame);
+    handle_user_fault(exception_frame, Esr::read(), Signal::BusError);
+    // Note: `Task::current()` must be dropped before calling `return_to_user`.
+    let context =
+        AArch64Context::of(&*SCHEDULER.get_current_task().unwrap()) as *const AArch64Context;
+    (*context).return_to_user();
+}
+
+/// Return from an exception taken without a task, through the frame on the current stack.
+unsafe fn return_from_exception(exception_frame: *mut ExceptionFrame) -> ! {
+    asm!(
+        "mov sp, {}",
+        "b exit_exception",
+        in(reg) exception_frame,
+        options(noreturn)
+    );
 }
 
 unsafe fn panic_for_unhandled_exception(exception_frame: *mut ExceptionFrame) -> ! {
-    let exception = get_exception_class();
+    let esr = Esr::read();
     info!(
         "Exception Frame: {:?} {:?}",
         exception_frame, *exception_frame
     );
     let far = FAR_EL1.get() as *mut ();
     let elr = ELR_EL1.get() as *mut ();
-    let esr = ESR_EL1.get() as *mut ();
     let eebr0_el1 = TTBR0_EL1.get() as *mut ();
     let sp_el0 = SP_EL0.get() as *mut ();
     info!(
         "Abort FAR={:?} ELR={:?} TTBR0_EL0={:?} esr_el1={:?} SP_EL0={:?}",
-        far, elr, eebr0_el1, esr as *mut (), sp_el0,
-    );
-    panic!(
-        "Unknown exception 0b{:b}",
-        ::core::mem::transmute::<_, u32>(exception)
+        far, elr, eebr0_el1, esr, sp_el0,
     );
+    info!("ELR: {}", Symbolized::kernel(elr as usize));
+    panic!("Unhandled exception: {}", esr);
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn handle_interrupt(exception_frame: &mut ExceptionFrame) {
     INTERRUPT.interrupt_begin();
     let irq = INTERRUPT.get_active_irq().unwrap();
+    trace::begin(Kind::Interrupt, irq);
     AArch64Context::of(&*SCHEDULER.get_current_task().unwrap())
         .push_exception_frame(exception_frame);
     super::super::handle_irq(irq);
     INTERRUPT.interrupt_end();
+    trace::end(Kind::Interrupt, irq);
     ::core::sync::atomic::fence(::core::sync::atomic::Ordering::SeqCst);
     // Note: `Task::current()` must be dropped before calling `return_to_user`.
     let context =
@@ -278,7 +497,6 @@ pub unsafe extern "C" fn handle_interrupt(exception_frame: &mut ExceptionFrame)
 
 extern "C" {
     pub fn exception_handlers() -> !;
-    pub fn exit_exception() -> !;
 }
 
 pub unsafe extern "C" fn setup_vbar() {
@@ -291,8 +509,35 @@ pub unsafe extern "C" fn setup_vbar() {
     barrier::isb(barrier::SY);
 }
 
-// FIXME: We may need to switch stack after enter an exception,
-//        to avoid stack overflow.
+/// Cores with an overflow stack.
+const OVERFLOW_STACK_CORES: usize = 16;
+const LOG_OVERFLOW_STACK_SIZE: usize = 14;
+
+/// Per-core stacks to report kernel stack overflows on,
+/// as the exception frame does not fit on the overflowed stack.
+#[repr(C, align(16))]
+struct OverflowStacks([[u8; 1 << LOG_OVERFLOW_STACK_SIZE]; OVERFLOW_STACK_CORES]);
+
+static mut OVERFLOW_STACKS: OverflowStacks =
+    OverflowStacks([[0; 1 << LOG_OVERFLOW_STACK_SIZE]; OVERFLOW_STACK_CORES]);
+
+/// Called on the overflow stack of the core, when a kernel exception frame does not fit
+/// on the kernel stack. The task has run into the guard page below its kernel stack.
+#[no_mangle]
+pub unsafe extern "C" fn handle_kernel_stack_overflow(exception_frame: &mut ExceptionFrame) -> ! {
+    let far: usize;
+    asm!("mrs {:x}, far_el1", out(reg) far);
+    let task = SCHEDULER.get_current_task();
+    panic!(
+        "kernel stack overflow in pid {:?} / task {:?}: FAR={:#x} ELR={:?} ({})",
+        task.as_ref().map(|t| t.pid),
+        task.as_ref().map(|t| t.id),
+        far,
+        exception_frame.elr_el1,
+        Symbolized::kernel(exception_frame.elr_el1 as usize)
+    );
+}
+
 // Exception handlers table
 global_asm! {"
 .global exception_handlers
@@ -392,6 +637,37 @@ except:
     bl handle_exception
     except_hang 0
 
+// Synchronous exceptions taken from EL1 check that the exception frame fits on the stack,
+// before pushing it. x0 is kept in TPIDRRO_EL0 meanwhile, and the register is cleared again.
+except_el1:
+    msr tpidrro_el0, x0
+    sub x0, sp, #{frame_size}
+    at s1e1w, x0
+    isb
+    mrs x0, par_el1
+    tbnz x0, #0, 1f
+    mrs x0, tpidrro_el0
+    msr tpidrro_el0, xzr
+    b except
+1:  // Stack overflow: switch to the overflow stack of this core
+    mrs x0, tpidr_el1
+    cmp x0, #{overflow_stack_cores}
+    b.hs 2f
+    adrp x0, {overflow_stacks}
+    add x0, x0, :lo12:{overflow_stacks}
+    mov sp, x0
+    mrs x0, tpidr_el1
+    add x0, x0, #1
+    lsl x0, x0, #{log_overflow_stack_size}
+    add sp, sp, x0
+    mrs x0, tpidrro_el0
+    msr tpidrro_el0, xzr
+    push_all
+    mov x0, sp
+    bl handle_kernel_stack_overflow
+2:  wfi
+    b 2b
+
 serror:
     push_all
     mov x0, sp
@@ -412,7 +688,7 @@ exception_handlers:
     .align 7; b serror
     .align 7; b serror
     // Same exeception level, ELx
-    .align 9; b except
+    .align 9; b except_el1
     .align 7; b irq
     .align 7; b serror
     .align 7; b serror
@@ -426,4 +702,9 @@ exception_handlers:
     .align 7; b irq
     .align 7; b serror
     .align 7; b serror
-"}
+",
+    frame_size = const core::mem::size_of::<ExceptionFrame>(),
+    overflow_stack_cores = const OVERFLOW_STACK_CORES,
+    log_overflow_stack_size = const LOG_OVERFLOW_STACK_SIZE,
+    overflow_stacks = sym OVERFLOW_STACKS,
+}
@@ -0,0 +1,576 @@
+//! A stub for the GDB remote serial protocol, on a second PL011.
+//!
+//! Enabled with the `gdb` kernel option. The kernel stops at boot, before the first process
+//! is started, and waits for GDB. Afterwards it stops on breakpoints, and on Ctrl-C in GDB.
+//! Each task is a thread. The registers of a thread are the frame it continues from, so
+//! a blocked task shows where it blocked.
+//!
+//! While stopped, the other cores are parked by an IPI, and nothing is allocated or logged,
+//! as a parked core may hold the heap or the logger. Memory is accessed in the address space
+//! of the stopped task. Writes go to the physical page, e.g. to insert breakpoints into
+//! read-only code. A breakpoint in a page shared by several processes stops them all.
+
+mod packet;
+mod serial;
+
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+
+use klib::task::TaskId;
+use memory::page::{PageSize, Size4K};
+use spin::Mutex;
+use syscall::Signal;
+
+use self::packet::{Buffer, PACKET_SIZE};
+use self::serial::Port;
+use super::context::AArch64Context;
+use super::esr::{Esr, ExceptionClass};
+use super::exception::ExceptionFrame;
+use crate::arch::ArchContext;
+use crate::memory::kernel::KERNEL_MEMORY_MAPPER;
+use crate::modules::INTERRUPT;
+use crate::task::sched::SCHEDULER;
+use crate::task::{ipi, PROCESS_MANAGER};
+
+/// `brk` immediates, as in Linux: one for the breakpoints GDB inserts, and one compiled in.
+const BREAKPOINT_IMM: u32 = 0x400;
+const COMPILED_BREAKPOINT_IMM: u32 = 0x401;
+const BREAKPOINT: u32 = 0xd420_0000 | (BREAKPOINT_IMM << 5);
+
+const MAX_BREAKPOINTS: usize = 64;
+
+/// `x0`-`x30`, `sp`, `pc`, and the 32-bit `cpsr`, in GDB's numbering.
+const NUM_REGISTERS: usize = 34;
+const CPSR: usize = 33;
+
+const SPSR_SS: usize = 1 << 21;
+const SPSR_D: usize = 1 << 9;
+const SPSR_I: usize = 1 << 7;
+const MDSCR_SS: usize = 1 << 0;
+const MDSCR_KDE: usize = 1 << 13;
+
+/// How the stopped core continues after a command.
+enum Resume {
+    Continue,
+    Step,
+    Detach,
+}
+
+struct Stub {
+    port: Port,
+    reply: Buffer<PACKET_SIZE>,
+    /// Inserted breakpoints, with the instructions they replace.
+    breakpoints: [Option<(usize, u32)>; MAX_BREAKPOINTS],
+    /// The thread selected with `Hg`. `None` is the stopped thread.
+    selected: Option<TaskId>,
+    /// The `D` and `I` bits of the stepped context, while single-stepping.
+    stepping: Option<usize>,
+    /// GDB waits for a stop reply, after `c` or `s`.
+    running: bool,
+    signal: Signal,
+}
+
+static STUB: Mutex<Option<Stub>> = Mutex::new(None);
+
+/// Start the debugger on the second PL011 if the `gdb` option is set, and wait for GDB.
+pub fn init() {
+    if crate::utils::cmdline::get("gdb").is_none() {
+        return;
+    }
+    let Some(port) = Port::probe() else {
+        warn!("gdb: no PL011 for the debugger besides the console");
+        return;
+    };
+    info!("gdb: waiting for GDB on the PL011 at {:?}", port.address);
+    let irq = port.irq;
+    *STUB.lock() = Some(Stub::new(port));
+    if let Some(irq) = irq {
+        INTERRUPT.set_irq_handler(irq, Box::new(handle_serial_interrupt));
+        INTERRUPT.enable_irq(irq);
+    }
+    breakpoint();
+    info!("gdb: attached");
+}
+
+/// Stop in the debugger, if it is started.
+#[inline(always)]
+pub fn breakpoint() {
+    unsafe { asm!("brk #{}", const COMPILED_BREAKPOINT_IMM) };
+}
+
+/// Stop for a `brk` or a software step of the debugger. Returns `false` for other debug
+/// exceptions, e.g. a `brk` in a user program.
+pub fn handle_debug_exception(frame: &mut ExceptionFrame, esr: Esr) -> bool {
+    let mut stub = STUB.lock();
+    let Some(stub) = stub.as_mut() else {
+        return false;
+    };
+    match esr.class() {
+        Some(ExceptionClass::Brk) => {
+            let pc = frame.elr_el1 as usize;
+            match esr.iss() & 0xffff {
+                COMPILED_BREAKPOINT_IMM if !is_el0(frame) => frame.elr_el1 = (pc + 4) as _,
+                BREAKPOINT_IMM if stub.breakpoints.iter().flatten().any(|b| b.0 == pc) => {}
+                _ => return false,
+            }
+        }
+        Some(ExceptionClass::SoftwareStepLowerEL | ExceptionClass::SoftwareStepHigherEL) => {
+            let Some(mask) = stub.stepping.take() else {
+                return false;
+            };
+            unsafe {
+                let mdscr: usize;
+                asm!("mrs {}, mdscr_el1", out(reg) mdscr);
+                asm!("msr mdscr_el1, {}", "isb", in(reg) mdscr & !MDSCR_SS);
+            }
+            frame.spsr_el1 = (frame.spsr_el1 & !(SPSR_SS | SPSR_D | SPSR_I)) | mask;
+        }
+        _ => return false,
+    }
+    stub.stop(frame, Signal::Trap);
+    true
+}
+
+/// Stop on Ctrl-C from GDB, or when GDB connects again after detaching.
+fn handle_serial_interrupt() -> isize {
+    let mut stub = STUB.lock();
+    let Some(stub) = stub.as_mut() else {
+        return 0;
+    };
+    let (mut interrupt, mut packet) = (false, false);
+    while let Some(byte) = stub.port.try_read() {
+        interrupt |= byte == 0x03;
+        packet |= byte == b'$';
+    }
+    stub.port.clear_interrupts();
+    if !interrupt && !packet {
+        return 0;
+    }
+    let Some(frame) = SCHEDULER
+        .get_current_task()
+        .and_then(|task| AArch64Context::of(&task).top_exception_frame())
+    else {
+        return 0;
+    };
+    if packet {
+        // The packet is partly drained. Ask for it again.
+        stub.port.write(b'-');
+    }
+    stub.stop(unsafe { &mut *frame }, Signal::Interrupt);
+    0
+}
+
+fn is_el0(frame: &ExceptionFrame) -> bool {
+    frame.spsr_el1 & 0b1111 == 0
+}
+
+/// GDB thread ids start at 1. 0 and -1 mean any and all threads.
+fn thread_id(task: TaskId) -> usize {
+    task.0 + 1
+}
+
+fn register(frame: &ExceptionFrame, i: usize) -> Option<usize> {
+    Some(match i {
+        0..=30 => frame.x(i),
+        // A kernel frame is pushed right below the stack pointer of the interrupted code.
+        31 if is_el0(frame) => frame.sp_el0,
+        31 => frame as *const ExceptionFrame as usize + core::mem::size_of::<ExceptionFrame>(),
+        32 => frame.elr_el1 as usize,
+        CPSR => frame.spsr_el1,
+        _ => return None,
+    })
+}
+
+/// The kernel stack pointer cannot be changed, as the frame is stored there.
+fn set_register(frame: &mut ExceptionFrame, i: usize, value: usize) -> Option<()> {
+    match i {
+        0..=30 => *frame.x_mut(i) = value,
+        31 if is_el0(frame) => frame.sp_el0 = value,
+        31 => {}
+        32 => frame.elr_el1 = value as _,
+        CPSR => frame.spsr_el1 = value as u32 as usize,
+        _ => return None,
+    }
+    Some(())
+}
+
+fn register_size(i: usize) -> usize {
+    if i == CPSR {
+        4
+    } else {
+        8
+    }
+}
+
+fn read_memory(address: usize) -> Option<u8> {
+    super::translate(address, false)?;
+    Some(unsafe { (address as *const u8).read_volatile() })
+}
+
+/// Write through the physical addresses, and make the writes visible to instruction fetches.
+fn write_memory(address: usize, bytes: &[u8]) -> Option<()> {
+    let mut written = 0;
+    while written < bytes.len() {
+        let start = address + written;
+        let page_end = (start & !(Size4K::BYTES - 1)) + Size4K::BYTES;
+        let len = usize::min(bytes.len() - written, page_end - start);
+        let physical = super::translate(start, false)?;
+        let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+        for (i, byte) in bytes[written..written + len].iter().enumerate() {
+            unsafe {
+                ((physical + i) as *mut u8).write_volatile(*byte);
+                asm!("dc cvau, {}", in(reg) physical + i);
+            }
+        }
+        written += len;
+    }
+    unsafe { asm!("dsb ish", "ic ialluis", "dsb ish", "isb") };
+    Some(())
+}
+
+fn read_instruction(address: usize) -> Option<u32> {
+    let mut bytes = [0; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = read_memory(address + i)?;
+    }
+    Some(u32::from_le_bytes(bytes))
+}
+
+impl Stub {
+    fn new(port: Port) -> Self {
+        Self {
+            port,
+            reply: Buffer::new(),
+            breakpoints: [None; MAX_BREAKPOINTS],
+            selected: None,
+            stepping: None,
+            running: false,
+            signal: Signal::Trap,
+        }
+    }
+
+    /// Serve GDB until it continues the stopped core.
+    fn stop(&mut self, frame: &mut ExceptionFrame, signal: Signal) {
+        ipi::stop_other_cores();
+        self.signal = signal;
+        self.selected = None;
+        if self.running {
+            self.running = false;
+            self.reply.clear();
+            self.stop_reply();
+            packet::send(&mut self.port, self.reply.as_bytes());
+        }
+        let mut packet = [0u8; PACKET_SIZE];
+        loop {
+            let len = packet::receive(&mut self.port, &mut packet);
+            self.reply.clear();
+            match self.handle(&packet[..len], frame) {
+                None => packet::send(&mut self.port, self.reply.as_bytes()),
+                Some(Resume::Continue) => {
+                    self.running = true;
+                    break;
+                }
+                Some(Resume::Step) => {
+                    self.start_step(frame);
+                    self.running = true;
+                    break;
+                }
+                Some(Resume::Detach) => {
+                    self.remove_all_breakpoints();
+                    packet::send(&mut self.port, b"OK");
+                    break;
+                }
+            }
+        }
+        ipi::resume_other_cores();
+    }
+
+    /// Handle a command. The reply is left in `self.reply`, unless the core resumes.
+    fn handle(&mut self, packet: &[u8], frame: &mut ExceptionFrame) -> Option<Resume> {
+        let Some((&command, args)) = packet.split_first() else {
+            return None;
+        };
+        match command {
+            b'?' => self.stop_reply(),
+            b'g' => match self.selected_frame(frame) {
+                Some(frame) => {
+                    for i in 0..NUM_REGISTERS {
+                        let value = register(frame, i).unwrap_or(0);
+                        self.reply
+                            .push_hex(&value.to_le_bytes()[..register_size(i)]);
+                    }
+                }
+                None => self.reply.push_str("E01"),
+            },
+            b'G' => {
+                let result = self.selected_frame(frame).and_then(|frame| {
+                    let mut args = args;
+                    for i in 0..NUM_REGISTERS {
+                        let size = register_size(i);
+                        let mut bytes = [0u8; 8];
+                        packet::decode_hex(args.get(..size * 2)?, &mut bytes[..size])?;
+                        set_register(frame, i, usize::from_le_bytes(bytes))?;
+                        args = &args[size * 2..];
+                    }
+                    Some(())
+                });
+                self.ok_or_error(result);
+            }
+            b'p' => {
+                let value = packet::parse_hex(args).and_then(|i| {
+                    let frame = self.selected_frame(frame)?;
+                    Some((register(frame, i)?, register_size(i)))
+                });
+                match value {
+                    Some((value, size)) => self.reply.push_hex(&value.to_le_bytes()[..size]),
+                    None => self.reply.push_str("E01"),
+                }
+            }
+            b'P' => {
+                let result = (|| {
+                    let eq = args.iter().position(|c| *c == b'=')?;
+                    let i = packet::parse_hex(&args[..eq])?;
+                    let value = &args[eq + 1..];
+                    let mut bytes = [0u8; 8];
+                    let size = usize::min(value.len() / 2, 8);
+                    packet::decode_hex(value, &mut bytes[..size])?;
+                    set_register(self.selected_frame(frame)?, i, usize::from_le_bytes(bytes))
+                })();
+                self.ok_or_error(result);
+            }
+            b'm' => {
+                let Some((address, len)) = packet::parse_pair(args) else {
+                    self.reply.push_str("E01");
+                    return None;
+                };
+                for i in 0..usize::min(len, PACKET_SIZE / 2) {
+                    match read_memory(address.wrapping_add(i)) {
+                        Some(byte) => self.reply.push_hex(&[byte]),
+                        None if i == 0 => self.reply.push_str("E14"),
+                        None => break,
+                    }
+                }
+            }
+            b'M' => {
+                let result = (|| {
+                    let colon = args.iter().position(|c| *c == b':')?;
+                    let (address, len) = packet::parse_pair(&args[..colon])?;
+                    let mut bytes = [0u8; PACKET_SIZE / 2];
+                    let bytes = bytes.get_mut(..len)?;
+                    packet::decode_hex(&args[colon + 1..], bytes)?;
+                    write_memory(address, bytes)
+                })();
+                self.ok_or_error(result);
+            }
+            b'Z' | b'z' => {
+                // Only software breakpoints: `Z0,<address>,<kind>`
+                let Some(args) = args.strip_prefix(b"0,") else {
+                    return None;
+                };
+                let comma = args.iter().position(|c| *c == b',').unwrap_or(args.len());
+                let result = packet::parse_hex(&args[..comma]).and_then(|address| {
+                    if command == b'Z' {
+                        self.insert_breakpoint(address)
+                    } else {
+                        self.remove_breakpoint(address)
+                    }
+                });
+                self.ok_or_error(result);
+            }
+            b'c' | b's' => {
+                if let Some(address) = packet::parse_hex(args) {
+                    frame.elr_el1 = address as _;
+                }
+                return Some(if command == b'c' {
+                    Resume::Continue
+                } else {
+                    Resume::Step
+                });
+            }
+            b'D' | b'k' => return Some(Resume::Detach),
+            b'H' => {
+                if args.first() == Some(&b'g') {
+                    self.selected = match packet::parse_hex(&args[1..]) {
+                        Some(id) if id != 0 => Some(TaskId(id - 1))
+                            .filter(|t| Some(*t) != SCHEDULER.get_current_task_id()),
+                        // 0, or -1
+                        _ => None,
+                    };
+                }
+                self.reply.push_str("OK");
+            }
+            b'T' => {
+                let alive = packet::parse_hex(args)
+                    .is_some_and(|id| id != 0 && self.find_task(TaskId(id - 1)).is_some());
+                self.ok_or_error(alive.then_some(()));
+            }
+            b'q' => self.query(args),
+            _ => {}
+        }
+        None
+    }
+
+    fn query(&mut self, query: &[u8]) {
+        if query.starts_with(b"Supported") {
+            let _ = write!(
+                self.reply,
+                "PacketSize={:x};qXfer:features:read+",
+                PACKET_SIZE
+            );
+        } else if let Some(range) = query.strip_prefix(b"Xfer:features:read:target.xml:") {
+            let Some((offset, len)) = packet::parse_pair(range) else {
+                self.reply.push_str("E01");
+                return;
+            };
+            let mut xml = Buffer::<2048>::new();
+            write_target_description(&mut xml);
+            let xml = xml.as_bytes();
+            let start = usize::min(offset, xml.len());
+            let end = usize::min(start + usize::min(len, PACKET_SIZE - 1), xml.len());
+            self.reply.push(if end < xml.len() { b'm' } else { b'l' });
+            xml[start..end].iter().for_each(|b| self.reply.push(*b));
+        } else if query == b"Attached" {
+            self.reply.push_str("1");
+        } else if query == b"C" {
+            if let Some(task) = SCHEDULER.get_current_task_id() {
+                let _ = write!(self.reply, "QC{:x}", thread_id(task));
+            }
+        } else if query == b"fThreadInfo" {
+            self.reply.push(b'm');
+            let reply = &mut self.reply;
+            let listed = SCHEDULER.try_for_each_task(|task| {
+                let _ = write!(reply, "{:x},", thread_id(task.id));
+            });
+            if !listed {
+                if let Some(task) = SCHEDULER.get_current_task_id() {
+                    let _ = write!(reply, "{:x},", thread_id(task));
+                }
+            }
+            // Drop the trailing comma. An empty list is `l`.
+            self.reply.pop();
+            if self.reply.as_bytes().is_empty() {
+                self.reply.push(b'l');
+            }
+        } else if query == b"sThreadInfo" {
+            self.reply.push(b'l');
+        } else if let Some(id) = query.strip_prefix(b"ThreadExtraInfo,") {
+            let mut info = Buffer::<128>::new();
+            if let Some(task) = packet::parse_hex(id)
+                .filter(|id| *id != 0)
+                .and_then(|id| self.find_task(TaskId(id - 1)))
+            {
+                let _ = write!(info, "pid {}", task.pid.0);
+                if let Some(proc) = PROCESS_MANAGER.try_get_proc_by_id(task.pid) {
+                    if let Some(name) = proc.name.try_lock() {
+                        let _ = write!(info, " ({})", name.rsplit('/').next().unwrap_or(""));
+                    }
+                }
+                let _ = write!(info, ", {:?}", task.state.load(Ordering::Relaxed));
+            }
+            self.reply.push_hex(info.as_bytes());
+        }
+    }
+
+    fn ok_or_error(&mut self, result: Option<()>) {
+        self.reply
+            .push_str(if result.is_some() { "OK" } else { "E01" });
+    }
+
+    fn stop_reply(&mut self) {
+        let signal = self.signal as usize;
+        let _ = match SCHEDULER.get_current_task_id() {
+            Some(task) => write!(self.reply, "T{:02x}thread:{:x};", signal, thread_id(task)),
+            // Before the first task
+            None => write!(self.reply, "S{:02x}", signal),
+        };
+    }
+
+    fn find_task(&self, id: TaskId) -> Option<alloc::sync::Arc<klib::task::Task>> {
+        let mut found = None;
+        SCHEDULER.try_for_each_task(|task| {
+            if task.id == id {
+                found = Some(task.clone());
+            }
+        });
+        found
+    }
+
+    /// The frame holding the registers of the selected thread.
+    fn selected_frame<'a>(
+        &self,
+        stopped: &'a mut ExceptionFrame,
+    ) -> Option<&'a mut ExceptionFrame> {
+        let Some(id) = self.selected else {
+            return Some(stopped);
+        };
+        let task = self.find_task(id)?;
+        let frame = AArch64Context::of(&task).top_exception_frame()?;
+        Some(unsafe { &mut *frame })
+    }
+
+    fn insert_breakpoint(&mut self, address: usize) -> Option<()> {
+        if address % 4 != 0 {
+            return None;
+        }
+        if self.breakpoints.iter().flatten().any(|b| b.0 == address) {
+            return Some(());
+        }
+        let slot = self.breakpoints.iter_mut().find(|b| b.is_none())?;
+        let instruction = read_instruction(address)?;
+        write_memory(address, &BREAKPOINT.to_le_bytes())?;
+        *slot = Some((address, instruction));
+        Some(())
+    }
+
+    fn remove_breakpoint(&mut self, address: usize) -> Option<()> {
+        let slot = self
+            .breakpoints
+            .iter_mut()
+            .find(|b| b.is_some_and(|b| b.0 == address))?;
+        let (_, instruction) = slot.take()?;
+        write_memory(address, &instruction.to_le_bytes())
+    }
+
+    fn remove_all_breakpoints(&mut self) {
+        for (address, instruction) in self.breakpoints.iter_mut().filter_map(|b| b.take()) {
+            let _ = write_memory(address, &instruction.to_le_bytes());
+        }
+    }
+
+    /// Step one instruction, with interrupts masked. The debug exception is unmasked for the
+    /// step, and the OS lock is cleared so that debug exceptions are generated at all.
+    fn start_step(&mut self, frame: &mut ExceptionFrame) {
+        self.stepping = Some(frame.spsr_el1 & (SPSR_D | SPSR_I));
+        frame.spsr_el1 = (frame.spsr_el1 & !SPSR_D) | SPSR_I | SPSR_SS;
+        unsafe {
+            let mdscr: usize;
+            asm!("mrs {}, mdscr_el1", out(reg) mdscr);
+            asm!(
+                "msr oslar_el1, xzr",
+                "msr mdscr_el1, {}",
+                "isb",
+                in(reg) mdscr | MDSCR_SS | MDSCR_KDE
+            );
+        }
+    }
+}
+
+/// The registers in `g` packets, for `qXfer:features:read:target.xml`.
+fn write_target_description(xml: &mut Buffer<2048>) {
+    xml.push_str(concat!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">",
+        "<target><architecture>aarch64</architecture>",
+        "<feature name=\"org.gnu.gdb.aarch64.core\">",
+    ));
+    for i in 0..=30 {
+        let _ = write!(xml, "<reg name=\"x{}\" bitsize=\"64\"/>", i);
+    }
+    xml.push_str(concat!(
+        "<reg name=\"sp\" bitsize=\"64\" type=\"data_ptr\"/>",
+        "<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/>",
+        "<reg name=\"cpsr\" bitsize=\"32\"/>",
+        "</feature></target>",
+    ));
+}
@@ -0,0 +1,166 @@
+//! Packets of the GDB remote serial protocol: `$<data>#<checksum>`, acknowledged with `+`,
+//! or with `-` to ask for a retransmission.
+
+use core::fmt;
+
+use super::serial::Port;
+
+/// The maximum packet size, announced to GDB in `qSupported`.
+pub const PACKET_SIZE: usize = 0x1000;
+
+/// A fixed-size output buffer. Writes past the end are dropped.
+pub struct Buffer<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Buffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            data: [0; N],
+            len: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        if self.len < N {
+            self.data[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    pub fn pop(&mut self) {
+        self.len = self.len.saturating_sub(1);
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        s.bytes().for_each(|b| self.push(b));
+    }
+
+    pub fn push_hex(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.push(HEX_DIGITS[(b >> 4) as usize]);
+            self.push(HEX_DIGITS[(b & 0xf) as usize]);
+        }
+    }
+}
+
+impl<const N: usize> fmt::Write for Buffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parse a big-endian hex number, as used for addresses and lengths.
+pub fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    s.iter()
+        .try_fold(0usize, |n, c| Some((n << 4) | hex_digit(*c)? as usize))
+}
+
+/// Decode hex encoded bytes into `out`, which must be exactly half as long as `s`.
+pub fn decode_hex(s: &[u8], out: &mut [u8]) -> Option<()> {
+    if s.len() != out.len() * 2 {
+        return None;
+    }
+    for (pair, byte) in s.chunks(2).zip(out) {
+        *byte = (hex_digit(pair[0])? << 4) | hex_digit(pair[1])?;
+    }
+    Some(())
+}
+
+/// Parse `<a>,<b>`, e.g. the address and length of `m`.
+pub fn parse_pair(s: &[u8]) -> Option<(usize, usize)> {
+    let comma = s.iter().position(|c| *c == b',')?;
+    Some((parse_hex(&s[..comma])?, parse_hex(&s[comma + 1..])?))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Wait for a packet, and acknowledge it. Returns the length of its data in `buf`.
+/// Bytes outside of packets, e.g. acks and Ctrl-C, are dropped.
+pub fn receive(port: &mut Port, buf: &mut [u8]) -> usize {
+    loop {
+        while port.read() != b'$' {}
+        let mut len = 0;
+        let mut overflow = false;
+        loop {
+            let c = port.read();
+            if c == b'#' {
+                break;
+            }
+            if len < buf.len() {
+                buf[len] = c;
+                len += 1;
+            } else {
+                overflow = true;
+            }
+        }
+        let mut sum = [0u8];
+        let received = decode_hex(&[port.read(), port.read()], &mut sum);
+        if !overflow && received.is_some() && sum[0] == checksum(&buf[..len]) {
+            port.write(b'+');
+            return len;
+        }
+        port.write(b'-');
+    }
+}
+
+/// Send a packet, until GDB acknowledges it.
+pub fn send(port: &mut Port, data: &[u8]) {
+    let mut sum = Buffer::<2>::new();
+    sum.push_hex(&[checksum(data)]);
+    loop {
+        port.write(b'$');
+        data.iter().for_each(|b| port.write(*b));
+        port.write(b'#');
+        sum.as_bytes().iter().for_each(|b| port.write(*b));
+        loop {
+            match port.read() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn packet_test() {
+    // `$OK#9a`
+    assert_eq!(checksum(b"OK"), 0x9a);
+    assert_eq!(parse_hex(b"ff0000001234"), Some(0xff00_0000_1234));
+    assert_eq!(parse_hex(b""), None);
+    assert_eq!(parse_hex(b"12g"), None);
+    assert_eq!(parse_pair(b"1000,4"), Some((0x1000, 4)));
+    let mut bytes = [0u8; 4];
+    assert_eq!(decode_hex(b"000020d4", &mut bytes), Some(()));
+    assert_eq!(u32::from_le_bytes(bytes), 0xd420_0000);
+    let mut hex = Buffer::<8>::new();
+    hex.push_hex(&bytes);
+    assert_eq!(hex.as_bytes(), b"000020d4");
+}
@@ -0,0 +1,120 @@
+//! A polling PL011 driver for the debugger.
+//!
+//! The first PL011 in the device tree is the console, so the debugger takes the next one that
+//! is not disabled. On QEMU it is the UART of the second `-serial` option.
+
+use memory::{
+    address::{Address, P},
+    page::{Frame, Size4K},
+    page_table::PageFlags,
+    volatile::Volatile,
+};
+
+use crate::memory::kernel::{KERNEL_HEAP, KERNEL_MEMORY_MAPPER};
+
+#[repr(C)]
+struct Registers {
+    dr: Volatile<u32>,      // 0x00
+    _rsrecr: Volatile<u32>, // 0x04
+    _0: [u8; 16],           // 0x08
+    fr: Volatile<u32>,      // 0x18
+    _1: [u8; 4],            // 0x1c
+    _ilpr: Volatile<u32>,   // 0x20
+    ibrd: Volatile<u32>,    // 0x24
+    fbrd: Volatile<u32>,    // 0x28
+    lcrh: Volatile<u32>,    // 0x2c
+    cr: Volatile<u32>,      // 0x30
+    _ifls: Volatile<u32>,   // 0x34
+    imsc: Volatile<u32>,    // 0x38
+    _ris: Volatile<u32>,    // 0x3c
+    _mis: Volatile<u32>,    // 0x40
+    icr: Volatile<u32>,     // 0x44
+}
+
+const FR_RXFE: u32 = 1 << 4;
+const FR_TXFF: u32 = 1 << 5;
+/// Receive and receive timeout interrupts. The timeout fires for input below the FIFO level,
+/// e.g. a single Ctrl-C.
+const INT_RX: u32 = (1 << 4) | (1 << 6);
+
+pub struct Port {
+    regs: *mut Registers,
+    /// The physical address of the registers.
+    pub address: Address<P>,
+    /// The receive interrupt.
+    pub irq: Option<usize>,
+}
+
+unsafe impl Send for Port {}
+
+impl Port {
+    /// Map and initialize the PL011 for the debugger. `None` if there is only the console.
+    pub fn probe() -> Option<Self> {
+        #[allow(static_mut_refs)]
+        let devtree = unsafe { crate::DEV_TREE.as_ref() }?;
+        let node = devtree
+            .all_compatible("arm,pl011")
+            .skip(1)
+            .find(|n| n.prop_str("status") != Some("disabled"))?;
+        let address = node.translate(node.regs()?.next()?.start);
+        let irq = node.interrupts().and_then(|mut i| i.next()).map(|i| i.0);
+        let page = KERNEL_HEAP.virtual_allocate::<Size4K>(1).start;
+        KERNEL_MEMORY_MAPPER.map(page, Frame::containing(address), PageFlags::device());
+        let offset = address - Frame::<Size4K>::align(address);
+        let mut port = Self {
+            regs: (page.start() + offset).as_mut_ptr(),
+            address,
+            irq,
+        };
+        port.init();
+        Some(port)
+    }
+
+    fn regs(&mut self) -> &mut Registers {
+        unsafe { &mut *self.regs }
+    }
+
+    fn init(&mut self) {
+        let regs = self.regs();
+        regs.cr.set(0);
+        regs.icr.set(0x7ff);
+        // 115200 baud on a 48 MHz clock. QEMU ignores it.
+        regs.ibrd.set(26);
+        regs.fbrd.set(3);
+        // 8 bits, FIFOs enabled
+        regs.lcrh.set((0b11 << 5) | (1 << 4));
+        regs.cr.set((1 << 0) | (1 << 8) | (1 << 9));
+        regs.imsc.set(INT_RX);
+    }
+
+    pub fn write(&mut self, byte: u8) {
+        let regs = self.regs();
+        while regs.fr.get() & FR_TXFF != 0 {
+            core::hint::spin_loop();
+        }
+        regs.dr.set(byte as u32);
+    }
+
+    pub fn try_read(&mut self) -> Option<u8> {
+        let regs = self.regs();
+        if regs.fr.get() & FR_RXFE != 0 {
+            return None;
+        }
+        Some(regs.dr.get() as u8)
+    }
+
+    /// Wait for a byte.
+    pub fn read(&mut self) -> u8 {
+        loop {
+            if let Some(byte) = self.try_read() {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Clear the receive interrupts, after the FIFO is drained.
+    pub fn clear_interrupts(&mut self) {
+        self.regs().icr.set(INT_RX);
+    }
+}
@@ -1,11 +1,19 @@
+mod backtrace;
 mod context;
+mod esr;
 mod exception;
+mod gdb;
+mod pmu;
+mod smp;
 
 use super::{Arch, TargetArch};
 use crate::memory::kernel::KERNEL_MEMORY_MAPPER;
 use boot::BootInfo;
 use context::AArch64Context;
 use core::arch::asm;
+use core::time::Duration;
+use cortex_a::registers::{CNTFRQ_EL0, CNTPCT_EL0};
+use tock_registers::interfaces::Readable;
 
 static mut SHUTDOWN: Option<extern "C" fn() -> !> = None;
 
@@ -17,6 +25,21 @@ impl Arch for AArch64 {
     fn init(boot_info: &'static BootInfo) {
         unsafe { SHUTDOWN = boot_info.shutdown };
         interrupt::disable();
+        smp::init();
+        pmu::init_core();
+    }
+
+    fn num_cores() -> usize {
+        smp::num_cores()
+    }
+
+    #[inline(always)]
+    fn current_core() -> usize {
+        smp::current_core()
+    }
+
+    fn start_secondary_cores() {
+        smp::start_secondary_cores()
     }
 
     fn setup_interrupt_table() {
@@ -25,6 +48,17 @@ impl Arch for AArch64 {
         }
     }
 
+    fn uptime() -> Duration {
+        let ticks = CNTPCT_EL0.get() as u128;
+        let freq = CNTFRQ_EL0.get() as u128;
+        Duration::from_nanos((ticks * 1_000_000_000 / freq) as u64)
+    }
+
+    #[inline(always)]
+    fn cycles() -> u64 {
+        pmu::cycles()
+    }
+
     fn halt(code: i32) -> ! {
         // Try QEMU exit service
         if cfg!(feature = "qemu") {
@@ -41,7 +75,8 @@ impl Arch for AArch64 {
                 );
             }
         }
-        // TODO: Try PSCI system off
+        // Try PSCI system off
+        smp::psci_system_off();
         // Try shutdown service from the bootloader
         unsafe {
             if let Some(shutdown) = SHUTDOWN {
@@ -55,6 +90,42 @@ impl Arch for AArch64 {
             unsafe { asm!("wfe") };
         }
     }
+
+    fn walk_stack(f: &mut dyn FnMut(usize)) {
+        backtrace::Frames::current().for_each(f)
+    }
+
+    fn start_debugger() {
+        gdb::init()
+    }
+
+    fn reboot() -> ! {
+        smp::psci_system_reset();
+        error!("ERROR: Failed to reboot.");
+        loop {
+            unsafe { asm!("wfe") };
+        }
+    }
+}
+
+/// The physical address of a virtual address that can be read at EL0 (`user`) or EL1,
+/// in the current page table.
+fn translate(address: usize, user: bool) -> Option<usize> {
+    // PAR_EL1 must not change between the translation and the read.
+    let _guard = interrupt::uninterruptible();
+    let par: usize;
+    unsafe {
+        if user {
+            asm!("at s1e0r, {}", in(reg) address);
+        } else {
+            asm!("at s1e1r, {}", in(reg) address);
+        }
+        asm!("isb", "mrs {}, par_el1", out(reg) par);
+    }
+    if par & 1 != 0 {
+        return None;
+    }
+    Some((par & 0xffff_ffff_f000) | (address & 0xfff))
 }
 
 #[allow(unused)]
@@ -0,0 +1,40 @@
+//! The PMU cycle counter, `PMCCNTR_EL0`. Each core has its own, so counts from different cores
+//! are not comparable.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_a::registers::CNTPCT_EL0;
+use tock_registers::interfaces::Readable;
+
+static HAS_PMU: AtomicBool = AtomicBool::new(false);
+
+/// Enable and reset the cycle counter of the current core.
+pub fn init_core() {
+    let dfr0: u64;
+    unsafe { asm!("mrs {}, id_aa64dfr0_el1", out(reg) dfr0) };
+    // PMUVer: 0 is no PMU, 0xf is an IMPLEMENTATION DEFINED one.
+    let version = (dfr0 >> 8) & 0xf;
+    if version == 0 || version == 0xf {
+        return;
+    }
+    HAS_PMU.store(true, Ordering::Relaxed);
+    unsafe {
+        // PMCR_EL0: E (enable), C (reset the cycle counter), LC (64-bit overflow)
+        asm!("msr pmcr_el0, {}", in(reg) (1u64 << 0) | (1 << 2) | (1 << 6));
+        // Count at EL0 and EL1
+        asm!("msr pmccfiltr_el0, xzr");
+        asm!("msr pmcntenset_el0, {}", in(reg) 1u64 << 31);
+        asm!("isb");
+    }
+}
+
+/// Cycles of the current core, or ticks of the system counter without a PMU.
+#[inline(always)]
+pub fn cycles() -> u64 {
+    if !HAS_PMU.load(Ordering::Relaxed) {
+        return CNTPCT_EL0.get();
+    }
+    let cycles: u64;
+    unsafe { asm!("mrs {}, pmccntr_el0", out(reg) cycles, options(nomem, nostack)) };
+    cycles
+}
@@ -0,0 +1,256 @@
+use crate::arch::{Arch, TargetArch};
+use crate::memory::kernel::KERNEL_MEMORY_MAPPER;
+use crate::modules::{INTERRUPT, TIMER};
+use crate::task::sched::SCHEDULER;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use cortex_a::registers::*;
+use memory::address::{Address, V};
+use memory::page::{PageSize, Size4K};
+use tock_registers::interfaces::{Readable, Writeable};
+
+use super::context::KernelStack;
+
+static NUM_CORES: AtomicUsize = AtomicUsize::new(1);
+static ONLINE_CORES: AtomicUsize = AtomicUsize::new(1);
+
+/// MPIDR affinity values of all the cores, indexed by logical core id.
+static mut CORE_IDS: Vec<usize> = Vec::new();
+
+/// PSCI conduit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PSCIMethod {
+    SMC,
+    HVC,
+}
+
+static mut PSCI_METHOD: Option<PSCIMethod> = None;
+
+const PSCI_CPU_ON: usize = 0xC400_0003;
+const PSCI_SYSTEM_OFF: usize = 0x8400_0008;
+const PSCI_SYSTEM_RESET: usize = 0x8400_0009;
+
+/// Parameters passed to `secondary_entry`. Read with MMU disabled.
+#[repr(C, align(64))]
+struct SecondaryBootArgs {
+    mair: u64,
+    tcr: u64,
+    ttbr0: u64,
+    sctlr: u64,
+    stack_top: u64,
+    entry: u64,
+    core: u64,
+}
+
+/// Discover all the cores and the PSCI conduit from the device tree.
+#[allow(static_mut_refs)]
+pub fn init() {
+    TPIDR_EL1.set(0);
+    let Some(devtree) = (unsafe { crate::DEV_TREE.as_ref() }) else {
+        return;
+    };
+    let mut core_ids = Vec::new();
+    for cpu in devtree.cpus() {
+        let Some(mut regs) = cpu.regs() else {
+            continue;
+        };
+        if let Some(reg) = regs.next() {
+            core_ids.push(reg.start.as_usize());
+        }
+    }
+    let psci = devtree
+        .compatible("arm,psci-1.0")
+        .or_else(|| devtree.compatible("arm,psci-0.2"))
+        .or_else(|| devtree.compatible("arm,psci"));
+    let method = psci.and_then(|node| match node.prop_str("method") {
+        Some("smc") => Some(PSCIMethod::SMC),
+        Some("hvc") => Some(PSCIMethod::HVC),
+        _ => None,
+    });
+    // The BSP must be the first core
+    let bsp = MPIDR_EL1.get() as usize & 0xff_00ff_ffff;
+    if let Some(i) = core_ids.iter().position(|id| *id == bsp) {
+        core_ids.swap(0, i);
+    }
+    if method.is_none() || core_ids.is_empty() {
+        warn!("PSCI not available. Running with a single core.");
+        core_ids.truncate(1);
+    }
+    info!(
+        "{} cores detected. PSCI method: {:?}",
+        core_ids.len().max(1),
+        method
+    );
+    NUM_CORES.store(core_ids.len().max(1), Ordering::SeqCst);
+    unsafe {
+        CORE_IDS = core_ids;
+        PSCI_METHOD = method;
+    }
+}
+
+pub fn num_cores() -> usize {
+    NUM_CORES.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+pub fn current_core() -> usize {
+    TPIDR_EL1.get() as usize
+}
+
+unsafe fn psci_call(function: usize, a: usize, b: usize, c: usize) -> isize {
+    let result: isize;
+    match PSCI_METHOD {
+        // `smc #0`. The assembler refuses it without the `el3` target feature.
+        Some(PSCIMethod::SMC) => asm!(
+            ".inst 0xd4000003",
+            inlateout("x0") function => result,
+            in("x1") a,
+            in("x2") b,
+            in("x3") c,
+        ),
+        Some(PSCIMethod::HVC) => asm!(
+            "hvc #0",
+            inlateout("x0") function => result,
+            in("x1") a,
+            in("x2") b,
+            in("x3") c,
+        ),
+        None => return -1,
+    }
+    result
+}
+
+/// Power off the machine with PSCI. Only returns if PSCI is not available.
+pub fn psci_system_off() {
+    unsafe { psci_call(PSCI_SYSTEM_OFF, 0, 0, 0) };
+}
+
+/// Reset the machine with PSCI. Only returns if PSCI is not available.
+pub fn psci_system_reset() {
+    unsafe { psci_call(PSCI_SYSTEM_RESET, 0, 0, 0) };
+}
+
+/// Clean data cache lines to the point of coherency, so that a core with MMU off can see the data.
+fn clean_dcache(start: Address<V>, size: usize) {
+    let ctr: usize;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr) };
+    let line = 4usize << ((ctr >> 16) & 0xf);
+    let mut cursor = start.as_usize() & !(line - 1);
+    while cursor < start.as_usize() + size {
+        unsafe { asm!("dc cvac, {}", in(reg) cursor) };
+        cursor += line;
+    }
+    unsafe { asm!("dsb sy") };
+}
+
+/// Start all the secondary cores and wait for them to be online.
+#[allow(static_mut_refs)]
+pub fn start_secondary_cores() {
+    let trampoline = Address::<V>::from(secondary_entry as usize);
+    let trampoline_paddr = KERNEL_MEMORY_MAPPER.translate(trampoline).unwrap();
+    clean_dcache(trampoline, Size4K::BYTES);
+    let mut expected_online = 1;
+    for core in 1..num_cores() {
+        let stack = KernelStack::new();
+        let args = Box::leak(Box::new(SecondaryBootArgs {
+            mair: MAIR_EL1.get(),
+            tcr: TCR_EL1.get(),
+            ttbr0: KERNEL_MEMORY_MAPPER.get_kernel_page_table() as u64,
+            sctlr: SCTLR_EL1.get(),
+            stack_top: stack.range().end.as_usize() as u64,
+            entry: secondary_main as usize as u64,
+            core: core as u64,
+        }));
+        let args_vaddr = Address::<V>::from(args as *const SecondaryBootArgs as usize);
+        let args_paddr = KERNEL_MEMORY_MAPPER.translate(args_vaddr).unwrap();
+        clean_dcache(args_vaddr, core::mem::size_of::<SecondaryBootArgs>());
+        let mpidr = unsafe { CORE_IDS[core] };
+        let result = unsafe {
+            psci_call(
+                PSCI_CPU_ON,
+                mpidr,
+                trampoline_paddr.as_usize(),
+                args_paddr.as_usize(),
+            )
+        };
+        if result != 0 {
+            error!(
+                "Failed to start core #{} (mpidr={:#x}): {}",
+                core, mpidr, result
+            );
+            continue;
+        }
+        expected_online += 1;
+        while ONLINE_CORES.load(Ordering::SeqCst) < expected_online {
+            core::hint::spin_loop();
+        }
+    }
+    info!("{} cores online", ONLINE_CORES.load(Ordering::SeqCst));
+}
+
+extern "C" fn secondary_main(core: usize) -> ! {
+    TPIDR_EL1.set(core as u64);
+    TargetArch::setup_interrupt_table();
+    super::pmu::init_core();
+    INTERRUPT.init(false);
+    crate::task::ipi::init_core();
+    TIMER.init(false);
+    info!("core #{} online", core);
+    ONLINE_CORES.fetch_add(1, Ordering::SeqCst);
+    SCHEDULER.schedule()
+}
+
+extern "C" {
+    fn secondary_entry() -> !;
+}
+
+// Entry point of the secondary cores, started by PSCI CPU_ON with MMU disabled.
+// `x0` holds the physical address of `SecondaryBootArgs`.
+// Note: This code must fit in a single page.
+global_asm! {"
+    .balign 4096
+.global secondary_entry
+secondary_entry:
+    // Drop to EL1, if we were started at EL2
+    mrs x1, CurrentEL
+    lsr x1, x1, #2
+    cmp x1, #2
+    b.ne 1f
+    mov x1, #3
+    msr cnthctl_el2, x1
+    msr cntvoff_el2, xzr
+    mov x1, #(1 << 31)
+    msr hcr_el2, x1
+    mov x1, #0x3c5
+    msr spsr_el2, x1
+    adr x1, 1f
+    msr elr_el2, x1
+    eret
+1:
+    // Enable FP/SIMD
+    mov x1, #0xfffffff
+    msr cpacr_el1, x1
+    // Load kernel page table and translation config
+    ldr x1, [x0, #0]
+    msr mair_el1, x1
+    ldr x1, [x0, #8]
+    msr tcr_el1, x1
+    ldr x1, [x0, #16]
+    msr ttbr0_el1, x1
+    isb
+    tlbi vmalle1
+    dsb nsh
+    isb
+    // Enable MMU. Physical memory is identity mapped.
+    ldr x1, [x0, #24]
+    msr sctlr_el1, x1
+    isb
+    // Jump to the kernel
+    ldr x1, [x0, #32]
+    mov sp, x1
+    ldr x2, [x0, #40]
+    ldr x0, [x0, #48]
+    br x2
+"}
@@ -1,4 +1,5 @@
 use boot::BootInfo;
+use core::time::Duration;
 use klib::task::Task;
 use memory::address::*;
 use memory::page_table::PageTable;
@@ -19,6 +20,8 @@ pub trait ArchContext: Sized + 'static {
     fn new(entry: *const extern "C" fn(ctx: *mut ()) -> !, ctx: *mut ()) -> Self;
     fn set_response_status(&self, s: isize);
     fn fork(&self) -> Self;
+    /// Set the thread pointer of the user thread, used for thread-local storage.
+    fn set_thread_pointer(&self, tp: usize);
 
     unsafe extern "C" fn return_to_user(&self) -> !;
     unsafe fn enter_usermode(
@@ -40,9 +43,34 @@ pub trait Arch {
 
     fn init(boot_info: &'static BootInfo);
 
+    /// Number of logical cores.
+    fn num_cores() -> usize;
+
+    /// Index of the current core. `0` is the BSP.
+    fn current_core() -> usize;
+
+    /// Boot all the secondary cores. Called after the interrupt and timer controllers are loaded.
+    fn start_secondary_cores();
+
     fn setup_interrupt_table();
 
+    /// Time since boot, from the system counter.
+    fn uptime() -> Duration;
+
+    /// Cycles of the current core, for measuring short latencies.
+    /// Counts from different cores are not comparable.
+    fn cycles() -> u64;
+
     fn halt(code: i32) -> !;
+
+    /// Call `f` with the call sites on the current kernel stack, innermost first.
+    fn walk_stack(f: &mut dyn FnMut(usize));
+
+    /// Start the kernel debugger if it is enabled on the command line, and wait for GDB to attach.
+    fn start_debugger();
+
+    /// Reset the machine.
+    fn reboot() -> !;
 }
 
 pub type TargetArch = impl Arch;
@@ -1,5 +1,6 @@
 use super::{Arch, ArchContext, TargetArch};
 use boot::BootInfo;
+use core::time::Duration;
 use memory::{address::Address, page_table::PageTable};
 
 #[repr(C)]
@@ -42,13 +43,39 @@ impl Arch for X64 {
         unimplemented!()
     }
 
+    fn num_cores() -> usize {
+        1
+    }
+
+    fn current_core() -> usize {
+        0
+    }
+
+    fn start_secondary_cores() {}
+
     fn setup_interrupt_table() {
         unimplemented!()
     }
 
+    fn uptime() -> Duration {
+        unimplemented!()
+    }
+
+    fn cycles() -> u64 {
+        unimplemented!()
+    }
+
     fn halt(_code: i32) -> ! {
         unimplemented!()
     }
+
+    fn walk_stack(_f: &mut dyn FnMut(usize)) {}
+
+    fn start_debugger() {}
+
+    fn reboot() -> ! {
+        unimplemented!()
+    }
 }
 
 #[allow(unused)]
@@ -70,10 +70,12 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> isize {
     if let Some(uart) = boot_info.uart {
         utils::boot_logger::init(uart);
     }
+    utils::cmdline::init(boot_info.command_line);
     info!("boot_info @ {:?} {:?}", boot_info as *const _, unsafe {
         *(boot_info as *const _ as *const usize)
     });
     info!("device_tree @ {:?}", boot_info.device_tree.as_ptr_range());
+    info!("command line: {:?}", boot_info.command_line);
     info!(
         "available_physical_memory @ {:?}",
         boot_info.available_physical_memory.as_ptr_range()
@@ -86,6 +88,8 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> isize {
     PHYSICAL_MEMORY.init(boot_info.available_physical_memory);
     info!("initialize kernel heap");
     KERNEL_HEAP.init();
+    utils::klog::init();
+    utils::backtrace::init(boot_info.kernel_image);
 
     // Initialize arch and boot drivers
     info!("load device tree");
@@ -95,6 +99,10 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> isize {
     info!("arch-specific initialization");
     TargetArch::init(boot_info);
 
+    info!("initialize entropy pool");
+    utils::random::init();
+    crate::memory::aslr::init();
+
     info!("load init-fs");
     INIT_FS.call_once(|| Box::leak(Box::new(RamFS::deserialize(boot_info.init_fs))));
     let initfs = *INIT_FS.get().unwrap();
@@ -106,6 +114,10 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> isize {
         crate::modules::register(name, file.to_vec());
     }
     info!("kernel modules loaded");
+    utils::klog::register_device();
+    utils::trace::init();
+    task::ipi::init();
+    TargetArch::start_debugger();
 
     info!("start sched process (pid=0)");
     PROCESS_MANAGER.spawn_sched_process();
@@ -118,6 +130,9 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> isize {
         crate::utils::testing::run_boot_tests();
     }
 
+    info!("start secondary cores");
+    TargetArch::start_secondary_cores();
+
     info!("start scheduler");
     SCHEDULER.schedule();
 }
@@ -129,6 +144,9 @@ fn panic(info: &PanicInfo<'_>) -> ! {
     let mut double_panic = DOUBLE_PANIC.lock();
     if !*double_panic {
         *double_panic = true;
+        // A panic while printing the backtrace is reported as a double panic.
+        drop(double_panic);
+        utils::backtrace::print_kernel_backtrace();
         TargetArch::halt(-1)
     } else {
         drop(double_panic);
@@ -142,6 +160,9 @@ pub extern "C" fn __chkstk() {}
 
 #[alloc_error_handler]
 fn alloc_error_handler(layout: ::alloc::alloc::Layout) -> ! {
+    if cfg!(feature = "heap_debug") {
+        crate::memory::kernel::dump_heap();
+    }
     panic!("Allocation error: {:?}", layout)
 }
 
@@ -0,0 +1,30 @@
+//! Address space layout randomization.
+//!
+//! User stacks, heaps, PIE executables, shared libraries and kernel modules are placed
+//! at random offsets. Boot with `aslr=off` to disable it for debugging.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::utils::{cmdline, random};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Read the boot option. Must be called after the entropy pool is seeded.
+pub fn init() {
+    if cmdline::get("aslr") == Some("off") {
+        ENABLED.store(false, Ordering::SeqCst);
+    }
+    info!("ASLR {}", if enabled() { "enabled" } else { "disabled" });
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A random multiple of `align` below `max`. Zero if ASLR is disabled.
+pub fn random_offset(max: usize, align: usize) -> usize {
+    if !enabled() {
+        return 0;
+    }
+    random::random_below(max / align) * align
+}
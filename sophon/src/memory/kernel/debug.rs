@@ -0,0 +1,210 @@
+//! Kernel heap debugging, enabled by the `heap_debug` feature.
+//!
+//! Each allocation gets a header and a red zone on both sides:
+//!
+//! ```text
+//! | padding | Header | red zone | object | red zone |
+//! ```
+//!
+//! The red zones are checked on free, and freed objects are poisoned.
+//! Live allocations are linked through their headers, and tagged with the caller,
+//! or with the kernel module they are allocated for.
+
+use core::alloc::Layout;
+use core::ptr;
+
+use interrupt::UninterruptibleMutex;
+use spin::Mutex;
+
+use super::KERNEL_HEAP;
+
+const REDZONE: usize = 16;
+const REDZONE_BYTE: u8 = 0xfb;
+/// Freed objects are filled with this byte.
+const POISON_BYTE: u8 = 0x6b;
+const MAGIC_LIVE: usize = 0x4c49_5645_414c_4c43;
+const MAGIC_FREED: usize = 0x4652_4545_414c_4c43;
+
+/// Who an allocation is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    /// Allocated by the kernel. The return address of the allocation call.
+    Caller(usize),
+    /// Allocated by a kernel module, by module id.
+    Module(usize),
+}
+
+#[repr(C)]
+struct Header {
+    prev: *mut Header,
+    next: *mut Header,
+    size: usize,
+    tag: Tag,
+    magic: usize,
+}
+
+struct LiveAllocations {
+    head: *mut Header,
+    count: usize,
+    bytes: usize,
+}
+
+unsafe impl Send for LiveAllocations {}
+
+static LIVE: Mutex<LiveAllocations> = Mutex::new(LiveAllocations {
+    head: ptr::null_mut(),
+    count: 0,
+    bytes: 0,
+});
+
+/// The return address of the enclosing function, after inlining. Best effort.
+#[inline(always)]
+pub fn return_address() -> usize {
+    #[cfg(target_arch = "aarch64")]
+    {
+        let lr: usize;
+        unsafe { core::arch::asm!("mov {}, x30", out(reg) lr) };
+        lr
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        0
+    }
+}
+
+/// Offset of the object from the start of the underlying allocation.
+fn prefix(layout: &Layout) -> usize {
+    (size_of::<Header>() + REDZONE).next_multiple_of(usize::max(layout.align(), 16))
+}
+
+fn inner_layout(layout: &Layout) -> Layout {
+    let align = usize::max(layout.align(), 16);
+    Layout::from_size_align(prefix(layout) + layout.size() + REDZONE, align).unwrap()
+}
+
+unsafe fn header_of(object: *mut u8) -> *mut Header {
+    object.sub(REDZONE + size_of::<Header>()) as *mut Header
+}
+
+unsafe fn redzone_intact(start: *const u8) -> bool {
+    core::slice::from_raw_parts(start, REDZONE)
+        .iter()
+        .all(|b| *b == REDZONE_BYTE)
+}
+
+pub unsafe fn alloc(layout: Layout, tag: Tag) -> *mut u8 {
+    let inner = KERNEL_HEAP.alloc(inner_layout(&layout));
+    if inner.is_null() {
+        return inner;
+    }
+    let object = inner.add(prefix(&layout));
+    ptr::write_bytes(object.sub(REDZONE), REDZONE_BYTE, REDZONE);
+    ptr::write_bytes(object.add(layout.size()), REDZONE_BYTE, REDZONE);
+    let header = header_of(object);
+    let mut live = LIVE.lock_uninterruptible();
+    header.write(Header {
+        prev: ptr::null_mut(),
+        next: live.head,
+        size: layout.size(),
+        tag,
+        magic: MAGIC_LIVE,
+    });
+    if !live.head.is_null() {
+        (*live.head).prev = header;
+    }
+    live.head = header;
+    live.count += 1;
+    live.bytes += layout.size();
+    object
+}
+
+pub unsafe fn dealloc(object: *mut u8, layout: Layout) {
+    let header = header_of(object);
+    match (*header).magic {
+        MAGIC_LIVE => {}
+        MAGIC_FREED => panic!("heap: double free of {:?} ({:?})", object, layout),
+        _ => panic!(
+            "heap: free of {:?} ({:?}) with a corrupted header",
+            object, layout
+        ),
+    }
+    let tag = (*header).tag;
+    if (*header).size != layout.size() {
+        panic!(
+            "heap: {:?} allocated with size {} by {:?}, but freed with {:?}",
+            object,
+            (*header).size,
+            tag,
+            layout
+        );
+    }
+    if !redzone_intact(object.sub(REDZONE)) || !redzone_intact(object.add(layout.size())) {
+        panic!(
+            "heap: red zone of {:?} ({:?}) allocated by {:?} is overwritten",
+            object, layout, tag
+        );
+    }
+    {
+        let mut live = LIVE.lock_uninterruptible();
+        let (prev, next) = ((*header).prev, (*header).next);
+        if prev.is_null() {
+            live.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+        live.count -= 1;
+        live.bytes -= layout.size();
+        (*header).magic = MAGIC_FREED;
+    }
+    ptr::write_bytes(object, POISON_BYTE, layout.size());
+    KERNEL_HEAP.dealloc(object.sub(prefix(&layout)), inner_layout(&layout));
+}
+
+/// Print the live allocations, grouped by tag, largest first.
+pub fn dump() {
+    const MAX_TAGS: usize = 64;
+    // No allocation here: this also runs when the heap is out of memory.
+    let mut tags: [(Option<Tag>, usize, usize); MAX_TAGS] = [(None, 0, 0); MAX_TAGS];
+    // The count and bytes of the tags that do not fit.
+    let mut others = (0, 0);
+    let (count, bytes) = {
+        let live = LIVE.lock_uninterruptible();
+        let mut header = live.head;
+        while !header.is_null() {
+            let (tag, size) = unsafe { ((*header).tag, (*header).size) };
+            let slot = tags
+                .iter_mut()
+                .find(|(t, _, _)| t.is_none() || *t == Some(tag));
+            let (count, bytes) = match slot {
+                Some((t, count, bytes)) => {
+                    t.get_or_insert(tag);
+                    (count, bytes)
+                }
+                None => (&mut others.0, &mut others.1),
+            };
+            *count += 1;
+            *bytes += size;
+            header = unsafe { (*header).next };
+        }
+        (live.count, live.bytes)
+    };
+    let used = tags.iter().take_while(|(t, _, _)| t.is_some()).count();
+    let tags = &mut tags[..used];
+    tags.sort_unstable_by_key(|(_, _, bytes)| usize::MAX - *bytes);
+    println!("heap: {} live allocations, {} bytes", count, bytes);
+    println!("{:>10} {:>8}  tag", "bytes", "count");
+    for (tag, count, bytes) in tags.iter() {
+        match tag.unwrap() {
+            Tag::Caller(address) => println!("{:>10} {:>8}  {:#x}", bytes, count, address),
+            Tag::Module(id) => crate::modules::with_name(id, |name| {
+                println!("{:>10} {:>8}  module {}", bytes, count, name)
+            }),
+        }
+    }
+    if others.0 != 0 {
+        println!("{:>10} {:>8}  other tags", others.1, others.0);
+    }
+}
@@ -1,4 +1,6 @@
+use super::debug::{self, Tag};
 use super::{KERNEL_HEAP_RANGE, KERNEL_MEMORY_MAPPER, LOG_KERNEL_HEAP_SIZE};
+use crate::memory::aslr;
 use crate::memory::physical::PHYSICAL_MEMORY;
 use core::alloc::{GlobalAlloc, Layout};
 use core::iter::Step;
@@ -10,30 +12,45 @@ use memory::bitmap_page_allocator::BitMapPageAllocator;
 use memory::free_list_allocator::FreeListAllocator;
 use memory::page::*;
 use memory::page_table::PageFlags;
+use memory::slab_allocator::{SlabAllocator, SlabStats, SIZE_CLASSES};
 use spin::Mutex;
 
+use crate::arch::{Arch, TargetArch};
+
+/// Cores with their own slab magazines. Other cores use the slab depots directly.
+const SLAB_CORES: usize = 16;
+
 static VIRTUAL_PAGE_ALLOCATOR: Mutex<BitMapPageAllocator<V, LOG_KERNEL_HEAP_SIZE>> =
     Mutex::new(BitMapPageAllocator::new());
 
 pub static KERNEL_HEAP: KernelHeap = KernelHeap::new();
 
 /// The kernel heap memory manager.
+/// Small objects are allocated from slabs, and larger objects from power-of-two free lists.
 pub struct KernelHeap {
+    slab: SlabAllocator<V, Self, SLAB_CORES>,
     fa: Mutex<FreeListAllocator<V, Self, { Size2M::LOG_BYTES + 1 }>>,
 }
 
 impl KernelHeap {
     const fn new() -> Self {
         Self {
+            slab: SlabAllocator::new(),
             fa: Mutex::new(FreeListAllocator::new()),
         }
     }
 
     pub fn init(&'static self) {
         VIRTUAL_PAGE_ALLOCATOR.lock().init(KERNEL_HEAP_RANGE.start);
+        self.slab.init(self, <TargetArch as Arch>::current_core);
         self.fa.lock().init(self)
     }
 
+    /// Allocation statistics of the slab size classes.
+    pub fn slab_stats(&self) -> [SlabStats; SIZE_CLASSES] {
+        self.slab.stats()
+    }
+
     /// Allocate virtual pages that are not backed by any physical memory.
     pub fn virtual_allocate<S: PageSize>(&self, pages: usize) -> Range<Page<S>> {
         VIRTUAL_PAGE_ALLOCATOR.lock().acquire(pages)
@@ -44,6 +61,56 @@ impl KernelHeap {
         VIRTUAL_PAGE_ALLOCATOR.lock().release(pages)
     }
 
+    /// Allocate virtual pages that are backed by physical memory, at a random offset of up to
+    /// `max_offset` pages from where `acquire_pages` would put them.
+    pub fn acquire_randomized_pages<S: PageSize>(
+        &self,
+        pages: usize,
+        max_offset: usize,
+    ) -> Option<Range<Page<S>>> {
+        let offset = aslr::random_offset(max_offset, 1);
+        let virtual_pages = self.virtual_allocate::<S>(pages + offset);
+        let start = Page::forward(virtual_pages.start, offset);
+        if offset != 0 {
+            self.virtual_release(virtual_pages.start..start);
+        }
+        self.back_pages(start..virtual_pages.end);
+        Some(start..virtual_pages.end)
+    }
+
+    /// Map fresh physical pages to virtual pages.
+    pub fn back_pages<S: PageSize>(&self, pages: Range<Page<S>>) {
+        for page in pages {
+            let frame = PHYSICAL_MEMORY.acquire::<S>().unwrap();
+            KERNEL_MEMORY_MAPPER.map(page, frame, PageFlags::kernel_data_flags::<S>());
+        }
+    }
+
+    #[inline(always)]
+    pub(super) unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if SlabAllocator::<V, KernelHeap, SLAB_CORES>::fits(&layout) {
+            self.slab
+                .alloc(&layout)
+                .map(|a| a.as_mut_ptr())
+                .unwrap_or(ptr::null_mut())
+        } else if layout.pad_to_align().size() < Size2M::BYTES {
+            self.fa.lock_uninterruptible().alloc(&layout).as_mut_ptr()
+        } else {
+            self.alloc_large(layout)
+        }
+    }
+
+    #[inline(always)]
+    pub(super) unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if SlabAllocator::<V, KernelHeap, SLAB_CORES>::fits(&layout) {
+            self.slab.free(ptr.into(), &layout)
+        } else if layout.pad_to_align().size() < Size2M::BYTES {
+            self.fa.lock_uninterruptible().free(ptr.into(), &layout)
+        } else {
+            self.dealloc_large(ptr, layout)
+        }
+    }
+
     #[cold]
     fn alloc_large(&self, layout: Layout) -> *mut u8 {
         let pages = (layout.pad_to_align().size() + Size2M::MASK) >> Size2M::LOG_BYTES;
@@ -65,14 +132,7 @@ impl PageResource<V> for KernelHeap {
     /// Allocate virtual pages that are backed by physical memory.
     fn acquire_pages<S: PageSize>(&self, pages: usize) -> Option<Range<Page<S>>> {
         let virtual_pages = KERNEL_HEAP.virtual_allocate::<S>(pages);
-        for i in 0..pages {
-            let frame = PHYSICAL_MEMORY.acquire::<S>().unwrap();
-            KERNEL_MEMORY_MAPPER.map(
-                Page::forward(virtual_pages.start, i),
-                frame,
-                PageFlags::kernel_data_flags::<S>(),
-            );
-        }
+        self.back_pages(virtual_pages.clone());
         Some(virtual_pages)
     }
 
@@ -87,31 +147,38 @@ impl PageResource<V> for KernelHeap {
 }
 
 /// Rust global allocator that allocate objects into the kernel heap.
+///
+/// With the `heap_debug` feature, allocations get red zones and call-site tags,
+/// and freed objects are poisoned. See `debug`.
 pub struct KernelHeapAllocator;
 
+impl KernelHeapAllocator {
+    /// Allocate an object on behalf of a kernel module.
+    pub unsafe fn alloc_for_module(&self, layout: Layout, module: usize) -> *mut u8 {
+        if cfg!(feature = "heap_debug") {
+            debug::alloc(layout, Tag::Module(module))
+        } else {
+            KERNEL_HEAP.alloc(layout)
+        }
+    }
+}
+
 unsafe impl GlobalAlloc for KernelHeapAllocator {
     #[inline(always)]
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if layout.pad_to_align().size() < Size2M::BYTES {
-            KERNEL_HEAP
-                .fa
-                .lock_uninterruptible()
-                .alloc(&layout)
-                .as_mut_ptr()
+        if cfg!(feature = "heap_debug") {
+            debug::alloc(layout, Tag::Caller(debug::return_address()))
         } else {
-            KERNEL_HEAP.alloc_large(layout)
+            KERNEL_HEAP.alloc(layout)
         }
     }
 
     #[inline(always)]
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        if layout.pad_to_align().size() < Size2M::BYTES {
-            KERNEL_HEAP
-                .fa
-                .lock_uninterruptible()
-                .free(ptr.into(), &layout)
+        if cfg!(feature = "heap_debug") {
+            debug::dealloc(ptr, layout)
         } else {
-            KERNEL_HEAP.dealloc_large(ptr, layout)
+            KERNEL_HEAP.dealloc(ptr, layout)
         }
     }
 }
@@ -125,3 +192,26 @@ fn alloc_test() {
     let sum: usize = array.iter().sum();
     assert_eq!(sum, (1 + 100) * 100 / 2);
 }
+
+#[test]
+fn slab_test() {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    // Objects of every size class, enough to overflow the magazines.
+    let mut objects: Vec<Box<[u8]>> = Vec::new();
+    for i in 0..1000 {
+        let size = 1 + (i * 7) % memory::slab_allocator::MAX_SIZE;
+        let object = alloc::vec![(i % 256) as u8; size].into_boxed_slice();
+        // Slab objects are aligned to their size class
+        assert_eq!(object.as_ptr() as usize % 16, 0);
+        objects.push(object);
+    }
+    for (i, object) in objects.iter().enumerate() {
+        assert!(object.iter().all(|b| *b == (i % 256) as u8));
+    }
+    let before = KERNEL_HEAP.slab_stats();
+    drop(objects);
+    let after = KERNEL_HEAP.slab_stats();
+    let frees = |stats: &[SlabStats]| stats.iter().map(|s| s.frees).sum::<usize>();
+    assert!(frees(&after) - frees(&before) >= 1000);
+}
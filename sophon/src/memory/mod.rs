@@ -2,7 +2,9 @@ use core::ops::Range;
 
 use memory::address::Address;
 
+pub mod aslr;
 pub mod kernel;
+pub mod oom;
 pub mod physical;
 pub mod utils;
 
@@ -0,0 +1,105 @@
+//! Out-of-memory handling for user memory.
+//!
+//! When no frame is free, the reclaimable caches are shrunk first. If that is not enough,
+//! the user process with the most pages is killed, and the allocation fails. The caller
+//! either returns an error to user space, or retries later.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use klib::proc::{MemSpace, PID};
+use memory::page::{Frame, PageSize, Size4K};
+use syscall::Signal;
+
+use super::physical::PHYSICAL_MEMORY;
+use crate::task::{signal, PROCESS_MANAGER};
+
+/// Caches that can give memory back. Each returns the number of 4K frames it released.
+///
+/// The retired page tables of `memory::utils` are left out. A core may still have one in
+/// TTBR0, and nothing records which. New processes reuse them, so they never take more than
+/// one frame per process alive at the peak.
+const SHRINKERS: &[(&str, fn() -> usize)] =
+    &[("shared libraries", crate::task::shlib::shrink_cache)];
+
+/// The process killed last time. No other process is killed until it has released its memory.
+static LAST_VICTIM: AtomicUsize = AtomicUsize::new(PID::NULL.0);
+
+/// Account `pages` new pages to an address space. Returns false if this exceeds its limit.
+pub fn charge(mem: &MemSpace, pages: usize) -> bool {
+    let limit = mem.page_limit.load(Ordering::SeqCst);
+    mem.pages
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+            used.checked_add(pages).filter(|used| *used <= limit)
+        })
+        .is_ok()
+}
+
+pub fn uncharge(mem: &MemSpace, pages: usize) {
+    mem.pages.fetch_sub(pages, Ordering::SeqCst);
+}
+
+/// Allocate a frame for user memory. Returns `None` if memory is still exhausted after
+/// shrinking the caches. An OOM victim is then on its way out.
+pub fn acquire<S: PageSize>() -> Option<Frame<S>> {
+    if let Some(frame) = PHYSICAL_MEMORY.acquire() {
+        return Some(frame);
+    }
+    for (name, shrink) in SHRINKERS {
+        let released = shrink();
+        if released != 0 {
+            info!("oom: released {} KB from {}", released << 2, name);
+            if let Some(frame) = PHYSICAL_MEMORY.acquire() {
+                return Some(frame);
+            }
+        }
+    }
+    kill_largest_process(S::BYTES);
+    None
+}
+
+fn kill_largest_process(request: usize) {
+    let last_victim = PROCESS_MANAGER.get_proc_by_id(PID(LAST_VICTIM.load(Ordering::SeqCst)));
+    if last_victim.is_some_and(|p| p.mem.has_user_page_table()) {
+        // Wait for the last victim to exit.
+        return;
+    }
+    let procs = PROCESS_MANAGER.get_all_procs();
+    let victim = procs
+        .iter()
+        .filter(|p| p.id != PID::INIT && !p.is_zombie.load(Ordering::SeqCst))
+        .filter(|p| p.mem.has_user_page_table())
+        .max_by_key(|p| p.mem.pages.load(Ordering::SeqCst));
+    let victim = match victim {
+        Some(victim) => victim.clone(),
+        None => {
+            let current = PROCESS_MANAGER.current_proc().unwrap();
+            if current.id == PID::INIT {
+                panic!("oom: out of memory in init");
+            }
+            current
+        }
+    };
+    let (total, free) = PHYSICAL_MEMORY.usage();
+    error!(
+        "oom: out of memory allocating {} KB (total {} KB, free {} KB)",
+        request >> 10,
+        total >> 10,
+        free >> 10
+    );
+    for proc in &procs {
+        info!(
+            "oom:   pid={:<4} pages={:<8} {}",
+            proc.id.0,
+            proc.mem.pages.load(Ordering::SeqCst),
+            proc.name.lock()
+        );
+    }
+    error!(
+        "oom: killed pid {} ({}), {} KB",
+        victim.id.0,
+        victim.name.lock(),
+        victim.mem.pages.load(Ordering::SeqCst) << (Size4K::LOG_BYTES - 10)
+    );
+    LAST_VICTIM.store(victim.id.0, Ordering::SeqCst);
+    signal::send(&victim, Signal::Kill);
+}
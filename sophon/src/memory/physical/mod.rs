@@ -1,10 +1,20 @@
 mod physical_page_resource;
 
 use self::physical_page_resource::PHYSICAL_PAGE_RESOURCE;
+pub use self::physical_page_resource::{Zone, ZoneStats};
 use super::kernel::KERNEL_MEMORY_MAPPER;
+use alloc::collections::BTreeMap;
 use core::ops::Range;
 use interrupt::UninterruptibleMutex;
-use memory::{address::P, page::*};
+use memory::{
+    address::{Address, P},
+    page::*,
+};
+use spin::Mutex;
+
+/// Extra references to frames that are mapped more than once: by copy-on-write pages after
+/// `fork`, or by shared libraries. Frames that are not in the map have a single owner.
+static SHARED_FRAMES: Mutex<BTreeMap<Address<P>, usize>> = Mutex::new(BTreeMap::new());
 
 pub struct PhysicalMemory {
     _private: (),
@@ -25,10 +35,63 @@ impl PhysicalMemory {
         PHYSICAL_PAGE_RESOURCE.lock_uninterruptible().acquire()
     }
 
+    /// Allocate a frame in a specific zone, e.g. for DMA buffers.
+    pub fn acquire_in<S: PageSize>(&self, zone: Zone) -> Option<Frame<S>> {
+        let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+        PHYSICAL_PAGE_RESOURCE
+            .lock_uninterruptible()
+            .acquire_in(zone)
+    }
+
     pub fn release<S: PageSize>(&self, frame: Frame<S>) {
         let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
         PHYSICAL_PAGE_RESOURCE.lock_uninterruptible().release(frame)
     }
+
+    /// Add a reference to a frame. The frame is released by the last `release_shared`.
+    pub fn share<S: PageSize>(&self, frame: Frame<S>) {
+        *SHARED_FRAMES
+            .lock_uninterruptible()
+            .entry(frame.start())
+            .or_insert(0) += 1;
+    }
+
+    /// Does the frame have more than one reference?
+    pub fn is_shared<S: PageSize>(&self, frame: Frame<S>) -> bool {
+        SHARED_FRAMES
+            .lock_uninterruptible()
+            .contains_key(&frame.start())
+    }
+
+    /// Drop a reference to a frame, and release the frame if it was the last one.
+    pub fn release_shared<S: PageSize>(&self, frame: Frame<S>) {
+        {
+            let mut shared_frames = SHARED_FRAMES.lock_uninterruptible();
+            match shared_frames.get_mut(&frame.start()) {
+                Some(1) => {
+                    shared_frames.remove(&frame.start());
+                    return;
+                }
+                Some(count) => {
+                    *count -= 1;
+                    return;
+                }
+                None => {}
+            }
+        }
+        self.release(frame)
+    }
+
+    /// Total and free physical memory, in bytes.
+    pub fn usage(&self) -> (usize, usize) {
+        let resource = PHYSICAL_PAGE_RESOURCE.lock_uninterruptible();
+        (resource.total(), resource.free())
+    }
+
+    /// Frame counts of each zone.
+    pub fn zone_stats(&self) -> [ZoneStats; Zone::ALL.len()] {
+        PHYSICAL_PAGE_RESOURCE.lock_uninterruptible().zone_stats()
+    }
 }
 
 pub static PHYSICAL_MEMORY: PhysicalMemory = PhysicalMemory::new();
@@ -60,3 +123,40 @@ impl<S: PageSize> Drop for SharedPhysicalPage<S> {
         PHYSICAL_MEMORY.release(self.frame);
     }
 }
+
+/// A boot test: the exact free memory counts need a single core.
+#[test(boot)]
+fn buddy_test() {
+    use alloc::vec::Vec;
+    let (_, free) = PHYSICAL_MEMORY.usage();
+    // Split a 2M block into 4K frames, and merge them back
+    let frames = (0..1024)
+        .map(|_| PHYSICAL_MEMORY.acquire::<Size4K>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(PHYSICAL_MEMORY.usage().1, free - 1024 * Size4K::BYTES);
+    for frame in frames {
+        PHYSICAL_MEMORY.release(frame);
+    }
+    assert_eq!(PHYSICAL_MEMORY.usage().1, free);
+    let huge = PHYSICAL_MEMORY.acquire::<Size2M>().unwrap();
+    assert!(huge.start().is_aligned_to(Size2M::BYTES));
+    PHYSICAL_MEMORY.release(huge);
+    let stats = PHYSICAL_MEMORY.zone_stats();
+    assert_eq!(
+        stats.iter().map(|s| s.free).sum::<usize>() << Size4K::LOG_BYTES,
+        free
+    );
+}
+
+#[test]
+fn shared_frame_test() {
+    let frame = PHYSICAL_MEMORY.acquire::<Size4K>().unwrap();
+    assert!(!PHYSICAL_MEMORY.is_shared(frame));
+    PHYSICAL_MEMORY.share(frame);
+    PHYSICAL_MEMORY.share(frame);
+    PHYSICAL_MEMORY.release_shared(frame);
+    assert!(PHYSICAL_MEMORY.is_shared(frame));
+    PHYSICAL_MEMORY.release_shared(frame);
+    assert!(!PHYSICAL_MEMORY.is_shared(frame));
+    PHYSICAL_MEMORY.release_shared(frame);
+}
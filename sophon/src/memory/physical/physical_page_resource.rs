@@ -2,140 +2,291 @@ use core::ops::Range;
 use memory::{address::*, page::*};
 use spin::Mutex;
 
-const LOG_MAX_ADDRESS_SPACE_SIZE: usize = 48;
-const NUM_SIZE_CLASS: usize = LOG_MAX_ADDRESS_SPACE_SIZE - Size4K::LOG_BYTES + 1;
+/// Largest block managed by the buddy allocator.
+const LOG_MAX_BLOCK_SIZE: usize = Size1G::LOG_BYTES;
+/// Block orders: 4K, 8K, ..., 1G.
+const ORDERS: usize = LOG_MAX_BLOCK_SIZE - Size4K::LOG_BYTES + 1;
+/// Legacy DMA masters on the Raspberry Pi 4 can only address the first 1 GiB.
+const DMA_ZONE_END: Address<P> = Address::new(1 << 30);
+const MAX_PHYSICAL_ADDRESS: Address<P> = Address::new(1 << 48);
 
-pub struct PhysicalPageResource {
-    table: [Address<P>; NUM_SIZE_CLASS],
+/// Physical memory zones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// Low memory that all DMA masters can address.
+    Dma,
+    Normal,
 }
 
-impl PhysicalPageResource {
-    pub const fn new() -> Self {
-        Self {
-            table: [Address::ZERO; NUM_SIZE_CLASS],
+impl Zone {
+    pub const ALL: [Zone; 2] = [Zone::Dma, Zone::Normal];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Zone::Dma => "DMA",
+            Zone::Normal => "Normal",
         }
     }
 
-    const fn size(size_class: usize) -> usize {
-        1 << (size_class + Size4K::LOG_BYTES)
+    const fn range(&self) -> Range<Address<P>> {
+        match self {
+            Zone::Dma => Address::ZERO..DMA_ZONE_END,
+            Zone::Normal => DMA_ZONE_END..MAX_PHYSICAL_ADDRESS,
+        }
     }
 
-    const fn size_class(size: usize) -> usize {
-        size.next_power_of_two().trailing_zeros() as usize - Size4K::LOG_BYTES
+    fn of(a: Address<P>) -> Self {
+        if a < DMA_ZONE_END {
+            Zone::Dma
+        } else {
+            Zone::Normal
+        }
     }
+}
 
-    #[inline(always)]
-    fn push(&mut self, cell: Address<P>, size_class: usize) {
-        unsafe {
-            cell.store(self.table[size_class]);
+/// Frame counts of a zone.
+#[derive(Debug, Clone, Copy)]
+pub struct ZoneStats {
+    pub zone: Zone,
+    pub total: usize,
+    pub free: usize,
+}
+
+impl ZoneStats {
+    pub const fn used(&self) -> usize {
+        self.total - self.free
+    }
+}
+
+/// Links of a free block, stored in the block itself.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FreeBlock {
+    next: Address<P>,
+    prev: Address<P>,
+}
+
+/// A binary buddy allocator over the frames of one zone.
+///
+/// Free blocks of each order are kept in doubly-linked lists, so that a free buddy can be
+/// taken out of its list when two buddies are merged.
+struct ZoneAllocator {
+    free_lists: [Address<P>; ORDERS],
+    /// The frames managed by this zone, including holes.
+    span: Range<Address<P>>,
+    /// One byte per frame in `span`: one plus the order of the free block starting
+    /// at the frame, or zero if no free block starts there.
+    orders: Address<P>,
+    /// Number of frames.
+    total: usize,
+    /// Number of free frames.
+    free: usize,
+}
+
+impl ZoneAllocator {
+    const fn new() -> Self {
+        Self {
+            free_lists: [Address::ZERO; ORDERS],
+            span: Address::ZERO..Address::ZERO,
+            orders: Address::ZERO,
+            total: 0,
+            free: 0,
         }
-        self.table[size_class] = cell;
     }
 
-    #[inline(always)]
-    fn pop(&mut self, size_class: usize) -> Option<Address<P>> {
-        if self.table[size_class].is_zero() {
+    const fn block_size(order: usize) -> usize {
+        1 << (order + Size4K::LOG_BYTES)
+    }
+
+    /// Bytes of metadata needed for `span`, rounded up to whole frames.
+    fn metadata_size(span: &Range<Address<P>>) -> usize {
+        ((span.end - span.start) >> Size4K::LOG_BYTES).next_multiple_of(Size4K::BYTES)
+    }
+
+    fn order_slot(&self, block: Address<P>) -> *mut u8 {
+        let index = (block - self.span.start) >> Size4K::LOG_BYTES;
+        unsafe { self.orders.as_mut_ptr::<u8>().add(index) }
+    }
+
+    /// The order of the free block starting at `block`, if there is one.
+    fn free_order(&self, block: Address<P>) -> Option<usize> {
+        if !self.span.contains(&block) {
             return None;
         }
-        let cell = self.table[size_class];
+        match unsafe { *self.order_slot(block) } {
+            0 => None,
+            x => Some(x as usize - 1),
+        }
+    }
+
+    fn push(&mut self, block: Address<P>, order: usize) {
+        let head = self.free_lists[order];
         unsafe {
-            self.table[size_class] = cell.load();
+            block.store(FreeBlock {
+                next: head,
+                prev: Address::ZERO,
+            });
+            if !head.is_zero() {
+                head.as_mut::<FreeBlock>().prev = block;
+            }
+            *self.order_slot(block) = order as u8 + 1;
         }
-        Some(cell)
-    }
-
-    fn split_cell(
-        &mut self,
-        parent: Address<P>,
-        parent_size_class: usize,
-    ) -> (Address<P>, Address<P>) {
-        let child_size_class = parent_size_class - 1;
-        let unit1 = parent;
-        let unit2 = parent + (1 << (child_size_class + Size4K::LOG_BYTES));
-        (unit1, unit2)
-    }
-
-    #[cold]
-    fn allocate_cell_slow(&mut self, request_size_class: usize) -> Option<Address<P>> {
-        for size_class in request_size_class..NUM_SIZE_CLASS {
-            if let Some(unit) = self.pop(size_class) {
-                let parent = unit;
-                for parent_size_class in ((request_size_class + 1)..=size_class).rev() {
-                    let (_unit1, unit2) = self.split_cell(parent, parent_size_class);
-                    let child_size_class = parent_size_class - 1;
-                    // Add second cell to list
-                    debug_assert!(child_size_class < NUM_SIZE_CLASS);
-                    self.push(unit2, child_size_class);
-                }
-                return Some(unit);
+        self.free_lists[order] = block;
+    }
+
+    fn remove(&mut self, block: Address<P>, order: usize) {
+        unsafe {
+            let FreeBlock { next, prev } = block.load();
+            if prev.is_zero() {
+                self.free_lists[order] = next;
+            } else {
+                prev.as_mut::<FreeBlock>().next = next;
+            }
+            if !next.is_zero() {
+                next.as_mut::<FreeBlock>().prev = prev;
             }
+            *self.order_slot(block) = 0;
         }
-        None
     }
 
-    #[inline(always)]
-    fn allocate_cell(&mut self, size_class: usize) -> Option<Address<P>> {
-        if let Some(cell) = self.pop(size_class) {
-            return Some(cell);
+    fn alloc(&mut self, order: usize) -> Option<Address<P>> {
+        let mut current = (order..ORDERS).find(|o| !self.free_lists[*o].is_zero())?;
+        let block = self.free_lists[current];
+        self.remove(block, current);
+        // Return the upper halves to the free lists
+        while current > order {
+            current -= 1;
+            self.push(block + Self::block_size(current), current);
         }
-        self.allocate_cell_slow(size_class)
+        self.free -= 1 << order;
+        Some(block)
     }
 
-    #[inline(always)]
-    fn release_cell(&mut self, cell: Address<P>, size_class: usize) {
-        self.push(cell, size_class);
+    fn dealloc(&mut self, block: Address<P>, order: usize) {
+        debug_assert!(block.is_aligned_to(Self::block_size(order)));
+        debug_assert!(self.free_order(block).is_none(), "double free");
+        self.free += 1 << order;
+        self.insert(block, order);
     }
 
-    fn release_contiguous(&mut self, mut start: Address<P>, mut size: usize) {
-        let limit = start + size;
-        while start < limit {
-            let curr_size_class = Self::size_class(size);
-            let prev_size_class = if size == Self::size(curr_size_class) {
-                curr_size_class
-            } else {
-                curr_size_class - 1
-            };
-            let size_class = usize::min(
-                prev_size_class,
-                start.trailing_zeros() as usize - Size4K::LOG_BYTES,
-            );
-            let end = start + Self::size(size_class);
-            if (*start & (Self::size(size_class) - 1)) != 0 {
-                loop {}
+    /// Add a free block, merged with its free buddies.
+    fn insert(&mut self, mut block: Address<P>, mut order: usize) {
+        while order + 1 < ORDERS {
+            let buddy = Address::new(block.as_usize() ^ Self::block_size(order));
+            if self.free_order(buddy) != Some(order) {
+                break;
             }
-            debug_assert_eq!((*start & (Self::size(size_class) - 1)), 0);
-            if *end > *limit {
-                loop {}
+            self.remove(buddy, order);
+            block = Address::min(block, buddy);
+            order += 1;
+        }
+        self.push(block, order);
+    }
+
+    /// Add a range of free frames.
+    fn add_range(&mut self, range: Range<Address<P>>) {
+        // Address zero marks the end of a free list.
+        let mut start = Address::max(range.start, Address::new(Size4K::BYTES));
+        while start < range.end {
+            let alignment = start.as_usize().trailing_zeros() as usize - Size4K::LOG_BYTES;
+            let fit = (range.end - start).ilog2() as usize - Size4K::LOG_BYTES;
+            let order = usize::min(usize::min(alignment, fit), ORDERS - 1);
+            self.insert(start, order);
+            self.total += 1 << order;
+            self.free += 1 << order;
+            start += Self::block_size(order);
+        }
+    }
+
+    /// Take ownership of the frames in `parts`. The metadata is placed at the end of
+    /// the largest part.
+    fn init(&mut self, parts: impl Iterator<Item = Range<Address<P>>> + Clone) {
+        let Some(start) = parts.clone().map(|r| r.start).min() else {
+            return;
+        };
+        let end = parts.clone().map(|r| r.end).max().unwrap();
+        self.span = start..end;
+        let metadata_size = Self::metadata_size(&self.span);
+        let largest = parts.clone().max_by_key(|r| r.end - r.start).unwrap();
+        assert!(
+            largest.end - largest.start > metadata_size,
+            "no room for the physical memory metadata"
+        );
+        self.orders = largest.end - metadata_size;
+        unsafe { Address::zero(self.orders..largest.end) };
+        for part in parts {
+            if part.start == largest.start {
+                self.add_range(part.start..self.orders);
+            } else {
+                self.add_range(part);
             }
-            debug_assert!(*end <= *limit);
-            self.release_cell(start, size_class);
-            start = end;
-            size = limit - end;
         }
-        debug_assert_eq!(start, limit);
+    }
+}
+
+pub struct PhysicalPageResource {
+    zones: [ZoneAllocator; Zone::ALL.len()],
+}
+
+impl PhysicalPageResource {
+    pub const fn new() -> Self {
+        Self {
+            zones: [ZoneAllocator::new(), ZoneAllocator::new()],
+        }
+    }
+
+    const fn order<S: PageSize>() -> usize {
+        S::LOG_BYTES - Size4K::LOG_BYTES
     }
 
     pub fn init(&mut self, frames: &'static [Range<Frame>]) {
-        for range in frames {
-            let start = range.start.start();
-            let end = range.end.start();
-            self.release_contiguous(start, end - start);
+        for zone in Zone::ALL {
+            let zone_range = zone.range();
+            let parts = frames.iter().filter_map(move |r| {
+                let start = Address::max(r.start.start(), zone_range.start);
+                let end = Address::min(r.end.start(), zone_range.end);
+                (start < end).then_some(start..end)
+            });
+            self.zones[zone as usize].init(parts);
         }
     }
 
+    /// Total physical memory, in bytes.
+    pub fn total(&self) -> usize {
+        self.zones.iter().map(|z| z.total).sum::<usize>() << Size4K::LOG_BYTES
+    }
+
+    /// Free physical memory, in bytes.
+    pub fn free(&self) -> usize {
+        self.zones.iter().map(|z| z.free).sum::<usize>() << Size4K::LOG_BYTES
+    }
+
+    pub fn zone_stats(&self) -> [ZoneStats; Zone::ALL.len()] {
+        Zone::ALL.map(|zone| ZoneStats {
+            zone,
+            total: self.zones[zone as usize].total,
+            free: self.zones[zone as usize].free,
+        })
+    }
+
+    /// Allocate a frame, preferably outside of the DMA zone.
     #[inline(always)]
     pub fn acquire<S: PageSize>(&mut self) -> Option<Frame<S>> {
-        let size = 1 << S::LOG_BYTES;
-        let size_class = Self::size_class(size);
-        let addr = self.allocate_cell(size_class)?;
+        self.acquire_in(Zone::Normal)
+            .or_else(|| self.acquire_in(Zone::Dma))
+    }
+
+    /// Allocate a frame in a specific zone.
+    #[inline(always)]
+    pub fn acquire_in<S: PageSize>(&mut self, zone: Zone) -> Option<Frame<S>> {
+        let addr = self.zones[zone as usize].alloc(Self::order::<S>())?;
         Some(Frame::new(addr))
     }
 
     #[inline(always)]
     pub fn release<S: PageSize>(&mut self, frame: Frame<S>) {
-        let size = 1 << S::LOG_BYTES;
-        let size_class = Self::size_class(size);
-        self.release_cell(frame.start(), size_class);
+        let zone = Zone::of(frame.start());
+        self.zones[zone as usize].dealloc(frame.start(), Self::order::<S>());
     }
 }
 
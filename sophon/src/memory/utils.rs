@@ -1,21 +1,58 @@
 use core::{
     iter::Step,
     ops::Range,
-    sync::atomic::{AtomicBool, AtomicPtr},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize},
 };
 
 use crate::memory::kernel::KERNEL_MEMORY_MAPPER;
 
 use super::kernel::KERNEL_MEMORY_RANGE;
+use super::oom;
 use super::physical::PHYSICAL_MEMORY;
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use atomic::{Atomic, Ordering};
+use interrupt::UninterruptibleMutex;
 use klib::proc::{MemSpace, Process};
 use memory::{
-    address::{Address, V},
+    address::{Address, P, V},
     page::{Frame, Page, PageSize, Size1G, Size2M, Size4K},
     page_table::*,
 };
+use spin::Mutex;
+
+/// Serializes copy-on-write faults with `fork`, which updates the share counts of the frames.
+static COW_LOCK: Mutex<()> = Mutex::new(());
+
+/// L4 tables of released address spaces. They are never freed, as cores that ran the
+/// process may still have them in TTBR0. The kernel entries stay valid for this reason.
+/// Not shrunk on OOM either, see `oom::SHRINKERS`.
+static RETIRED_PAGE_TABLES: Mutex<Vec<Address<P>>> = Mutex::new(Vec::new());
+
+/// An L4 table with no user pages, and the kernel space mapped.
+/// The kernel page table must be enabled.
+pub fn alloc_user_page_table() -> &'static mut PageTable<L4> {
+    if let Some(p4) = RETIRED_PAGE_TABLES.lock_uninterruptible().pop() {
+        return unsafe { p4.as_mut() };
+    }
+    let page_table = PageTable::alloc(&PHYSICAL_MEMORY);
+    let index = PageTable::<L4>::get_index(KERNEL_MEMORY_RANGE.start);
+    debug_assert_eq!(
+        index,
+        PageTable::<L4>::get_index(KERNEL_MEMORY_RANGE.end - 1)
+    );
+    page_table[index] = unsafe { (*KERNEL_MEMORY_MAPPER.get_kernel_page_table())[index].clone() };
+    page_table
+}
+
+/// Add a reference to the frame of a leaf entry found by `PageTable::walk_mut`.
+fn share_frame(entry: &PageTableEntry, level: usize) {
+    match level {
+        0 => PHYSICAL_MEMORY.share::<Size4K>(Frame::new(entry.address())),
+        1 => PHYSICAL_MEMORY.share::<Size2M>(Frame::new(entry.address())),
+        2 => PHYSICAL_MEMORY.share::<Size1G>(Frame::new(entry.address())),
+        _ => unreachable!(),
+    }
+}
 
 pub fn fork_mem_space(mem: &MemSpace) -> Box<MemSpace> {
     if !mem.has_user_page_table() {
@@ -25,22 +62,38 @@ pub fn fork_mem_space(mem: &MemSpace) -> Box<MemSpace> {
             page_table: AtomicPtr::new(mem.page_table.load(Ordering::SeqCst)),
             has_user_page_table: AtomicBool::new(false),
             highwater: Atomic::new(mem.highwater.load(Ordering::SeqCst)),
+            stack_base: Atomic::new(mem.stack_base.load(Ordering::SeqCst)),
+            tls: Mutex::new(*mem.tls.lock()),
+            program: Mutex::new(mem.program.lock().clone()),
+            pages: AtomicUsize::new(0),
+            page_limit: AtomicUsize::new(mem.page_limit.load(Ordering::SeqCst)),
         });
     }
     // Traverse the page table, set every entry to copy-on-write
     let cloned_p4 = {
         let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+        let _lock = COW_LOCK.lock_uninterruptible();
         let page_table = mem.get_page_table();
         fn mark_cow(entry: &mut PageTableEntry, _vaddr: Address<V>, _level: usize) {
-            if !entry.flags().contains(PageFlags::USER) {
+            // Read-only pages (e.g. code) are shared as they are. Making them copy-on-write
+            // would let a write fault turn them into writable, executable pages.
+            let flags = entry.flags();
+            if !flags.contains(PageFlags::USER) || flags.contains(PageFlags::NO_WRITE) {
                 return;
             }
-            let mut flags = entry.flags();
-            flags |= PageFlags::COPY_ON_WRITE | PageFlags::NO_WRITE;
-            entry.update_flags(flags);
+            entry.update_flags(flags | PageFlags::COPY_ON_WRITE | PageFlags::NO_WRITE);
         }
-        page_table.walk_mut(&mut mark_cow);
-        let page_table_cloned = page_table.clone(&PHYSICAL_MEMORY);
+        // The child maps the same frames
+        page_table.walk_mut(&mut |entry, vaddr, level| {
+            if entry.flags().contains(PageFlags::USER) {
+                share_frame(entry, level);
+            }
+            mark_cow(entry, vaddr, level);
+        });
+        // Other cores may still cache the old writable entries
+        crate::task::ipi::tlb_shootdown();
+        let page_table_cloned = alloc_user_page_table();
+        page_table.clone_into(page_table_cloned, &PHYSICAL_MEMORY);
         page_table_cloned.walk_mut(&mut mark_cow);
         page_table_cloned
     };
@@ -48,6 +101,11 @@ pub fn fork_mem_space(mem: &MemSpace) -> Box<MemSpace> {
         page_table: AtomicPtr::new(cloned_p4),
         has_user_page_table: AtomicBool::new(true),
         highwater: Atomic::new(mem.highwater.load(Ordering::SeqCst)),
+        stack_base: Atomic::new(mem.stack_base.load(Ordering::SeqCst)),
+        tls: Mutex::new(*mem.tls.lock()),
+        program: Mutex::new(mem.program.lock().clone()),
+        pages: AtomicUsize::new(mem.pages.load(Ordering::SeqCst)),
+        page_limit: AtomicUsize::new(mem.page_limit.load(Ordering::SeqCst)),
     };
     Box::new(mem_space)
 }
@@ -63,9 +121,9 @@ pub fn release_user_page_table<L: TableLevel>(page_table: &mut PageTable<L>) {
         if page_table[i].is_block() || L::ID == L1::ID {
             let page = page_table[i].address();
             match L::ID {
-                L1::ID => PHYSICAL_MEMORY.release::<Size4K>(Frame::new(page)),
-                L2::ID => PHYSICAL_MEMORY.release::<Size2M>(Frame::new(page)),
-                L3::ID => PHYSICAL_MEMORY.release::<Size1G>(Frame::new(page)),
+                L1::ID => PHYSICAL_MEMORY.release_shared::<Size4K>(Frame::new(page)),
+                L2::ID => PHYSICAL_MEMORY.release_shared::<Size2M>(Frame::new(page)),
+                L3::ID => PHYSICAL_MEMORY.release_shared::<Size1G>(Frame::new(page)),
                 _ => unreachable!(),
             }
         } else {
@@ -76,7 +134,96 @@ pub fn release_user_page_table<L: TableLevel>(page_table: &mut PageTable<L>) {
     PHYSICAL_MEMORY.release::<Size4K>(Frame::new(page_table.into()));
 }
 
+/// Unmap all the user pages, and release them together with the page tables below the L4.
+/// The kernel page table must be enabled.
+pub fn clear_user_space(p4: &mut PageTable<L4>) {
+    let kernel_index = PageTable::<L4>::get_index(KERNEL_MEMORY_RANGE.start);
+    let mut tables = Vec::new();
+    for i in 0..kernel_index {
+        if p4[i].present() {
+            tables.push(p4[i].address());
+            p4[i].clear();
+        }
+    }
+    // Other cores may still cache the old entries
+    crate::task::ipi::tlb_shootdown();
+    for table in tables {
+        release_user_page_table::<L3>(unsafe { table.as_mut() });
+    }
+}
+
+/// Release the user memory of an exited process, after all its threads are gone.
+pub fn release_mem_space(mem: &MemSpace) {
+    if !mem.has_user_page_table.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+    let p4 = mem.get_page_table();
+    mem.page_table.store(
+        KERNEL_MEMORY_MAPPER.get_kernel_page_table() as *mut PageTable,
+        Ordering::SeqCst,
+    );
+    clear_user_space(p4);
+    mem.pages.store(0, Ordering::SeqCst);
+    RETIRED_PAGE_TABLES
+        .lock_uninterruptible()
+        .push(Address::from(p4 as *mut PageTable<L4>));
+}
+
+/// Resolve a write fault on a copy-on-write page. Returns false if out of memory.
+/// The kernel page table must be enabled.
+pub fn copy_on_write<S: PageSize>(page_table: &mut PageTable, page: Page<S>) -> bool {
+    let _lock = COW_LOCK.lock_uninterruptible();
+    let Some((address, flags, _)) = page_table.translate_with_flags(page.start()) else {
+        return true;
+    };
+    if !flags.contains(PageFlags::COPY_ON_WRITE) {
+        // Resolved by another thread
+        return true;
+    }
+    let flags = flags - PageFlags::NO_WRITE - PageFlags::COPY_ON_WRITE;
+    let old_frame = Frame::<S>::new(address);
+    if !PHYSICAL_MEMORY.is_shared(old_frame) {
+        // All the other references are gone
+        page_table.map(page, old_frame, flags, &PHYSICAL_MEMORY);
+        return true;
+    }
+    let Some(new_frame) = oom::acquire::<S>() else {
+        return false;
+    };
+    unsafe {
+        core::ptr::copy_nonoverlapping::<u8>(
+            old_frame.start().as_ptr(),
+            new_frame.start().as_mut_ptr(),
+            S::BYTES,
+        );
+    }
+    page_table.map(page, new_frame, flags, &PHYSICAL_MEMORY);
+    PHYSICAL_MEMORY.release_shared(old_frame);
+    true
+}
+
+/// Unmap and release the pages in `pages`, mapped by `sbrk`.
+fn unmap_heap_pages(page_table: &mut PageTable, pages: Range<Page<Size4K>>) {
+    let mut page = pages.start;
+    while page < pages.end {
+        let (address, _, level) = page_table.translate_with_flags(page.start()).unwrap();
+        if level == 2 {
+            page_table.unmap(Page::<Size2M>::new(page.start()), &PHYSICAL_MEMORY);
+            PHYSICAL_MEMORY.release_shared::<Size2M>(Frame::new(address));
+            page = Page::new(page.start() + Size2M::BYTES);
+        } else {
+            page_table.unmap(page, &PHYSICAL_MEMORY);
+            PHYSICAL_MEMORY.release_shared::<Size4K>(Frame::new(address));
+            page = Page::forward(page, 1);
+        }
+    }
+}
+
 pub fn sbrk(proc: Arc<Process>, num_pages: usize) -> Option<Range<Page<Size4K>>> {
+    if !oom::charge(&proc.mem, num_pages) {
+        return None;
+    }
     let result = proc
         .mem
         .highwater
@@ -95,18 +242,59 @@ pub fn sbrk(proc: Arc<Process>, num_pages: usize) -> Option<Range<Page<Size4K>>>
             {
                 let page_table = proc.mem.get_page_table();
                 let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
-                for page in start..end {
-                    let frame = PHYSICAL_MEMORY.acquire().unwrap();
+                let mut page = start;
+                while page < end {
+                    if let Some(huge_page) = map_huge_page(page_table, page, end) {
+                        page = huge_page;
+                        continue;
+                    }
+                    let Some(frame) = oom::acquire() else {
+                        unmap_heap_pages(page_table, start..page);
+                        crate::task::ipi::tlb_shootdown();
+                        oom::uncharge(&proc.mem, num_pages);
+                        // Give back the address range, unless another thread has grown the heap.
+                        let _ = proc.mem.highwater.compare_exchange(
+                            end.start(),
+                            start.start(),
+                            Ordering::SeqCst,
+                            Ordering::SeqCst,
+                        );
+                        return None;
+                    };
                     page_table.map(
                         page,
                         frame,
                         PageFlags::user_data_flags_4k(),
                         &PHYSICAL_MEMORY,
                     );
+                    page = Page::forward(page, 1);
                 }
             }
             Some(start..end)
         }
-        Err(_e) => return None,
+        Err(_e) => {
+            oom::uncharge(&proc.mem, num_pages);
+            None
+        }
+    }
+}
+
+/// Map a 2M page at `page`, if it is 2M-aligned, the 2M page ends before `end`, and a 2M frame
+/// is available. Returns the page after the 2M page.
+fn map_huge_page(
+    page_table: &mut PageTable,
+    page: Page<Size4K>,
+    end: Page<Size4K>,
+) -> Option<Page<Size4K>> {
+    if !Page::<Size2M>::is_aligned(page.start()) || end.start() - page.start() < Size2M::BYTES {
+        return None;
     }
+    let frame = PHYSICAL_MEMORY.acquire::<Size2M>()?;
+    page_table.map(
+        Page::<Size2M>::new(page.start()),
+        frame,
+        PageFlags::user_data_flags_2m(),
+        &PHYSICAL_MEMORY,
+    );
+    Some(Page::new(page.start() + Size2M::BYTES))
 }
@@ -1,12 +1,15 @@
 use alloc::{borrow::ToOwned, boxed::Box, collections::BTreeMap, string::String, vec::Vec};
 use core::iter::Step;
+use core::ops::Range;
 use kernel_module::KernelServiceWrapper;
 use kernel_module::ModuleCallHandler;
-use memory::page::{Page, PageResource, Size4K};
+use memory::page::{Page, Size4K};
 use spin::RwLock;
+use syscall::trace::Kind;
 use syscall::RawModuleRequest;
 
 use crate::memory::kernel::KERNEL_HEAP;
+use crate::utils::trace;
 
 use self::services::KernelService;
 
@@ -16,11 +19,15 @@ mod services;
 pub use named_modules::{INTERRUPT, TIMER, VFS};
 
 struct KernelModule {
-    _name: String,
+    name: String,
     _service: Box<KernelService>,
     _deinit: Option<extern "C" fn()>,
     call: Option<&'static dyn ModuleCallHandler>,
-    _elf: Vec<u8>,
+    elf: Vec<u8>,
+    /// The addresses the module is loaded at.
+    image: Range<usize>,
+    /// Load address minus link address.
+    offset: usize,
 }
 
 const MAX_MODULES: usize = 256;
@@ -30,23 +37,35 @@ static MODULES: RwLock<[Option<Box<KernelModule>>; MAX_MODULES]> = {
 };
 static MODULE_NAMES: RwLock<BTreeMap<String, usize>> = RwLock::new(BTreeMap::new());
 
+/// With ASLR, modules are loaded up to this many pages away from the next free heap pages.
+const MODULE_RANDOM_PAGES: usize = 1024;
+
 fn load_elf(
     elf_data: &[u8],
 ) -> (
     extern "C" fn(kernel_module::KernelServiceWrapper) -> usize,
     Option<&[extern "C" fn()]>,
+    Range<usize>,
+    usize,
 ) {
+    let mut image = 0..0;
+    let mut offset = 0;
     let entry = elf_loader::ELFLoader::load(elf_data, &mut |pages| {
         let range = KERNEL_HEAP
-            .acquire_pages::<Size4K>(Page::steps_between(&pages.start, &pages.end).unwrap())
+            .acquire_randomized_pages::<Size4K>(
+                Page::steps_between(&pages.start, &pages.end).unwrap(),
+                MODULE_RANDOM_PAGES,
+            )
             .unwrap();
         // log!("code: {:?}", range);
-        range
+        image = range.start.start().as_usize()..range.end.start().as_usize();
+        offset = range.start.start().as_usize() - pages.start.start().as_usize();
+        Ok(range)
     })
     .unwrap();
     let init_array = unsafe { core::mem::transmute(entry.init_array) };
     let entry = unsafe { core::mem::transmute(entry.entry) };
-    (entry, init_array)
+    (entry, init_array, image, offset)
 }
 
 pub fn register(name: &str, elf: Vec<u8>) {
@@ -57,7 +76,7 @@ pub fn register(name: &str, elf: Vec<u8>) {
             return;
         }
         let id = names.len();
-        let (start, init_array) = load_elf(&elf);
+        let (start, init_array, image, offset) = load_elf(&elf);
         let service = Box::new(KernelService(id));
         let service_ptr = service.as_ref() as *const KernelService;
         if let Some(init_array) = init_array {
@@ -66,11 +85,13 @@ pub fn register(name: &str, elf: Vec<u8>) {
             }
         }
         modules[id] = Some(Box::new(KernelModule {
-            _name: name.to_owned(),
+            name: name.to_owned(),
             _service: service,
             _deinit: None,
             call: None,
-            _elf: elf,
+            elf,
+            image,
+            offset,
         }));
         names.insert(name.to_owned(), id);
         (start, service_ptr)
@@ -78,6 +99,46 @@ pub fn register(name: &str, elf: Vec<u8>) {
     start(KernelServiceWrapper::from_service(unsafe { &*service_ptr }));
 }
 
+/// Names of the loaded modules, in the order they are loaded.
+pub fn names() -> Vec<String> {
+    let names = MODULE_NAMES.read();
+    let mut names = names.iter().collect::<Vec<_>>();
+    names.sort_by_key(|(_, id)| **id);
+    names.into_iter().map(|(name, _)| name.clone()).collect()
+}
+
+/// Call `f` with the name of a module, or `"?"` if it is not known or the module table is locked.
+/// Does not allocate.
+pub fn with_name(id: usize, f: impl FnOnce(&str)) {
+    let modules = MODULES.try_read();
+    let name = modules
+        .as_ref()
+        .and_then(|m| m.get(id)?.as_ref())
+        .map(|m| m.name.as_str());
+    f(name.unwrap_or("?"))
+}
+
+/// Call `f` with the name of the module an address is in, the offset into the module,
+/// and the function containing the address with the offset into it.
+///
+/// Returns `None` if no module holds the address, or the module table is locked.
+/// Does not allocate, so that it can be used to report panics.
+pub fn symbolize<R>(
+    address: usize,
+    f: impl FnOnce(&str, usize, Option<(&str, usize)>) -> R,
+) -> Option<R> {
+    let modules = MODULES.try_read()?;
+    let module = modules
+        .iter()
+        .flatten()
+        .find(|m| m.image.contains(&address))?;
+    let link_address = address - module.offset;
+    let function = elf_loader::ELFObject::parse(&module.elf)
+        .ok()
+        .and_then(|object| object.find_function(link_address));
+    Some(f(&module.name, link_address, function))
+}
+
 pub fn raw_module_call(module: &str, privileged: bool, args: [usize; 4]) -> isize {
     // trace!("module call #{} {:x?}", module, args);
     let _guard = ::interrupt::uninterruptible();
@@ -85,10 +146,11 @@ pub fn raw_module_call(module: &str, privileged: bool, args: [usize; 4]) -> isiz
         panic!("module not found: {}", module);
     }
     let id = *MODULE_NAMES.read().get(module).unwrap();
+    trace::begin(Kind::ModuleCall, id);
     let modules_ptr = MODULES.read()[id]
         .as_ref()
         .map(|m| m.as_ref() as *const KernelModule);
-    if let Some(modules_ptr) = modules_ptr {
+    let result = if let Some(modules_ptr) = modules_ptr {
         let m = unsafe { &*modules_ptr };
         m.call
             .as_ref()
@@ -96,7 +158,9 @@ pub fn raw_module_call(module: &str, privileged: bool, args: [usize; 4]) -> isiz
             .unwrap_or(-1)
     } else {
         -1
-    }
+    };
+    trace::end(Kind::ModuleCall, id);
+    result
 }
 
 pub fn module_call<'a>(
@@ -5,7 +5,7 @@ use crate::memory::kernel::KERNEL_HEAP;
 use crate::memory::kernel::KERNEL_MEMORY_MAPPER;
 use crate::task::proc::PROCESS_MANAGER;
 use crate::task::sched::SCHEDULER;
-use crate::task::sync::SysMonitor;
+use crate::task::sync::{RawKMutex, SysMonitor};
 use crate::utils::testing::Tests;
 use alloc::boxed::Box;
 use alloc::sync::Arc;
@@ -31,6 +31,10 @@ impl kernel_module::KernelService for KernelService {
         print!("{}", s);
     }
 
+    fn log_record(&self, record: &log::Record) {
+        crate::utils::klog::log(record.level(), record.target(), *record.args());
+    }
+
     fn create_monitor(&self) -> alloc::boxed::Box<dyn kernel_module::monitor::SysMonitor> {
         let handle = SysMonitor::new();
         struct Wrapper {
@@ -53,6 +57,19 @@ impl kernel_module::KernelService for KernelService {
         Box::new(Wrapper { handle })
     }
 
+    fn create_mutex(&self, name: &'static str) -> Box<dyn kernel_module::monitor::SysMutex> {
+        struct Wrapper(RawKMutex);
+        impl kernel_module::monitor::SysMutex for Wrapper {
+            fn lock(&self) {
+                self.0.lock();
+            }
+            fn unlock(&self) {
+                self.0.unlock();
+            }
+        }
+        Box::new(Wrapper(RawKMutex::new(name)))
+    }
+
     fn set_sys_logger(&self, write: *mut dyn core::fmt::Write) {
         crate::utils::print::init(unsafe { &mut *write });
     }
@@ -72,7 +89,7 @@ impl kernel_module::KernelService for KernelService {
     }
 
     fn alloc(&self, layout: core::alloc::Layout) -> Option<Address> {
-        let ptr = unsafe { crate::ALLOCATOR.alloc(layout) };
+        let ptr = unsafe { crate::ALLOCATOR.alloc_for_module(layout, self.0) };
         if ptr.is_null() {
             None
         } else {
@@ -88,7 +105,7 @@ impl kernel_module::KernelService for KernelService {
         if cfg!(sophon_test) {
             TargetArch::halt(-1)
         }
-        syscall::exit();
+        syscall::exit(101);
     }
 
     fn vfs(&self) -> &'static dyn vfs::VFSManager {
@@ -142,11 +159,11 @@ impl kernel_module::KernelService for KernelService {
     }
 
     fn num_cores(&self) -> usize {
-        1
+        TargetArch::num_cores()
     }
 
     fn current_core(&self) -> usize {
-        0
+        TargetArch::current_core()
     }
 
     fn timer_tick(&self) -> ! {
@@ -160,4 +177,8 @@ impl kernel_module::KernelService for KernelService {
     fn current_proc(&self) -> Option<Arc<Process>> {
         PROCESS_MANAGER.current_proc()
     }
+
+    fn signal_process_group(&self, pgid: klib::proc::PID, signal: syscall::Signal) {
+        crate::task::signal::send_to_group_later(pgid, signal);
+    }
 }
@@ -0,0 +1,56 @@
+//! Futexes: user-space words that threads block on, keyed by process and address.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU32, Ordering};
+use interrupt::UninterruptibleMutex;
+use klib::proc::PID;
+use spin::Mutex;
+
+use super::sync::WaitQueue;
+
+/// The wait queues of the futexes in use. An entry is removed once no task holds it.
+static FUTEXES: Mutex<BTreeMap<(PID, usize), Arc<WaitQueue>>> = Mutex::new(BTreeMap::new());
+
+fn queue(pid: PID, address: usize) -> Arc<WaitQueue> {
+    FUTEXES
+        .lock_uninterruptible()
+        .entry((pid, address))
+        .or_insert_with(|| Arc::new(WaitQueue::new()))
+        .clone()
+}
+
+/// Drop the queue of a futex, unless another task is using it.
+fn release(pid: PID, address: usize, queue: Arc<WaitQueue>) {
+    drop(queue);
+    let mut futexes = FUTEXES.lock_uninterruptible();
+    if futexes
+        .get(&(pid, address))
+        .is_some_and(|queue| Arc::strong_count(queue) == 1)
+    {
+        futexes.remove(&(pid, address));
+    }
+}
+
+/// Block while the word at `address` is `expected`, until woken up by `wake`.
+///
+/// The value is checked with the queue locked, so a `wake` after the value changes is never
+/// lost. May return early, and the caller checks the value again.
+/// Returns false if `timeout_ns` passed first.
+pub fn wait(pid: PID, address: usize, expected: u32, timeout_ns: Option<u64>) -> bool {
+    let word = unsafe { AtomicU32::from_ptr(address as *mut u32) };
+    let queue = queue(pid, address);
+    let woken = queue.wait_once(|| word.load(Ordering::SeqCst) != expected, timeout_ns);
+    release(pid, address, queue);
+    woken
+}
+
+/// Wake up to `count` tasks blocked on the word at `address`. Returns the number woken up.
+pub fn wake(pid: PID, address: usize, count: usize) -> usize {
+    let Some(queue) = FUTEXES.lock_uninterruptible().get(&(pid, address)).cloned() else {
+        return 0;
+    };
+    let woken = (0..count).take_while(|_| queue.wake_one()).count();
+    release(pid, address, queue);
+    woken
+}
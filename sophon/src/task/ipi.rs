@@ -0,0 +1,121 @@
+use crate::arch::{Arch, TargetArch};
+use crate::modules::INTERRUPT;
+use crate::task::sched::SCHEDULER;
+use crate::utils::pls::ProcessorLocalStorage;
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+
+/// Inter-processor interrupts. The values are the SGI numbers.
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IPI {
+    /// Ask an idle core to pick up the tasks in the run queues.
+    Reschedule = 1,
+    /// Ask a core to flush its TLB.
+    TLBShootdown = 2,
+    /// Park a core until the debugger resumes it.
+    Stop = 3,
+}
+
+impl IPI {
+    const ALL: [IPI; 3] = [IPI::Reschedule, IPI::TLBShootdown, IPI::Stop];
+}
+
+/// Set while the other cores are held by `stop_other_cores`.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+/// Number of cores parked in the `Stop` handler.
+static STOPPED_CORES: AtomicUsize = AtomicUsize::new(0);
+
+/// Cores that do not take the `Stop` IPI in time, e.g. with interrupts masked, are not waited for.
+const STOP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Register the IPI handlers. Called once after the interrupt controller is loaded.
+pub fn init() {
+    INTERRUPT.set_irq_handler(
+        IPI::Reschedule as usize,
+        Box::new(|| {
+            if SCHEDULER.is_idle() {
+                INTERRUPT.interrupt_end();
+                SCHEDULER.enqueue_current_task_as_ready();
+                SCHEDULER.schedule();
+            }
+            0
+        }),
+    );
+    INTERRUPT.set_irq_handler(
+        IPI::TLBShootdown as usize,
+        Box::new(|| {
+            flush_local_tlb();
+            0
+        }),
+    );
+    INTERRUPT.set_irq_handler(
+        IPI::Stop as usize,
+        Box::new(|| {
+            STOPPED_CORES.fetch_add(1, Ordering::SeqCst);
+            while STOPPING.load(Ordering::SeqCst) {
+                core::hint::spin_loop();
+            }
+            STOPPED_CORES.fetch_sub(1, Ordering::SeqCst);
+            0
+        }),
+    );
+    init_core();
+}
+
+/// Enable IPIs on the current core.
+pub fn init_core() {
+    for ipi in IPI::ALL {
+        INTERRUPT.enable_irq(ipi as usize);
+    }
+}
+
+/// Send an IPI to a core.
+pub fn send(core: usize, ipi: IPI) {
+    INTERRUPT.send_ipi(core, ipi as usize);
+}
+
+/// Send an IPI to all the other cores.
+pub fn broadcast(ipi: IPI) {
+    let me = ProcessorLocalStorage::<usize>::current_core();
+    for core in (0..ProcessorLocalStorage::<usize>::num_cores()).filter(|c| *c != me) {
+        send(core, ipi);
+    }
+}
+
+fn flush_local_tlb() {
+    unsafe {
+        asm!("tlbi vmalle1", "dsb nsh", "isb");
+    }
+}
+
+/// Invalidate the TLBs of all cores, after a live page table is modified.
+///
+/// The inner-shareable invalidation covers all cores once `dsb ish` completes.
+/// The IPI additionally forces the other cores through a context synchronization event
+/// before they return to user space.
+pub fn tlb_shootdown() {
+    unsafe {
+        asm!("tlbi vmalle1is", "dsb ish", "isb");
+    }
+    broadcast(IPI::TLBShootdown);
+}
+
+/// Park all the other cores in their IPI handler, until `resume_other_cores`.
+/// Used by the debugger, so that memory and the task list do not change while it is stopped.
+pub fn stop_other_cores() {
+    STOPPING.store(true, Ordering::SeqCst);
+    broadcast(IPI::Stop);
+    let others = ProcessorLocalStorage::<usize>::num_cores() - 1;
+    let deadline = TargetArch::uptime() + STOP_TIMEOUT;
+    while STOPPED_CORES.load(Ordering::SeqCst) < others && TargetArch::uptime() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+/// Release the cores parked by `stop_other_cores`.
+pub fn resume_other_cores() {
+    STOPPING.store(false, Ordering::SeqCst);
+}
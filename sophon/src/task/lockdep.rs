@@ -0,0 +1,103 @@
+//! Lock-order checking for sleeping locks. Only enabled in debug builds.
+//!
+//! Locks are grouped into classes by name. Whenever a task acquires a lock of class `B`
+//! while holding a lock of class `A`, the order `A -> B` is recorded.
+//! Acquiring locks in an order that closes a cycle may deadlock, and causes a panic.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use interrupt::UninterruptibleMutex;
+use klib::task::TaskId;
+use spin::Mutex;
+
+type LockClass = &'static str;
+
+#[derive(Default)]
+struct LockGraph {
+    /// Lock classes held by each task, in acquisition order.
+    held: BTreeMap<TaskId, Vec<LockClass>>,
+    /// Observed orders. `(a, b)` means `b` was acquired while holding `a`.
+    edges: BTreeSet<(LockClass, LockClass)>,
+}
+
+impl LockGraph {
+    /// Is `to` reachable from `from`?
+    fn reachable(&self, from: LockClass, to: LockClass) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut stack = alloc::vec![from];
+        while let Some(class) = stack.pop() {
+            if class == to {
+                return true;
+            }
+            if !visited.insert(class) {
+                continue;
+            }
+            let next = self
+                .edges
+                .range((class, "")..)
+                .take_while(|(a, _)| *a == class)
+                .map(|(_, b)| *b);
+            stack.extend(next);
+        }
+        false
+    }
+}
+
+static GRAPH: Mutex<Option<LockGraph>> = Mutex::new(None);
+
+const ENABLED: bool = cfg!(debug_assertions);
+
+/// Check the lock order before `task` tries to acquire a lock of class `class`.
+pub fn before_acquire(task: TaskId, class: LockClass) {
+    if !ENABLED {
+        return;
+    }
+    let mut graph = GRAPH.lock_uninterruptible();
+    let graph = graph.get_or_insert_with(Default::default);
+    let Some(held) = graph.held.get(&task) else {
+        return;
+    };
+    for &h in held.iter().filter(|h| **h != class) {
+        if !graph.edges.contains(&(h, class)) && graph.reachable(class, h) {
+            panic!(
+                "Possible deadlock: {:?} acquires {:?} while holding {:?}, but the reverse order was seen before. Held locks: {:?}",
+                task, class, h, held
+            );
+        }
+    }
+}
+
+/// Record that `task` has acquired a lock of class `class`.
+pub fn after_acquire(task: TaskId, class: LockClass) {
+    if !ENABLED {
+        return;
+    }
+    let mut graph = GRAPH.lock_uninterruptible();
+    let graph = graph.get_or_insert_with(Default::default);
+    let held = graph.held.entry(task).or_default();
+    let new_edges: Vec<_> = held
+        .iter()
+        .filter(|h| **h != class)
+        .map(|h| (*h, class))
+        .collect();
+    held.push(class);
+    graph.edges.extend(new_edges);
+}
+
+/// Record that `task` has released a lock of class `class`.
+pub fn release(task: TaskId, class: LockClass) {
+    if !ENABLED {
+        return;
+    }
+    let mut graph = GRAPH.lock_uninterruptible();
+    let graph = graph.get_or_insert_with(Default::default);
+    let Some(held) = graph.held.get_mut(&task) else {
+        return;
+    };
+    if let Some(i) = held.iter().rposition(|h| *h == class) {
+        held.remove(i);
+    }
+    if held.is_empty() {
+        graph.held.remove(&task);
+    }
+}
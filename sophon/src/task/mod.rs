@@ -1,6 +1,12 @@
+pub mod futex;
+pub mod ipi;
+mod lockdep;
+pub mod policy;
 pub mod proc;
 pub mod runnables;
 pub mod sched;
+pub mod shlib;
+pub mod signal;
 pub mod sync;
 pub mod syscall;
 pub mod user;
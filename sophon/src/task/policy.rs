@@ -0,0 +1,203 @@
+use super::sched::{RunQueue, SchedPolicy};
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use core::cmp::Reverse;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use crossbeam::queue::SegQueue;
+use klib::task::{SchedInfo, Task, TaskId};
+use spin::Mutex;
+
+/// Round-robin scheduling. Ignores task priorities.
+pub struct RoundRobin;
+
+#[derive(Default)]
+pub struct RoundRobinRunQueue(SegQueue<TaskId>);
+
+impl RunQueue for RoundRobinRunQueue {
+    fn push(&self, task: &Task) {
+        self.0.push(task.id);
+    }
+
+    fn pop(&self) -> Option<TaskId> {
+        self.0.pop()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl SchedPolicy for RoundRobin {
+    type RunQueue = RoundRobinRunQueue;
+
+    fn update_runtime(_task: &Task, _delta_ns: u64) {}
+
+    fn should_preempt(_current: &Task, queue: &Self::RunQueue) -> bool {
+        !queue.0.is_empty()
+    }
+}
+
+/// Weight of a nice-0 task.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// Task weights for nice values `-20..=19`. Each nice level is ~1.25x CPU share of the next one.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// A woken-up task can be at most this far behind the queue, so that it does not monopolize the core.
+const SCHED_LATENCY_NS: u64 = 24_000_000;
+
+/// The running task is preempted only if it is ahead of the next task by at least this amount.
+const WAKEUP_GRANULARITY_NS: u64 = 1_000_000;
+
+/// Fair-share scheduling, similar to Linux's CFS, with fixed real-time priorities.
+///
+/// Real-time tasks always run before normal tasks, highest priority first,
+/// and in round-robin order among tasks of the same priority.
+/// Normal tasks are ordered by their virtual run time, weighted by their nice values.
+pub struct Fair;
+
+impl Fair {
+    fn weight(nice: i8) -> u64 {
+        let nice = nice.clamp(SchedInfo::MIN_NICE, SchedInfo::MAX_NICE);
+        NICE_TO_WEIGHT[(nice - SchedInfo::MIN_NICE) as usize]
+    }
+}
+
+#[derive(Default)]
+struct FairRunQueueInner {
+    /// Ready real-time tasks, grouped by priority. Highest priority first.
+    realtime: BTreeMap<Reverse<u8>, VecDeque<TaskId>>,
+    /// Ready normal tasks, ordered by virtual run time.
+    normal: BTreeSet<(u64, TaskId)>,
+}
+
+#[derive(Default)]
+pub struct FairRunQueue {
+    inner: Mutex<FairRunQueueInner>,
+    /// Monotonically increasing lower bound of the virtual run time in this queue.
+    min_vruntime: AtomicU64,
+    len: AtomicUsize,
+}
+
+impl RunQueue for FairRunQueue {
+    fn push(&self, task: &Task) {
+        let mut inner = self.inner.lock();
+        let rt_priority = task.sched.rt_priority.load(Ordering::SeqCst);
+        if rt_priority > 0 {
+            inner
+                .realtime
+                .entry(Reverse(rt_priority))
+                .or_default()
+                .push_back(task.id);
+        } else {
+            let floor = self
+                .min_vruntime
+                .load(Ordering::SeqCst)
+                .saturating_sub(SCHED_LATENCY_NS);
+            let vruntime = task.sched.vruntime.load(Ordering::SeqCst).max(floor);
+            task.sched.vruntime.store(vruntime, Ordering::SeqCst);
+            inner.normal.insert((vruntime, task.id));
+        }
+        self.len.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn pop(&self) -> Option<TaskId> {
+        let mut inner = self.inner.lock();
+        if let Some(mut entry) = inner.realtime.first_entry() {
+            let task = entry.get_mut().pop_front();
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+            self.len.fetch_sub(1, Ordering::SeqCst);
+            return task;
+        }
+        let (vruntime, task) = inner.normal.pop_first()?;
+        self.min_vruntime.fetch_max(vruntime, Ordering::SeqCst);
+        self.len.fetch_sub(1, Ordering::SeqCst);
+        Some(task)
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+}
+
+impl SchedPolicy for Fair {
+    type RunQueue = FairRunQueue;
+
+    fn update_runtime(task: &Task, delta_ns: u64) {
+        if task.sched.rt_priority.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        let weight = Self::weight(task.sched.nice.load(Ordering::SeqCst));
+        task.sched
+            .vruntime
+            .fetch_add(delta_ns * NICE_0_WEIGHT / weight, Ordering::SeqCst);
+    }
+
+    fn should_preempt(current: &Task, queue: &Self::RunQueue) -> bool {
+        let inner = queue.inner.lock();
+        let rt_priority = current.sched.rt_priority.load(Ordering::SeqCst);
+        if let Some(Reverse(p)) = inner.realtime.keys().next() {
+            // Higher priority task is waiting, or round-robin among the same priority.
+            if *p >= rt_priority {
+                return true;
+            }
+        }
+        if rt_priority > 0 {
+            return false;
+        }
+        match inner.normal.first() {
+            Some((vruntime, _)) => {
+                vruntime + WAKEUP_GRANULARITY_NS < current.sched.vruntime.load(Ordering::SeqCst)
+            }
+            None => false,
+        }
+    }
+}
+
+#[test]
+fn fair_run_queue_order_test() {
+    use alloc::boxed::Box;
+    use atomic::Atomic;
+    use klib::proc::PID;
+    use klib::task::RunState;
+
+    let new_task = |id: usize, vruntime: u64, rt_priority: u8| {
+        let sched = SchedInfo::default();
+        sched.vruntime.store(vruntime, Ordering::SeqCst);
+        sched.rt_priority.store(rt_priority, Ordering::SeqCst);
+        Task {
+            id: TaskId(id),
+            pid: PID(0),
+            state: Atomic::new(RunState::Ready),
+            ticks: AtomicUsize::new(0),
+            sched,
+            context: Box::new(()),
+            runnable: None,
+        }
+    };
+    let queue = FairRunQueue::default();
+    queue.push(&new_task(1, 300, 0));
+    queue.push(&new_task(2, 100, 0));
+    queue.push(&new_task(3, 0, 10));
+    queue.push(&new_task(4, 0, 50));
+    queue.push(&new_task(5, 200, 0));
+    assert_eq!(queue.len(), 5);
+    // Real-time tasks first, then the normal tasks by virtual run time
+    let order: alloc::vec::Vec<_> = core::iter::from_fn(|| queue.pop()).collect();
+    assert_eq!(
+        order,
+        [TaskId(4), TaskId(3), TaskId(2), TaskId(5), TaskId(1)]
+    );
+    // A lower nice value accumulates virtual run time slower
+    let a = new_task(6, 0, 0);
+    let b = new_task(7, 0, 0);
+    b.sched.nice.store(5, Ordering::SeqCst);
+    Fair::update_runtime(&a, 1_000_000);
+    Fair::update_runtime(&b, 1_000_000);
+    assert!(a.sched.vruntime.load(Ordering::SeqCst) < b.sched.vruntime.load(Ordering::SeqCst));
+}
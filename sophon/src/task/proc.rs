@@ -3,23 +3,30 @@ use crate::arch::{Arch, TargetArch};
 use crate::memory::kernel::KERNEL_MEMORY_MAPPER;
 use crate::modules::VFS;
 use crate::task::sched::SCHEDULER;
+use crate::utils::pls::ProcessorLocalStorage;
 use alloc::borrow::ToOwned;
 use alloc::boxed::Box;
 use alloc::collections::btree_map::BTreeMap;
+use alloc::ffi::CString;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::{vec, vec::Vec};
 use atomic::{Atomic, Ordering};
 use core::any::Any;
-use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicPtr, AtomicUsize};
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicPtr, AtomicU32, AtomicUsize};
 use klib::proc::{MemSpace, Process, PID};
-use klib::task::{RunState, Runnable, Task, TaskId};
+use klib::task::{RunState, Runnable, SchedInfo, Task, TaskId};
+use memory::address::Address;
 use memory::page_table::PageTable;
 use spin::Mutex;
 use vfs::{Fd, VFSRequest};
 
 use super::runnables::Idle;
 use super::runnables::Init;
-use super::sync::SysMonitor;
+use super::runnables::SignalWorker;
+use super::runnables::UserThread;
+use super::sync::WaitQueue;
+use super::user::UserEntry;
 
 // impl Drop for MMState {
 //     fn drop(&mut self) {
@@ -34,6 +41,8 @@ static TASK_ID_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 pub struct ProcessManager {
     procs: Mutex<BTreeMap<PID, Arc<Process>>>,
+    /// Exited processes whose user memory is not released yet.
+    exited: Mutex<Vec<Arc<Process>>>,
 }
 
 unsafe impl Sync for ProcessManager {}
@@ -42,10 +51,14 @@ pub static PROCESS_MANAGER: ProcessManager = ProcessManager::new();
 
 static COUNTER: AtomicUsize = AtomicUsize::new(0);
 
+/// Tasks waiting for a thread to exit. See `ProcessManager::join_thread`.
+static THREAD_EXIT_WAITERS: WaitQueue = WaitQueue::new();
+
 impl ProcessManager {
     const fn new() -> Self {
         Self {
             procs: Mutex::new(BTreeMap::new()),
+            exited: Mutex::new(Vec::new()),
         }
     }
 
@@ -58,21 +71,36 @@ impl ProcessManager {
             },
             has_user_page_table: AtomicBool::new(false),
             highwater: Atomic::new(crate::memory::USER_SPACE_MEMORY_RANGE.start),
+            stack_base: Atomic::new(Address::ZERO),
+            tls: Mutex::new(None),
+            program: Mutex::new((Address::ZERO..Address::ZERO, 0)),
+            pages: AtomicUsize::new(0),
+            page_limit: AtomicUsize::new(usize::MAX),
         })
     }
 
-    fn spawn_process(&self, runnable: impl Runnable + 'static) -> Arc<Process> {
+    fn create_process(&self) -> Arc<Process> {
         let pid = PID(COUNTER.fetch_add(1, Ordering::SeqCst));
         let fs = VFS.register_process(pid, "".to_owned());
-        let proc = Arc::new(Process {
+        Arc::new(Process {
             id: pid,
+            parent: AtomicUsize::new(PID::NULL.0),
+            name: Mutex::new(String::new()),
             threads: Mutex::new(Vec::new()),
             mem: self.new_mem_space(),
             fs,
-            monitor: Box::new(SysMonitor::new()),
+            exit_waiters: Box::new(WaitQueue::new()),
+            child_waiters: Box::new(WaitQueue::new()),
             is_zombie: AtomicBool::new(false),
             exit_code: AtomicIsize::new(0),
-        });
+            pgid: AtomicUsize::new(pid.0),
+            pending_signals: AtomicU32::new(0),
+            stopped: AtomicBool::new(false),
+        })
+    }
+
+    fn spawn_process(&self, runnable: impl Runnable + 'static) -> Arc<Process> {
+        let proc = self.create_process();
         // Create main thread
         let ctx = SCHEDULER.create_task_context();
         let runnable = Box::new(runnable);
@@ -85,14 +113,50 @@ impl ProcessManager {
         proc
     }
 
+    /// Spawn the sched process, with one idle thread for each core, and the signal worker.
     pub fn spawn_sched_process(&self) -> Arc<Process> {
-        self.spawn_process(Idle)
+        let proc = self.create_process();
+        for core in 0..ProcessorLocalStorage::<usize>::num_cores() {
+            let ctx = SCHEDULER.create_task_context();
+            let task = self.create_task(proc.clone(), Box::new(Idle), ctx);
+            proc.threads.lock().push(task.id);
+            SCHEDULER.register_idle_task(core, task);
+        }
+        let ctx = SCHEDULER.create_task_context();
+        let task = self.create_task(proc.clone(), Box::new(SignalWorker), ctx);
+        proc.threads.lock().push(task.id);
+        SCHEDULER.register_new_task(task);
+        self.procs.lock().insert(proc.id, proc.clone());
+        proc
     }
 
     pub fn spawn_init_process(&self) -> Arc<Process> {
         self.spawn_process(Init::new())
     }
 
+    /// Start a user thread in `proc`. Returns the new thread id.
+    pub fn spawn_user_thread(
+        &self,
+        proc: Arc<Process>,
+        entry: UserEntry,
+        arg: usize,
+        stack_top: Address,
+    ) -> TaskId {
+        let ctx = SCHEDULER.create_task_context();
+        let runnable = Box::new(UserThread::new(entry, arg, stack_top));
+        let task = self.create_task(proc.clone(), runnable, ctx);
+        let id = task.id;
+        proc.threads.lock().push(id);
+        SCHEDULER.register_new_task(task);
+        id
+    }
+
+    /// Block until a thread of the current process exits.
+    pub fn join_thread(&self, task: TaskId) {
+        let proc = self.current_proc().unwrap();
+        THREAD_EXIT_WAITERS.wait_until(|| !proc.threads.lock().contains(&task));
+    }
+
     fn create_task(
         &self,
         proc: Arc<Process>,
@@ -105,6 +169,7 @@ impl ProcessManager {
             pid: proc.id,
             state: Atomic::new(RunState::Ready),
             ticks: AtomicUsize::new(0),
+            sched: SchedInfo::default(),
             context,
             // proc: Arc::downgrade(&proc),
             // live: Lazy::new(|| Monitor::new(true)),
@@ -129,6 +194,57 @@ impl ProcessManager {
         self.procs.lock().get(&id).cloned()
     }
 
+    /// Like `get_proc_by_id`, but gives up if the process list is locked.
+    pub fn try_get_proc_by_id(&self, id: PID) -> Option<Arc<Process>> {
+        self.procs.try_lock()?.get(&id).cloned()
+    }
+
+    pub fn get_all_procs(&self) -> Vec<Arc<Process>> {
+        self.procs.lock().values().cloned().collect()
+    }
+
+    pub fn get_children(&self, parent: PID) -> Vec<Arc<Process>> {
+        self.procs
+            .lock()
+            .values()
+            .filter(|p| p.parent.load(Ordering::SeqCst) == parent.0)
+            .cloned()
+            .collect()
+    }
+
+    /// Remove an exited process, after its exit status is collected by the parent.
+    pub fn reap(&self, proc: &Process) {
+        debug_assert!(proc.is_zombie.load(Ordering::SeqCst));
+        self.procs.lock().remove(&proc.id);
+    }
+
+    /// Release the user memory of the exited processes that have no threads left on any core.
+    pub fn release_exited_mem_spaces(&self) {
+        let released = {
+            let mut exited = self.exited.lock();
+            if exited.is_empty() {
+                return;
+            }
+            let (released, remaining) = core::mem::take(&mut *exited)
+                .into_iter()
+                .partition::<Vec<_>, _>(|p| !SCHEDULER.has_tasks_of(p.id));
+            *exited = remaining;
+            released
+        };
+        for proc in released {
+            crate::memory::utils::release_mem_space(&proc.mem);
+        }
+    }
+
+    pub fn get_procs_in_group(&self, pgid: PID) -> Vec<Arc<Process>> {
+        self.procs
+            .lock()
+            .values()
+            .filter(|p| p.pgid.load(Ordering::SeqCst) == pgid.0)
+            .cloned()
+            .collect()
+    }
+
     pub fn current_proc(&self) -> Option<Arc<Process>> {
         self.current_proc_id()
             .and_then(|id| self.get_proc_by_id(id))
@@ -152,17 +268,19 @@ impl ProcessManager {
         let mut tasks = proc.threads.lock();
         let index = tasks.iter().position(|t| *t == task.id).unwrap();
         tasks.swap_remove(index);
+        drop(tasks);
         // Remove from scheduler
         SCHEDULER.remove_task(task.id);
+        THREAD_EXIT_WAITERS.wake_all();
     }
 
-    pub fn exit_current_proc(&self) {
+    pub fn exit_current_proc(&self, exit_code: isize) {
         let _guard = interrupt::uninterruptible();
         let proc = self.current_proc().unwrap();
         // Release file handles
         VFS.deregister_process(proc.id);
         // Release memory
-        // - Note: this is done in the MMState destructor
+        // - Note: this is done by `release_exited_mem_spaces`
         // Mark as dead
         // {
         //     let mut live = self.live.lock();
@@ -170,18 +288,41 @@ impl ProcessManager {
         //     self.live.notify_all();
         // }
         // Remove from scheduler
-        let monitor = proc.monitor.as_ref().downcast_ref::<SysMonitor>().unwrap();
-        monitor.lock();
+        if proc.id == PID::INIT {
+            panic!("init exited with code {}", exit_code);
+        }
+        proc.exit_code.store(exit_code, Ordering::SeqCst);
+        // Give the children to init
+        let children = self.get_children(proc.id);
+        for child in &children {
+            child.parent.store(PID::INIT.0, Ordering::SeqCst);
+        }
+        let init = self.get_proc_by_id(PID::INIT);
+        if let Some(init) = init.as_ref().filter(|_| !children.is_empty()) {
+            init.child_waiters
+                .downcast_ref::<WaitQueue>()
+                .unwrap()
+                .wake_all();
+        }
         proc.is_zombie.store(true, Ordering::SeqCst);
-        proc.exit_code.store(0, Ordering::SeqCst);
-        monitor.notify_all();
-        monitor.unlock();
+        // Release memory once all the threads are off the cores
+        self.exited.lock().push(proc.clone());
+        let exit_waiters = proc.exit_waiters.downcast_ref::<WaitQueue>().unwrap();
+        exit_waiters.wake_all();
         let threads = proc.threads.lock();
         for t in &*threads {
             SCHEDULER.remove_task(*t)
         }
-        // Remove from procs
-        self.procs.lock().remove(&proc.id);
+        // Stay as a zombie until the parent collects the exit status.
+        // Processes created by the kernel have no parent to do that.
+        let parent = PID(proc.parent.load(Ordering::SeqCst));
+        match self.get_proc_by_id(parent) {
+            Some(parent) => {
+                let child_waiters = parent.child_waiters.downcast_ref::<WaitQueue>().unwrap();
+                child_waiters.wake_all();
+            }
+            None => self.reap(&proc),
+        }
     }
 
     pub fn fork(&self, proc: Arc<Process>) -> Arc<Process> {
@@ -189,12 +330,18 @@ impl ProcessManager {
         let fs = VFS.fork_process(&proc, child_pid);
         let child = Arc::new(Process {
             id: child_pid,
+            parent: AtomicUsize::new(proc.id.0),
+            name: Mutex::new(proc.name.lock().clone()),
             threads: Mutex::new(Vec::new()),
             mem: crate::memory::utils::fork_mem_space(&proc.mem),
             fs,
-            monitor: Box::new(SysMonitor::new()),
+            exit_waiters: Box::new(WaitQueue::new()),
+            child_waiters: Box::new(WaitQueue::new()),
             is_zombie: AtomicBool::new(false),
             exit_code: AtomicIsize::new(0),
+            pgid: AtomicUsize::new(proc.pgid.load(Ordering::SeqCst)),
+            pending_signals: AtomicU32::new(0),
+            stopped: AtomicBool::new(false),
         });
         trace!(
             "Fork: Created child process pid={:?} parent={:?}",
@@ -211,6 +358,7 @@ impl ProcessManager {
             pid: child.id,
             state: Atomic::new(RunState::Ready),
             ticks: AtomicUsize::new(current_task.ticks.load(Ordering::SeqCst)),
+            sched: current_task.sched.fork(),
             context: Box::new(ctx),
             runnable: None,
         };
@@ -220,11 +368,12 @@ impl ProcessManager {
         child
     }
 
-    fn load_elf_for_exec(&self, path: &str) -> Result<Vec<u8>, ()> {
-        let mut elf = vec![];
+    /// Read a whole file. Used to load programs and shared libraries.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, ()> {
+        let mut data = vec![];
         let fd = crate::modules::module_call("vfs", false, &VFSRequest::Open(path));
         if fd < 0 {
-            println!("load_elf_for_exec failed: fd={}", fd);
+            println!("read_file failed: fd={}", fd);
             return Err(());
         }
         let mut buf = [0u8; 256];
@@ -232,24 +381,35 @@ impl ProcessManager {
             let size =
                 crate::modules::module_call("vfs", false, &VFSRequest::Read(Fd(fd as _), &mut buf));
             if size > 0 {
-                elf.extend_from_slice(&buf[0..size as usize]);
+                data.extend_from_slice(&buf[0..size as usize]);
             } else if size < 0 {
-                println!("load_elf_for_exec failed: size={}", size);
+                println!("read_file failed: size={}", size);
                 return Err(());
             } else {
                 break;
             }
         }
         crate::modules::module_call("vfs", false, &VFSRequest::Close(Fd(fd as _)));
-        Ok(elf)
+        Ok(data)
     }
 
-    pub fn exec(&self, path: &str, _args: &[&str]) -> isize {
-        let Ok(elf) = self.load_elf_for_exec(path) else {
+    /// Replace the current process with the program at `path`.
+    /// The program receives `path` as `argv[0]`, followed by `args`.
+    pub fn exec(&self, path: &str, args: &[&str]) -> isize {
+        // Copy the arguments out of the user space. It is going to be replaced.
+        let Ok(args) = core::iter::once(path)
+            .chain(args.iter().copied())
+            .map(CString::new)
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return -1;
+        };
+        let Ok(elf) = self.read_file(path) else {
             println!("exec failed: {}", path);
             return -1;
         };
         let proc = PROCESS_MANAGER.current_proc().unwrap();
-        super::user::exec(proc, elf, &[])
+        *proc.name.lock() = path.to_owned();
+        super::user::exec(proc, elf, &args)
     }
 }
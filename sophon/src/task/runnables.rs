@@ -1,28 +1,62 @@
 use super::proc::PROCESS_MANAGER;
+use super::sched::SCHEDULER;
+use super::user::UserEntry;
+use crate::modules::TIMER;
 use crate::INIT_FS;
 use alloc::ffi::CString;
 use alloc::vec::Vec;
 use core::arch::asm;
 use klib::task::Runnable;
+use memory::address::Address;
 
-/// The idle task.
+/// The idle task. Each core has one.
 ///
-/// The task scheduler should schedule this task when no other task is ready.
+/// The task scheduler only picks this task when all the run queues are empty.
+/// It sleeps the core with `wfi`, and stops the periodic timer in the meantime,
+/// unless the timer is needed to wake up sleeping tasks.
+/// Ready tasks enqueued by other cores wake it up with an IPI.
 pub struct Idle;
 
 impl Runnable for Idle {
     fn run(&mut self) -> ! {
         loop {
+            interrupt::disable();
+            if SCHEDULER.has_ready_tasks() {
+                interrupt::enable();
+                syscall::_yield();
+                continue;
+            }
+            // Interrupts are masked, so anything pending from now on will terminate `wfi`,
+            // and is handled right after the tick is restarted.
+            let tickless = !SCHEDULER.has_sleepers();
+            if tickless {
+                TIMER.stop();
+            }
             unsafe {
-                asm!("wfe");
+                asm!("dsb sy", "wfi");
+            }
+            if tickless {
+                TIMER.restart();
             }
+            interrupt::enable();
+        }
+    }
+}
+
+/// Delivers the signals that interrupt handlers send to process groups.
+/// A thread of the sched process.
+pub struct SignalWorker;
+
+impl Runnable for SignalWorker {
+    fn run(&mut self) -> ! {
+        loop {
+            super::signal::deliver_deferred();
         }
     }
 }
 
 /// Main thread for the init process
 pub struct Init {
-    #[allow(unused)]
     args: Vec<CString>,
     elf: Vec<u8>,
 }
@@ -37,14 +71,16 @@ impl Init {
             .as_file()
             .unwrap()
             .to_vec();
-        let args = Vec::new();
+        let args = alloc::vec![CString::new("/bin/init").unwrap()];
         Self { args, elf }
     }
 
     fn run_user(&mut self) -> ! {
         let proc = PROCESS_MANAGER.current_proc().unwrap();
         let elf = core::mem::take(&mut self.elf);
-        let err = super::user::exec(proc, elf, &[]);
+        let args = core::mem::take(&mut self.args);
+        *proc.name.lock() = "/bin/init".into();
+        let err = super::user::exec(proc, elf, &args);
         panic!("Failed to exec init: {:?}", err);
     }
 }
@@ -59,3 +95,26 @@ impl Runnable for Init {
         self.run_user();
     }
 }
+
+/// A thread started by a user process with `spawn_thread`.
+pub struct UserThread {
+    entry: UserEntry,
+    arg: usize,
+    stack_top: Address,
+}
+
+impl UserThread {
+    pub fn new(entry: UserEntry, arg: usize, stack_top: Address) -> Self {
+        Self {
+            entry,
+            arg,
+            stack_top,
+        }
+    }
+}
+
+impl Runnable for UserThread {
+    fn run(&mut self) -> ! {
+        super::user::enter_thread(self.entry, self.arg, self.stack_top)
+    }
+}
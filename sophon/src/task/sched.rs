@@ -1,36 +1,100 @@
-use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 use crate::arch::{Arch, ArchContext, TargetArch};
-use alloc::{boxed::Box, collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{
+    boxed::Box,
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
 use atomic::Atomic;
-use crossbeam::queue::SegQueue;
+use klib::proc::PID;
 use klib::task::{RunState, Task, TaskId};
 use spin::{Lazy, Mutex};
 
+use super::ipi::{self, IPI};
+use super::proc::PROCESS_MANAGER;
 use crate::utils::pls::ProcessorLocalStorage;
+use crate::utils::trace;
+use syscall::trace::Kind;
 
-pub struct Scheduler {
-    current_task: Lazy<Vec<Atomic<Option<TaskId>>>>,
-    per_core_task_queue: Lazy<ProcessorLocalStorage<SegQueue<TaskId>>>,
+/// A scheduling policy.
+/// Decides the order of ready tasks, and whether the running task should give up its core.
+pub trait SchedPolicy: 'static {
+    /// Per-core queue of ready tasks.
+    type RunQueue: RunQueue;
+
+    /// Number of timer ticks a task can run before the policy is consulted again.
+    fn time_slice(_task: &Task) -> usize {
+        UNIT_TIME_SLICE
+    }
+
+    /// Charge `delta_ns` nanoseconds of CPU time to a task.
+    fn update_runtime(task: &Task, delta_ns: u64);
+
+    /// Should the running task be preempted by a task in the queue, after its time slice expires?
+    fn should_preempt(current: &Task, queue: &Self::RunQueue) -> bool;
+}
+
+/// A per-core queue of ready tasks.
+pub trait RunQueue: Default + Send + Sync + 'static {
+    /// Add a ready task.
+    fn push(&self, task: &Task);
+    /// Take the next task to run.
+    fn pop(&self) -> Option<TaskId>;
+    /// Number of tasks in the queue.
+    fn len(&self) -> usize;
+}
+
+/// The scheduling policy used by the kernel.
+pub type Policy = super::policy::Fair;
+
+/// Per-core scheduler states.
+#[derive(Default)]
+struct CoreState<Q: RunQueue> {
+    /// The task that is running on this core.
+    current_task: Atomic<Option<TaskId>>,
+    /// The process of `current_task`.
+    current_pid: AtomicUsize,
+    /// The task whose kernel stack is used by this core.
+    /// This falls behind `current_task` until a context switch is finished.
+    stack_owner: Atomic<Option<TaskId>>,
+    /// A preempted task. It is pushed back to a run queue after this core leaves its kernel stack.
+    preempted_task: Atomic<Option<TaskId>>,
+    /// The idle task of this core. This task never enters a run queue.
+    idle_task: Atomic<Option<TaskId>>,
+    /// Local run queue. Other cores may steal tasks from it.
+    task_queue: Q,
+    /// Timestamp (ns) when the CPU time of the current task was last accounted.
+    last_accounted: AtomicU64,
+}
+
+pub struct Scheduler<P: SchedPolicy> {
+    cores: Lazy<ProcessorLocalStorage<CoreState<P::RunQueue>>>,
     tasks: Mutex<BTreeMap<TaskId, Arc<Task>>>,
+    /// Set when the first task runs. `cores` is not initialized before.
+    started: AtomicBool,
+    /// Removed tasks. They are dropped, together with their kernel stacks,
+    /// once no core is running on their kernel stacks.
+    exited_tasks: Mutex<Vec<Arc<Task>>>,
+    /// Sleeping tasks, ordered by their wake-up time (ns).
+    sleepers: Mutex<BTreeSet<(u64, TaskId)>>,
 }
 
-unsafe impl Sync for Scheduler {}
+unsafe impl<P: SchedPolicy> Sync for Scheduler<P> {}
 
-pub static SCHEDULER: Scheduler = Scheduler::new();
+pub static SCHEDULER: Scheduler<Policy> = Scheduler::new();
 
 const UNIT_TIME_SLICE: usize = 1;
 
-impl Scheduler {
+impl<P: SchedPolicy> Scheduler<P> {
     const fn new() -> Self {
         Self {
-            current_task: Lazy::new(|| {
-                (0..ProcessorLocalStorage::<usize>::num_cores())
-                    .map(|_| Atomic::new(None))
-                    .collect()
-            }),
-            per_core_task_queue: Lazy::new(|| ProcessorLocalStorage::new()),
+            cores: Lazy::new(|| ProcessorLocalStorage::new()),
             tasks: Mutex::new(BTreeMap::new()),
+            started: AtomicBool::new(false),
+            exited_tasks: Mutex::new(Vec::new()),
+            sleepers: Mutex::new(BTreeSet::new()),
         }
     }
 
@@ -38,35 +102,172 @@ impl Scheduler {
         ProcessorLocalStorage::<usize>::current_core()
     }
 
+    fn num_cores() -> usize {
+        ProcessorLocalStorage::<usize>::num_cores()
+    }
+
     pub fn get_current_task(&self) -> Option<Arc<Task>> {
         self.get_current_task_id().map(|id| self.get_task_by_id(id))
     }
 
     pub fn get_current_task_id(&self) -> Option<TaskId> {
-        self.current_task[Self::current_core()].load(Ordering::Relaxed)
+        self.cores.current_task.load(Ordering::Relaxed)
+    }
+
+    /// The process running on this core, without taking any lock, e.g. to tag log records.
+    /// `None` before the first task runs.
+    pub fn get_current_pid(&self) -> Option<PID> {
+        if !self.started.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(PID(self.cores.current_pid.load(Ordering::Relaxed)))
     }
 
     #[inline]
-    fn set_current_task_id(&self, id: TaskId) {
-        self.current_task[Self::current_core()].store(Some(id), Ordering::Relaxed);
+    fn set_current_task(&self, task: &Task) {
+        self.cores
+            .current_task
+            .store(Some(task.id), Ordering::Relaxed);
+        self.cores.current_pid.store(task.pid.0, Ordering::Relaxed);
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    /// Is the current core running its idle task?
+    pub fn is_idle(&self) -> bool {
+        let core = &*self.cores;
+        let current = core.current_task.load(Ordering::Relaxed);
+        current.is_some() && current == core.idle_task.load(Ordering::Relaxed)
+    }
+
+    /// Is there any task waiting in the run queues?
+    pub fn has_ready_tasks(&self) -> bool {
+        (0..Self::num_cores()).any(|c| self.cores.get(c).task_queue.len() != 0)
     }
 
+    /// Total time a core has spent in its idle task, in nanoseconds.
+    pub fn idle_time(&self, core: usize) -> Option<u64> {
+        if core >= Self::num_cores() {
+            return None;
+        }
+        let _guard = interrupt::uninterruptible();
+        let state = self.cores.get(core);
+        let idle_task = self.get_task_by_id(state.idle_task.load(Ordering::SeqCst)?);
+        let mut idle_time = idle_task.sched.cpu_time.load(Ordering::SeqCst);
+        if state.current_task.load(Ordering::SeqCst) == Some(idle_task.id) {
+            // The idle period in progress is not accounted yet.
+            let now = TargetArch::uptime().as_nanos() as u64;
+            idle_time += now.saturating_sub(state.last_accounted.load(Ordering::SeqCst));
+        }
+        Some(idle_time)
+    }
+
+    /// Find another core that is running its idle task.
+    fn find_idle_core(&self) -> Option<usize> {
+        let me = Self::current_core();
+        (0..Self::num_cores()).filter(|c| *c != me).find(|c| {
+            let core = self.cores.get(*c);
+            let current = core.current_task.load(Ordering::Relaxed);
+            current.is_some() && current == core.idle_task.load(Ordering::Relaxed)
+        })
+    }
+
+    /// Is another core still running on the kernel stack of this task?
+    fn is_on_other_core(&self, task: TaskId) -> bool {
+        let me = Self::current_core();
+        (0..Self::num_cores())
+            .filter(|c| *c != me)
+            .any(|c| self.cores.get(c).stack_owner.load(Ordering::SeqCst) == Some(task))
+    }
+
+    /// Push a ready task to a run queue.
+    /// If any other core is idle, the task goes to that core, and the core is woken up by an IPI.
+    fn enqueue_task(&self, task: TaskId) {
+        debug_assert!(!interrupt::is_enabled());
+        let task = self.get_task_by_id(task);
+        if let Some(core) = self.find_idle_core() {
+            self.cores.get(core).task_queue.push(&task);
+            ipi::send(core, IPI::Reschedule);
+        } else {
+            self.cores.task_queue.push(&task);
+        }
+    }
+
+    /// Take a task from the local run queue, or steal one from other cores.
+    /// Fall back to the preempted task, and then the idle task.
     #[inline]
     fn get_next_schedulable_task(&self) -> TaskId {
         debug_assert!(!interrupt::is_enabled());
-        if let Some(next_runnable_task) = self.per_core_task_queue.pop() {
-            return next_runnable_task;
-        } else {
-            // We should at least have an `idle` task that is runnable
-            panic!("No more tasks to run!");
+        let me = Self::current_core();
+        let num_cores = Self::num_cores();
+        for i in 0..num_cores {
+            let queue = &self.cores.get((me + i) % num_cores).task_queue;
+            for _ in 0..queue.len() {
+                let Some(task) = queue.pop() else {
+                    break;
+                };
+                if !self.tasks.lock().contains_key(&task) {
+                    // The task has exited while waiting in the queue.
+                    continue;
+                }
+                if self.is_on_other_core(task) {
+                    // The task is blocked and then woken up, but another core has not finished switching away from it yet.
+                    queue.push(&self.get_task_by_id(task));
+                    continue;
+                }
+                return task;
+            }
+        }
+        if let Some(task) = self.cores.preempted_task.swap(None, Ordering::SeqCst) {
+            return task;
         }
+        // Nothing to run. Fall back to the idle task of this core.
+        self.cores
+            .idle_task
+            .load(Ordering::SeqCst)
+            .expect("No idle task registered for this core")
     }
 
     #[inline]
-    fn get_task_by_id(&self, task: TaskId) -> Arc<Task> {
+    pub fn get_task_by_id(&self, task: TaskId) -> Arc<Task> {
         self.tasks.lock().get(&task).unwrap().clone()
     }
 
+    /// Like `get_task_by_id`, but returns `None` if the task has exited.
+    pub fn find_task_by_id(&self, task: TaskId) -> Option<Arc<Task>> {
+        let _guard = interrupt::uninterruptible();
+        self.tasks.lock().get(&task).cloned()
+    }
+
+    /// Call `f` with each task. Returns `false` without calling it if the task list is locked,
+    /// e.g. by a core stopped by the debugger.
+    pub fn try_for_each_task(&self, mut f: impl FnMut(&Arc<Task>)) -> bool {
+        let Some(tasks) = self.tasks.try_lock() else {
+            return false;
+        };
+        tasks.values().for_each(|t| f(t));
+        true
+    }
+
+    /// Charge the CPU time since the last accounting to the current task.
+    fn update_current_task_runtime(&self, task: &Task) {
+        debug_assert!(!interrupt::is_enabled());
+        let now = TargetArch::uptime().as_nanos() as u64;
+        let last = self.cores.last_accounted.swap(now, Ordering::SeqCst);
+        let delta = now.saturating_sub(last);
+        task.sched.cpu_time.fetch_add(delta, Ordering::SeqCst);
+        P::update_runtime(task, delta);
+    }
+
+    /// Total CPU time of the current task, in nanoseconds.
+    pub fn current_task_cpu_time(&self) -> u64 {
+        let _guard = interrupt::uninterruptible();
+        let task = self.get_current_task().unwrap();
+        self.update_current_task_runtime(&task);
+        task.sched.cpu_time.load(Ordering::SeqCst)
+    }
+
+    /// Mark the current task as ready.
+    /// The task is pushed back to a run queue only after the next context switch is finished.
     #[inline]
     pub fn enqueue_current_task_as_ready(&self) {
         debug_assert!(!interrupt::is_enabled());
@@ -74,7 +275,21 @@ impl Scheduler {
         let task = self.get_task_by_id(tid);
         debug_assert_ne!(task.state.load(Ordering::SeqCst), RunState::Ready);
         task.state.store(RunState::Ready, Ordering::SeqCst);
-        self.per_core_task_queue.get(0).push(tid);
+        if !self.is_idle() {
+            self.cores.preempted_task.store(Some(tid), Ordering::SeqCst);
+        }
+    }
+
+    /// Give up the core. A running task goes back to the run queues, a blocked task stays blocked.
+    pub fn yield_current_task(&self) -> ! {
+        interrupt::disable();
+        let task = self.get_current_task().unwrap();
+        let running = task.state.load(Ordering::SeqCst) == RunState::Running;
+        drop(task);
+        if running {
+            self.enqueue_current_task_as_ready();
+        }
+        self.schedule()
     }
 
     pub fn register_new_task(&self, task: Arc<Task>) {
@@ -82,24 +297,65 @@ impl Scheduler {
         self.tasks.lock().insert(task.id, task.clone());
         if task.state.load(Ordering::SeqCst) == RunState::Ready {
             debug_assert!(!interrupt::is_enabled());
-            self.per_core_task_queue.get(0).push(task.id);
+            self.enqueue_task(task.id);
         }
     }
 
+    /// Register the idle task of a core.
+    pub fn register_idle_task(&self, core: usize, task: Arc<Task>) {
+        let _guard = interrupt::uninterruptible();
+        self.tasks.lock().insert(task.id, task.clone());
+        self.cores
+            .get(core)
+            .idle_task
+            .store(Some(task.id), Ordering::SeqCst);
+    }
+
     pub fn remove_task(&self, task: TaskId) {
         debug_assert!(!interrupt::is_enabled());
-        let _ = self.current_task[Self::current_core()].fetch_update(
-            Ordering::SeqCst,
-            Ordering::SeqCst,
-            |curr| {
+        let _ = self
+            .cores
+            .current_task
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |curr| {
                 if curr == Some(task) {
                     Some(None)
                 } else {
                     None
                 }
-            },
-        );
-        self.tasks.lock().remove(&task).unwrap();
+            });
+        let task = self.tasks.lock().remove(&task).unwrap();
+        self.exited_tasks.lock().push(task);
+    }
+
+    /// Drop the exited tasks whose kernel stacks are no longer in use.
+    fn reap_exited_tasks(&self) {
+        let in_use = |task: &Task| {
+            (0..Self::num_cores()).any(|c| {
+                let core = self.cores.get(c);
+                core.stack_owner.load(Ordering::SeqCst) == Some(task.id)
+                    || core.current_task.load(Ordering::SeqCst) == Some(task.id)
+            })
+        };
+        let reaped = {
+            let mut exited_tasks = self.exited_tasks.lock();
+            if exited_tasks.is_empty() {
+                return;
+            }
+            let (reaped, remaining) = core::mem::take(&mut *exited_tasks)
+                .into_iter()
+                .partition::<Vec<_>, _>(|t| !in_use(t));
+            *exited_tasks = remaining;
+            reaped
+        };
+        // Free the kernel stacks outside of the lock.
+        drop(reaped);
+        PROCESS_MANAGER.release_exited_mem_spaces();
+    }
+
+    /// Does the process have any task that is not reaped yet?
+    pub fn has_tasks_of(&self, pid: PID) -> bool {
+        self.tasks.lock().values().any(|t| t.pid == pid)
+            || self.exited_tasks.lock().iter().any(|t| t.pid == pid)
     }
 
     pub(super) fn create_task_context(&self) -> Box<<TargetArch as Arch>::Context> {
@@ -109,20 +365,34 @@ impl Scheduler {
         ))
     }
 
-    pub fn block_current_task(&self) {
-        let _guard = interrupt::uninterruptible();
+    /// Mark the current task as blocked, without switching away from it.
+    /// The task will be switched out by the next `schedule()`, unless it is unblocked before that.
+    pub fn mark_current_task_as_blocked(&self) {
+        debug_assert!(!interrupt::is_enabled());
         let tid = self.get_current_task_id().unwrap();
         let task = self.get_task_by_id(tid);
         assert_eq!(task.state.load(Ordering::SeqCst), RunState::Running);
         task.state.store(RunState::Blocked, Ordering::SeqCst);
+    }
+
+    /// Block the current task and switch to another one.
+    ///
+    /// `guard` is released after the task is marked as blocked.
+    /// Wakers holding the same lock will always see the task as blocked.
+    pub fn block_current_task<G>(&self, guard: G) {
+        let _guard = interrupt::uninterruptible();
+        self.mark_current_task_as_blocked();
+        drop(guard);
         syscall::_yield();
     }
 
-    #[allow(unused)]
+    /// Wake up a blocked task. Does nothing if the task is not blocked, or has exited.
     pub fn unblock_task(&self, tid: TaskId) {
         let _guard = interrupt::uninterruptible();
 
-        let task = self.get_task_by_id(tid);
+        let Some(task) = self.tasks.lock().get(&tid).cloned() else {
+            return;
+        };
         let old = task
             .state
             .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
@@ -133,7 +403,57 @@ impl Scheduler {
                 }
             });
         if old == Ok(RunState::Blocked) {
-            self.per_core_task_queue.get(0).push(tid);
+            self.enqueue_task(tid);
+        }
+    }
+
+    /// Block the current task for at least `duration_ns` nanoseconds.
+    ///
+    /// The task is woken up by the timer tick after the deadline, or earlier by `cancel_sleep`.
+    pub fn sleep_current_task(&self, duration_ns: u64) {
+        self.sleep_current_task_with(duration_ns, ());
+    }
+
+    /// Same as `sleep_current_task`, and `guard` is released once the task is blocked,
+    /// as with `block_current_task`.
+    pub fn sleep_current_task_with<G>(&self, duration_ns: u64, guard: G) {
+        let _guard = interrupt::uninterruptible();
+        let tid = self.get_current_task_id().unwrap();
+        let deadline = (TargetArch::uptime().as_nanos() as u64).saturating_add(duration_ns);
+        let mut sleepers = self.sleepers.lock();
+        sleepers.insert((deadline, tid));
+        self.block_current_task((guard, sleepers));
+        self.sleepers.lock().remove(&(deadline, tid));
+    }
+
+    /// Wake up a sleeping task before its deadline. Does nothing if the task is not sleeping.
+    pub fn cancel_sleep(&self, tid: TaskId) {
+        let _guard = interrupt::uninterruptible();
+        let mut sleepers = self.sleepers.lock();
+        if let Some(entry) = sleepers.iter().find(|(_, t)| *t == tid).copied() {
+            sleepers.remove(&entry);
+            drop(sleepers);
+            self.unblock_task(tid);
+        }
+    }
+
+    /// Are there any sleeping tasks? The periodic timer is needed to wake them up.
+    pub fn has_sleepers(&self) -> bool {
+        let _guard = interrupt::uninterruptible();
+        !self.sleepers.lock().is_empty()
+    }
+
+    /// Wake up the sleeping tasks whose deadlines have passed.
+    fn wake_sleepers(&self) {
+        debug_assert!(!interrupt::is_enabled());
+        let now = TargetArch::uptime().as_nanos() as u64;
+        let expired = {
+            let mut sleepers = self.sleepers.lock();
+            let remaining = sleepers.split_off(&(now.saturating_add(1), TaskId::NULL));
+            core::mem::replace(&mut *sleepers, remaining)
+        };
+        for (_, tid) in expired {
+            self.unblock_task(tid);
         }
     }
 
@@ -149,17 +469,22 @@ impl Scheduler {
             unsafe { self.return_to_user(task.unwrap()) }
         } else {
             // No current task or the current Task is blocked, switch to a new task.
+            if let Some(task) = task.as_ref() {
+                self.update_current_task_runtime(task);
+            }
+            drop(task);
 
             // Find a schedulable task
             let next_task_id = self.get_next_schedulable_task();
             let next_task = self.get_task_by_id(next_task_id);
 
-            if task.as_ref().map(|t| t.id) != Some(next_task.id) {
+            if tid != Some(next_task.id) {
+                trace::instant(Kind::Schedule, next_task.id.0);
                 //     static SYNC: Mutex<()> = Mutex::new(());
                 //     let _guard = SYNC.lock();
                 // trace!(
                 //     "Switch: {:?} -> {:?}",
-                //     task.as_ref().map(|t| t.id),
+                //     tid,
                 //     next_task.id
                 // );
             }
@@ -167,9 +492,14 @@ impl Scheduler {
             {
                 debug_assert_eq!(next_task.state.load(Ordering::SeqCst), RunState::Ready);
                 next_task.state.store(RunState::Running, Ordering::SeqCst);
-                next_task.ticks.store(UNIT_TIME_SLICE, Ordering::SeqCst);
+                next_task
+                    .ticks
+                    .store(P::time_slice(&next_task), Ordering::SeqCst);
             }
-            self.set_current_task_id(next_task_id);
+            self.cores
+                .last_accounted
+                .store(TargetArch::uptime().as_nanos() as u64, Ordering::SeqCst);
+            self.set_current_task(&next_task);
             atomic::fence(Ordering::SeqCst);
             // Return to user
             unsafe { self.return_to_user(next_task) }
@@ -187,6 +517,19 @@ impl Scheduler {
         (*context_ptr).return_to_user()
     }
 
+    /// Called by the arch-specific context switch code, right after the core switched to the kernel stack of the current task.
+    fn finish_task_switch(&self) {
+        debug_assert!(!interrupt::is_enabled());
+        let core = &*self.cores;
+        core.stack_owner
+            .store(core.current_task.load(Ordering::SeqCst), Ordering::SeqCst);
+        // The preempted task is no longer running on this core. Make it visible to others.
+        if let Some(task) = core.preempted_task.swap(None, Ordering::SeqCst) {
+            self.enqueue_task(task);
+        }
+        self.reap_exited_tasks();
+    }
+
     pub fn timer_tick(&self) -> ! {
         debug_assert!(!interrupt::is_enabled());
         let tid = self.get_current_task_id().unwrap();
@@ -195,16 +538,27 @@ impl Scheduler {
         if task.ticks.load(Ordering::SeqCst) == 0 {
             panic!("time_slice_units is zero");
         }
+        self.wake_sleepers();
 
         {
             debug_assert_eq!(task.state.load(Ordering::SeqCst), RunState::Running);
+            self.update_current_task_runtime(&task);
             let old = task.ticks.fetch_sub(1, Ordering::SeqCst);
             if old == 1 {
-                self.enqueue_current_task_as_ready();
-                self.schedule();
-            } else {
-                unsafe { self.return_to_user(task) }
+                if self.is_idle() || P::should_preempt(&task, &self.cores.task_queue) {
+                    drop(task);
+                    self.enqueue_current_task_as_ready();
+                    self.schedule();
+                }
+                // Nothing better to run. Start a new time slice.
+                task.ticks.store(P::time_slice(&task), Ordering::SeqCst);
             }
+            unsafe { self.return_to_user(task) }
         }
     }
 }
+
+/// Finish a context switch. See `Scheduler::finish_task_switch`.
+pub extern "C" fn finish_task_switch() {
+    SCHEDULER.finish_task_switch()
+}
@@ -0,0 +1,271 @@
+use core::iter::Step;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+use elf_loader::{ELFLoader, ELFObject};
+use klib::proc::MemSpace;
+use memory::{
+    address::{Address, V},
+    page::{Frame, Page, PageSize, Size4K},
+    page_table::{PageFlags, PageTable},
+};
+use spin::{Mutex, Once};
+
+use crate::memory::{aslr, kernel::KERNEL_MEMORY_MAPPER, oom, physical::PHYSICAL_MEMORY};
+
+use super::PROCESS_MANAGER;
+
+/// Shared libraries are looked up in this directory.
+const LIBRARY_DIR: &str = "/lib";
+
+/// Shared libraries are loaded from here upwards, below the user heap.
+/// Each library has a fixed address, the same in all the processes.
+const SHARED_LIBRARY_START: usize = 0x0800_0000_0000;
+/// With ASLR, the first library is loaded up to this many bytes above `SHARED_LIBRARY_START`.
+/// The addresses are randomized once per boot, so that the pages can still be shared.
+const SHARED_LIBRARY_RANDOM_RANGE: usize = 1 << 42;
+
+static NEXT_BASE: Once<AtomicUsize> = Once::new();
+
+/// All the shared libraries loaded so far, by path.
+static SHARED_LIBRARIES: Mutex<BTreeMap<String, Arc<SharedLibrary>>> = Mutex::new(BTreeMap::new());
+
+pub struct SharedLibrary {
+    pub path: String,
+    data: Vec<u8>,
+    base: Address,
+    /// Size of the pages of the library.
+    size: usize,
+    /// `base` minus the start of the first page of the library.
+    offset: isize,
+    /// Can the read-only pages be shared? Not if the library has text relocations,
+    /// or a page holds both read-only and writable data.
+    shareable: bool,
+    /// The frames of the read-only pages, recorded by the first process that loads the library.
+    shared_pages: Mutex<Option<BTreeMap<Page, Frame>>>,
+}
+
+impl SharedLibrary {
+    fn new(path: String, data: Vec<u8>) -> Result<Self, &'static str> {
+        let object = ELFObject::parse(&data)?;
+        let segments = object.segments().collect::<Vec<_>>();
+        let start = segments
+            .iter()
+            .map(|(s, _)| s.start)
+            .min()
+            .ok_or("no segments")?;
+        let end = segments.iter().map(|(s, _)| s.end).max().unwrap();
+        let start = start & !(Size4K::BYTES - 1);
+        let size = (end - start).next_multiple_of(Size4K::BYTES);
+        let pages =
+            |range: &Range<usize>| range.start / Size4K::BYTES..range.end.div_ceil(Size4K::BYTES);
+        let mixed = segments.iter().filter(|(_, w)| !*w).any(|(ro, _)| {
+            segments.iter().filter(|(_, w)| *w).any(|(rw, _)| {
+                let (ro, rw) = (pages(ro), pages(rw));
+                ro.start < rw.end && rw.start < ro.end
+            })
+        });
+        let shareable = !mixed && !object.has_text_relocations();
+        let base = NEXT_BASE
+            .call_once(|| {
+                AtomicUsize::new(
+                    SHARED_LIBRARY_START
+                        + aslr::random_offset(SHARED_LIBRARY_RANDOM_RANGE, Size4K::BYTES),
+                )
+            })
+            .fetch_add(size, Ordering::SeqCst);
+        Ok(Self {
+            path,
+            offset: base as isize - start as isize,
+            base: base.into(),
+            size,
+            shareable,
+            shared_pages: Mutex::new(None),
+            data,
+        })
+    }
+
+    /// The relocated address of a symbol defined by this library.
+    pub fn lookup(&self, name: &str) -> Option<Address> {
+        let object = ELFObject::parse(&self.data).ok()?;
+        object.lookup(name).map(|a| Address::from(a) + self.offset)
+    }
+
+    /// Map and load the library into the current address space, and charge its pages to `mem`.
+    /// The read-only pages are shared with the other processes that loaded it.
+    pub fn load(
+        &self,
+        page_table: &mut PageTable,
+        mem: &MemSpace,
+        resolve: &dyn Fn(&str) -> Option<Address>,
+    ) -> Result<(), &'static str> {
+        let mut shared = self.shared_pages.lock();
+        let base = self.base;
+        let shared_pages = shared.as_ref();
+        let entry = ELFLoader::load_dynamic(
+            &self.data,
+            &mut |pages| {
+                let start_page = Page::new(base);
+                let num_pages = Page::steps_between(&pages.start, &pages.end).unwrap();
+                if !oom::charge(mem, num_pages) {
+                    return Err("memory limit exceeded");
+                }
+                for i in 0..num_pages {
+                    let page = Page::<Size4K>::forward(start_page, i);
+                    let frame = match shared_pages.and_then(|s| s.get(&page)) {
+                        Some(frame) => {
+                            PHYSICAL_MEMORY.share(*frame);
+                            *frame
+                        }
+                        None => oom::acquire::<Size4K>().ok_or("out of memory")?,
+                    };
+                    let _kernel_page_table = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+                    page_table.map(
+                        page,
+                        frame,
+                        PageFlags::user_code_flags_4k(),
+                        &PHYSICAL_MEMORY,
+                    );
+                }
+                Ok(start_page..Page::<Size4K>::forward(start_page, num_pages))
+            },
+            resolve,
+            shared_pages.is_some(),
+        )?;
+        if entry.tls.is_some() {
+            return Err("TLS in shared libraries is not supported");
+        }
+        super::user::protect_segments(page_table, &entry.segments)?;
+        if shared.is_none() && self.shareable {
+            // First load: keep the frames of the read-only pages for later loads.
+            // The cache holds a reference to each frame.
+            let _kernel_page_table = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+            let frames = entry
+                .segments
+                .iter()
+                .filter(|segment| !segment.writable)
+                .flat_map(|segment| segment.pages.clone())
+                .map(|page| {
+                    let frame = Frame::new(page_table.translate(page.start()).unwrap());
+                    PHYSICAL_MEMORY.share(frame);
+                    (page, frame)
+                })
+                .collect();
+            *shared = Some(frames);
+        }
+        Ok(())
+    }
+
+    /// Release the shared pages, if no process maps them. Returns the number of frames released,
+    /// or `None` if the pages are in use.
+    fn release_unused_pages(&self) -> Option<usize> {
+        let mut shared = self.shared_pages.lock();
+        let Some(frames) = shared.as_ref() else {
+            return Some(0);
+        };
+        if frames
+            .values()
+            .any(|frame| PHYSICAL_MEMORY.is_shared(*frame))
+        {
+            return None;
+        }
+        for frame in frames.values() {
+            PHYSICAL_MEMORY.release_shared(*frame);
+        }
+        let released = frames.len();
+        *shared = None;
+        Some(released)
+    }
+}
+
+/// Drop the cached libraries that no process is using. A shrinker for the OOM handler.
+///
+/// Returns the number of frames released.
+pub fn shrink_cache() -> usize {
+    let mut libraries = SHARED_LIBRARIES.lock();
+    let mut released = 0;
+    libraries.retain(|_, library| {
+        // Only the cache has the library, so no process is loading it.
+        if Arc::strong_count(library) != 1 {
+            return true;
+        }
+        match library.release_unused_pages() {
+            Some(frames) => {
+                released += frames;
+                false
+            }
+            None => true,
+        }
+    });
+    released
+}
+
+/// Call `f` with the file name of the shared library an address is in, the offset of the
+/// address before relocation, and the function containing it with the offset into it.
+///
+/// Returns `None` if no library holds the address, or the library table is locked.
+/// Does not allocate, so that it can be used to report faults.
+pub fn symbolize<R>(
+    address: Address,
+    f: impl FnOnce(&str, usize, Option<(&str, usize)>) -> R,
+) -> Option<R> {
+    let libraries = SHARED_LIBRARIES.try_lock()?;
+    let library = libraries
+        .values()
+        .find(|l| l.base <= address && address < l.base + l.size)?;
+    let link_address = (address.as_usize() as isize - library.offset) as usize;
+    let function = ELFObject::parse(&library.data)
+        .ok()
+        .and_then(|object| object.find_function(link_address));
+    let name = library.path.rsplit('/').next().unwrap_or(&library.path);
+    Some(f(name, link_address, function))
+}
+
+/// Get a loaded library, or read it from `/lib`.
+fn get_or_read(name: &str) -> Result<Arc<SharedLibrary>, &'static str> {
+    let path = format!("{}/{}", LIBRARY_DIR, name);
+    if let Some(library) = SHARED_LIBRARIES.lock().get(&path) {
+        return Ok(library.clone());
+    }
+    let Ok(data) = PROCESS_MANAGER.read_file(&path) else {
+        error!("shared library not found: {}", path);
+        return Err("shared library not found");
+    };
+    let library = Arc::new(SharedLibrary::new(path.clone(), data)?);
+    // Another process may have read the library in the meantime.
+    Ok(SHARED_LIBRARIES
+        .lock()
+        .entry(path)
+        .or_insert(library)
+        .clone())
+}
+
+/// Find all the shared libraries a program needs, in breadth-first order.
+/// This is the order their symbols are looked up in, after the program's own symbols.
+pub fn needed_libraries(elf: &[u8]) -> Result<Vec<Arc<SharedLibrary>>, &'static str> {
+    let mut libraries: Vec<Arc<SharedLibrary>> = Vec::new();
+    let mut queue = ELFObject::parse(elf)?
+        .needed()?
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let mut i = 0;
+    while i < queue.len() {
+        let library = get_or_read(&queue[i])?;
+        i += 1;
+        if libraries.iter().any(|l| l.path == library.path) {
+            continue;
+        }
+        let needed = ELFObject::parse(&library.data)?.needed()?;
+        queue.extend(needed.into_iter().map(String::from));
+        libraries.push(library);
+    }
+    Ok(libraries)
+}
+
+/// The relocated address of a symbol defined by a program loaded at `offset`.
+pub fn lookup_in_program(elf: &[u8], offset: isize, name: &str) -> Option<Address<V>> {
+    let object = ELFObject::parse(elf).ok()?;
+    object.lookup(name).map(|a| Address::from(a) + offset)
+}
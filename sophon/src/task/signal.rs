@@ -0,0 +1,167 @@
+use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+use interrupt::UninterruptibleMutex;
+use klib::proc::{Process, PID};
+use klib::task::TaskId;
+use spin::Mutex;
+use syscall::Signal;
+
+use super::proc::PROCESS_MANAGER;
+use super::sched::SCHEDULER;
+use super::sync::WaitQueue;
+
+/// Tasks blocked because their process is stopped.
+static STOPPED_TASKS: Mutex<BTreeMap<PID, Vec<TaskId>>> = Mutex::new(BTreeMap::new());
+
+/// Group signals sent from interrupt handlers, for the signal worker to deliver.
+static DEFERRED: Mutex<[Option<(PID, Signal)>; MAX_DEFERRED]> = Mutex::new([None; MAX_DEFERRED]);
+const MAX_DEFERRED: usize = 16;
+static DEFERRED_WAITERS: WaitQueue = WaitQueue::new();
+
+const fn mask(signal: Signal) -> u32 {
+    1 << signal as usize
+}
+
+/// Send a signal to a process.
+///
+/// Stop and terminate signals take effect when the process returns to user space.
+/// `Continue` takes effect immediately.
+/// Init has no default signal actions. Its signals stay pending until it takes them.
+pub fn send(proc: &Process, signal: Signal) {
+    let _guard = interrupt::uninterruptible();
+    match signal {
+        Signal::Continue => {
+            proc.pending_signals.fetch_and(
+                !(mask(Signal::Stop) | mask(Signal::TerminalStop)),
+                Ordering::SeqCst,
+            );
+            resume(proc);
+        }
+        _ => {
+            proc.pending_signals
+                .fetch_or(mask(signal), Ordering::SeqCst);
+            // Interrupt `wait`.
+            let child_waiters = proc.child_waiters.downcast_ref::<WaitQueue>().unwrap();
+            child_waiters.wake_all();
+            if proc.id == PID::INIT {
+                // Init takes its signals with `take_pending`.
+                return;
+            }
+            if signal.is_fatal() {
+                // A stopped or sleeping process must be resumed to terminate.
+                resume(proc);
+                for task in proc.threads.lock().iter() {
+                    SCHEDULER.cancel_sleep(*task);
+                }
+            }
+        }
+    }
+}
+
+/// Send a signal to all the processes in a group. Returns the number of processes signaled.
+pub fn send_to_group(pgid: PID, signal: Signal) -> usize {
+    let procs = PROCESS_MANAGER.get_procs_in_group(pgid);
+    for proc in &procs {
+        send(proc, signal);
+    }
+    procs.len()
+}
+
+/// Send a signal to all the processes in a group, from the signal worker.
+///
+/// Safe in interrupt handlers: it neither allocates nor takes the process table lock.
+/// A signal that is already queued for the group is not queued twice.
+pub fn send_to_group_later(pgid: PID, signal: Signal) {
+    {
+        let mut deferred = DEFERRED.lock_uninterruptible();
+        let entry = Some((pgid, signal));
+        if !deferred.contains(&entry) {
+            match deferred.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => *slot = entry,
+                None => return,
+            }
+        }
+    }
+    DEFERRED_WAITERS.wake_all();
+}
+
+/// Wait for signals queued by `send_to_group_later`, and deliver them. Run by the signal worker.
+pub fn deliver_deferred() {
+    let mut signals = [None; MAX_DEFERRED];
+    DEFERRED_WAITERS.wait_until(|| {
+        let mut deferred = DEFERRED.lock_uninterruptible();
+        signals = core::mem::replace(&mut *deferred, [None; MAX_DEFERRED]);
+        signals.iter().any(Option::is_some)
+    });
+    for (pgid, signal) in signals.into_iter().flatten() {
+        send_to_group(pgid, signal);
+    }
+}
+
+fn resume(proc: &Process) {
+    let tasks = {
+        let mut stopped_tasks = STOPPED_TASKS.lock_uninterruptible();
+        proc.stopped.store(false, Ordering::SeqCst);
+        stopped_tasks.remove(&proc.id).unwrap_or_default()
+    };
+    for task in tasks {
+        SCHEDULER.unblock_task(task);
+    }
+}
+
+/// Handle the pending signals of the current process.
+/// Called right before the current task returns to user space.
+///
+/// Does not return if the process is terminated or stopped.
+pub fn handle_pending_signals() {
+    debug_assert!(!interrupt::is_enabled());
+    let Some(proc) = PROCESS_MANAGER.current_proc() else {
+        return;
+    };
+    if proc.id == PID::INIT {
+        // Signals have no default actions for init.
+        return;
+    }
+    let pending = proc.pending_signals.load(Ordering::SeqCst);
+    if pending == 0 && !proc.stopped.load(Ordering::SeqCst) {
+        return;
+    }
+    if let Some(signal) = Signal::ALL
+        .iter()
+        .find(|s| s.is_fatal() && pending & mask(**s) != 0)
+    {
+        let exit_code = 128 + *signal as isize;
+        drop(proc);
+        PROCESS_MANAGER.exit_current_proc(exit_code);
+        SCHEDULER.schedule();
+    }
+    let stop = mask(Signal::Stop) | mask(Signal::TerminalStop);
+    let stopped = {
+        // Checked again with the lock held, in case a `Continue` has just arrived.
+        let mut stopped_tasks = STOPPED_TASKS.lock();
+        let pending = proc.pending_signals.fetch_and(!stop, Ordering::SeqCst);
+        if pending & stop != 0 || proc.stopped.load(Ordering::SeqCst) {
+            proc.stopped.store(true, Ordering::SeqCst);
+            let task = SCHEDULER.get_current_task_id().unwrap();
+            stopped_tasks.entry(proc.id).or_default().push(task);
+            SCHEDULER.mark_current_task_as_blocked();
+            true
+        } else {
+            false
+        }
+    };
+    if stopped {
+        // Let the parent know.
+        let exit_waiters = proc.exit_waiters.downcast_ref::<WaitQueue>().unwrap();
+        exit_waiters.wake_all();
+        drop(proc);
+        SCHEDULER.schedule();
+    }
+}
+
+/// Take and clear the pending signals of the current process.
+pub fn take_pending() -> u32 {
+    let proc = PROCESS_MANAGER.current_proc().unwrap();
+    proc.pending_signals.swap(0, Ordering::SeqCst)
+}
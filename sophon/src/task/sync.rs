@@ -1,59 +1,323 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use alloc::collections::VecDeque;
 use atomic::Atomic;
-use crossbeam::queue::SegQueue;
 use klib::task::TaskId;
+use spin::Mutex;
 
+use super::lockdep;
 use super::sched::SCHEDULER;
 
+/// A queue of blocked tasks, waiting for some condition to become true.
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<TaskId>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Block the current task until `condition` returns true.
+    ///
+    /// `condition` is checked with the queue locked, and the waker takes the same lock.
+    /// So a wake-up between checking the condition and blocking is never lost.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        let _guard = interrupt::uninterruptible();
+        let me = SCHEDULER.get_current_task_id().unwrap();
+        loop {
+            let mut waiters = self.waiters.lock();
+            if condition() {
+                return;
+            }
+            waiters.push_back(me);
+            SCHEDULER.block_current_task(waiters);
+        }
+    }
+
+    /// Block the current task once, unless `condition` returns true, as in `wait_until`.
+    ///
+    /// Returns after a wake-up, or after `timeout_ns` nanoseconds if given.
+    /// Returns false if the timeout passed first.
+    pub fn wait_once(&self, condition: impl FnOnce() -> bool, timeout_ns: Option<u64>) -> bool {
+        let _guard = interrupt::uninterruptible();
+        let me = SCHEDULER.get_current_task_id().unwrap();
+        let mut waiters = self.waiters.lock();
+        if condition() {
+            return true;
+        }
+        waiters.push_back(me);
+        match timeout_ns {
+            Some(timeout_ns) => SCHEDULER.sleep_current_task_with(timeout_ns, waiters),
+            None => SCHEDULER.block_current_task(waiters),
+        }
+        // Still queued: not woken up by `wake_one` or `wake_all`.
+        let mut waiters = self.waiters.lock();
+        match waiters.iter().position(|t| *t == me) {
+            Some(i) => {
+                waiters.remove(i);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Wake up the longest waiting task. Returns false if the queue is empty.
+    pub fn wake_one(&self) -> bool {
+        let _guard = interrupt::uninterruptible();
+        let Some(task) = self.waiters.lock().pop_front() else {
+            return false;
+        };
+        SCHEDULER.unblock_task(task);
+        true
+    }
+
+    /// Wake up all the waiting tasks. Returns the number of tasks woken up.
+    pub fn wake_all(&self) -> usize {
+        let _guard = interrupt::uninterruptible();
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+        let n = waiters.len();
+        for task in waiters {
+            SCHEDULER.unblock_task(task);
+        }
+        n
+    }
+}
+
+/// A sleeping lock without data. Tasks waiting for the lock are blocked instead of spinning.
+///
+/// Unlike spin locks, the lock holder can block or be preempted.
+/// Use this for long critical sections. Must not be used in interrupt handlers.
+pub struct RawKMutex {
+    name: &'static str,
+    owner: Atomic<Option<TaskId>>,
+    waiters: WaitQueue,
+}
+
+impl RawKMutex {
+    /// Create a lock. Locks with the same `name` are in the same class for lock-order checking.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            owner: Atomic::new(None),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    fn current_task() -> TaskId {
+        // Before the scheduler starts, all the code runs in the boot task.
+        SCHEDULER.get_current_task_id().unwrap_or(TaskId::NULL)
+    }
+
+    fn try_acquire(&self, me: TaskId) -> bool {
+        self.owner
+            .compare_exchange(None, Some(me), Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    pub fn lock(&self) {
+        let me = Self::current_task();
+        if self.owner.load(Ordering::SeqCst) == Some(me) {
+            panic!("Deadlock: {:?} is locking {:?} recursively", me, self.name);
+        }
+        lockdep::before_acquire(me, self.name);
+        if me == TaskId::NULL {
+            while !self.try_acquire(me) {
+                core::hint::spin_loop();
+            }
+        } else {
+            self.waiters.wait_until(|| self.try_acquire(me));
+        }
+        lockdep::after_acquire(me, self.name);
+    }
+
+    pub fn try_lock(&self) -> bool {
+        let me = Self::current_task();
+        if !self.try_acquire(me) {
+            return false;
+        }
+        lockdep::after_acquire(me, self.name);
+        true
+    }
+
+    pub fn unlock(&self) {
+        let owner = self.owner.load(Ordering::SeqCst);
+        debug_assert_eq!(owner, Some(Self::current_task()));
+        if let Some(owner) = owner {
+            lockdep::release(owner, self.name);
+        }
+        self.owner.store(None, Ordering::SeqCst);
+        self.waiters.wake_one();
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.owner.load(Ordering::SeqCst).is_some()
+    }
+}
+
+/// A sleeping mutex. See `RawKMutex`.
+pub struct KMutex<T: ?Sized> {
+    raw: RawKMutex,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for KMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for KMutex<T> {}
+
+impl<T> KMutex<T> {
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            raw: RawKMutex::new(name),
+            data: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> KMutex<T> {
+    pub fn lock(&self) -> KMutexGuard<'_, T> {
+        self.raw.lock();
+        KMutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<KMutexGuard<'_, T>> {
+        if self.raw.try_lock() {
+            Some(KMutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+}
+
+pub struct KMutexGuard<'a, T: ?Sized> {
+    mutex: &'a KMutex<T>,
+}
+
+impl<T: ?Sized> Deref for KMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for KMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for KMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.raw.unlock();
+    }
+}
+
+/// A counting semaphore. Tasks are blocked when no permits are available.
+///
+/// Permits are not owned by tasks, so semaphores are not covered by lock-order checking.
+pub struct KSemaphore {
+    permits: AtomicUsize,
+    waiters: WaitQueue,
+}
+
+impl KSemaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Take a permit. Block until one is available.
+    pub fn acquire(&self) {
+        self.waiters.wait_until(|| self.try_acquire());
+    }
+
+    /// Return a permit, and wake up a waiting task.
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::SeqCst);
+        self.waiters.wake_one();
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::SeqCst)
+    }
+}
+
+/// A monitor for kernel modules: a sleeping lock, plus a condition to wait on.
 pub struct SysMonitor {
-    is_locked: AtomicBool,
-    #[allow(unused)]
-    owner: Atomic<TaskId>,
-    blocked_tasks: SegQueue<TaskId>,
-    waiting_tasks: SegQueue<TaskId>,
+    lock: RawKMutex,
+    /// Incremented by each `notify_all`, so that waiters can tell whether they are notified.
+    epoch: AtomicUsize,
+    /// The epoch when the lock holder got the lock, or last returned from `wait`.
+    /// Only accessed by the lock holder.
+    seen: AtomicUsize,
+    waiters: WaitQueue,
 }
 
 impl SysMonitor {
     pub fn new() -> Self {
         Self {
-            is_locked: AtomicBool::new(false),
-            owner: Atomic::new(TaskId::NULL),
-            blocked_tasks: SegQueue::new(),
-            waiting_tasks: SegQueue::new(),
+            lock: RawKMutex::new("SysMonitor"),
+            epoch: AtomicUsize::new(0),
+            seen: AtomicUsize::new(0),
+            waiters: WaitQueue::new(),
         }
     }
 
     pub fn lock(&self) {
-        let _guard = interrupt::uninterruptible();
-        let requester = SCHEDULER.get_current_task_id().unwrap();
-        while self.is_locked.fetch_or(true, Ordering::SeqCst) {
-            self.blocked_tasks.push(requester);
-            SCHEDULER.block_current_task();
-        }
+        self.lock.lock();
+        self.seen
+            .store(self.epoch.load(Ordering::SeqCst), Ordering::SeqCst);
     }
 
     pub fn unlock(&self) {
-        let _guard = interrupt::uninterruptible();
-        self.is_locked.store(false, Ordering::SeqCst);
-        while let Some(t) = self.blocked_tasks.pop() {
-            SCHEDULER.unblock_task(t);
-        }
+        self.lock.unlock();
     }
 
+    /// Release the lock and wait for a `notify_all`. The lock is acquired again before returning.
+    ///
+    /// Returns at once if there was a `notify_all` since the lock was acquired,
+    /// so that a notifier without the lock is never missed.
     pub fn wait(&self) {
-        let _guard = interrupt::uninterruptible();
-        let requester = SCHEDULER.get_current_task_id().unwrap();
-        self.waiting_tasks.push(requester);
-        self.unlock();
-        SCHEDULER.block_current_task();
+        let epoch = self.seen.load(Ordering::SeqCst);
+        self.lock.unlock();
+        self.waiters
+            .wait_until(|| self.epoch.load(Ordering::SeqCst) != epoch);
         self.lock();
     }
 
+    /// Wake up all the waiting tasks.
+    ///
+    /// Does not need the lock, and can be called from interrupt handlers.
+    /// The waiters must re-check their condition, as the notifier may not hold the lock.
     pub fn notify_all(&self) {
-        let _guard = interrupt::uninterruptible();
-        while let Some(t) = self.waiting_tasks.pop() {
-            SCHEDULER.unblock_task(t);
-        }
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        self.waiters.wake_all();
+    }
+}
+
+#[test]
+fn kmutex_test() {
+    let mutex = KMutex::new("test", 0usize);
+    {
+        let mut guard = mutex.lock();
+        *guard += 1;
+        assert!(mutex.try_lock().is_none());
     }
+    assert_eq!(*mutex.try_lock().unwrap(), 1);
+    let sem = KSemaphore::new(1);
+    sem.acquire();
+    assert!(!sem.try_acquire());
+    sem.release();
+    assert_eq!(sem.available_permits(), 1);
 }
@@ -2,12 +2,21 @@ use core::sync::atomic::Ordering;
 
 use super::proc::PROCESS_MANAGER;
 use super::sched::SCHEDULER;
+use super::user::UserEntry;
 use crate::arch::Arch;
 use crate::arch::TargetArch;
-use crate::task::sync::SysMonitor;
-use klib::proc::PID;
+use crate::memory::kernel::KERNEL_HEAP;
+use crate::memory::physical::PHYSICAL_MEMORY;
+use crate::task::signal;
+use crate::task::sync::WaitQueue;
+use crate::utils::trace;
+use alloc::sync::Arc;
+use klib::proc::{Process, PID};
+use klib::task::{RunState, SchedInfo, TaskId};
+use memory::address::Address;
 use memory::page::{PageSize, Size4K};
-use syscall::Syscall;
+use syscall::trace::Kind;
+use syscall::{MemInfo, ProcInfo, ProcState, Signal, SlabInfo, Syscall, UtsName, ZoneInfo};
 
 // =====================
 // ===   Syscalls   ===
@@ -22,7 +31,8 @@ pub fn handle_syscall<const PRIVILEGED: bool>(
     e: usize,
 ) -> isize {
     let syscall: Syscall = unsafe { core::mem::transmute(syscall_id) };
-    match syscall {
+    trace::begin(Kind::Syscall, syscall_id);
+    let result = match syscall {
         Syscall::Log => log(a, b, c, d, e),
         Syscall::ModuleCall => module_request::<PRIVILEGED>(a, b, c, d, e),
         Syscall::WaitPid => waitpid(a, b, c, d, e),
@@ -38,7 +48,61 @@ pub fn handle_syscall<const PRIVILEGED: bool>(
         Syscall::ThreadExit => thread_exit(a, b, c, d, e),
         Syscall::Halt => halt(a, b, c, d, e),
         Syscall::Yield => _yield(a, b, c, d, e),
-    }
+        Syscall::Nice => nice(a, b, c, d, e),
+        Syscall::SetPriority => set_priority(a, b, c, d, e),
+        Syscall::SetRtPriority => set_rt_priority(a, b, c, d, e),
+        Syscall::Uptime => TargetArch::uptime().as_nanos() as isize,
+        Syscall::CpuTime => SCHEDULER.current_task_cpu_time() as isize,
+        Syscall::NumCores => TargetArch::num_cores() as isize,
+        Syscall::IdleTime => SCHEDULER.idle_time(a).map(|t| t as isize).unwrap_or(-1),
+        Syscall::Kill => kill(a, b, c, d, e),
+        Syscall::SetPgid => set_pgid(a, b, c, d, e),
+        Syscall::GetPgid => get_proc_or_current(a)
+            .map(|p| p.pgid.load(Ordering::SeqCst) as isize)
+            .unwrap_or(-1),
+        Syscall::ListProcs => list_procs(a, b, c, d, e),
+        Syscall::MemInfo => meminfo(a, b, c, d, e),
+        Syscall::Uname => uname(a, b, c, d, e),
+        Syscall::Sleep => {
+            SCHEDULER.sleep_current_task(a as u64);
+            0
+        }
+        Syscall::ListModules => list_modules(a, b, c, d, e),
+        Syscall::ReadKernelLog => {
+            let buf = unsafe { core::slice::from_raw_parts_mut(b as *mut u8, c) };
+            crate::utils::klog::read(a as u64, buf) as isize
+        }
+        Syscall::Wait => wait(a, b, c, d, e),
+        Syscall::TakeSignals => signal::take_pending() as isize,
+        Syscall::Power => power(a, b, c, d, e),
+        Syscall::SpawnThread => {
+            let proc = PROCESS_MANAGER.current_proc().unwrap();
+            let entry = unsafe { core::mem::transmute::<usize, UserEntry>(a) };
+            let task = PROCESS_MANAGER.spawn_user_thread(proc, entry, b, Address::from(c));
+            task.0 as isize
+        }
+        Syscall::JoinThread => {
+            PROCESS_MANAGER.join_thread(TaskId(a));
+            0
+        }
+        Syscall::GetPid => PROCESS_MANAGER.current_proc_id().unwrap().0 as isize,
+        Syscall::SlabInfo => slab_info(a, b, c, d, e),
+        Syscall::HeapDump => {
+            if !cfg!(feature = "heap_debug") {
+                return -1;
+            }
+            crate::memory::kernel::dump_heap();
+            0
+        }
+        Syscall::ZoneInfo => zone_info(a, b, c, d, e),
+        Syscall::SetMemLimit => set_mem_limit(a, b, c, d, e),
+        Syscall::SetLogLevel => set_log_level(a, b, c, d, e),
+        Syscall::GetRandom => get_random(a, b, c, d, e),
+        Syscall::FutexWait => futex_wait(a, b, c, d, e),
+        Syscall::FutexWake => futex_wake(a, b, c, d, e),
+    };
+    trace::end(Kind::Syscall, syscall_id);
+    result
 }
 
 fn log(a: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
@@ -60,24 +124,91 @@ fn module_request<const PRIVILEGED: bool>(
     crate::modules::raw_module_call(s, PRIVILEGED, [b, c, d, e])
 }
 
-fn waitpid(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+fn waitpid(a: usize, b: usize, c: usize, _: usize, _: usize) -> isize {
     let pid = PID(a);
     let Some(proc) = PROCESS_MANAGER.get_proc_by_id(pid) else {
         return -1;
     };
     let exit_code_pointer = b as *mut isize;
-    let monitor = proc.monitor.downcast_ref::<SysMonitor>().unwrap();
-    monitor.lock();
-    while !proc.is_zombie.load(Ordering::SeqCst) {
-        monitor.wait();
+    let report_stop = c & syscall::WEXITED == 0;
+    let changed = || {
+        proc.is_zombie.load(Ordering::SeqCst)
+            || (report_stop && proc.stopped.load(Ordering::SeqCst))
+    };
+    if c & syscall::WNOHANG != 0 {
+        if !changed() {
+            return 2;
+        }
+    } else {
+        let exit_waiters = proc.exit_waiters.downcast_ref::<WaitQueue>().unwrap();
+        exit_waiters.wait_until(changed);
+    }
+    if !proc.is_zombie.load(Ordering::SeqCst) {
+        return 1;
     }
     unsafe {
         *exit_code_pointer = proc.exit_code.load(Ordering::SeqCst);
     }
-    monitor.unlock();
+    if PROCESS_MANAGER.current_proc_id() == Some(PID(proc.parent.load(Ordering::SeqCst))) {
+        PROCESS_MANAGER.reap(&proc);
+    }
     0
 }
 
+fn wait(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let exit_code_pointer = a as *mut isize;
+    let proc = PROCESS_MANAGER.current_proc().unwrap();
+    let mut result = None;
+    let mut check = || {
+        let children = PROCESS_MANAGER.get_children(proc.id);
+        result = if let Some(child) = children.iter().find(|c| c.is_zombie.load(Ordering::SeqCst)) {
+            Some(Ok(child.clone()))
+        } else if children.is_empty() {
+            Some(Err(-1))
+        } else if proc.pending_signals.load(Ordering::SeqCst) != 0 {
+            Some(Err(-2))
+        } else {
+            None
+        };
+        result.is_some()
+    };
+    if b & syscall::WNOHANG != 0 {
+        if !check() {
+            return 0;
+        }
+    } else {
+        let child_waiters = proc.child_waiters.downcast_ref::<WaitQueue>().unwrap();
+        child_waiters.wait_until(check);
+    }
+    match result.unwrap() {
+        Ok(child) => {
+            unsafe {
+                *exit_code_pointer = child.exit_code.load(Ordering::SeqCst);
+            }
+            PROCESS_MANAGER.reap(&child);
+            child.id.0 as isize
+        }
+        Err(e) => e,
+    }
+}
+
+fn power(a: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+    if PROCESS_MANAGER.current_proc_id() != Some(PID::INIT) {
+        return -1;
+    }
+    match a {
+        0 => {
+            info!("Power off");
+            TargetArch::halt(0)
+        }
+        1 => {
+            info!("Reboot");
+            TargetArch::reboot()
+        }
+        _ => -1,
+    }
+}
+
 fn fork(_: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
     let proc = PROCESS_MANAGER.current_proc().unwrap();
     let child = PROCESS_MANAGER.fork(proc);
@@ -91,8 +222,8 @@ fn exec(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
     PROCESS_MANAGER.exec(path, args)
 }
 
-fn exit(_: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
-    PROCESS_MANAGER.exit_current_proc();
+fn exit(a: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+    PROCESS_MANAGER.exit_current_proc(a as isize);
     SCHEDULER.schedule()
 }
 
@@ -107,5 +238,289 @@ fn halt(a: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
 }
 
 fn _yield(_: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
-    SCHEDULER.schedule()
+    SCHEDULER.yield_current_task()
+}
+
+fn get_proc_or_current(pid: usize) -> Option<Arc<Process>> {
+    if pid == 0 {
+        PROCESS_MANAGER.current_proc()
+    } else {
+        PROCESS_MANAGER.get_proc_by_id(PID(pid))
+    }
+}
+
+/// Whether the current process may change the scheduling parameters of `proc`:
+/// itself or one of its descendants.
+fn may_reschedule(proc: &Process) -> bool {
+    let Some(current) = PROCESS_MANAGER.current_proc_id() else {
+        return false;
+    };
+    let mut pid = proc.id;
+    loop {
+        if pid == current {
+            return true;
+        }
+        let Some(p) = PROCESS_MANAGER.get_proc_by_id(pid) else {
+            return false;
+        };
+        pid = PID(p.parent.load(Ordering::SeqCst));
+        if pid == PID::NULL {
+            return false;
+        }
+    }
+}
+
+fn is_init() -> bool {
+    PROCESS_MANAGER.current_proc_id() == Some(PID::INIT)
+}
+
+fn set_nice(proc: &Process, nice: i8) {
+    for t in proc.threads.lock().iter() {
+        let task = SCHEDULER.get_task_by_id(*t);
+        task.sched.nice.store(nice, Ordering::SeqCst);
+    }
+}
+
+fn nice(a: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+    let inc = a as isize;
+    let proc = PROCESS_MANAGER.current_proc().unwrap();
+    let task = SCHEDULER.get_current_task().unwrap();
+    let old = task.sched.nice.load(Ordering::SeqCst) as isize;
+    // Only init may go below 0.
+    let min = if is_init() {
+        SchedInfo::MIN_NICE as isize
+    } else {
+        old.min(0)
+    };
+    let nice = (old + inc).clamp(min, SchedInfo::MAX_NICE as isize);
+    set_nice(&proc, nice as i8);
+    nice
+}
+
+fn set_priority(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let nice = b as isize;
+    if nice < SchedInfo::MIN_NICE as isize || nice > SchedInfo::MAX_NICE as isize {
+        return -1;
+    }
+    let Some(proc) = get_proc_or_current(a) else {
+        return -1;
+    };
+    if !may_reschedule(&proc) {
+        return -1;
+    }
+    if nice < 0 && !is_init() {
+        let old = proc
+            .threads
+            .lock()
+            .first()
+            .map_or(0, |t| SCHEDULER.get_task_by_id(*t).sched.nice.load(Ordering::SeqCst));
+        if nice < old as isize {
+            return -1;
+        }
+    }
+    set_nice(&proc, nice as i8);
+    0
+}
+
+fn set_rt_priority(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    if b > SchedInfo::MAX_RT_PRIORITY as usize {
+        return -1;
+    }
+    let Some(proc) = get_proc_or_current(a) else {
+        return -1;
+    };
+    if !may_reschedule(&proc) {
+        return -1;
+    }
+    let threads = proc.threads.lock();
+    if !is_init() {
+        let old = threads
+            .first()
+            .map_or(0, |t| SCHEDULER.get_task_by_id(*t).sched.rt_priority.load(Ordering::SeqCst));
+        if b > old as usize {
+            return -1;
+        }
+    }
+    for t in threads.iter() {
+        let task = SCHEDULER.get_task_by_id(*t);
+        task.sched.rt_priority.store(b as u8, Ordering::SeqCst);
+    }
+    0
+}
+
+fn kill(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let pid = a as isize;
+    let Some(signal) = Signal::from_usize(b) else {
+        return -1;
+    };
+    if pid < 0 {
+        if signal::send_to_group(PID(-pid as usize), signal) == 0 {
+            return -1;
+        }
+    } else {
+        let Some(proc) = PROCESS_MANAGER.get_proc_by_id(PID(pid as usize)) else {
+            return -1;
+        };
+        signal::send(&proc, signal);
+    }
+    0
+}
+
+fn set_pgid(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let Some(proc) = get_proc_or_current(a) else {
+        return -1;
+    };
+    let pgid = if b == 0 { proc.id.0 } else { b };
+    proc.pgid.store(pgid, Ordering::SeqCst);
+    0
+}
+
+fn set_mem_limit(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let Some(proc) = get_proc_or_current(a) else {
+        return -1;
+    };
+    let pages = if b == usize::MAX {
+        usize::MAX
+    } else {
+        b >> Size4K::LOG_BYTES
+    };
+    proc.mem.page_limit.store(pages, Ordering::SeqCst);
+    0
+}
+
+fn set_log_level(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let module: &str = unsafe { *(a as *const &str) };
+    let Some(level) = syscall::klog::level_filter_from_usize(b) else {
+        return -1;
+    };
+    crate::utils::klog::set_level(module, level);
+    0
+}
+
+fn get_random(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let buf = unsafe { core::slice::from_raw_parts_mut(a as *mut u8, b) };
+    for chunk in buf.chunks_mut(8) {
+        let bytes = crate::utils::random::random_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+    0
+}
+
+fn futex_wait(a: usize, b: usize, c: usize, _: usize, _: usize) -> isize {
+    if a == 0 || a % 4 != 0 {
+        return -1;
+    }
+    let pid = PROCESS_MANAGER.current_proc_id().unwrap();
+    let timeout_ns = (c != usize::MAX).then_some(c as u64);
+    if super::futex::wait(pid, a, b as u32, timeout_ns) {
+        0
+    } else {
+        -1
+    }
+}
+
+fn futex_wake(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let pid = PROCESS_MANAGER.current_proc_id().unwrap();
+    super::futex::wake(pid, a, b) as isize
+}
+
+fn proc_info(proc: &Process) -> ProcInfo {
+    let mut info = ProcInfo::EMPTY;
+    info.pid = proc.id.0;
+    info.ppid = proc.parent.load(Ordering::SeqCst);
+    info.pgid = proc.pgid.load(Ordering::SeqCst);
+    let threads = proc.threads.lock().clone();
+    let tasks = threads.iter().filter_map(|t| SCHEDULER.find_task_by_id(*t));
+    let mut running = false;
+    for task in tasks {
+        info.threads += 1;
+        info.cpu_time += task.sched.cpu_time.load(Ordering::SeqCst);
+        running |= task.state.load(Ordering::SeqCst) != RunState::Blocked;
+    }
+    info.state = if proc.is_zombie.load(Ordering::SeqCst) {
+        ProcState::Zombie
+    } else if proc.stopped.load(Ordering::SeqCst) {
+        ProcState::Stopped
+    } else if running {
+        ProcState::Running
+    } else {
+        ProcState::Sleeping
+    };
+    info.memory = proc.mem.pages.load(Ordering::SeqCst) << Size4K::LOG_BYTES;
+    syscall::set_str_field(&mut info.name, &proc.name.lock());
+    info
+}
+
+fn list_procs(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let buf = unsafe { core::slice::from_raw_parts_mut(a as *mut ProcInfo, b) };
+    let procs = PROCESS_MANAGER.get_all_procs();
+    for (slot, proc) in buf.iter_mut().zip(&procs) {
+        *slot = proc_info(proc);
+    }
+    procs.len() as isize
+}
+
+fn meminfo(a: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+    let (total, free) = PHYSICAL_MEMORY.usage();
+    unsafe {
+        *(a as *mut MemInfo) = MemInfo { total, free };
+    }
+    0
+}
+
+fn slab_info(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let buf = unsafe { core::slice::from_raw_parts_mut(a as *mut SlabInfo, b) };
+    let stats = KERNEL_HEAP.slab_stats();
+    for (slot, s) in buf.iter_mut().zip(&stats) {
+        *slot = SlabInfo {
+            size: s.size,
+            allocs: s.allocs,
+            frees: s.frees,
+            slabs: s.slabs,
+            depot_transfers: s.depot_transfers,
+        };
+    }
+    stats.len() as isize
+}
+
+fn zone_info(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let buf = unsafe { core::slice::from_raw_parts_mut(a as *mut ZoneInfo, b) };
+    let stats = PHYSICAL_MEMORY.zone_stats();
+    for (slot, s) in buf.iter_mut().zip(&stats) {
+        *slot = ZoneInfo {
+            total: s.total,
+            free: s.free,
+            used: s.used(),
+            ..Default::default()
+        };
+        syscall::set_str_field(&mut slot.name, s.zone.name());
+    }
+    stats.len() as isize
+}
+
+fn uname(a: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+    let uts = unsafe { &mut *(a as *mut UtsName) };
+    syscall::set_str_field(&mut uts.sysname, "Sophon");
+    syscall::set_str_field(&mut uts.nodename, "sophon");
+    syscall::set_str_field(&mut uts.release, env!("CARGO_PKG_VERSION"));
+    let machine = if cfg!(target_arch = "aarch64") {
+        "aarch64"
+    } else {
+        "x86_64"
+    };
+    syscall::set_str_field(&mut uts.machine, machine);
+    0
+}
+
+fn list_modules(a: usize, b: usize, _: usize, _: usize, _: usize) -> isize {
+    let buf = unsafe { core::slice::from_raw_parts_mut(a as *mut u8, b) };
+    let mut len = 0;
+    for name in crate::modules::names() {
+        let line = name.as_bytes().iter().chain(b"\n");
+        for (slot, byte) in buf[len..].iter_mut().zip(line) {
+            *slot = *byte;
+            len += 1;
+        }
+    }
+    len as isize
 }
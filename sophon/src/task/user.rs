@@ -1,39 +1,132 @@
-use core::{iter::Step, sync::atomic::Ordering};
+use core::{iter::Step, ops::Range, sync::atomic::Ordering};
 
-use alloc::{ffi::CString, sync::Arc, vec::Vec};
+use alloc::{collections::BTreeMap, ffi::CString, sync::Arc, vec::Vec};
+use elf_loader::Segment;
 use interrupt::UninterruptibleMutex;
-use klib::proc::Process;
+use klib::proc::{MemSpace, Process, TLSTemplate};
 use memory::{
     address::{Address, V},
-    page::{Page, PageSize, Size4K},
-    page_table::{PageFlags, PageTable, L4},
+    page::{Frame, Page, PageSize, Size2M, Size4K},
+    page_table::{PageFlags, PageTable},
 };
+use spin::Lazy;
 
 use crate::arch::ArchContext;
 use crate::{
     arch::{Arch, TargetArch},
-    memory::{
-        kernel::{KERNEL_MEMORY_MAPPER, KERNEL_MEMORY_RANGE},
-        physical::PHYSICAL_MEMORY,
-    },
+    memory::{aslr, kernel::KERNEL_MEMORY_MAPPER, oom, physical::PHYSICAL_MEMORY},
     task::PROCESS_MANAGER,
+    utils::cmdline,
 };
 
 use super::sched::SCHEDULER;
+use super::shlib::{self, SharedLibrary};
 
 const USER_STACK_START: Address<V> = Address::new(0x111900000);
-const USER_STACK_PAGES: usize = 4; // Too many???
+/// Address space reserved for each stack. The lowest page is never mapped, so that a stack
+/// overflow faults instead of running into other memory.
+const USER_STACK_REGION_SIZE: usize = 8 << 20;
+/// Pages mapped when the stack is set up. Below them, the stack grows on demand.
+const USER_STACK_PAGES: usize = 4;
 const USER_STACK_SIZE: usize = USER_STACK_PAGES * Size4K::BYTES;
 
-fn load_elf(page_table: &mut PageTable, elf_data: &[u8]) -> extern "C" fn(isize, *const *const u8) {
-    let base = Address::<V>::from(0x200000);
+/// The largest stack size, in bytes. Set with the `stack_limit=<KB>` boot option.
+static USER_STACK_LIMIT: Lazy<usize> = Lazy::new(|| {
+    let max = USER_STACK_REGION_SIZE - Size4K::BYTES;
+    cmdline::get("stack_limit")
+        .and_then(|kb| kb.parse::<usize>().ok())
+        .map_or(max, |kb| {
+            (kb << 10)
+                .next_multiple_of(Size4K::BYTES)
+                .clamp(USER_STACK_SIZE, max)
+        })
+});
+/// With ASLR, the stacks start up to this many bytes above `USER_STACK_START`.
+const USER_STACK_RANDOM_RANGE: usize = 1 << 36;
+
+/// The entry point of a user program or thread.
+pub type UserEntry = extern "C" fn(isize, *const *const u8);
+
+/// Executables are loaded here.
+const USER_ELF_BASE: Address<V> = Address::new(0x200000);
+/// With ASLR, position-independent executables are loaded up to this many bytes above
+/// `USER_ELF_BASE`. Together with the maximum image size, this keeps them below the stacks.
+const USER_ELF_RANDOM_RANGE: usize = 0xc000_0000;
+
+/// With ASLR, the heap starts up to this many bytes above the start of the user space.
+/// It stays 2M-aligned, so that large `sbrk` requests can be backed by 2M pages.
+const USER_HEAP_RANDOM_RANGE: usize = 1 << 40;
+
+/// Set the page permissions of loaded segments.
+/// A page shared by two segments gets the permissions of both, which must not break W^X.
+pub(super) fn protect_segments(
+    page_table: &mut PageTable,
+    segments: &[Segment],
+) -> Result<(), &'static str> {
+    let mut permissions = BTreeMap::<Page, (bool, bool)>::new();
+    for segment in segments {
+        for page in segment.pages.clone() {
+            let (writable, executable) = permissions.entry(page).or_default();
+            *writable |= segment.writable;
+            *executable |= segment.executable;
+        }
+    }
+    let _kernel_page_table = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+    for (page, (writable, executable)) in permissions {
+        if writable && executable {
+            return Err("writable and executable page");
+        }
+        let frame = Frame::new(
+            page_table
+                .translate(page.start())
+                .ok_or("page not mapped")?,
+        );
+        let flags = PageFlags::user_flags_4k(writable, executable);
+        page_table.map(page, frame, flags, &PHYSICAL_MEMORY);
+    }
+    crate::task::ipi::tlb_shootdown();
+    Ok(())
+}
+
+fn load_elf(
+    page_table: &mut PageTable,
+    mem: &MemSpace,
+    elf_data: &[u8],
+    libraries: &[Arc<SharedLibrary>],
+) -> Result<(UserEntry, Option<TLSTemplate>), &'static str> {
     assert!(PageTable::is_set(page_table));
-    let entry = elf_loader::ELFLoader::load(elf_data, &mut |pages| {
+    let object = elf_loader::ELFObject::parse(elf_data)?;
+    let base = if object.is_position_independent() {
+        USER_ELF_BASE + aslr::random_offset(USER_ELF_RANDOM_RANGE, Size4K::BYTES)
+    } else {
+        USER_ELF_BASE
+    };
+    // Symbols are looked up in the program, then in the libraries in load order.
+    let first_page = object
+        .segments()
+        .map(|(range, _)| range.start)
+        .min()
+        .unwrap_or(0)
+        & !(Size4K::BYTES - 1);
+    let program_offset = base.as_usize() as isize - first_page as isize;
+    let resolve = |name: &str| {
+        shlib::lookup_in_program(elf_data, program_offset, name)
+            .or_else(|| libraries.iter().find_map(|l| l.lookup(name)))
+    };
+    for library in libraries {
+        library.load(page_table, mem, &resolve)?;
+    }
+    let mut image = base..base;
+    let mut map_pages = |pages: Range<Page>| {
         let start_page = Page::new(base);
         let num_pages = Page::steps_between(&pages.start, &pages.end).unwrap();
+        if !oom::charge(mem, num_pages) {
+            return Err("memory limit exceeded");
+        }
+        image = base..base + (num_pages << Size4K::LOG_BYTES);
         for (i, _) in pages.enumerate() {
             let page = Page::<Size4K>::forward(start_page, i);
-            let frame = PHYSICAL_MEMORY.acquire::<Size4K>().unwrap();
+            let frame = oom::acquire::<Size4K>().ok_or("out of memory")?;
             let _kernel_page_table = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
             page_table.map(
                 page,
@@ -43,36 +136,57 @@ fn load_elf(page_table: &mut PageTable, elf_data: &[u8]) -> extern "C" fn(isize,
             );
         }
         assert!(PageTable::is_set(page_table));
-        start_page..Page::<Size4K>::forward(start_page, num_pages)
-    })
-    .unwrap();
+        Ok(start_page..Page::<Size4K>::forward(start_page, num_pages))
+    };
+    let entry = if libraries.is_empty() {
+        elf_loader::ELFLoader::load(elf_data, &mut map_pages)?
+    } else {
+        elf_loader::ELFLoader::load_dynamic(elf_data, &mut map_pages, &resolve, false)?
+    };
     assert!(PageTable::is_set(page_table));
-    unsafe { core::mem::transmute(entry.entry) }
+    *mem.program.lock() = (image, program_offset);
+    protect_segments(page_table, &entry.segments)?;
+    let tls = entry.tls.map(|tls| TLSTemplate {
+        image: tls.image,
+        image_size: tls.image_size,
+        size: tls.size,
+        align: tls.align,
+    });
+    if tls.is_some_and(|tls| tls.size.saturating_add(tls.align) > USER_STACK_SIZE / 2) {
+        return Err("TLS does not fit on the stack");
+    }
+    Ok((unsafe { core::mem::transmute(entry.entry) }, tls))
 }
 
-fn initialize_user_space(proc: &Process, elf: &[u8]) -> extern "C" fn(isize, *const *const u8) {
+fn initialize_user_space(
+    proc: &Process,
+    elf: &[u8],
+    libraries: &[Arc<SharedLibrary>],
+) -> Result<UserEntry, &'static str> {
     // Initialize addr space, page table and load ELF
     debug_assert_eq!(proc.id, PROCESS_MANAGER.current_proc().unwrap().id);
     // User page table
     let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
-    if proc.mem.has_user_page_table() {
-        let _page_table = proc.mem.get_page_table();
-        // Buggy: this will release COW user pages but should not
-        // TODO: RC count for all shared user pages
-        // page_table.release(&PHYSICAL_MEMORY);
-    }
     let page_table = {
-        let page_table = PageTable::alloc(&PHYSICAL_MEMORY);
-        // Map kernel pages
-        let kernel_memory = KERNEL_MEMORY_RANGE;
-        let index = PageTable::<L4>::get_index(kernel_memory.start);
-        debug_assert_eq!(index, PageTable::<L4>::get_index(kernel_memory.end - 1));
-        page_table[index] = PageTable::get()[index].clone();
+        let page_table = if proc.mem.has_user_page_table() {
+            // Reuse the page table of the old program
+            let page_table = proc.mem.get_page_table();
+            crate::memory::utils::clear_user_space(page_table);
+            page_table
+        } else {
+            crate::memory::utils::alloc_user_page_table()
+        };
         // Set page table
         proc.mem.page_table.store(page_table, Ordering::SeqCst);
         proc.mem.has_user_page_table.store(true, Ordering::SeqCst);
+        proc.mem.pages.store(0, Ordering::SeqCst);
         proc.mem.highwater.store(
-            crate::memory::USER_SPACE_MEMORY_RANGE.start,
+            crate::memory::USER_SPACE_MEMORY_RANGE.start
+                + aslr::random_offset(USER_HEAP_RANDOM_RANGE, Size2M::BYTES),
+            Ordering::SeqCst,
+        );
+        proc.mem.stack_base.store(
+            USER_STACK_START + aslr::random_offset(USER_STACK_RANDOM_RANGE, Size4K::BYTES),
             Ordering::SeqCst,
         );
         page_table
@@ -86,37 +200,124 @@ fn initialize_user_space(proc: &Process, elf: &[u8]) -> extern "C" fn(isize, *co
         page_table as *const PageTable,
         PageTable::get() as *const PageTable
     );
-    let entry = load_elf(page_table, elf);
-    entry
+    let (entry, tls) = load_elf(page_table, &proc.mem, elf, libraries)?;
+    *proc.mem.tls.lock() = tls;
+    Ok(entry)
 }
 
-pub fn setup_user_stack(page_table: &mut PageTable) -> Address {
+/// Map the initial pages of the stack of the current thread. Returns the stack top.
+/// Fails if the process is out of memory, or over its memory limit.
+pub fn setup_user_stack(page_table: &mut PageTable) -> Result<Address, &'static str> {
     let tid = SCHEDULER.get_current_task_id().unwrap();
-    let i = PROCESS_MANAGER
-        .current_proc()
-        .unwrap()
+    let proc = PROCESS_MANAGER.current_proc().unwrap();
+    let i = proc
         .threads
         .lock_uninterruptible()
         .iter()
         .position(|t| *t == tid)
         .unwrap();
     // println!("User stack #{}", i);
-    let user_stack_start = USER_STACK_START + i * USER_STACK_SIZE;
+    let user_stack_end =
+        proc.mem.stack_base.load(Ordering::SeqCst) + (i + 1) * USER_STACK_REGION_SIZE;
+    let user_stack_start = user_stack_end - USER_STACK_SIZE;
+    if !oom::charge(&proc.mem, USER_STACK_PAGES) {
+        return Err("memory limit exceeded");
+    }
     for i in 0..USER_STACK_PAGES {
         let page = Step::forward(Page::<Size4K>::new(user_stack_start), i);
-        let frame = PHYSICAL_MEMORY.acquire::<Size4K>().unwrap();
+        let frame = oom::acquire::<Size4K>().ok_or("out of memory")?;
         let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
         page_table.map(page, frame, PageFlags::user_stack_flags(), &PHYSICAL_MEMORY);
     }
-    user_stack_start + USER_STACK_SIZE
+    Ok(user_stack_end)
+}
+
+/// The outcome of a user page fault, as far as the stacks are concerned.
+pub enum StackFault {
+    /// Not a stack address.
+    NotStack,
+    /// The stack is grown. Or out of memory, and the access is to be retried.
+    Handled,
+    /// The access is below the stack limit, or in the guard page,
+    /// or growing the stack would exceed the memory limit of the process.
+    Overflow,
+}
+
+/// Grow a stack of the current process down to a faulting address.
+pub fn handle_stack_fault(proc: &Process, address: Address<V>) -> StackFault {
+    let stack_base = proc.mem.stack_base.load(Ordering::SeqCst);
+    if address < stack_base {
+        return StackFault::NotStack;
+    }
+    let i = (address - stack_base) / USER_STACK_REGION_SIZE;
+    if i >= proc.threads.lock_uninterruptible().len() {
+        return StackFault::NotStack;
+    }
+    let end = stack_base + (i + 1) * USER_STACK_REGION_SIZE;
+    let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+    let page_table = proc.mem.get_page_table();
+    if page_table.translate(end - Size4K::BYTES).is_none() {
+        // The thread runs on a stack allocated by the process
+        return StackFault::NotStack;
+    }
+    if address < end - *USER_STACK_LIMIT {
+        return StackFault::Overflow;
+    }
+    let mut page = Page::<Size4K>::containing(address);
+    while page_table.translate(page.start()).is_none() {
+        if !oom::charge(&proc.mem, 1) {
+            return StackFault::Overflow;
+        }
+        let Some(frame) = oom::acquire::<Size4K>() else {
+            oom::uncharge(&proc.mem, 1);
+            break;
+        };
+        page_table.map(page, frame, PageFlags::user_stack_flags(), &PHYSICAL_MEMORY);
+        page = Page::forward(page, 1);
+    }
+    StackFault::Handled
+}
+
+/// Copy the TLS image of the process below `stack_top`,
+/// and point the thread pointer of the current task at the copy. Returns the new stack top.
+///
+/// AArch64 uses TLS variant 1: The thread pointer points to a 16-byte thread control block,
+/// followed by the TLS block.
+fn setup_tls(stack_top: Address) -> Address {
+    let proc = PROCESS_MANAGER.current_proc().unwrap();
+    let Some(tls) = *proc.mem.tls.lock() else {
+        return stack_top;
+    };
+    let align = usize::max(tls.align, 16);
+    let tcb_size = 16usize.next_multiple_of(align);
+    let block = (stack_top - tls.size).align_down(align);
+    let tp = block - tcb_size;
+    unsafe {
+        core::ptr::write_bytes(tp.as_mut_ptr::<u8>(), 0, tcb_size);
+        core::ptr::copy_nonoverlapping(
+            tls.image.as_ptr::<u8>(),
+            block.as_mut_ptr::<u8>(),
+            tls.image_size,
+        );
+        core::ptr::write_bytes(
+            (block + tls.image_size).as_mut_ptr::<u8>(),
+            0,
+            tls.size - tls.image_size,
+        );
+    }
+    let task = SCHEDULER.get_current_task().unwrap();
+    <TargetArch as Arch>::Context::of(&task).set_thread_pointer(tp.as_usize());
+    tp
 }
 
+/// Copy the arguments to the user stack.
+/// Returns `argc`, `argv` and the new stack top. `argv` is null-terminated.
 pub fn prepare_args(
     args: &[CString],
     mut stack_top: Address,
 ) -> (isize, *const *const u8, Address) {
     let argc = args.len();
-    let mut ptrs: Vec<*const u8> = Vec::with_capacity(argc);
+    let mut ptrs: Vec<*const u8> = Vec::with_capacity(argc + 1);
     for arg in args {
         let buf = arg.to_bytes_with_nul();
         let ptr = stack_top - buf.len();
@@ -124,15 +325,17 @@ pub fn prepare_args(
         ptrs.push(ptr.as_ptr());
         stack_top = ptr;
     }
-    for ptr in ptrs {
-        stack_top = stack_top - size_of::<*const u8>();
-        unsafe { stack_top.store(ptr) };
+    ptrs.push(core::ptr::null());
+    // The stack pointer must be 16-byte aligned.
+    stack_top = (stack_top - ptrs.len() * size_of::<*const u8>()).align_down(16);
+    unsafe {
+        core::ptr::copy_nonoverlapping(ptrs.as_ptr(), stack_top.as_mut_ptr(), ptrs.len());
     }
     (argc as isize, stack_top.as_ptr::<*const u8>(), stack_top)
 }
 
 fn enter_usermode(
-    entry: extern "C" fn(_argc: isize, _argv: *const *const u8),
+    entry: UserEntry,
     sp: Address,
     page_table: &mut PageTable,
     argc: isize,
@@ -155,10 +358,27 @@ pub fn exec(proc: Arc<Process>, elf: Vec<u8>, args: &[CString]) -> isize {
     if proc.threads.lock().len() != 1 {
         return -1;
     }
-    let entry = initialize_user_space(&proc, &elf);
+    // Find the shared libraries before the old address space is dropped,
+    // so that a missing library fails the call.
+    let Ok(libraries) = shlib::needed_libraries(&elf) else {
+        return -1;
+    };
+    let loaded = initialize_user_space(&proc, &elf, &libraries).and_then(|entry| {
+        let page_table = proc.mem.get_page_table();
+        Ok((entry, setup_user_stack(page_table)?))
+    });
+    let (entry, mut stack_top) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            // There is no address space to return to.
+            error!("exec: failed to load {}: {}", proc.name.lock(), e);
+            core::mem::drop(proc);
+            PROCESS_MANAGER.exit_current_proc(-1);
+            SCHEDULER.schedule()
+        }
+    };
     let page_table = proc.mem.get_page_table();
-    // Setup user stack
-    let mut stack_top = super::user::setup_user_stack(page_table);
+    stack_top = setup_tls(stack_top);
     // Prepare arguments
     let (argc, argv, s) = super::user::prepare_args(&args, stack_top);
     stack_top = s;
@@ -167,3 +387,13 @@ pub fn exec(proc: Arc<Process>, elf: Vec<u8>, args: &[CString]) -> isize {
     // Enter usermode
     super::user::enter_usermode(entry, stack_top, page_table, argc, argv);
 }
+
+/// Start a user thread of the current process. `arg` is passed as the first argument,
+/// and `sp` is a stack allocated by the process.
+pub fn enter_thread(entry: UserEntry, arg: usize, sp: Address) -> ! {
+    let sp = setup_tls(sp);
+    let proc = PROCESS_MANAGER.current_proc().unwrap();
+    let page_table = proc.mem.get_page_table();
+    core::mem::drop(proc);
+    enter_usermode(entry, sp, page_table, arg as isize, core::ptr::null());
+}
@@ -0,0 +1,159 @@
+//! Symbolized backtraces for kernel panics and user faults.
+//!
+//! Addresses are printed as `image+offset function+offset`. Kernel addresses are looked up
+//! in the symbol table of the kernel image, copied at boot, and in the ELF files of the loaded
+//! modules. User addresses are looked up in the shared libraries. Programs are not kept after
+//! they are loaded, so only `program+offset` is printed for them, e.g. for `llvm-addr2line`.
+//!
+//! Nothing here allocates after `init`, so that it works with a broken heap.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use core::ops::Range;
+use elf_loader::ELFObject;
+use klib::proc::Process;
+use memory::address::Address;
+use spin::Once;
+
+use crate::arch::{Arch, TargetArch};
+
+/// The functions of the kernel image.
+struct KernelSymbols {
+    /// The addresses of the kernel image.
+    image: Range<usize>,
+    /// Offsets into the image of each function, and the range of its name in `names`,
+    /// sorted by offset.
+    functions: Vec<(Range<usize>, Range<usize>)>,
+    names: String,
+}
+
+impl KernelSymbols {
+    fn find_function(&self, offset: usize) -> Option<(&str, usize)> {
+        let i = self.functions.partition_point(|(f, _)| f.start <= offset);
+        let (function, name) = self.functions.get(i.checked_sub(1)?)?;
+        if !function.contains(&offset) {
+            return None;
+        }
+        Some((&self.names[name.clone()], offset - function.start))
+    }
+}
+
+static KERNEL_SYMBOLS: Once<KernelSymbols> = Once::new();
+
+/// Copy the symbol table out of the kernel ELF file. The file is not mapped after boot.
+pub fn init(kernel_image: &[u8]) {
+    let Ok(object) = ELFObject::parse(kernel_image) else {
+        warn!("backtrace: no kernel symbols");
+        return;
+    };
+    KERNEL_SYMBOLS.call_once(|| {
+        let base = crate::_start as usize - object.entry_point();
+        let size = object.segments().map(|(s, _)| s.end).max().unwrap_or(0);
+        let mut names = String::new();
+        let mut functions = Vec::new();
+        for (function, name) in object.functions() {
+            let start = names.len();
+            names.push_str(name);
+            functions.push((function, start..names.len()));
+        }
+        functions.sort_by_key(|(f, _)| f.start);
+        KernelSymbols {
+            image: base..base + size,
+            functions,
+            names,
+        }
+    });
+}
+
+/// An address, formatted as `image+offset function+offset`.
+///
+/// User addresses are looked up in the program and the shared libraries of `proc`.
+pub struct Symbolized<'a> {
+    pub address: usize,
+    pub proc: Option<&'a Process>,
+}
+
+impl Symbolized<'_> {
+    pub fn kernel(address: usize) -> Self {
+        Self {
+            address,
+            proc: None,
+        }
+    }
+}
+
+fn write_location(
+    f: &mut fmt::Formatter<'_>,
+    image: &str,
+    offset: usize,
+    function: Option<(&str, usize)>,
+) -> fmt::Result {
+    write!(f, "{}+{:#x}", image, offset)?;
+    if let Some((name, offset)) = function {
+        write!(f, " {:#}+{:#x}", rustc_demangle::demangle(name), offset)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Symbolized<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let address = self.address;
+        if let Some(kernel) = KERNEL_SYMBOLS.get() {
+            if kernel.image.contains(&address) {
+                let offset = address - kernel.image.start;
+                return write_location(f, "sophon", offset, kernel.find_function(offset));
+            }
+        }
+        if let Some(result) = crate::modules::symbolize(address, |module, offset, function| {
+            write_location(f, module, offset, function)
+        }) {
+            return result;
+        }
+        let Some(proc) = self.proc else {
+            return write!(f, "?");
+        };
+        if let Some(result) =
+            crate::task::shlib::symbolize(Address::from(address), |library, offset, function| {
+                write_location(f, library, offset, function)
+            })
+        {
+            return result;
+        }
+        if let (Some(program), Some(name)) = (proc.mem.program.try_lock(), proc.name.try_lock()) {
+            let (image, offset) = &*program;
+            if image.contains(&Address::from(address)) {
+                let name = name.rsplit('/').next().unwrap_or(&name);
+                let offset = (address as isize - *offset) as usize;
+                return write_location(f, name, offset, None);
+            }
+        }
+        write!(f, "?")
+    }
+}
+
+/// Print the call sites on the current kernel stack.
+pub fn print_kernel_backtrace() {
+    error!("backtrace:");
+    let mut i = 0;
+    TargetArch::walk_stack(&mut |address| {
+        error!(
+            "  #{:<2} {:#018x} {}",
+            i,
+            address,
+            Symbolized::kernel(address)
+        );
+        i += 1;
+    });
+}
+
+/// Print the frames of a faulting user thread: the faulting instruction, then the call sites.
+pub fn print_user_backtrace(proc: &Process, pc: usize, call_sites: impl Iterator<Item = usize>) {
+    error!("  backtrace:");
+    for (i, address) in core::iter::once(pc).chain(call_sites).enumerate() {
+        let location = Symbolized {
+            address,
+            proc: Some(proc),
+        };
+        error!("    #{:<2} {:#018x} {}", i, address, location);
+    }
+}
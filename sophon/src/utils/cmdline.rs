@@ -0,0 +1,21 @@
+use spin::Once;
+
+static COMMAND_LINE: Once<&'static str> = Once::new();
+
+/// Set the kernel command line passed by the bootloader.
+pub fn init(command_line: &'static str) {
+    COMMAND_LINE.call_once(|| command_line);
+}
+
+/// Get the value of a `key=value` option. Flags without a value give an empty string.
+/// The last occurrence of an option wins.
+pub fn get(key: &str) -> Option<&'static str> {
+    COMMAND_LINE
+        .get()?
+        .split_whitespace()
+        .filter_map(|option| match option.split_once('=') {
+            Some((k, v)) => (k == key).then_some(v),
+            None => (option == key).then_some(""),
+        })
+        .last()
+}
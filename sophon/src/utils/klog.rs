@@ -0,0 +1,367 @@
+//! The kernel log: a ring buffer of structured records.
+//!
+//! Each record keeps the time, core, process, module and level of a message, in the format of
+//! `syscall::klog`. Records come from the `log` macros of the kernel and of kernel modules, and
+//! from `print!` (one record per line). The ring keeps the latest records, including the ones
+//! logged before a console is up. They are read with `dmesg`, or as text from `/dev/kmsg`.
+//!
+//! Levels are set per module, with the `klog` option on the kernel command line, e.g.
+//! `klog=info,sophon::task=trace,vfs=warn`, or at runtime with `dmesg -n`.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt::{self, Write};
+use dev::{DevRequest, Device};
+use interrupt::UninterruptibleMutex;
+use spin::Mutex;
+use syscall::klog::{self, Header, Level, LevelFilter, HEADER_SIZE, MAX_MESSAGE_LEN};
+
+use crate::arch::{Arch, TargetArch};
+use crate::task::sched::SCHEDULER;
+
+static LOG: Mutex<LogRing> = Mutex::new(LogRing::new());
+static FILTERS: Mutex<Filters> = Mutex::new(Filters::new());
+
+struct LogRing {
+    buf: [u8; Self::CAPACITY],
+    /// Position of the oldest record. Positions only grow, and wrap around the buffer.
+    tail: usize,
+    /// Position of the next record.
+    head: usize,
+    /// Sequence number of the oldest record.
+    first_seq: u64,
+    next_seq: u64,
+    /// An unfinished `print!` line.
+    line: [u8; MAX_MESSAGE_LEN],
+    line_len: usize,
+}
+
+impl LogRing {
+    const CAPACITY: usize = 64 << 10;
+
+    const fn new() -> Self {
+        Self {
+            buf: [0; Self::CAPACITY],
+            tail: 0,
+            head: 0,
+            first_seq: 0,
+            next_seq: 0,
+            line: [0; MAX_MESSAGE_LEN],
+            line_len: 0,
+        }
+    }
+
+    fn read_at(&self, pos: usize, out: &mut [u8]) {
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = self.buf[(pos + i) % Self::CAPACITY];
+        }
+    }
+
+    fn write_at(&mut self, pos: usize, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.buf[(pos + i) % Self::CAPACITY] = *byte;
+        }
+    }
+
+    fn header_at(&self, pos: usize) -> Header {
+        let mut header = [0; HEADER_SIZE];
+        self.read_at(pos, &mut header);
+        Header::decode(&header).unwrap()
+    }
+
+    /// Drop the oldest records until `len` more bytes fit.
+    fn make_room(&mut self, len: usize) {
+        while self.head + len - self.tail > Self::CAPACITY {
+            self.tail += self.header_at(self.tail).record_len();
+            self.first_seq += 1;
+        }
+    }
+
+    fn push(&mut self, level: Level, module: &str, args: fmt::Arguments) {
+        let module = klog::truncate(module, klog::MAX_MODULE_LEN);
+        self.make_room(HEADER_SIZE + module.len() + MAX_MESSAGE_LEN);
+        let start = self.head + HEADER_SIZE + module.len();
+        let mut message = MessageWriter {
+            ring: self,
+            start,
+            len: 0,
+        };
+        let _ = message.write_fmt(args);
+        let message_len = message.len;
+        let header = Header {
+            seq: self.next_seq,
+            timestamp: TargetArch::uptime().as_nanos() as u64,
+            pid: SCHEDULER.get_current_pid().map(|pid| pid.0 as u32),
+            core: TargetArch::current_core() as u16,
+            level,
+            module_len: module.len() as u8,
+            message_len: message_len as u16,
+        };
+        self.write_at(self.head, &header.encode());
+        self.write_at(self.head + HEADER_SIZE, module.as_bytes());
+        self.head += header.record_len();
+        self.next_seq += 1;
+    }
+
+    /// Append `print!` output, without a `\n`, to the unfinished line.
+    fn push_print(&mut self, bytes: &[u8]) {
+        let len = usize::min(bytes.len(), MAX_MESSAGE_LEN - self.line_len);
+        self.line[self.line_len..self.line_len + len].copy_from_slice(&bytes[..len]);
+        self.line_len += len;
+    }
+
+    fn flush_line(&mut self) {
+        let line = self.line;
+        let text = match core::str::from_utf8(&line[..self.line_len]) {
+            Ok(text) => text,
+            // Cut in the middle of a character
+            Err(e) => unsafe { core::str::from_utf8_unchecked(&line[..e.valid_up_to()]) },
+        };
+        self.push(Level::Info, "print", format_args!("{}", text));
+        self.line_len = 0;
+    }
+
+    /// Copy whole records from `seq` on, oldest first.
+    fn read(&self, seq: u64, out: &mut [u8]) -> usize {
+        let mut pos = self.tail;
+        let mut len = 0;
+        for s in self.first_seq..self.next_seq {
+            let record_len = self.header_at(pos).record_len();
+            if s >= seq {
+                if len + record_len > out.len() {
+                    break;
+                }
+                self.read_at(pos, &mut out[len..len + record_len]);
+                len += record_len;
+            }
+            pos += record_len;
+        }
+        len
+    }
+}
+
+/// The lines of one `print!`, formatted on the stack. Longer lines are truncated.
+struct PrintLines {
+    line: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+}
+
+impl PrintLines {
+    /// Add the buffered text to the ring under one lock, and log it if the line is complete.
+    fn flush(&mut self, complete: bool) {
+        let mut log = LOG.lock_uninterruptible();
+        log.push_print(&self.line[..self.len]);
+        if complete {
+            log.flush_line();
+        }
+        self.len = 0;
+    }
+}
+
+impl Write for PrintLines {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.flush(true);
+            } else if self.len < MAX_MESSAGE_LEN {
+                self.line[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a message in place, after the record header. Longer messages are truncated.
+struct MessageWriter<'a> {
+    ring: &'a mut LogRing,
+    start: usize,
+    len: usize,
+}
+
+impl Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let s = klog::truncate(s, MAX_MESSAGE_LEN - self.len);
+        self.ring.write_at(self.start + self.len, s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+/// Per-module log levels.
+struct Filters {
+    default: LevelFilter,
+    /// Module path prefixes, e.g. `sophon::task` or `vfs`.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filters {
+    const fn new() -> Self {
+        Self {
+            default: LevelFilter::Trace,
+            modules: Vec::new(),
+        }
+    }
+
+    /// The level of the longest matching module prefix.
+    fn level(&self, module: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(prefix, _)| {
+                module
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn set(&mut self, module: &str, level: LevelFilter) {
+        if module.is_empty() {
+            self.default = level;
+        } else if let Some(filter) = self.modules.iter_mut().find(|(m, _)| m == module) {
+            filter.1 = level;
+        } else {
+            self.modules.push((module.into(), level));
+        }
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        let levels = self.modules.iter().map(|(_, level)| *level);
+        levels.fold(self.default, Ord::max)
+    }
+}
+
+/// Apply the `klog` option of the kernel command line: comma-separated `level` or
+/// `module=level` items.
+pub fn init() {
+    let Some(option) = super::cmdline::get("klog") else {
+        return;
+    };
+    for item in option.split(',').filter(|item| !item.is_empty()) {
+        let (module, level) = item.split_once('=').unwrap_or(("", item));
+        match level.parse() {
+            Ok(level) => set_level(module, level),
+            Err(_) => warn!("klog: bad level {:?}", item),
+        }
+    }
+}
+
+/// Set the level of `module` and its submodules. An empty `module` sets the default level.
+pub fn set_level(module: &str, level: LevelFilter) {
+    let mut filters = FILTERS.lock_uninterruptible();
+    filters.set(module, level);
+    log::set_max_level(filters.max_level());
+}
+
+pub fn enabled(level: Level, module: &str) -> bool {
+    level <= FILTERS.lock_uninterruptible().level(module)
+}
+
+/// Log a message to the console and the ring buffer, if `module` is enabled at `level`.
+pub fn log(level: Level, module: &str, args: fmt::Arguments) {
+    if !enabled(level, module) {
+        return;
+    }
+    super::print::console_print(format_args!("[{}][{}] {}\n", level, module, args));
+    LOG.lock_uninterruptible().push(level, module, args);
+}
+
+/// Record `print!` output. Each line is added at once, so that the lines printed by different
+/// cores do not mix. Text after the last `\n` waits in the ring for the next `print!`.
+pub(super) fn log_print(args: fmt::Arguments) {
+    let mut lines = PrintLines {
+        line: [0; MAX_MESSAGE_LEN],
+        len: 0,
+    };
+    let _ = lines.write_fmt(args);
+    if lines.len != 0 {
+        lines.flush(false);
+    }
+}
+
+/// Copy whole records from sequence number `seq` on to `out`, oldest first.
+/// Returns the number of bytes copied.
+pub fn read(seq: u64, out: &mut [u8]) -> usize {
+    LOG.lock_uninterruptible().read(seq, out)
+}
+
+/// `/dev/kmsg`. Reads give the retained records as text, one line each:
+/// `<level>,<seq>,<usecs>,<core>,<pid>;<module>: <message>`. The offset is in this text, so a
+/// reader that falls behind the ring skips or repeats lines; `dmesg` uses sequence numbers
+/// instead. Each written line is logged, at the level of an optional `<N>` prefix.
+struct KernelMessages;
+
+static KMSG: KernelMessages = KernelMessages;
+
+impl Device for KernelMessages {
+    fn name(&self) -> &'static str {
+        "kmsg"
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Option<usize> {
+        let mut records = vec![0u8; LogRing::CAPACITY];
+        let len = read(0, &mut records);
+        let mut text = String::new();
+        for record in klog::records(&records[..len]) {
+            let header = record.header;
+            let pid = header.pid.map(|pid| pid as i64).unwrap_or(-1);
+            let _ = write!(
+                text,
+                "{},{},{},{},{};{}: ",
+                header.level as usize,
+                header.seq,
+                header.timestamp / 1000,
+                header.core,
+                pid,
+                record.module,
+            );
+            for c in record.message.chars() {
+                match c {
+                    '\n' => text.push_str("\\x0a"),
+                    c => text.push(c),
+                }
+            }
+            text.push('\n');
+        }
+        let text = text.as_bytes().get(offset..).unwrap_or_default();
+        let len = usize::min(text.len(), buf.len());
+        buf[..len].copy_from_slice(&text[..len]);
+        Some(len)
+    }
+
+    fn write(&self, _offset: usize, buf: &[u8]) -> Option<usize> {
+        let text = core::str::from_utf8(buf).ok()?;
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let (level, message) = line
+                .strip_prefix('<')
+                .and_then(|s| s.split_once('>'))
+                .and_then(|(n, message)| Some((klog::level_from_u8(n.parse().ok()?)?, message)))
+                .unwrap_or((Level::Info, line));
+            log(level, "user", format_args!("{}", message));
+        }
+        Some(buf.len())
+    }
+}
+
+/// Register `/dev/kmsg`, once the `dev` module is loaded.
+pub fn register_device() {
+    crate::modules::module_call(
+        "dev",
+        true,
+        &DevRequest::RegisterDev(&(&KMSG as &'static dyn Device)),
+    );
+}
+
+#[test]
+fn klog_test() {
+    let mut filters = Filters::new();
+    filters.set("sophon::task", LevelFilter::Warn);
+    filters.set("sophon::task::sched", LevelFilter::Trace);
+    assert_eq!(filters.level("sophon::task::proc"), LevelFilter::Warn);
+    assert_eq!(filters.level("sophon::task::sched"), LevelFilter::Trace);
+    assert_eq!(filters.level("sophon::tasks"), LevelFilter::Trace);
+    filters.set("", LevelFilter::Off);
+    assert_eq!(filters.level("vfs"), LevelFilter::Off);
+    assert_eq!(filters.max_level(), LevelFilter::Trace);
+}
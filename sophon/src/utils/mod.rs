@@ -1,5 +1,10 @@
+pub mod backtrace;
 pub mod boot_logger;
+pub mod cmdline;
+pub mod klog;
 #[macro_use]
 pub mod print;
 pub mod pls;
+pub mod random;
 pub mod testing;
+pub mod trace;
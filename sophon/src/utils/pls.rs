@@ -1,5 +1,6 @@
 use core::ops::Deref;
 
+use crate::arch::{Arch, TargetArch};
 use alloc::vec::Vec;
 
 pub struct ProcessorLocalStorage<T: Default> {
@@ -23,11 +24,12 @@ impl<T: Default> ProcessorLocalStorage<T> {
     }
 
     pub fn num_cores() -> usize {
-        1
+        TargetArch::num_cores()
     }
 
+    #[inline(always)]
     pub fn current_core() -> usize {
-        0
+        TargetArch::current_core()
     }
 }
 
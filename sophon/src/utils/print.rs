@@ -2,9 +2,34 @@ use core::{
     fmt::Write,
     sync::atomic::{AtomicBool, Ordering},
 };
+use interrupt::UninterruptibleMutex;
+use spin::Mutex;
 
 static mut LOGGER: Option<&'static mut dyn Write> = None;
 static IS_LOG_LOGGER_SET: AtomicBool = AtomicBool::new(false);
+/// Serializes outputs from different cores.
+static PRINT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Writes to the logger, if any.
+struct Output;
+
+impl Write for Output {
+    #[allow(static_mut_refs)]
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        unsafe {
+            if let Some(logger) = LOGGER.as_mut() {
+                logger.write_str(s)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print to the console only. Log records use this, and keep their own copy in `klog`.
+pub fn console_print(args: core::fmt::Arguments) {
+    let _guard = PRINT_LOCK.lock_uninterruptible();
+    Output.write_fmt(args).unwrap();
+}
 
 pub fn init(logger: &'static mut dyn Write) {
     unsafe {
@@ -18,13 +43,9 @@ pub fn init(logger: &'static mut dyn Write) {
 
 #[doc(hidden)]
 #[inline(never)]
-#[allow(static_mut_refs)]
 pub fn _print(args: core::fmt::Arguments) {
-    unsafe {
-        LOGGER
-            .as_mut()
-            .map(|logger| logger.write_fmt(args).unwrap())
-    };
+    console_print(args);
+    super::klog::log_print(args);
 }
 
 #[macro_export]
@@ -46,23 +67,13 @@ struct KernelLogLogger;
 static LOG_LOGGER: KernelLogLogger = KernelLogLogger;
 
 impl log::Log for KernelLogLogger {
-    fn enabled(&self, _metadata: &log::Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        super::klog::enabled(metadata.level(), metadata.target())
     }
 
     #[inline]
-    #[allow(static_mut_refs)]
     fn log(&self, record: &log::Record) {
-        let out = unsafe { LOGGER.as_mut().unwrap() };
-
-        writeln!(
-            out,
-            "[{}][{}] {}",
-            record.level(),
-            record.target(),
-            record.args()
-        )
-        .unwrap();
+        super::klog::log(record.level(), record.target(), *record.args());
     }
 
     fn flush(&self) {}
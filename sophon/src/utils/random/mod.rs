@@ -0,0 +1,65 @@
+//! Kernel entropy pool.
+//!
+//! Seeded at boot from the generic timer counter and, under QEMU, a virtio-rng device.
+//! Every output also mixes in the current counter. Good enough for ASLR, but not for cryptography.
+
+mod virtio_rng;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::arch::{Arch, TargetArch};
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// The splitmix64 increment.
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The splitmix64 output function.
+const fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn counter() -> u64 {
+    TargetArch::uptime().as_nanos() as u64
+}
+
+/// Seed the pool. Must be called after the device tree is loaded.
+pub fn init() {
+    // The counter is only a few bits of entropy, but its low bits jitter between samples.
+    for _ in 0..64 {
+        add_entropy(&counter().to_le_bytes());
+        core::hint::spin_loop();
+    }
+    if cfg!(feature = "qemu") {
+        let mut buf = [0u8; 32];
+        let len = virtio_rng::read(&mut buf);
+        if len == 0 {
+            warn!("virtio-rng not found, entropy is from the timer only");
+        }
+        add_entropy(&buf[..len]);
+    }
+}
+
+/// Mix bytes into the pool.
+pub fn add_entropy(bytes: &[u8]) {
+    for chunk in bytes.chunks(8) {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let word = u64::from_le_bytes(word);
+        let _ = STATE.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |s| {
+            Some(mix(s.wrapping_add(GAMMA) ^ word))
+        });
+    }
+}
+
+pub fn random_u64() -> u64 {
+    let state = STATE.fetch_add(GAMMA, Ordering::SeqCst).wrapping_add(GAMMA);
+    mix(state ^ counter().rotate_left(32))
+}
+
+/// A random number in `0..bound`.
+pub fn random_below(bound: usize) -> usize {
+    ((random_u64() as u128 * bound as u128) >> 64) as usize
+}
@@ -0,0 +1,222 @@
+//! A polling virtio-rng driver over virtio-mmio, used once at boot to seed the entropy pool.
+//! Supports both the legacy (version 1) and the modern (version 2) MMIO interface.
+
+use core::iter::Step;
+use core::sync::atomic::{fence, Ordering};
+use core::time::Duration;
+
+use memory::{
+    address::{Address, P},
+    page::{Frame, PageSize, Size2M, Size4K},
+    page_table::PageFlags,
+    volatile::Volatile,
+};
+
+use crate::arch::{Arch, TargetArch};
+use crate::memory::kernel::{KERNEL_HEAP, KERNEL_MEMORY_MAPPER};
+use crate::memory::physical::PHYSICAL_MEMORY;
+
+const MAGIC: u32 = 0x7472_6976;
+const DEVICE_ID_ENTROPY: u32 = 4;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// `VIRTIO_F_VERSION_1`, bit 32 of the feature bits.
+const FEATURE_VERSION_1: u32 = 1 << 0;
+
+const DESC_F_WRITE: u16 = 2;
+
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+#[repr(C)]
+struct Registers {
+    magic: Volatile<u32>,               // 0x000
+    version: Volatile<u32>,             // 0x004
+    device_id: Volatile<u32>,           // 0x008
+    _vendor_id: Volatile<u32>,          // 0x00c
+    device_features: Volatile<u32>,     // 0x010
+    device_features_sel: Volatile<u32>, // 0x014
+    _0: [u32; 2],                       // 0x018
+    driver_features: Volatile<u32>,     // 0x020
+    driver_features_sel: Volatile<u32>, // 0x024
+    guest_page_size: Volatile<u32>,     // 0x028
+    _1: u32,                            // 0x02c
+    queue_sel: Volatile<u32>,           // 0x030
+    queue_num_max: Volatile<u32>,       // 0x034
+    queue_num: Volatile<u32>,           // 0x038
+    queue_align: Volatile<u32>,         // 0x03c
+    queue_pfn: Volatile<u32>,           // 0x040
+    queue_ready: Volatile<u32>,         // 0x044
+    _2: [u32; 2],                       // 0x048
+    queue_notify: Volatile<u32>,        // 0x050
+    _3: [u32; 7],                       // 0x054
+    status: Volatile<u32>,              // 0x070
+    _4: [u32; 3],                       // 0x074
+    queue_desc: [Volatile<u32>; 2],     // 0x080
+    _5: [u32; 2],                       // 0x088
+    queue_driver: [Volatile<u32>; 2],   // 0x090
+    _6: [u32; 2],                       // 0x098
+    queue_device: [Volatile<u32>; 2],   // 0x0a0
+}
+
+#[repr(C)]
+struct Descriptor {
+    addr: Volatile<u64>,
+    len: Volatile<u32>,
+    flags: Volatile<u16>,
+    next: Volatile<u16>,
+}
+
+#[repr(C)]
+struct AvailableRing {
+    flags: Volatile<u16>,
+    idx: Volatile<u16>,
+    ring: [Volatile<u16>; 1],
+}
+
+#[repr(C)]
+struct UsedRing {
+    _flags: Volatile<u16>,
+    idx: Volatile<u16>,
+    _id: Volatile<u32>,
+    len: Volatile<u32>,
+}
+
+/// A single-entry virtqueue and its buffer, in one physical frame, starting with the descriptor.
+/// The legacy interface wants the used ring on its own page.
+const AVAILABLE_OFFSET: usize = 0x10;
+const USED_OFFSET: usize = Size4K::BYTES;
+const BUFFER_OFFSET: usize = 2 * Size4K::BYTES;
+
+fn set_address(reg: &mut [Volatile<u32>; 2], a: Address<P>) {
+    reg[0].set(a.as_usize() as u32);
+    reg[1].set((a.as_usize() >> 32) as u32);
+}
+
+/// Fill `buf` from the first virtio-rng device in the device tree.
+/// Returns the number of bytes read, or zero if there is no device.
+pub fn read(buf: &mut [u8]) -> usize {
+    #[allow(static_mut_refs)]
+    let Some(devtree) = (unsafe { crate::DEV_TREE.as_ref() }) else {
+        return 0;
+    };
+    let _guard = KERNEL_MEMORY_MAPPER.with_kernel_address_space();
+    for node in devtree.all_compatible("virtio,mmio") {
+        let Some(mut regs) = node.regs() else {
+            continue;
+        };
+        let Some(range) = regs.next() else {
+            continue;
+        };
+        let mmio = node.translate(range.start);
+        let page = KERNEL_HEAP.virtual_allocate::<Size4K>(1).start;
+        KERNEL_MEMORY_MAPPER.map(page, Frame::containing(mmio), PageFlags::device());
+        let offset = mmio - Frame::<Size4K>::align(mmio);
+        let regs = unsafe { (page.start() + offset).as_mut::<Registers>() };
+        let result = if regs.magic.get() == MAGIC && regs.device_id.get() == DEVICE_ID_ENTROPY {
+            read_from_device(regs, buf)
+        } else {
+            None
+        };
+        KERNEL_MEMORY_MAPPER.unmap(page);
+        KERNEL_HEAP.virtual_release(page..Step::forward(page, 1));
+        if let Some(len) = result {
+            return len;
+        }
+    }
+    0
+}
+
+fn read_from_device(regs: &mut Registers, buf: &mut [u8]) -> Option<usize> {
+    let version = regs.version.get();
+    // Reset and initialize the device
+    regs.status.set(0);
+    regs.status.set(STATUS_ACKNOWLEDGE);
+    regs.status.set(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+    let mut status = STATUS_ACKNOWLEDGE | STATUS_DRIVER;
+    match version {
+        1 => {
+            regs.driver_features_sel.set(0);
+            regs.driver_features.set(0);
+            regs.guest_page_size.set(Size4K::BYTES as u32);
+        }
+        2 => {
+            regs.device_features_sel.set(1);
+            if regs.device_features.get() & FEATURE_VERSION_1 == 0 {
+                return None;
+            }
+            regs.driver_features_sel.set(0);
+            regs.driver_features.set(0);
+            regs.driver_features_sel.set(1);
+            regs.driver_features.set(FEATURE_VERSION_1);
+            status |= STATUS_FEATURES_OK;
+            regs.status.set(status);
+            if regs.status.get() & STATUS_FEATURES_OK == 0 {
+                regs.status.set(0);
+                return None;
+            }
+        }
+        _ => return None,
+    }
+    regs.queue_sel.set(0);
+    if regs.queue_num_max.get() == 0 {
+        regs.status.set(0);
+        return None;
+    }
+    // Set up the queue
+    let frame = PHYSICAL_MEMORY.acquire::<Size2M>()?;
+    let queue = frame.start();
+    unsafe { core::ptr::write_bytes(queue.as_mut_ptr::<u8>(), 0, BUFFER_OFFSET) };
+    regs.queue_num.set(1);
+    if version == 1 {
+        regs.queue_align.set(Size4K::BYTES as u32);
+        regs.queue_pfn
+            .set((queue.as_usize() >> Size4K::LOG_BYTES) as u32);
+    } else {
+        set_address(&mut regs.queue_desc, queue);
+        set_address(&mut regs.queue_driver, queue + AVAILABLE_OFFSET);
+        set_address(&mut regs.queue_device, queue + USED_OFFSET);
+        regs.queue_ready.set(1);
+    }
+    regs.status.set(status | STATUS_DRIVER_OK);
+    // Submit a device-writable buffer
+    let len = usize::min(buf.len(), Size2M::BYTES - BUFFER_OFFSET);
+    let (desc, available, used) = unsafe {
+        (
+            queue.as_mut::<Descriptor>(),
+            (queue + AVAILABLE_OFFSET).as_mut::<AvailableRing>(),
+            (queue + USED_OFFSET).as_mut::<UsedRing>(),
+        )
+    };
+    desc.addr.set((queue + BUFFER_OFFSET).as_usize() as u64);
+    desc.len.set(len as u32);
+    desc.flags.set(DESC_F_WRITE);
+    desc.next.set(0);
+    available.flags.set(0);
+    available.ring[0].set(0);
+    fence(Ordering::SeqCst);
+    available.idx.set(1);
+    fence(Ordering::SeqCst);
+    regs.queue_notify.set(0);
+    // Wait for the device
+    let deadline = TargetArch::uptime() + TIMEOUT;
+    while used.idx.get() == 0 && TargetArch::uptime() < deadline {
+        core::hint::spin_loop();
+    }
+    fence(Ordering::SeqCst);
+    let result = if used.idx.get() != 0 {
+        let n = usize::min(used.len.get() as usize, len);
+        let data = (queue + BUFFER_OFFSET).as_ptr::<u8>();
+        buf[..n].copy_from_slice(unsafe { core::slice::from_raw_parts(data, n) });
+        Some(n)
+    } else {
+        None
+    };
+    // Reset the device, so it stops using the queue
+    regs.status.set(0);
+    PHYSICAL_MEMORY.release(frame);
+    result
+}
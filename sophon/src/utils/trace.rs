@@ -0,0 +1,223 @@
+//! Static tracepoints, recorded to per-core ring buffers.
+//!
+//! A tracepoint claims a slot of its core's ring with an atomic increment, so tracepoints may
+//! nest, e.g. an interrupt taken inside a syscall, without taking any lock. When a ring is full
+//! the oldest events are overwritten.
+//!
+//! Tracing starts at boot with the `trace` option on the kernel command line, or at runtime with
+//! `trace start`. The trace is read from `/dev/trace` in the format of `syscall::trace`, or
+//! printed on the console, and decoded on the host with `cargo dev trace`. Stop tracing before
+//! reading: events recorded during a read may be torn.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use dev::{DevRequest, Device};
+use spin::Lazy;
+use syscall::trace::{self, Event, Kind, Phase, TraceHeader, EVENT_SIZE};
+
+use super::pls::ProcessorLocalStorage;
+use crate::arch::{Arch, TargetArch};
+use crate::task::sched::SCHEDULER;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static BUFFERS: Lazy<ProcessorLocalStorage<TraceBuffer>> = Lazy::new(ProcessorLocalStorage::new);
+
+struct TraceBuffer {
+    events: Box<[UnsafeCell<[u8; EVENT_SIZE]>]>,
+    /// Number of events ever recorded.
+    head: AtomicU64,
+}
+
+unsafe impl Sync for TraceBuffer {}
+
+impl TraceBuffer {
+    const CAPACITY: usize = 4096;
+
+    /// The retained events, oldest first, and the number of overwritten ones.
+    fn events(&self) -> (impl Iterator<Item = &[u8; EVENT_SIZE]>, u64) {
+        let head = self.head.load(Ordering::SeqCst);
+        let len = u64::min(head, Self::CAPACITY as u64);
+        let events =
+            (head - len..head).map(|i| unsafe { &*self.events[i as usize % Self::CAPACITY].get() });
+        (events, head - len)
+    }
+}
+
+impl Default for TraceBuffer {
+    fn default() -> Self {
+        Self {
+            events: (0..Self::CAPACITY)
+                .map(|_| UnsafeCell::new([0; EVENT_SIZE]))
+                .collect(),
+            head: AtomicU64::new(0),
+        }
+    }
+}
+
+#[inline(always)]
+pub fn begin(kind: Kind, arg: usize) {
+    record(kind, Phase::Begin, arg)
+}
+
+#[inline(always)]
+pub fn end(kind: Kind, arg: usize) {
+    record(kind, Phase::End, arg)
+}
+
+#[inline(always)]
+pub fn instant(kind: Kind, arg: usize) {
+    record(kind, Phase::Instant, arg)
+}
+
+#[inline(always)]
+fn record(kind: Kind, phase: Phase, arg: usize) {
+    if ENABLED.load(Ordering::Relaxed) {
+        record_slow(kind, phase, arg)
+    }
+}
+
+#[inline(never)]
+fn record_slow(kind: Kind, phase: Phase, arg: usize) {
+    let event = Event {
+        time: TargetArch::uptime().as_nanos() as u64,
+        cycles: TargetArch::cycles(),
+        arg: arg as u64,
+        task: SCHEDULER.get_current_task_id().map(|id| id.0 as u32),
+        core: TargetArch::current_core() as u16,
+        kind,
+        phase,
+    };
+    // Slots are unique even if the task moves to another core in between.
+    let buffer = BUFFERS.get(event.core as usize);
+    let i = buffer.head.fetch_add(1, Ordering::Relaxed);
+    unsafe { *buffer.events[i as usize % TraceBuffer::CAPACITY].get() = event.encode() };
+}
+
+pub fn start() {
+    Lazy::force(&BUFFERS);
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn stop() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// Drop all the recorded events.
+pub fn clear() {
+    for core in 0..TargetArch::num_cores() {
+        BUFFERS.get(core).head.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Encode the recorded events of all cores.
+pub fn dump() -> Vec<u8> {
+    let modules = crate::modules::names();
+    let mut header = TraceHeader {
+        num_cores: TargetArch::num_cores() as u32,
+        num_modules: modules.len() as u32,
+        num_events: 0,
+        dropped: 0,
+    };
+    let mut events = Vec::new();
+    for core in 0..TargetArch::num_cores() {
+        let (core_events, dropped) = BUFFERS.get(core).events();
+        core_events.for_each(|e| events.extend_from_slice(e));
+        header.dropped += dropped;
+    }
+    header.num_events = (events.len() / EVENT_SIZE) as u64;
+    let mut out = header.encode().to_vec();
+    for name in &modules {
+        let name = syscall::klog::truncate(name, u8::MAX as usize);
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+    }
+    out.extend_from_slice(&events);
+    out
+}
+
+/// Print the trace on the console, e.g. to capture it from the serial port.
+/// Output does not go to the kernel log.
+fn print() {
+    let trace = dump();
+    for chunk in trace.chunks(64) {
+        let mut line = alloc::string::String::from(trace::CONSOLE_PREFIX);
+        chunk
+            .iter()
+            .for_each(|b| write!(line, "{:02x}", b).unwrap());
+        super::print::console_print(format_args!("{}\n", line));
+    }
+    super::print::console_print(format_args!("{}\n", trace::CONSOLE_END));
+}
+
+/// `/dev/trace`. Reads give the trace, and `ioctl`s control tracing.
+struct TraceDevice;
+
+static TRACE_DEVICE: TraceDevice = TraceDevice;
+
+impl Device for TraceDevice {
+    fn name(&self) -> &'static str {
+        "trace"
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Option<usize> {
+        let trace = dump();
+        let trace = trace.get(offset..).unwrap_or_default();
+        let len = usize::min(trace.len(), buf.len());
+        buf[..len].copy_from_slice(&trace[..len]);
+        Some(len)
+    }
+
+    fn write(&self, _offset: usize, _buf: &[u8]) -> Option<usize> {
+        None
+    }
+
+    fn ioctl(&self, cmd: usize, _arg: usize) -> Option<usize> {
+        match cmd {
+            trace::TRACE_START => start(),
+            trace::TRACE_STOP => stop(),
+            trace::TRACE_CLEAR => clear(),
+            trace::TRACE_PRINT => print(),
+            _ => return None,
+        }
+        Some(0)
+    }
+}
+
+/// Register `/dev/trace`, and start tracing if the `trace` option is set.
+pub fn init() {
+    crate::modules::module_call(
+        "dev",
+        true,
+        &DevRequest::RegisterDev(&(&TRACE_DEVICE as &'static dyn Device)),
+    );
+    if super::cmdline::get("trace").is_some() {
+        start();
+    }
+}
+
+#[test]
+fn trace_test() {
+    let buffer = TraceBuffer::default();
+    let event = Event {
+        time: 1,
+        cycles: 2,
+        arg: 3,
+        task: None,
+        core: 0,
+        kind: Kind::Syscall,
+        phase: Phase::Begin,
+    };
+    for i in 0..TraceBuffer::CAPACITY + 2 {
+        let slot = buffer.head.fetch_add(1, Ordering::Relaxed) as usize;
+        let event = Event {
+            arg: i as u64,
+            ..event
+        };
+        unsafe { *buffer.events[slot % TraceBuffer::CAPACITY].get() = event.encode() };
+    }
+    let (mut events, dropped) = buffer.events();
+    assert_eq!(dropped, 2);
+    assert_eq!(Event::decode(events.next().unwrap()).unwrap().arg, 2);
+}
@@ -12,6 +12,9 @@ pub struct Build {
     pub boot: Boot,
     #[clap(flatten)]
     pub cargo: CargoFlags,
+    /// Kernel command line, e.g. `aslr=off`.
+    #[clap(long)]
+    pub cmdline: Option<String>,
 }
 
 impl Build {
@@ -56,6 +59,13 @@ impl Build {
         // Build image
         shell.create_dir("./target/_boot").unwrap();
         shell.create_dir("./target/_boot/EFI/BOOT").unwrap();
+        //  - write kernel command line
+        match &self.cmdline {
+            Some(cmdline) => shell
+                .write_file("./target/_boot/cmdline.txt", cmdline)
+                .unwrap(),
+            _ => shell.remove_path("./target/_boot/cmdline.txt").unwrap(),
+        }
         //  - copy kernel
         shell
             .copy_file("./target/_out/sophon", "./target/_boot/")
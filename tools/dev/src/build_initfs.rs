@@ -22,13 +22,47 @@ impl BuildInitFS {
         fs: &mut RamFS,
     ) -> Result<(), Box<dyn Error>> {
         if let Some(cargo_module) = entry["+ cargo-build"].as_str() {
+            // `--features` is for the kernel crate only.
             shell.build_package(
                 cargo_module,
-                self.cargo.features.clone(),
+                None,
                 self.cargo.release,
                 Some(&self.cargo.kernel_module_traget()),
             );
         }
+        if let Some(program) = entry["+ cargo-build-dynamic"].as_str() {
+            // Link `libs/user` as `libuser.so`, loaded from `/lib` by the kernel.
+            // The library itself is built the same way, so that its symbols match.
+            // A separate target dir keeps the static builds from being rebuilt each time.
+            let rustflags = std::env::var("RUSTFLAGS").unwrap_or_default();
+            let _flags = shell.push_env("RUSTFLAGS", format!("{} -C prefer-dynamic", rustflags));
+            let _dir = shell.push_env(
+                "CARGO_TARGET_DIR",
+                shell.current_dir().join("target/dynamic"),
+            );
+            shell.build_package(
+                program,
+                None,
+                self.cargo.release,
+                Some(&self.cargo.user_traget()),
+            );
+        }
+        if let Some(program) = entry["+ cargo-build-std"].as_str() {
+            shell.build_package(
+                program,
+                None,
+                self.cargo.release,
+                Some(&self.cargo.std_target()),
+            );
+        }
+        for (key, dynamic) in [("+ cc", false), ("+ cc-dynamic", true)] {
+            if let Some(source) = entry[key].as_str() {
+                let name = path.rsplit('/').next().unwrap();
+                if !self.build_c_program(shell, source, name, dynamic) {
+                    return Ok(());
+                }
+            }
+        }
         if let Some(from) = entry["+ copy"].as_str() {
             let file = fs::read(from).unwrap();
             fs.insert(path, ramfs::File::new(file));
@@ -36,9 +70,58 @@ impl BuildInitFS {
         if let Some(data) = entry["+ copy-str"].as_str() {
             fs.insert(path, ramfs::File::new(data.as_bytes().to_vec()));
         }
+        if let Some(target) = entry["+ symlink"].as_str() {
+            fs.symlink(path, target);
+        }
         Ok(())
     }
 
+    /// Compile a C program and link it with `libs/libc`, into `target/_out/<name>`.
+    /// A `dynamic` program is linked against `libc.so`, loaded from `/lib` by the kernel.
+    /// Returns false if clang is not installed.
+    fn build_c_program(&self, shell: &Shell, source: &str, name: &str, dynamic: bool) -> bool {
+        shell.build_package(
+            "libs/libc",
+            None,
+            self.cargo.release,
+            Some(&self.cargo.user_traget()),
+        );
+        let clang = cmd!(shell, "clang --version")
+            .quiet()
+            .ignore_stdout()
+            .ignore_stderr()
+            .run();
+        if clang.is_err() {
+            eprintln!("warning: clang is not installed, skipping {}", source);
+            return false;
+        }
+        let out = format!("target/_out/{}", name);
+        let libc: &[&str] = if dynamic {
+            // Global variables of libc.so are accessed through the GOT. There are no copy relocations.
+            &[
+                "-fno-direct-access-external-data",
+                "libs/libc/crt/start.c",
+                "-Ltarget/_out",
+                "-lc",
+            ]
+        } else {
+            &["target/_out/libc.a"]
+        };
+        let cmd = cmd!(
+            shell,
+            "clang --target=aarch64-unknown-none-elf -ffreestanding -fPIE -mno-unaligned-access"
+        )
+        .args(["-O2", "-Wall", "-nostdlib", "-Ilibs/libc/include"])
+        .args(["-fuse-ld=lld", "-Wl,-pie,--no-dynamic-linker,-e,_start"])
+        .arg(source)
+        .args(libc)
+        .args(["-o", &out])
+        .quiet();
+        eprintln!("$ {}", cmd);
+        cmd.run().unwrap();
+        true
+    }
+
     fn gen_dir(
         &self,
         shell: &Shell,
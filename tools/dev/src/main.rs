@@ -10,6 +10,7 @@ mod clean;
 mod dis;
 mod run;
 mod test;
+mod trace;
 mod util;
 
 use clap::Parser;
@@ -44,6 +45,9 @@ enum SubCommand {
     /// Disassemble executables under ./target/_out
     #[clap(name = "dis")]
     Disassemble(dis::Disassemble),
+    /// Decode a kernel trace, and print latency histograms
+    #[clap(name = "trace")]
+    Trace(trace::Trace),
 }
 
 fn main() {
@@ -63,5 +67,6 @@ fn main() {
         SubCommand::BuildInitFS(t) => t.run(&shell),
         SubCommand::Clean(t) => t.run(&shell),
         SubCommand::Disassemble(t) => t.run(&shell),
+        SubCommand::Trace(t) => t.run(&shell),
     }
 }
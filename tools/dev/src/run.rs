@@ -12,6 +12,9 @@ pub struct Run {
     pub boot: Boot,
     #[clap(flatten)]
     pub cargo: CargoFlags,
+    /// Kernel command line, e.g. `aslr=off`.
+    #[clap(long)]
+    pub cmdline: Option<String>,
     #[clap(last = true, allow_hyphen_values = true)]
     pub args: Vec<String>,
 }
@@ -24,12 +27,13 @@ impl Run {
         let build = Build {
             boot: self.boot,
             cargo: self.cargo.clone(),
+            cmdline: self.cmdline.clone(),
         };
         build.run(shell, true);
         // Run
         let code = shell.run_package(
             "boot/uefi",
-            self.cargo.features.clone(),
+            None,
             self.cargo.release,
             Some(self.cargo.uefi_target()),
             &self.args,
@@ -12,6 +12,9 @@ pub struct Test {
     boot: Boot,
     #[clap(flatten)]
     pub cargo: CargoFlags,
+    /// Kernel command line, e.g. `aslr=off`.
+    #[clap(long)]
+    cmdline: Option<String>,
     #[clap(last = true, allow_hyphen_values = true)]
     args: Vec<String>,
 }
@@ -25,6 +28,7 @@ impl Test {
         let run = Run {
             boot: self.boot,
             cargo: self.cargo.clone(),
+            cmdline: self.cmdline.clone(),
             args: self.args.clone(),
         };
         run.run(shell);
@@ -0,0 +1,168 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use syscall::trace::{self, Event, Kind, Phase};
+use xshell::Shell;
+
+#[derive(Parser)]
+pub struct Trace {
+    /// A trace copied from `/dev/trace`, or a console log with the output of `trace dump`.
+    file: PathBuf,
+    /// Print all the events.
+    #[clap(long)]
+    events: bool,
+}
+
+impl Trace {
+    pub fn run(&self, _shell: &Shell) {
+        let data = fs::read(&self.file).unwrap();
+        let data = if data.starts_with(&trace::MAGIC) {
+            data
+        } else {
+            from_console(&data).expect("no trace found in the console log")
+        };
+        let (header, modules, mut events) = trace::parse(&data).expect("malformed trace");
+        // Each core is in order. Cycles are per core, but uptime is global.
+        events.sort_by_key(|e| e.time);
+        println!(
+            "{} events on {} cores, {} dropped",
+            events.len(),
+            header.num_cores,
+            header.dropped
+        );
+        if self.events {
+            for e in &events {
+                println!(
+                    "{:>14.3}us core {} task {:>4} {:?} {:?} {}",
+                    e.time as f64 / 1000.0,
+                    e.core,
+                    e.task.map(|t| t.to_string()).unwrap_or("-".to_owned()),
+                    e.kind,
+                    e.phase,
+                    describe(e, &modules)
+                );
+            }
+        }
+        print_latencies(&events, &modules);
+        let mut switches = BTreeMap::<u16, usize>::new();
+        for e in events.iter().filter(|e| e.kind == Kind::Schedule) {
+            *switches.entry(e.core).or_default() += 1;
+        }
+        for (core, count) in switches {
+            println!("core {}: {} context switches", core, count);
+        }
+    }
+}
+
+fn describe(e: &Event, modules: &[&str]) -> String {
+    match e.kind {
+        Kind::Syscall => format!("syscall {}", e.arg),
+        Kind::ModuleCall => match modules.get(e.arg as usize) {
+            Some(name) => format!("module {}", name),
+            None => format!("module #{}", e.arg),
+        },
+        Kind::Schedule => format!("-> task {}", e.arg),
+        Kind::Exception => format!("EC {:#04x}", e.arg),
+        Kind::Interrupt => format!("IRQ {}", e.arg),
+    }
+}
+
+/// Match begin and end events, and print latency statistics and histograms, in cycles.
+/// Spans are nested per task, or per core outside of tasks. A span that ends on another core
+/// is skipped, as cycle counts of different cores are not comparable.
+fn print_latencies(events: &[Event], modules: &[&str]) {
+    let mut open = BTreeMap::<(Kind, Option<u32>, Option<u16>), Vec<&Event>>::new();
+    let mut spans = BTreeMap::<(Kind, String), Vec<u64>>::new();
+    let mut migrated = 0;
+    for e in events {
+        let key = (e.kind, e.task, e.task.is_none().then_some(e.core));
+        match e.phase {
+            Phase::Begin => open.entry(key).or_default().push(e),
+            Phase::End => {
+                let Some(begin) = open.get_mut(&key).and_then(|s| s.pop()) else {
+                    continue;
+                };
+                if begin.arg != e.arg {
+                    continue;
+                }
+                if begin.core != e.core {
+                    migrated += 1;
+                    continue;
+                }
+                let cycles = e.cycles.wrapping_sub(begin.cycles);
+                let name = describe(e, modules);
+                spans.entry((e.kind, name)).or_default().push(cycles);
+            }
+            Phase::Instant => {}
+        }
+    }
+    println!(
+        "{:<32} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "span (cycles)", "count", "min", "p50", "p99", "max"
+    );
+    for ((_, name), cycles) in &mut spans {
+        cycles.sort();
+        let percentile = |p: usize| cycles[(cycles.len() - 1) * p / 100];
+        println!(
+            "{:<32} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            name,
+            cycles.len(),
+            cycles[0],
+            percentile(50),
+            percentile(99),
+            cycles[cycles.len() - 1]
+        );
+    }
+    if migrated != 0 {
+        println!("{} spans ended on another core", migrated);
+    }
+    let mut kinds = BTreeMap::<Kind, Vec<u64>>::new();
+    for ((kind, _), cycles) in spans {
+        kinds.entry(kind).or_default().extend(cycles);
+    }
+    for (kind, cycles) in kinds {
+        println!("\n{:?} latency (cycles):", kind);
+        print_histogram(&cycles);
+    }
+}
+
+/// Print a histogram with power-of-two buckets.
+fn print_histogram(values: &[u64]) {
+    let mut buckets = BTreeMap::<u32, usize>::new();
+    for v in values {
+        *buckets.entry(u64::BITS - v.leading_zeros()).or_default() += 1;
+    }
+    let max = buckets.values().copied().max().unwrap_or(0);
+    for (bucket, count) in buckets {
+        let low = if bucket == 0 { 0 } else { 1u64 << (bucket - 1) };
+        let bar = "#".repeat((count * 50).div_ceil(max));
+        println!(
+            "  [{:>10}, {:>10}) {:>8} {}",
+            low,
+            1u128 << bucket,
+            count,
+            bar
+        );
+    }
+}
+
+/// Extract the last complete trace printed by `trace dump`.
+fn from_console(log: &[u8]) -> Option<Vec<u8>> {
+    let log = String::from_utf8_lossy(log);
+    let mut trace = None;
+    let mut current = Vec::new();
+    for line in log.lines() {
+        let Some(start) = line.find(trace::CONSOLE_PREFIX) else {
+            continue;
+        };
+        let line = line[start..].trim_end();
+        if line == trace::CONSOLE_END {
+            trace = Some(std::mem::take(&mut current));
+            continue;
+        }
+        let hex = &line[trace::CONSOLE_PREFIX.len()..];
+        for i in (0..hex.len()).step_by(2) {
+            current.push(u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()?);
+        }
+    }
+    trace
+}
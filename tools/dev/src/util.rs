@@ -72,6 +72,12 @@ impl CargoFlags {
         target_path.to_str().unwrap().to_owned()
     }
 
+    /// The target of programs that use `std`. Its `os` is `hermit`, see `libs/std-sys`.
+    pub fn std_target(&self) -> String {
+        self.user_traget()
+            .replace("-sophon.json", "-unknown-sophon.json")
+    }
+
     pub fn kernel_module_traget(&self) -> String {
         self.user_traget()
     }
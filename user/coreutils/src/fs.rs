@@ -0,0 +1,247 @@
+//! File commands.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user::io::{self, Write};
+use user::{fs, path};
+
+use crate::{FAILURE, USAGE};
+
+/// Split the leading `-x` options from the operands.
+fn split_options<'a, 'b>(args: &'b [&'a str]) -> (Vec<char>, &'b [&'a str]) {
+    let count = args
+        .iter()
+        .take_while(|a| a.len() > 1 && a.starts_with('-'))
+        .count();
+    let options = args[..count].iter().flat_map(|a| a[1..].chars()).collect();
+    (options, &args[count..])
+}
+
+fn basename(path: &str) -> &str {
+    user::path::file_name(path).unwrap_or(path)
+}
+
+fn is_dir(path: &str) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_dir())
+}
+
+fn list_dir(path: &str) -> io::Result<Vec<String>> {
+    fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.file_name().to_owned()))
+        .collect()
+}
+
+pub fn cat(args: &[&str]) -> isize {
+    let mut status = 0;
+    for path in args {
+        let result = fs::read(path).and_then(|data| io::stdout().write_all(&data));
+        if let Err(e) = result {
+            eprintln!("cat: {}: {}", path, e);
+            status = FAILURE;
+        }
+    }
+    status
+}
+
+pub fn ls(args: &[&str]) -> isize {
+    let (options, paths) = split_options(args);
+    let long = options.contains(&'l');
+    let all = options.contains(&'a');
+    if let Some(c) = options.iter().find(|c| !"la".contains(**c)) {
+        eprintln!("ls: invalid option -- '{}'", c);
+        println!("Usage: ls [-la] [path...]");
+        return USAGE;
+    }
+    let paths = if paths.is_empty() { &["."] } else { paths };
+    let mut status = 0;
+    for (i, path) in paths.iter().enumerate() {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("ls: {}: {}", path, e);
+                status = FAILURE;
+                continue;
+            }
+        };
+        if !metadata.is_dir() {
+            print_entry(path, path, long);
+            continue;
+        }
+        let Ok(mut names) = list_dir(path) else {
+            eprintln!("ls: {}: cannot read directory", path);
+            status = FAILURE;
+            continue;
+        };
+        if paths.len() > 1 {
+            if i > 0 {
+                println!("");
+            }
+            println!("{}:", path);
+        }
+        names.sort();
+        if all {
+            names.insert(0, "..".to_owned());
+            names.insert(0, ".".to_owned());
+        }
+        for name in names.iter().filter(|n| all || !n.starts_with('.')) {
+            print_entry(name, &path::join(path, name), long);
+        }
+    }
+    status
+}
+
+fn print_entry(name: &str, path: &str, long: bool) {
+    let metadata = fs::metadata(path).ok();
+    let is_dir = metadata.is_some_and(|m| m.is_dir());
+    let suffix = if is_dir { "/" } else { "" };
+    if long {
+        let kind = if is_dir { 'd' } else { '-' };
+        let size = metadata.map(|m| m.len()).unwrap_or(0);
+        println!("{} {:8} {}{}", kind, size, name, suffix);
+    } else {
+        println!("{}{}", name, suffix);
+    }
+}
+
+pub fn mkdir(args: &[&str]) -> isize {
+    if args.is_empty() {
+        println!("Usage: mkdir <path...>");
+        return USAGE;
+    }
+    let mut status = 0;
+    for path in args {
+        if fs::create_dir(path).is_err() {
+            eprintln!("mkdir: {}: cannot create directory", path);
+            status = FAILURE;
+        }
+    }
+    status
+}
+
+fn remove(path: &str, recursive: bool) -> io::Result<()> {
+    if recursive && is_dir(path) {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+pub fn rm(args: &[&str]) -> isize {
+    let (options, paths) = split_options(args);
+    let recursive = options.contains(&'r');
+    if paths.is_empty() || options.iter().any(|c| *c != 'r') {
+        println!("Usage: rm [-r] <path...>");
+        return USAGE;
+    }
+    let mut status = 0;
+    for path in paths {
+        if !recursive && is_dir(path) {
+            eprintln!("rm: {}: is a directory", path);
+            status = FAILURE;
+        } else if remove(path, recursive).is_err() {
+            eprintln!("rm: {}: cannot remove", path);
+            status = FAILURE;
+        }
+    }
+    status
+}
+
+/// The destination path of `src`. A directory `dst` means a file in that directory.
+fn target(src: &str, dst: &str) -> String {
+    if is_dir(dst) {
+        path::join(dst, basename(src))
+    } else {
+        dst.to_owned()
+    }
+}
+
+pub fn cp(args: &[&str]) -> isize {
+    let [src, dst] = args else {
+        println!("Usage: cp <source> <destination>");
+        return USAGE;
+    };
+    if is_dir(src) {
+        eprintln!("cp: {}: is a directory", src);
+        return FAILURE;
+    }
+    if fs::copy(src, &target(src, dst)).is_err() {
+        eprintln!("cp: cannot copy {} to {}", src, dst);
+        return FAILURE;
+    }
+    0
+}
+
+pub fn mv(args: &[&str]) -> isize {
+    let [src, dst] = args else {
+        println!("Usage: mv <source> <destination>");
+        return USAGE;
+    };
+    if fs::rename(src, &target(src, dst)).is_err() {
+        eprintln!("mv: cannot move {} to {}", src, dst);
+        return FAILURE;
+    }
+    0
+}
+
+pub fn hexdump(args: &[&str]) -> isize {
+    let [path] = args else {
+        println!("Usage: hexdump <path>");
+        return USAGE;
+    };
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("hexdump: {}: {}", path, e);
+            return FAILURE;
+        }
+    };
+    for (i, line) in data.chunks(16).enumerate() {
+        print!("{:08x} ", i * 16);
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => print!(" {:02x}", b),
+                None => print!("   "),
+            }
+            if j == 7 {
+                print!(" ");
+            }
+        }
+        let text = line
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        println!("  |{}|", text);
+    }
+    println!("{:08x}", data.len());
+    0
+}
+
+pub fn mount(args: &[&str]) -> isize {
+    match args {
+        [] => match user::sys::mounts() {
+            Ok(mounts) => {
+                print!("{}", mounts);
+                0
+            }
+            Err(_) => FAILURE,
+        },
+        [fs, path] => {
+            if user::sys::mount(path, 0, fs).is_err() {
+                eprintln!("mount: cannot mount {} on {}", fs, path);
+                return FAILURE;
+            }
+            0
+        }
+        _ => {
+            println!("Usage: mount [<fs> <path>]");
+            USAGE
+        }
+    }
+}
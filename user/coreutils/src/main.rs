@@ -0,0 +1,78 @@
+//! A multi-call binary with the basic user commands.
+//!
+//! The command is picked by the name the program is invoked with, so `/bin/cat` can be a
+//! symlink to `/bin/coreutils`. `coreutils <command> [args...]` also works.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user;
+
+mod fs;
+mod sys;
+
+use alloc::vec::Vec;
+
+/// Exit status for failed commands.
+const FAILURE: isize = 1;
+/// Exit status for invalid arguments.
+const USAGE: isize = 2;
+
+type Applet = fn(&[&str]) -> isize;
+
+const APPLETS: &[(&str, Applet)] = &[
+    ("cat", fs::cat),
+    ("cp", fs::cp),
+    ("dmesg", sys::dmesg),
+    ("echo", sys::echo),
+    ("free", sys::free),
+    ("heapdump", sys::heapdump),
+    ("hexdump", fs::hexdump),
+    ("kill", sys::kill),
+    ("ls", fs::ls),
+    ("lsmod", sys::lsmod),
+    ("memlimit", sys::memlimit),
+    ("mkdir", fs::mkdir),
+    ("mount", fs::mount),
+    ("mv", fs::mv),
+    ("poweroff", sys::poweroff),
+    ("ps", sys::ps),
+    ("reboot", sys::reboot),
+    ("rm", fs::rm),
+    ("slabinfo", sys::slabinfo),
+    ("sleep", sys::sleep),
+    ("trace", sys::trace),
+    ("uname", sys::uname),
+];
+
+fn run(name: &str, args: &[&str]) -> isize {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    if let Some((_, applet)) = APPLETS.iter().find(|(n, _)| *n == name) {
+        return applet(args);
+    }
+    if name == "coreutils" {
+        if let Some((name, args)) = args.split_first() {
+            return run(name, args);
+        }
+    } else {
+        eprintln!("coreutils: unknown command: {}", name);
+    }
+    let names = APPLETS.iter().map(|(n, _)| *n).collect::<Vec<_>>();
+    println!("Usage: coreutils <command> [args...]");
+    println!("Commands: {}", names.join(" "));
+    USAGE
+}
+
+#[no_mangle]
+pub extern "C" fn _start(argc: isize, argv: *const *const u8) -> isize {
+    unsafe { user::env::init(argc, argv) };
+    let args = user::env::args().collect::<Vec<_>>();
+    let status = match args.split_first() {
+        Some((name, args)) => run(name, args),
+        None => run("coreutils", &[]),
+    };
+    user::sys::exit(status)
+}
@@ -0,0 +1,419 @@
+//! Process and system information commands.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+use user::fs::{self, File};
+use user::io::Read;
+use user::sys::{klog, trace, ProcInfo, ProcState, Signal, SlabInfo, ZoneInfo};
+
+use crate::{FAILURE, USAGE};
+
+pub fn echo(args: &[&str]) -> isize {
+    let (newline, args) = match args.split_first() {
+        Some((&"-n", args)) => (false, args),
+        _ => (true, args),
+    };
+    print!("{}", args.join(" "));
+    if newline {
+        println!("");
+    }
+    0
+}
+
+pub fn ps(args: &[&str]) -> isize {
+    if !args.is_empty() {
+        println!("Usage: ps");
+        return USAGE;
+    }
+    let mut procs = vec![ProcInfo::EMPTY; 64];
+    loop {
+        let len = user::sys::list_procs(&mut procs);
+        if len <= procs.len() {
+            procs.truncate(len);
+            break;
+        }
+        procs.resize(len, ProcInfo::EMPTY);
+    }
+    println!("  PID  PPID  PGID THR S     TIME    RSS CMD");
+    for p in &procs {
+        let state = match p.state {
+            ProcState::Running => 'R',
+            ProcState::Sleeping => 'S',
+            ProcState::Stopped => 'T',
+            ProcState::Zombie => 'Z',
+        };
+        let ms = p.cpu_time / 1_000_000;
+        println!(
+            "{:5} {:5} {:5} {:3} {} {:5}.{:03} {:6} {}",
+            p.pid,
+            p.ppid,
+            p.pgid,
+            p.threads,
+            state,
+            ms / 1000,
+            ms % 1000,
+            p.memory >> 10,
+            p.name()
+        );
+    }
+    0
+}
+
+fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(n) = name.parse() {
+        return Signal::from_usize(n);
+    }
+    let signal = match name.trim_start_matches("SIG") {
+        "INT" => Signal::Interrupt,
+        "ILL" => Signal::IllegalInstruction,
+        "TRAP" => Signal::Trap,
+        "BUS" => Signal::BusError,
+        "FPE" => Signal::FloatingPoint,
+        "KILL" => Signal::Kill,
+        "USR1" => Signal::User1,
+        "SEGV" => Signal::SegmentationFault,
+        "USR2" => Signal::User2,
+        "TERM" => Signal::Terminate,
+        "CONT" => Signal::Continue,
+        "STOP" => Signal::Stop,
+        "TSTP" => Signal::TerminalStop,
+        _ => return None,
+    };
+    Some(signal)
+}
+
+pub fn kill(args: &[&str]) -> isize {
+    let (signal, pids) = match args.split_first() {
+        Some((s, pids)) if s.starts_with('-') && !pids.is_empty() => match parse_signal(&s[1..]) {
+            Some(signal) => (signal, pids),
+            None => {
+                eprintln!("kill: {}: invalid signal", &s[1..]);
+                return USAGE;
+            }
+        },
+        _ => (Signal::Terminate, args),
+    };
+    if pids.is_empty() {
+        println!("Usage: kill [-SIGNAL] <pid...>");
+        return USAGE;
+    }
+    let mut status = 0;
+    for pid in pids {
+        let Ok(pid) = pid.parse::<isize>() else {
+            eprintln!("kill: {}: invalid pid", pid);
+            status = FAILURE;
+            continue;
+        };
+        if user::sys::kill(pid, signal) != 0 {
+            eprintln!("kill: {}: no such process", pid);
+            status = FAILURE;
+        }
+    }
+    status
+}
+
+pub fn free(_args: &[&str]) -> isize {
+    let mem = user::sys::meminfo();
+    let kb = |bytes: usize| bytes >> 10;
+    println!("{:<8}{:>12} {:>12} {:>12}", "", "total", "used", "free");
+    println!(
+        "{:<8}{:>9} KB {:>9} KB {:>9} KB",
+        "Mem:",
+        kb(mem.total),
+        kb(mem.total - mem.free),
+        kb(mem.free)
+    );
+    // Zones count 4 KiB frames
+    let mut zones = vec![ZoneInfo::default(); 4];
+    let len = user::sys::zone_info(&mut zones);
+    zones.truncate(len);
+    for zone in zones.iter().filter(|z| z.total != 0) {
+        println!(
+            "{:<8}{:>9} KB {:>9} KB {:>9} KB",
+            format!("{}:", zone.name()),
+            zone.total * 4,
+            zone.used * 4,
+            zone.free * 4
+        );
+    }
+    0
+}
+
+pub fn heapdump(_args: &[&str]) -> isize {
+    if user::sys::heap_dump() < 0 {
+        println!("heapdump: the kernel is built without heap_debug");
+        return FAILURE;
+    }
+    0
+}
+
+/// Run a command with its user memory limited to the given number of KB.
+pub fn memlimit(args: &[&str]) -> isize {
+    let Some((limit, command)) = args.split_first().filter(|(_, c)| !c.is_empty()) else {
+        println!("Usage: memlimit <KB> <command> [args...]");
+        return USAGE;
+    };
+    let Some(bytes) = limit
+        .parse::<usize>()
+        .ok()
+        .and_then(|kb| kb.checked_mul(1024))
+    else {
+        eprintln!("memlimit: {}: invalid limit", limit);
+        return USAGE;
+    };
+    if user::sys::set_mem_limit(0, bytes) != 0 {
+        eprintln!("memlimit: failed to set the limit");
+        return FAILURE;
+    }
+    let path = if command[0].contains('/') {
+        String::from(command[0])
+    } else {
+        format!("/bin/{}", command[0])
+    };
+    user::sys::exec(&path, &command[1..]);
+    eprintln!("memlimit: {}: command not found", command[0]);
+    127
+}
+
+pub fn slabinfo(_args: &[&str]) -> isize {
+    let mut slabs = vec![SlabInfo::default(); 16];
+    let len = user::sys::slab_info(&mut slabs);
+    slabs.truncate(len);
+    println!(
+        "{:>6} {:>10} {:>10} {:>8} {:>6} {:>9}",
+        "size", "allocs", "frees", "in use", "slabs", "transfers"
+    );
+    for s in &slabs {
+        println!(
+            "{:>6} {:>10} {:>10} {:>8} {:>6} {:>9}",
+            s.size,
+            s.allocs,
+            s.frees,
+            s.allocs.saturating_sub(s.frees),
+            s.slabs,
+            s.depot_transfers
+        );
+    }
+    0
+}
+
+pub fn uname(args: &[&str]) -> isize {
+    let uts = user::sys::uname();
+    let fields: &[(char, &str)] = &[
+        ('s', uts.sysname()),
+        ('n', uts.nodename()),
+        ('r', uts.release()),
+        ('m', uts.machine()),
+    ];
+    let mut options = String::new();
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(o) if o.chars().all(|c| "asnrm".contains(c)) => options.push_str(o),
+            _ => {
+                println!("Usage: uname [-asnrm]");
+                return USAGE;
+            }
+        }
+    }
+    if options.is_empty() {
+        options.push('s');
+    }
+    let selected = fields
+        .iter()
+        .filter(|(c, _)| options.contains(*c) || options.contains('a'))
+        .map(|(_, v)| *v)
+        .collect::<Vec<_>>();
+    println!("{}", selected.join(" "));
+    0
+}
+
+/// Parse a duration like `1`, `0.5`, `2s`, `100ms`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, scale) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 1_000_000)
+    } else {
+        (s.strip_suffix('s').unwrap_or(s), 1_000_000_000)
+    };
+    let (int, frac) = number.split_once('.').unwrap_or((number, ""));
+    let mut nanos = int.parse::<u64>().ok()? * scale;
+    let mut unit = scale;
+    for c in frac.chars() {
+        unit /= 10;
+        nanos += c.to_digit(10)? as u64 * unit;
+    }
+    Some(Duration::from_nanos(nanos))
+}
+
+pub fn sleep(args: &[&str]) -> isize {
+    let Some(duration) = args.first().and_then(|s| parse_duration(s)) else {
+        println!("Usage: sleep <seconds>");
+        return USAGE;
+    };
+    user::sys::sleep(duration);
+    0
+}
+
+pub fn lsmod(_args: &[&str]) -> isize {
+    let mut buf = [0u8; 1024];
+    let len = user::sys::list_modules(&mut buf);
+    print!("{}", String::from_utf8_lossy(&buf[..len]));
+    0
+}
+
+const DMESG_USAGE: &str =
+    "Usage: dmesg [-w] [-l <level>] [-m <module>] | dmesg -n [<module>=]<level>";
+
+pub fn dmesg(args: &[&str]) -> isize {
+    let mut follow = false;
+    let mut max_level = klog::LevelFilter::Trace;
+    let mut module = "";
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-w" => follow = true,
+            "-l" => match args.next().map(|level| level.parse()) {
+                Some(Ok(level)) => max_level = level,
+                _ => {
+                    println!("{}", DMESG_USAGE);
+                    return USAGE;
+                }
+            },
+            "-m" => match args.next() {
+                Some(prefix) => module = prefix,
+                None => {
+                    println!("{}", DMESG_USAGE);
+                    return USAGE;
+                }
+            },
+            "-n" if args.len() == 1 => return set_log_level(args.next().unwrap()),
+            _ => {
+                println!("{}", DMESG_USAGE);
+                return USAGE;
+            }
+        }
+    }
+    let mut buf = vec![0u8; 16 << 10];
+    let mut seq = 0;
+    loop {
+        let len = user::sys::read_kernel_log(seq, &mut buf);
+        for record in klog::records(&buf[..len]) {
+            let header = record.header;
+            if header.seq > seq {
+                println!("[... {} records dropped ...]", header.seq - seq);
+            }
+            seq = header.seq + 1;
+            let matches = record
+                .module
+                .strip_prefix(module)
+                .is_some_and(|rest| module.is_empty() || rest.is_empty() || rest.starts_with("::"));
+            if header.level > max_level || !matches {
+                continue;
+            }
+            let pid = match header.pid {
+                Some(pid) => format!("{}", pid),
+                None => String::from("-"),
+            };
+            println!(
+                "[{:5}.{:06}] {}:{} {} {}: {}",
+                header.timestamp / 1_000_000_000,
+                header.timestamp % 1_000_000_000 / 1000,
+                header.core,
+                pid,
+                record.module,
+                header.level,
+                record.message
+            );
+        }
+        if len == 0 {
+            if !follow {
+                return 0;
+            }
+            user::sys::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// `dmesg -n`: set the kernel log level of a module, or the default level.
+fn set_log_level(filter: &str) -> isize {
+    let (module, level) = filter.split_once('=').unwrap_or(("", filter));
+    let Ok(level) = level.parse() else {
+        eprintln!("dmesg: bad level {}", level);
+        return USAGE;
+    };
+    if user::sys::set_log_level(module, level) != 0 {
+        eprintln!("dmesg: cannot set the log level");
+        return FAILURE;
+    }
+    0
+}
+
+/// Control kernel tracing through `/dev/trace`. `dump` prints the trace on the console, for
+/// `cargo dev trace`, or copies it to a file.
+pub fn trace(args: &[&str]) -> isize {
+    let command = match args {
+        ["start"] => trace::TRACE_START,
+        ["stop"] => trace::TRACE_STOP,
+        ["clear"] => trace::TRACE_CLEAR,
+        ["dump"] => trace::TRACE_PRINT,
+        ["dump", file] => return dump_trace(file),
+        _ => {
+            println!("Usage: trace start|stop|clear|dump [<file>]");
+            return USAGE;
+        }
+    };
+    let Ok(device) = File::open("/dev/trace") else {
+        eprintln!("trace: cannot open /dev/trace");
+        return FAILURE;
+    };
+    if user::sys::ioctl(device.fd(), command, 0).is_err() {
+        eprintln!("trace: failed");
+        return FAILURE;
+    }
+    0
+}
+
+fn dump_trace(file: &str) -> isize {
+    let Ok(mut device) = File::open("/dev/trace") else {
+        eprintln!("trace: cannot open /dev/trace");
+        return FAILURE;
+    };
+    // The kernel encodes the whole trace for each read, so read it in large chunks.
+    let mut data = Vec::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        match device.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => data.extend_from_slice(&buf[..len]),
+            Err(_) => {
+                eprintln!("trace: cannot read /dev/trace");
+                return FAILURE;
+            }
+        }
+    }
+    if fs::write(file, &data).is_err() {
+        eprintln!("trace: cannot write {}", file);
+        return FAILURE;
+    }
+    println!("trace: {} bytes written to {}", data.len(), file);
+    0
+}
+
+/// Ask init to shut down. See `user/init`.
+fn request_shutdown(name: &str, signal: Signal) -> isize {
+    if user::sys::kill(1, signal) != 0 {
+        eprintln!("{}: cannot signal init", name);
+        return FAILURE;
+    }
+    0
+}
+
+pub fn reboot(_args: &[&str]) -> isize {
+    request_shutdown("reboot", Signal::Terminate)
+}
+
+pub fn poweroff(_args: &[&str]) -> isize {
+    request_shutdown("poweroff", Signal::User2)
+}
@@ -0,0 +1,149 @@
+//! Triggers user faults, to check that the kernel terminates only the faulting process.
+//!
+//! `faults <kind>` triggers one fault. `faults` or `faults all` runs each kind in a child
+//! process, and checks that it exits with the status of the expected signal (128 + signal).
+//!
+//! SP alignment checks are disabled for EL0, and floating-point exceptions are not trapped,
+//! so there are no tests for them.
+
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::hint::black_box;
+use core::ptr;
+use user::sys::Signal;
+
+/// The fault kinds, with the signal each one is terminated by.
+const FAULTS: &[(&str, Signal, fn())] = &[
+    ("null", Signal::SegmentationFault, null_read),
+    ("write-code", Signal::SegmentationFault, write_code),
+    ("exec-unmapped", Signal::SegmentationFault, exec_unmapped),
+    ("exec-stack", Signal::SegmentationFault, exec_stack),
+    (
+        "undefined",
+        Signal::IllegalInstruction,
+        undefined_instruction,
+    ),
+    ("sysreg", Signal::IllegalInstruction, system_register),
+    ("brk", Signal::Trap, breakpoint),
+    ("pc-align", Signal::BusError, misaligned_pc),
+    ("exclusive-align", Signal::BusError, misaligned_exclusive),
+    ("stack-overflow", Signal::SegmentationFault, stack_overflow),
+];
+
+fn null_read() {
+    unsafe { black_box(ptr::read_volatile(black_box(ptr::null::<usize>()))) };
+}
+
+fn write_code() {
+    unsafe { ptr::write_volatile(black_box(null_read as *mut u32), 0) };
+}
+
+fn exec_unmapped() {
+    let f: extern "C" fn() = unsafe { core::mem::transmute(black_box(0xdead_0000usize)) };
+    f();
+}
+
+fn exec_stack() {
+    // `ret`
+    let code = black_box([0xd65f03c0u32; 4]);
+    let f: extern "C" fn() = unsafe { core::mem::transmute(code.as_ptr()) };
+    f();
+}
+
+fn undefined_instruction() {
+    unsafe { asm!("udf #0") };
+}
+
+fn system_register() {
+    unsafe { asm!("mrs {}, ttbr1_el1", out(reg) _) };
+}
+
+fn breakpoint() {
+    unsafe { asm!("brk #0") };
+}
+
+fn misaligned_pc() {
+    let target = black_box(misaligned_pc as usize + 2);
+    unsafe { asm!("br {}", in(reg) target) };
+}
+
+fn misaligned_exclusive() {
+    let data = black_box([0u64; 2]);
+    let address = data.as_ptr() as usize + 1;
+    unsafe { asm!("ldxr {}, [{}]", out(reg) _, in(reg) address) };
+}
+
+#[allow(unconditional_recursion)]
+fn recurse(depth: usize) -> usize {
+    let frame = black_box([depth; 64]);
+    recurse(depth + 1) + frame[0]
+}
+
+fn stack_overflow() {
+    black_box(recurse(0));
+}
+
+fn run_all() -> isize {
+    let mut failed = 0;
+    for (name, signal, fault) in FAULTS {
+        let pid = user::sys::fork();
+        if pid == 0 {
+            fault();
+            println!("faults: {}: no fault", name);
+            user::sys::exit(0);
+        }
+        let mut exit_code = 0;
+        user::sys::waitpid(pid as _, &mut exit_code);
+        let expected = 128 + *signal as isize;
+        if exit_code == expected {
+            println!("faults: {:<16} ok ({:?})", name, signal);
+        } else {
+            println!(
+                "faults: {:<16} FAILED: exit code {}, expected {}",
+                name, exit_code, expected
+            );
+            failed += 1;
+        }
+    }
+    println!(
+        "faults: {} of {} passed",
+        FAULTS.len() - failed,
+        FAULTS.len()
+    );
+    if failed == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start(argc: isize, argv: *const *const u8) -> isize {
+    unsafe { user::env::init(argc, argv) };
+    let args = user::env::args().collect::<Vec<_>>();
+    let status = match args.get(1).copied() {
+        None | Some("all") => run_all(),
+        Some(kind) => match FAULTS.iter().find(|(name, _, _)| *name == kind) {
+            Some((_, _, fault)) => {
+                fault();
+                println!("faults: {}: no fault", kind);
+                1
+            }
+            None => {
+                let names = FAULTS.iter().map(|(n, _, _)| *n).collect::<Vec<_>>();
+                println!("Usage: faults [all|<kind>]");
+                println!("Kinds: {}", names.join(" "));
+                2
+            }
+        },
+    };
+    user::sys::exit(status)
+}
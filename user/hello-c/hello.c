@@ -0,0 +1,60 @@
+/*
+ * A C program built against libs/libc.
+ *
+ * Prints its arguments and the working directory, lists a directory,
+ * and runs /bin/echo in a child process.
+ */
+
+#include <dirent.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int list(const char *path) {
+    DIR *dir = opendir(path);
+    if (dir == NULL) {
+        perror(path);
+        return -1;
+    }
+    struct dirent *entry;
+    while ((entry = readdir(dir)) != NULL) {
+        printf("  %-16s %s\n", entry->d_name, entry->d_type == DT_DIR ? "dir" : "file");
+    }
+    closedir(dir);
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    printf("Hello from C! pid=%d\n", getpid());
+    for (int i = 0; i < argc; i++) {
+        printf("argv[%d] = \"%s\"\n", i, argv[i]);
+    }
+
+    char *cwd = getcwd(NULL, 0);
+    printf("cwd: %s\n", cwd);
+    free(cwd);
+
+    const char *path = argc > 1 ? argv[1] : "/etc";
+    printf("%s:\n", path);
+    if (list(path) != 0) {
+        return EXIT_FAILURE;
+    }
+
+    pid_t pid = fork();
+    if (pid < 0) {
+        perror("fork");
+        return EXIT_FAILURE;
+    }
+    if (pid == 0) {
+        char *args[] = {"echo", "hello from a child process", NULL};
+        execvp(args[0], args);
+        perror("execvp");
+        _exit(127);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    printf("child %d exited with %d\n", pid, WEXITSTATUS(status));
+    return 0;
+}
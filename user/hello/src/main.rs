@@ -7,5 +7,5 @@ extern crate user;
 #[no_mangle]
 pub extern "C" fn _start(_argc: isize, _argv: *const *const u8) -> isize {
     println!("Hello, world!");
-    user::sys::exit()
+    user::sys::exit(0)
 }
@@ -0,0 +1,70 @@
+//! Parser for `/etc/inittab`.
+//!
+//! Each line is `<tty>::<action>:<command> [args...]`. `<tty>` is a device under `/dev`
+//! used as the standard input and output of the command. An empty `<tty>` inherits
+//! the console of init. Lines starting with `#` are comments.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Run at boot, before everything else. Init waits for it to exit.
+    SysInit,
+    /// Run once at boot, without waiting.
+    Once,
+    /// Run at boot, and restart whenever it exits.
+    Respawn,
+    /// Run before shutdown or reboot. Init waits for it to exit.
+    Shutdown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub tty: Option<String>,
+    pub action: Action,
+    pub command: Vec<String>,
+}
+
+/// Parse the entries. Invalid lines are reported and skipped.
+pub fn parse(content: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Some(entry) => entries.push(entry),
+            None => println!("init: inittab:{}: invalid entry", i + 1),
+        }
+    }
+    entries
+}
+
+fn parse_line(line: &str) -> Option<Entry> {
+    let mut fields = line.splitn(4, ':');
+    let tty = fields.next()?.trim();
+    let _runlevels = fields.next()?;
+    let action = match fields.next()?.trim() {
+        "sysinit" => Action::SysInit,
+        "once" => Action::Once,
+        "respawn" => Action::Respawn,
+        "shutdown" => Action::Shutdown,
+        _ => return None,
+    };
+    let command: Vec<String> = fields
+        .next()?
+        .split_whitespace()
+        .map(|s| s.to_owned())
+        .collect();
+    if command.is_empty() {
+        return None;
+    }
+    Some(Entry {
+        tty: (!tty.is_empty()).then(|| tty.to_owned()),
+        action,
+        command,
+    })
+}
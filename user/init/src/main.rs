@@ -1,3 +1,9 @@
+//! The init process.
+//!
+//! Starts the services listed in `/etc/inittab`, restarts the ones that should respawn,
+//! and reaps orphaned processes. Shutdown and reboot are requested with signals:
+//! `Terminate` reboots, `User1` and `User2` power off.
+
 #![no_std]
 #![no_main]
 
@@ -5,62 +11,249 @@
 extern crate user;
 extern crate alloc;
 
-use alloc::boxed::Box;
-
-// static COUNTER: AtomicUsize = AtomicUsize::new(0);
-
-// extern "C" fn thread_start() {
-//     log!("thread_start");
-//     for _ in 0..10 {
-//         COUNTER.fetch_add(1, Ordering::SeqCst);
-//         for _ in 0..100000 {
-//             unsafe {
-//                 asm!("");
-//             }
-//         }
-//         log!(" - {}", COUNTER.load(Ordering::SeqCst));
-//     }
-//     exit_thread();
-// }
-
-// fn exit_thread() {
-//     Resource::open("proc:/me/thread-exit", 0, Mode::ReadWrite)
-//         .unwrap()
-//         .write(&[])
-//         .unwrap();
-// }
-
-// fn spawn_thread(f: *const extern "C" fn()) {
-//     Resource::open("proc:/me/spawn-thread", 0, Mode::ReadWrite)
-//         .unwrap()
-//         .write(Args::new(f))
-//         .unwrap();
-// }
+mod inittab;
 
-#[no_mangle]
-pub extern "C" fn _start(_argc: isize, _argv: *const *const u8) -> isize {
-    println!("Init process start...");
-    // println!("Launch tty...");
-    let mut ptr = Box::new(233);
-    println!("Forking... ptr={:?} {:?}", ptr.as_ref() as *const i32, ptr);
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+use inittab::{Action, Entry};
+use user::sys::{Fd, PowerAction, ProcInfo, ProcState, Signal};
+
+const INITTAB: &str = "/etc/inittab";
+
+/// Used when `/etc/inittab` is missing.
+const DEFAULT_INITTAB: &str = "::respawn:/bin/tty";
+
+/// A respawned service that exits sooner than this after it starts is failing.
+const MIN_UPTIME: Duration = Duration::from_secs(1);
+/// A service failing this many times in a row is no longer respawned.
+const MAX_FAILURES: usize = 10;
+
+/// How long processes get to exit after `Terminate`, before they are killed.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+const fn mask(signal: Signal) -> u32 {
+    1 << signal as usize
+}
+
+struct Service {
+    entry: Entry,
+    pid: Option<usize>,
+    started_at: Duration,
+    failures: usize,
+}
+
+struct Init {
+    services: Vec<Service>,
+    /// Signals received but not handled yet.
+    signals: u32,
+}
+
+impl Init {
+    fn new(entries: Vec<Entry>) -> Self {
+        let services = entries
+            .into_iter()
+            .map(|entry| Service {
+                entry,
+                pid: None,
+                started_at: Duration::ZERO,
+                failures: 0,
+            })
+            .collect();
+        Self {
+            services,
+            signals: 0,
+        }
+    }
+
+    fn boot(&mut self) {
+        for action in [Action::SysInit, Action::Once, Action::Respawn] {
+            for i in 0..self.services.len() {
+                if self.services[i].entry.action != action {
+                    continue;
+                }
+                self.start(i);
+                if action == Action::SysInit {
+                    self.wait_for(i);
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, i: usize) {
+        let service = &mut self.services[i];
+        service.pid = spawn(&service.entry);
+        service.started_at = user::sys::uptime();
+    }
+
+    /// Wait for a service to exit, reaping other processes in the meantime.
+    fn wait_for(&mut self, i: usize) {
+        while self.services[i].pid.is_some() {
+            let mut exit_code = 0;
+            match user::sys::wait(&mut exit_code, 0) {
+                -1 => break,
+                -2 => self.signals |= user::sys::take_signals(),
+                pid if pid > 0 => self.on_exit(pid as usize, exit_code, false),
+                _ => {}
+            }
+        }
+    }
+
+    /// Handle an exited child, and restart it if `respawn` is true and the service should respawn.
+    fn on_exit(&mut self, pid: usize, exit_code: isize, respawn: bool) {
+        let Some(i) = self.services.iter().position(|s| s.pid == Some(pid)) else {
+            // An orphan, just reaped.
+            return;
+        };
+        let service = &mut self.services[i];
+        service.pid = None;
+        if !respawn || service.entry.action != Action::Respawn {
+            return;
+        }
+        let command = service.entry.command.join(" ");
+        if user::sys::uptime() - service.started_at < MIN_UPTIME {
+            service.failures += 1;
+        } else {
+            service.failures = 0;
+        }
+        if service.failures >= MAX_FAILURES {
+            println!(
+                "init: '{}' is respawning too fast (exit code {}), disabled",
+                command, exit_code
+            );
+            return;
+        }
+        self.start(i);
+    }
+
+    fn run(&mut self) -> ! {
+        loop {
+            let mut exit_code = 0;
+            match user::sys::wait(&mut exit_code, 0) {
+                pid if pid > 0 => self.on_exit(pid as usize, exit_code, true),
+                // No children left. Wait for a signal.
+                -1 => user::sys::sleep(Duration::from_secs(1)),
+                _ => {}
+            }
+            let signals = core::mem::take(&mut self.signals) | user::sys::take_signals();
+            if signals & mask(Signal::Terminate) != 0 {
+                self.shutdown(PowerAction::Reboot);
+            } else if signals & (mask(Signal::User1) | mask(Signal::User2)) != 0 {
+                self.shutdown(PowerAction::PowerOff);
+            }
+        }
+    }
+
+    fn shutdown(&mut self, action: PowerAction) -> ! {
+        println!(
+            "init: {}...",
+            match action {
+                PowerAction::PowerOff => "powering off",
+                PowerAction::Reboot => "rebooting",
+            }
+        );
+        for i in 0..self.services.len() {
+            if self.services[i].entry.action == Action::Shutdown {
+                self.start(i);
+                self.wait_for(i);
+            }
+        }
+        // Ask all the processes to exit, then force the remaining ones.
+        if signal_all(Signal::Terminate) {
+            let deadline = user::sys::uptime() + SHUTDOWN_GRACE_PERIOD;
+            while user::sys::uptime() < deadline && reap_all() {
+                user::sys::sleep(Duration::from_millis(100));
+            }
+            signal_all(Signal::Kill);
+        }
+        while reap_all() {
+            user::sys::sleep(Duration::from_millis(10));
+        }
+        user::sys::power(action);
+        println!("init: power management is not available");
+        loop {
+            user::sys::sleep(Duration::from_secs(60));
+        }
+    }
+}
+
+/// Fork and run an inittab entry. Returns the pid of the new process.
+fn spawn(entry: &Entry) -> Option<usize> {
     let pid = user::sys::fork();
-    *ptr = 666;
-    println!(
-        "Forked: {} ptr={:?} {:?}",
-        pid,
-        ptr.as_ref() as *const i32,
-        ptr
-    );
-    if pid == 0 {
-        println!("I'm the child");
-        let status = user::sys::exec("/bin/tty", &[]);
-        println!("exec: {}", status);
-    } else {
-        println!("I'm the parent");
+    if pid < 0 {
+        println!("init: fork failed");
+        return None;
+    }
+    if pid > 0 {
+        return Some(pid as usize);
+    }
+    // Each service runs in its own process group.
+    user::sys::setpgid(0, 0);
+    if let Some(tty) = &entry.tty {
+        let path = format!("/dev/{}", tty);
+        let Some(fd) = user::sys::open(&path) else {
+            println!("init: {}: no such device", path);
+            user::sys::exit(1);
+        };
+        for stdio in [Fd::STDIN, Fd::STDOUT, Fd::STDERR] {
+            let _ = user::sys::dup2(fd, stdio);
+        }
+        user::sys::close(fd);
+    }
+    let args: Vec<&str> = entry.command[1..].iter().map(|s| s.as_str()).collect();
+    user::sys::exec(&entry.command[0], &args);
+    println!("init: {}: cannot execute", entry.command[0]);
+    user::sys::exit(127)
+}
+
+/// Send a signal to all the user processes except init.
+/// Returns false if there are no such processes.
+fn signal_all(signal: Signal) -> bool {
+    let mut procs = vec![ProcInfo::EMPTY; 64];
+    let len = user::sys::list_procs(&mut procs);
+    let mut sent = false;
+    for p in &procs[..usize::min(len, procs.len())] {
+        if p.pid > 1 && p.state != ProcState::Zombie {
+            user::sys::kill(p.pid as isize, signal);
+            sent = true;
+        }
+    }
+    sent
+}
+
+/// Reap the exited children. Returns true if some children are still running.
+fn reap_all() -> bool {
+    loop {
         let mut exit_code = 0;
-        user::sys::waitpid(pid as _, &mut exit_code);
-        println!("init: Child exited with code {}", exit_code);
+        match user::sys::wait(&mut exit_code, user::sys::WNOHANG) {
+            pid if pid > 0 => continue,
+            -1 => return false,
+            _ => return true,
+        }
+    }
+}
+
+fn read_inittab() -> String {
+    let Some(fd) = user::sys::open(INITTAB) else {
+        println!("init: {} not found, starting a shell", INITTAB);
+        return String::from(DEFAULT_INITTAB);
+    };
+    let mut content = vec![];
+    let mut buf = [0u8; 256];
+    while let Ok(len @ 1..) = user::sys::read(fd, &mut buf) {
+        content.extend_from_slice(&buf[..len]);
     }
-    loop {}
-    // user::sys::exit()
+    user::sys::close(fd);
+    String::from_utf8_lossy(&content).into_owned()
+}
+
+#[no_mangle]
+pub extern "C" fn _start(_argc: isize, _argv: *const *const u8) -> isize {
+    println!("Init process start...");
+    let entries = inittab::parse(&read_inittab());
+    let mut init = Init::new(entries);
+    init.boot();
+    init.run()
 }
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::hint::black_box;
+use core::time::Duration;
+
+/// Nice values of the workers. Workers with the same nice value should get a similar CPU share,
+/// and a worker with a lower nice value should get a larger CPU share.
+/// There are more workers than cores, so that they compete with each other.
+const WORKERS: [isize; 8] = [0, 0, 0, 0, 5, 5, 10, 10];
+
+const DURATION: Duration = Duration::from_secs(3);
+
+fn work_unit() {
+    let mut x = 0usize;
+    for i in 0..10000 {
+        x = black_box(x.wrapping_mul(31).wrapping_add(i));
+    }
+}
+
+fn run_worker(id: usize, nice: isize, deadline: Duration) -> ! {
+    user::sys::setpriority(0, nice);
+    let start = user::sys::uptime();
+    let mut units = 0usize;
+    while user::sys::uptime() < deadline {
+        work_unit();
+        units += 1;
+    }
+    let cpu = user::sys::cpu_time();
+    let wall = user::sys::uptime() - start;
+    println!(
+        "worker #{}: nice={:>3} units={:>8} cpu={:>5}ms share={:>3}%",
+        id,
+        nice,
+        units,
+        cpu.as_millis(),
+        cpu.as_millis() * 100 / wall.as_millis().max(1)
+    );
+    user::sys::exit(0)
+}
+
+fn idle_times() -> Vec<Duration> {
+    (0..user::sys::num_cores())
+        .map(|core| user::sys::idle_time(core).unwrap())
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn _start(_argc: isize, _argv: *const *const u8) -> isize {
+    println!(
+        "schedbench: {} workers, {}s",
+        WORKERS.len(),
+        DURATION.as_secs()
+    );
+    let idle_before = idle_times();
+    let deadline = user::sys::uptime() + DURATION;
+    let mut pids = Vec::new();
+    for (id, nice) in WORKERS.iter().enumerate() {
+        let pid = user::sys::fork();
+        if pid == 0 {
+            run_worker(id, *nice, deadline);
+        }
+        pids.push(pid);
+    }
+    for pid in pids {
+        let mut exit_code = 0;
+        user::sys::waitpid(pid as _, &mut exit_code);
+    }
+    // All the cores should be busy while the workers are running
+    for (core, (before, after)) in idle_before.iter().zip(idle_times()).enumerate() {
+        println!("core #{}: idle={}ms", core, (after - *before).as_millis());
+    }
+    println!("schedbench: done");
+    user::sys::exit(0)
+}
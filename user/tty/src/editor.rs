@@ -0,0 +1,304 @@
+//! Interactive line editing, with history and tab completion.
+//!
+//! While editing, the terminal is switched to non-canonical mode without echo and signals,
+//! and this module does the editing. Commands run with the original terminal settings.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use termios::{Termios, ECHO, ECHOE, ECHOK, ICANON, ISIG, VMIN};
+use user::sys::Fd;
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_U: u8 = 0x15;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+const ESCAPE: u8 = 0x1b;
+
+pub struct LineEditor {
+    history: Vec<String>,
+    /// The terminal settings for running commands. `None` if stdin is not a terminal.
+    cooked: Option<Termios>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self {
+            history: vec![],
+            cooked: termios::tcgetattr(Fd::STDIN).ok(),
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Print the prompt and read a line. Returns `None` on end-of-file.
+    pub fn read_line(&mut self, prompt: &str) -> Option<String> {
+        print!("{}", prompt);
+        let Some(cooked) = self.cooked else {
+            return read_canonical_line();
+        };
+        let mut editing = cooked;
+        editing.lflag &= !(ICANON | ECHO | ECHOE | ECHOK | ISIG);
+        editing.cc[VMIN] = 1;
+        let _ = termios::tcsetattr(Fd::STDIN, &editing);
+        let line = self.edit(prompt);
+        let _ = termios::tcsetattr(Fd::STDIN, &cooked);
+        if let Some(line) = &line {
+            let line = line.trim();
+            if !line.is_empty() && self.history.last().map(|s| s.as_str()) != Some(line) {
+                self.history.push(line.to_owned());
+            }
+        }
+        line
+    }
+
+    fn edit(&self, prompt: &str) -> Option<String> {
+        let mut line = Buffer {
+            prompt,
+            text: vec![],
+            cursor: 0,
+        };
+        // Position in the history. `history.len()` is the line being edited.
+        let mut index = self.history.len();
+        let mut edited = String::new();
+        loop {
+            let Some(c) = read_byte() else {
+                return None;
+            };
+            match c {
+                b'\n' | b'\r' => {
+                    println!("");
+                    return Some(String::from_utf8_lossy(&line.text).into_owned());
+                }
+                CTRL_C => {
+                    println!("^C");
+                    return Some(String::new());
+                }
+                CTRL_D if line.text.is_empty() => {
+                    println!("");
+                    return None;
+                }
+                CTRL_U => line.set(""),
+                BACKSPACE | DELETE => line.backspace(),
+                b'\t' => line.complete(),
+                ESCAPE => {
+                    if read_byte() != Some(b'[') {
+                        continue;
+                    }
+                    match read_byte() {
+                        // Up
+                        Some(b'A') if index > 0 => {
+                            if index == self.history.len() {
+                                edited = line.as_string();
+                            }
+                            index -= 1;
+                            line.set(&self.history[index]);
+                        }
+                        // Down
+                        Some(b'B') if index < self.history.len() => {
+                            index += 1;
+                            if index == self.history.len() {
+                                line.set(&edited);
+                            } else {
+                                line.set(&self.history[index]);
+                            }
+                        }
+                        // Right
+                        Some(b'C') => line.move_cursor(line.cursor + 1),
+                        // Left
+                        Some(b'D') if line.cursor > 0 => line.move_cursor(line.cursor - 1),
+                        // Home
+                        Some(b'H') => line.move_cursor(0),
+                        // End
+                        Some(b'F') => line.move_cursor(line.text.len()),
+                        _ => {}
+                    }
+                }
+                0x20..0x7f => line.insert(&[c]),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The line being edited.
+struct Buffer<'a> {
+    prompt: &'a str,
+    text: Vec<u8>,
+    cursor: usize,
+}
+
+impl Buffer<'_> {
+    fn as_string(&self) -> String {
+        String::from_utf8_lossy(&self.text).into_owned()
+    }
+
+    /// Redraw the line, and move the terminal cursor to the editing position.
+    fn refresh(&self) {
+        print!(
+            "\r\x1b[K{}{}",
+            self.prompt,
+            core::str::from_utf8(&self.text).unwrap_or_default()
+        );
+        if self.cursor < self.text.len() {
+            print!("\x1b[{}D", self.text.len() - self.cursor);
+        }
+    }
+
+    fn set(&mut self, text: &str) {
+        self.text = text.as_bytes().to_vec();
+        self.cursor = self.text.len();
+        self.refresh();
+    }
+
+    fn insert(&mut self, s: &[u8]) {
+        self.text
+            .splice(self.cursor..self.cursor, s.iter().copied());
+        self.cursor += s.len();
+        self.refresh();
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.text.remove(self.cursor);
+            self.refresh();
+        }
+    }
+
+    fn move_cursor(&mut self, cursor: usize) {
+        self.cursor = usize::min(cursor, self.text.len());
+        self.refresh();
+    }
+
+    /// Complete the word before the cursor.
+    ///
+    /// The first word is completed as a command, from the builtins and `/bin`.
+    /// Other words are completed as paths.
+    fn complete(&mut self) {
+        let before = &self.text[..self.cursor];
+        let start = before
+            .iter()
+            .rposition(|c| *c == b' ')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let Ok(word) = core::str::from_utf8(&before[start..]) else {
+            return;
+        };
+        let is_command = before[..start].iter().all(|c| *c == b' ');
+        let candidates = completions(word, is_command);
+        if candidates.is_empty() {
+            return;
+        }
+        if candidates.len() == 1 {
+            let candidate = &candidates[0];
+            let mut rest = candidate[word.len()..].to_owned();
+            if !candidate.ends_with('/') {
+                rest.push(' ');
+            }
+            self.insert(rest.as_bytes());
+            return;
+        }
+        let prefix = common_prefix(&candidates);
+        if prefix.len() > word.len() {
+            self.insert(prefix[word.len()..].as_bytes());
+        } else {
+            // Show the candidates, without the directory part of paths.
+            let dir_len = word.rfind('/').map(|i| i + 1).unwrap_or(0);
+            let names: Vec<&str> = candidates.iter().map(|c| &c[dir_len..]).collect();
+            println!("");
+            println!("{}", names.join("  "));
+            self.refresh();
+        }
+    }
+}
+
+fn common_prefix(strings: &[String]) -> &str {
+    let first = &strings[0];
+    let mut len = first.len();
+    for s in &strings[1..] {
+        len = first
+            .bytes()
+            .zip(s.bytes())
+            .take(len)
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+    &first[..len]
+}
+
+/// Candidates for completing `word`. Directories end with a `/`.
+fn completions(word: &str, is_command: bool) -> Vec<String> {
+    let mut candidates: Vec<String> = if is_command && !word.contains('/') {
+        crate::BUILTINS
+            .iter()
+            .map(|s| String::from(*s))
+            .chain(list_dir("/bin"))
+            .filter(|s| s.starts_with(word))
+            .collect()
+    } else {
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => (&word[..=i], &word[i + 1..]),
+            None => ("", word),
+        };
+        list_dir(if dir.is_empty() { "." } else { dir })
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| {
+                let path = format!("{}{}", dir, name);
+                if is_dir(&path) {
+                    path + "/"
+                } else {
+                    path
+                }
+            })
+            .collect()
+    };
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+fn list_dir(path: &str) -> Vec<String> {
+    let mut entries = vec![];
+    let Some(fd) = user::sys::open(path) else {
+        return entries;
+    };
+    while let Ok(Some(name)) = user::sys::readdir(fd, entries.len()) {
+        entries.push(name);
+    }
+    user::sys::close(fd);
+    entries
+}
+
+fn is_dir(path: &str) -> bool {
+    let Some(fd) = user::sys::open(path) else {
+        return false;
+    };
+    let result = user::sys::readdir(fd, 0).is_ok();
+    user::sys::close(fd);
+    result
+}
+
+fn read_byte() -> Option<u8> {
+    let mut buf = [0u8; 1];
+    match user::sys::read(Fd::STDIN, &mut buf) {
+        Ok(1) => Some(buf[0]),
+        _ => None,
+    }
+}
+
+/// Read a line when stdin is not a terminal.
+fn read_canonical_line() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let len = user::sys::read(Fd::STDIN, &mut buf).ok()?;
+    if len == 0 {
+        return None;
+    }
+    let line = buf[..len].strip_suffix(b"\n").unwrap_or(&buf[..len]);
+    Some(String::from_utf8_lossy(line).into_owned())
+}
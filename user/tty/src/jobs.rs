@@ -0,0 +1,92 @@
+//! Job control. Each job is a process running in its own process group.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user::sys::{Fd, Signal};
+
+/// Exit status of a stopped job, as seen by `$?`.
+pub const STOPPED_STATUS: isize = 128 + Signal::TerminalStop as isize;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+}
+
+pub struct Job {
+    pub id: usize,
+    pub pgid: usize,
+    pub command: String,
+    pub state: JobState,
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn add(&mut self, pgid: usize, command: String, state: JobState) -> usize {
+        let id = self.jobs.last().map(|j| j.id + 1).unwrap_or(1);
+        self.jobs.push(Job {
+            id,
+            pgid,
+            command,
+            state,
+        });
+        id
+    }
+
+    /// Remove a job by id. `None` means the latest job.
+    pub fn take(&mut self, id: Option<usize>) -> Option<Job> {
+        let index = match id {
+            Some(id) => self.jobs.iter().position(|j| j.id == id)?,
+            None => self.jobs.len().checked_sub(1)?,
+        };
+        Some(self.jobs.remove(index))
+    }
+
+    /// Update the state of the background jobs, and report the finished ones.
+    pub fn poll(&mut self) {
+        self.jobs.retain_mut(|job| {
+            let mut exit_code = 0;
+            match user::sys::waitpid_with_options(job.pgid, &mut exit_code, user::sys::WNOHANG) {
+                0 => {
+                    println!("[{}] Done ({}) {}", job.id, exit_code, job.command);
+                    false
+                }
+                1 => {
+                    job.state = JobState::Stopped;
+                    true
+                }
+                2 => {
+                    job.state = JobState::Running;
+                    true
+                }
+                _ => false,
+            }
+        });
+    }
+
+    /// Give the terminal to a job and wait for it to exit or stop.
+    /// A stopped job is added to the job list. Returns the exit status.
+    pub fn wait_foreground(&mut self, pgid: usize, command: String) -> isize {
+        let _ = termios::tcsetpgrp(Fd::STDIN, pgid);
+        let mut exit_code = 0;
+        let result = user::sys::waitpid(pgid, &mut exit_code);
+        let _ = termios::tcsetpgrp(Fd::STDIN, user::sys::getpgid(0) as usize);
+        match result {
+            0 => exit_code,
+            1 => {
+                let id = self.add(pgid, command.clone(), JobState::Stopped);
+                println!("[{}] Stopped {}", id, command);
+                STOPPED_STATUS
+            }
+            _ => -1,
+        }
+    }
+}
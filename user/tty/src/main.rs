@@ -6,87 +6,182 @@ extern crate alloc;
 #[macro_use]
 extern crate user;
 
-use alloc::{borrow::ToOwned, format, string::String, vec, vec::Vec};
-use user::sys::Fd;
+mod editor;
+mod jobs;
+mod parse;
 
-struct TTY {}
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::ffi::CStr;
+use editor::LineEditor;
+use jobs::{JobState, Jobs};
+use parse::Separator;
+use user::sys::{Fd, Signal};
+
+const BUILTINS: &[&str] = &["exit", "cd", "pwd", "export", "jobs", "fg", "bg", "history"];
+
+struct TTY {
+    editor: LineEditor,
+    jobs: Jobs,
+    /// Shell variables, set by `export`.
+    vars: BTreeMap<String, String>,
+    /// Exit status of the last command.
+    status: isize,
+}
 
 impl TTY {
     fn new() -> Self {
-        Self {}
+        Self {
+            editor: LineEditor::new(),
+            jobs: Jobs::default(),
+            vars: BTreeMap::new(),
+            status: 0,
+        }
     }
 
-    fn prompt(&self) -> String {
-        let cwd = user::sys::cwd().unwrap();
-        print!("{} $ ", cwd);
-        self.readline()
+    fn var(&self, name: &str) -> Option<String> {
+        if name == "?" {
+            return Some(self.status.to_string());
+        }
+        self.vars.get(name).cloned()
     }
 
-    fn read_byte(&self, first: bool) -> u8 {
-        let mut buf = [0u8; 1];
-        let _len = user::sys::read(Fd::STDIN, &mut buf).unwrap();
-        let mut c = buf[0];
-        if c == 127 {
-            c = 8;
-            buf[0] = c;
-            let s = core::str::from_utf8(&buf).unwrap();
-            if !first {
-                print!("{} {}", s, s);
-            }
-        } else {
-            let s = core::str::from_utf8(&buf).unwrap();
-            print!("{}", s);
-        }
-        c
+    fn prompt(&mut self) -> Option<String> {
+        let cwd = user::sys::cwd().unwrap();
+        self.editor.read_line(&format!("{} $ ", cwd))
     }
 
-    fn readline(&self) -> String {
-        let mut buf = vec![];
-        loop {
-            let c = self.read_byte(buf.is_empty());
-            if c == 8 {
-                buf.pop();
-            } else if c == b'\n' {
-                break;
-            } else {
-                buf.push(c);
+    /// Run a line of commands.
+    fn run_line(&mut self, line: &str) {
+        let commands = match parse::split_commands(line) {
+            Ok(commands) => commands,
+            Err(e) => {
+                println!("tty: {}", e);
+                self.status = 2;
+                return;
             }
+        };
+        let mut skip = false;
+        for command in commands {
+            if !skip {
+                let words = parse::expand_words(command.source, |name| self.var(name));
+                let background = command.separator == Separator::Background;
+                self.status = self.run_command(&words, background);
+            }
+            skip = match command.separator {
+                Separator::And => self.status != 0,
+                Separator::Or => self.status == 0,
+                Separator::Sequence | Separator::Background => false,
+            };
         }
-        core::str::from_utf8(&buf).unwrap().to_owned()
     }
 
-    fn is_internal_cmd(&self, cmd: &str) -> bool {
-        ["exit", "cd", "pwd"].iter().any(|&x| x == cmd)
+    /// Run a command and return its exit status.
+    fn run_command(&mut self, words: &[String], background: bool) -> isize {
+        let Some(cmd) = words.first() else {
+            return self.status;
+        };
+        let args: Vec<&str> = words[1..].iter().map(|s| s.as_str()).collect();
+        if BUILTINS.contains(&cmd.as_str()) {
+            self.exec_internal_cmd(cmd, &args)
+        } else {
+            self.exec_external_cmd(cmd, &args, background)
+        }
     }
 
-    fn exec_internal_cmd(&self, cmd: &str, args: &[&str]) {
+    fn exec_internal_cmd(&mut self, cmd: &str, args: &[&str]) -> isize {
         match cmd {
             "exit" => {
-                println!("Sophon TTY exited.");
-                user::sys::halt(0);
+                let code = match args.first() {
+                    Some(code) => code.parse().unwrap_or(2),
+                    None => self.status,
+                };
+                user::sys::exit(code);
             }
             "cd" => {
                 if args.len() == 1 {
-                    match user::sys::chdir(&args[0]) {
-                        Ok(_) => {}
+                    match user::sys::chdir(args[0]) {
+                        Ok(_) => 0,
                         Err(_) => {
                             println!("cd: no such file or directory");
+                            1
                         }
-                    };
+                    }
                 } else {
                     println!("Usage: cd <path>");
+                    2
                 }
             }
             "pwd" => {
                 let cwd = user::sys::cwd().unwrap();
                 println!("{}", cwd);
+                0
+            }
+            "export" => {
+                if args.is_empty() {
+                    for (name, value) in &self.vars {
+                        println!("export {}={}", name, value);
+                    }
+                }
+                for arg in args {
+                    let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+                    self.vars.insert(name.to_owned(), value.to_owned());
+                }
+                0
+            }
+            "jobs" => {
+                self.jobs.poll();
+                for job in self.jobs.iter() {
+                    let state = match job.state {
+                        JobState::Running => "Running",
+                        JobState::Stopped => "Stopped",
+                    };
+                    println!("[{}] {} {} {}", job.id, job.pgid, state, job.command);
+                }
+                0
+            }
+            "fg" | "bg" => {
+                let id = match args.first() {
+                    Some(arg) => match arg.trim_start_matches('%').parse() {
+                        Ok(id) => Some(id),
+                        Err(_) => {
+                            println!("Usage: {} [%job]", cmd);
+                            return 2;
+                        }
+                    },
+                    None => None,
+                };
+                let Some(mut job) = self.jobs.take(id) else {
+                    println!("{}: no such job", cmd);
+                    return 1;
+                };
+                println!("{}", job.command);
+                if cmd == "fg" {
+                    let _ = termios::tcsetpgrp(Fd::STDIN, job.pgid);
+                    user::sys::kill(-(job.pgid as isize), Signal::Continue);
+                    self.jobs.wait_foreground(job.pgid, job.command)
+                } else {
+                    user::sys::kill(-(job.pgid as isize), Signal::Continue);
+                    job.state = JobState::Running;
+                    self.jobs.add(job.pgid, job.command, job.state);
+                    0
+                }
+            }
+            "history" => {
+                for (i, line) in self.editor.history().iter().enumerate() {
+                    println!("{:5}  {}", i + 1, line);
+                }
+                0
             }
             _ => unreachable!(),
         }
     }
 
-    fn exec_external_cmd(&self, cmd: &str, args: &[&str]) {
-        let cmd = if !cmd.starts_with("/") && !cmd.starts_with(".") {
+    fn exec_external_cmd(&mut self, cmd: &str, args: &[&str], background: bool) -> isize {
+        let path = if !cmd.starts_with("/") && !cmd.starts_with(".") {
             format!("/bin/{}", cmd)
         } else {
             cmd.to_owned()
@@ -94,47 +189,78 @@ impl TTY {
         let pid = user::sys::fork();
         if pid < 0 {
             println!("ERROR: fork failed");
-            return;
+            return -1;
         }
         if pid == 0 {
-            let err = user::sys::exec(&cmd, args);
+            // Run in a new process group, so that terminal signals do not reach the shell.
+            user::sys::setpgid(0, 0);
+            let err = user::sys::exec(&path, args);
             if err != 0 {
-                println!("ERROR: command not found");
+                println!("{}: command not found", cmd);
             }
-            user::sys::exit();
+            user::sys::exit(127);
+        }
+        let pid = pid as usize;
+        user::sys::setpgid(pid, pid);
+        let command = [cmd]
+            .iter()
+            .chain(args)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if background {
+            let id = self.jobs.add(pid, command, JobState::Running);
+            println!("[{}] {}", id, pid);
+            0
         } else {
-            let mut exit_code = 0;
-            let _ = user::sys::waitpid(pid as usize, &mut exit_code);
-            println!("Process exited with code {}", exit_code);
+            self.jobs.wait_foreground(pid, command)
         }
     }
 
-    pub fn run(&self) {
+    pub fn run(&mut self) {
         println!("[[Sophon TTY]]");
+        // Take the terminal, so that Ctrl-C does not reach the parent.
+        user::sys::setpgid(0, 0);
+        let _ = termios::tcsetpgrp(Fd::STDIN, user::sys::getpgid(0) as usize);
         loop {
-            let cmd = self.prompt();
-            // println!("{:?}", cmd);
-
-            let segments = cmd
-                .split(" ")
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .collect::<Vec<_>>();
-            let cmd = segments[0];
-            let args = &segments[1..];
-            if self.is_internal_cmd(cmd) {
-                self.exec_internal_cmd(cmd, args)
-            } else {
-                self.exec_external_cmd(cmd, args)
-            }
+            self.jobs.poll();
+            let Some(line) = self.prompt() else {
+                break;
+            };
+            self.run_line(&line);
+        }
+    }
+
+    /// Run a script file. Returns the exit status of the last command.
+    pub fn run_script(&mut self, path: &str) -> isize {
+        let Some(fd) = user::sys::open(path) else {
+            println!("tty: {}: no such file", path);
+            return 127;
+        };
+        let mut content = vec![];
+        let mut buf = [0u8; 256];
+        while let Ok(len @ 1..) = user::sys::read(fd, &mut buf) {
+            content.extend_from_slice(&buf[..len]);
         }
+        user::sys::close(fd);
+        for line in String::from_utf8_lossy(&content).lines() {
+            self.run_line(line);
+        }
+        self.status
     }
 }
 
 #[no_mangle]
-pub extern "C" fn _start(_argc: isize, _argv: *const *const u8) -> isize {
-    let tty = TTY::new();
+pub extern "C" fn _start(argc: isize, argv: *const *const u8) -> isize {
+    // argv[0] is the program itself.
+    if argc > 1 {
+        let c_str: &CStr = unsafe { CStr::from_ptr(argv.add(1).read() as _) };
+        let path = c_str.to_str().unwrap().trim();
+        let status = TTY::new().run_script(path);
+        user::sys::exit(status)
+    }
+    let mut tty = TTY::new();
     tty.run();
     println!("TTY exited.");
-    user::sys::exit()
+    user::sys::exit(tty.status)
 }
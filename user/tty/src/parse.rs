@@ -0,0 +1,187 @@
+//! Command line parsing.
+//!
+//! A line is first split into commands at the control operators (`;`, `&`, `&&`, `||`).
+//! Each command is then split into words right before it runs, so that `$?` sees the
+//! status of the previous command.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// How a command is connected to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    /// `;` or the end of the line.
+    Sequence,
+    /// `&`: Run in the background.
+    Background,
+    /// `&&`: Run the next command only if this one succeeds.
+    And,
+    /// `||`: Run the next command only if this one fails.
+    Or,
+}
+
+pub struct Command<'a> {
+    pub source: &'a str,
+    pub separator: Separator,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+/// Split a line into commands. Comments are removed.
+pub fn split_commands(line: &str) -> Result<Vec<Command<'_>>, &'static str> {
+    let bytes = line.as_bytes();
+    let mut commands = Vec::new();
+    let mut quote = Quote::None;
+    let mut start = 0;
+    let mut end = bytes.len();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match quote {
+            Quote::Single => {
+                if c == b'\'' {
+                    quote = Quote::None;
+                }
+            }
+            Quote::Double => match c {
+                b'\\' => i += 1,
+                b'"' => quote = Quote::None,
+                _ => {}
+            },
+            Quote::None => match c {
+                b'\\' => i += 1,
+                b'\'' => quote = Quote::Single,
+                b'"' => quote = Quote::Double,
+                b'#' if i == 0 || b" \t;&|".contains(&bytes[i - 1]) => {
+                    end = i;
+                    break;
+                }
+                b';' | b'&' | b'|' => {
+                    let (separator, len) = match (c, bytes.get(i + 1)) {
+                        (b'&', Some(b'&')) => (Separator::And, 2),
+                        (b'|', Some(b'|')) => (Separator::Or, 2),
+                        (b'&', _) => (Separator::Background, 1),
+                        (b';', _) => (Separator::Sequence, 1),
+                        _ => return Err("pipes are not supported"),
+                    };
+                    let source = line[start..i].trim();
+                    if source.is_empty() {
+                        return Err("syntax error near unexpected operator");
+                    }
+                    commands.push(Command { source, separator });
+                    i += len;
+                    start = i;
+                    continue;
+                }
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    if quote != Quote::None {
+        return Err("unterminated quote");
+    }
+    let source = line[start..end].trim();
+    if !source.is_empty() {
+        commands.push(Command {
+            source,
+            separator: Separator::Sequence,
+        });
+    } else if let Some(last) = commands.last() {
+        if last.separator == Separator::And || last.separator == Separator::Or {
+            return Err("unexpected end of line");
+        }
+    }
+    Ok(commands)
+}
+
+/// Split a command into words, removing quotes and escapes, and expanding variables.
+///
+/// Variables are expanded in unquoted text and in double quotes. The expanded values are
+/// not split into more words.
+pub fn expand_words(source: &str, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // Distinguishes an empty quoted word from no word.
+    let mut in_word = false;
+    let mut quote = Quote::None;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Quote::Single, '\'') => quote = Quote::None,
+            (Quote::Single, _) => word.push(c),
+            (Quote::Double, '"') => quote = Quote::None,
+            (Quote::Double, '\\') => match chars.peek() {
+                Some(&next) if "\"\\$".contains(next) => {
+                    word.push(next);
+                    chars.next();
+                }
+                _ => word.push('\\'),
+            },
+            (_, '$') => {
+                let mut name = String::new();
+                if chars.peek() == Some(&'?') {
+                    name.push('?');
+                    chars.next();
+                } else if chars.peek() == Some(&'{') {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                        name.push(c);
+                    }
+                } else {
+                    while let Some(&c) = chars.peek() {
+                        if !c.is_ascii_alphanumeric() && c != '_' {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                }
+                if name.is_empty() {
+                    word.push('$');
+                } else if let Some(value) = var(&name) {
+                    word.push_str(&value);
+                }
+                // An unquoted empty variable is not a word.
+                in_word |= quote == Quote::Double || !word.is_empty();
+            }
+            (Quote::Double, _) => word.push(c),
+            (Quote::None, '\'') => {
+                quote = Quote::Single;
+                in_word = true;
+            }
+            (Quote::None, '"') => {
+                quote = Quote::Double;
+                in_word = true;
+            }
+            (Quote::None, '\\') => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+                in_word = true;
+            }
+            (Quote::None, _) if c.is_whitespace() => {
+                if in_word {
+                    words.push(core::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (Quote::None, _) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
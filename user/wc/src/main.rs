@@ -0,0 +1,78 @@
+//! Count the lines, words and bytes of files, one thread per file.
+//!
+//! Built against `std`, as an example of the `aarch64-unknown-sophon` target.
+
+use std::io::{self, Read, Write};
+use std::{env, fs, thread};
+
+use std_sys as _;
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    lines: usize,
+    words: usize,
+    bytes: usize,
+}
+
+impl Counts {
+    fn of(data: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(data);
+        Self {
+            lines: data.iter().filter(|b| **b == b'\n').count(),
+            words: text.split_whitespace().count(),
+            bytes: data.len(),
+        }
+    }
+
+    fn add(&mut self, other: Self) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.bytes += other.bytes;
+    }
+}
+
+fn print(out: &mut impl Write, counts: Counts, name: &str) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>7} {:>7} {:>7} {}",
+        counts.lines, counts.words, counts.bytes, name
+    )
+}
+
+fn main() -> io::Result<()> {
+    let paths = env::args().skip(1).collect::<Vec<_>>();
+    let mut out = io::stdout().lock();
+    if paths.is_empty() {
+        let mut data = vec![];
+        io::stdin().read_to_end(&mut data)?;
+        return print(&mut out, Counts::of(&data), "");
+    }
+    let workers = paths
+        .iter()
+        .map(|path| {
+            let path = path.clone();
+            thread::spawn(move || fs::read(path).map(|data| Counts::of(&data)))
+        })
+        .collect::<Vec<_>>();
+    let mut total = Counts::default();
+    let mut failed = false;
+    for (path, worker) in paths.iter().zip(workers) {
+        match worker.join().unwrap() {
+            Ok(counts) => {
+                total.add(counts);
+                print(&mut out, counts, path)?;
+            }
+            Err(e) => {
+                eprintln!("wc: {path}: {e}");
+                failed = true;
+            }
+        }
+    }
+    if paths.len() > 1 {
+        print(&mut out, total, "total")?;
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}